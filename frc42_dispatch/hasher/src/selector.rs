@@ -0,0 +1,198 @@
+use thiserror::Error;
+
+use crate::hash::Hasher;
+
+/// Hasher that uses the keccak256 hash syscall provided by the FVM.
+#[cfg(feature = "use_sdk")]
+#[derive(Default)]
+pub struct Keccak256Syscall {}
+
+#[cfg(feature = "use_sdk")]
+impl Hasher for Keccak256Syscall {
+    // fvm_sdk dependence can be removed by setting default-features to false
+    fn hash(&self, bytes: &[u8]) -> Vec<u8> {
+        use fvm_shared::crypto::hash::SupportedHashes;
+        fvm_sdk::crypto::hash_owned(SupportedHashes::Keccak256, bytes)
+    }
+}
+
+/// A Solidity function selector.
+pub type Selector = [u8; 4];
+
+/// Uses an underlying hashing function (keccak256 by convention) to generate Solidity function
+/// selectors from function signatures.
+#[derive(Default)]
+pub struct SelectorResolver<T: Hasher> {
+    hasher: T,
+}
+
+#[derive(Error, PartialEq, Eq, Debug)]
+pub enum SignatureErr {
+    #[error("empty function signature provided")]
+    EmptyString,
+    #[error("function name is not a valid Solidity identifier")]
+    IllegalName,
+    #[error("function signature must be of the form name(type1,type2,...)")]
+    MissingParameterList,
+    #[error("function signature is not canonical: {0:?} is not an elementary type")]
+    IllegalType(String),
+}
+
+impl<T: Hasher> SelectorResolver<T> {
+    /// Creates a [`SelectorResolver`] with an instance of a hasher (keccak256 by convention).
+    pub fn new(hasher: T) -> Self {
+        Self { hasher }
+    }
+
+    /// Generates the Solidity function selector for a canonical function signature.
+    ///
+    /// The selector is the first four bytes of `hash(signature)`. The signature must be in the
+    /// canonical form used by the Solidity ABI, e.g. `transfer(address,uint256)`, without spaces
+    /// or parameter names.
+    pub fn selector(&self, signature: &str) -> Result<Selector, SignatureErr> {
+        check_signature(signature)?;
+        let digest = self.hasher.hash(signature.as_bytes());
+        Ok(digest[0..4].try_into().expect("digest was not at least length 4"))
+    }
+}
+
+/// Checks that a function signature is in the canonical form used to compute selectors.
+///
+/// - The function name is a Solidity identifier `[a-zA-Z_$][a-zA-Z0-9_$]*`.
+/// - The parameter list is enclosed in parentheses and comma separated, without whitespace.
+/// - Each parameter is a canonical type (e.g. `uint256` rather than `uint`), optionally a tuple
+///   and/or an array.
+fn check_signature(signature: &str) -> Result<(), SignatureErr> {
+    if signature.is_empty() {
+        return Err(SignatureErr::EmptyString);
+    }
+
+    let (name, params) = signature.split_once('(').ok_or(SignatureErr::MissingParameterList)?;
+    let mut name_chars = name.chars();
+    let valid_start =
+        name_chars.next().is_some_and(|c| c.is_ascii_alphabetic() || "_$".contains(c));
+    if !valid_start || !name_chars.all(|c| c.is_ascii_alphanumeric() || "_$".contains(c)) {
+        return Err(SignatureErr::IllegalName);
+    }
+
+    let params = params.strip_suffix(')').ok_or(SignatureErr::MissingParameterList)?;
+    check_parameter_list(params)
+}
+
+/// Checks a comma separated list of parameter types, recursing into tuples.
+fn check_parameter_list(params: &str) -> Result<(), SignatureErr> {
+    if params.is_empty() {
+        return Ok(());
+    }
+
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in params.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth = depth.checked_sub(1).ok_or(SignatureErr::MissingParameterList)?;
+            }
+            ',' if depth == 0 => {
+                check_type(&params[start..i])?;
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(SignatureErr::MissingParameterList);
+    }
+    check_type(&params[start..])
+}
+
+/// Checks a single canonical parameter type.
+fn check_type(ty: &str) -> Result<(), SignatureErr> {
+    let illegal = || SignatureErr::IllegalType(ty.to_string());
+
+    // strip any array suffixes, e.g. uint256[2][]
+    let mut base = ty;
+    while let Some(inner) = base.strip_suffix(']') {
+        let (inner, len) = inner.rsplit_once('[').ok_or_else(illegal)?;
+        if !len.chars().all(|c| c.is_ascii_digit()) {
+            return Err(illegal());
+        }
+        base = inner;
+    }
+
+    if let Some(tuple) = base.strip_prefix('(') {
+        let tuple = tuple.strip_suffix(')').ok_or_else(illegal)?;
+        return check_parameter_list(tuple);
+    }
+
+    let sized = |prefix: &str, valid: fn(u32) -> bool| {
+        base.strip_prefix(prefix).and_then(|size| size.parse::<u32>().ok()).is_some_and(valid)
+    };
+    let valid = matches!(base, "address" | "bool" | "string" | "bytes" | "function")
+        || sized("uint", |n| n % 8 == 0 && (8..=256).contains(&n))
+        || sized("int", |n| n % 8 == 0 && (8..=256).contains(&n))
+        || sized("bytes", |n| (1..=32).contains(&n));
+    if valid {
+        Ok(())
+    } else {
+        Err(illegal())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SelectorResolver, SignatureErr};
+    use crate::hash::Hasher;
+
+    #[derive(Clone, Copy)]
+    struct FakeHasher {}
+    impl Hasher for FakeHasher {
+        fn hash(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.to_vec()
+        }
+    }
+
+    #[test]
+    fn selector_is_hash_prefix() {
+        let resolver = SelectorResolver::new(FakeHasher {});
+        assert_eq!(resolver.selector("transfer(address,uint256)").unwrap(), *b"tran");
+        assert_eq!(resolver.selector("name()").unwrap(), *b"name");
+    }
+
+    #[test]
+    fn accepts_canonical_signatures() {
+        let resolver = SelectorResolver::new(FakeHasher {});
+        resolver.selector("balanceOf(address)").unwrap();
+        resolver.selector("safeTransferFrom(address,address,uint256,bytes)").unwrap();
+        resolver.selector("batch(uint64[],bytes32[2],(address,int8)[])").unwrap();
+        resolver.selector("$fn_1(bool)").unwrap();
+    }
+
+    #[test]
+    fn disallows_invalid_signatures() {
+        let resolver = SelectorResolver::new(FakeHasher {});
+        assert_eq!(resolver.selector("").unwrap_err(), SignatureErr::EmptyString);
+        assert_eq!(resolver.selector("transfer").unwrap_err(), SignatureErr::MissingParameterList);
+        assert_eq!(
+            resolver.selector("transfer(address").unwrap_err(),
+            SignatureErr::MissingParameterList
+        );
+        assert_eq!(resolver.selector("1transfer()").unwrap_err(), SignatureErr::IllegalName);
+        assert_eq!(
+            resolver.selector("transfer(address,uint)").unwrap_err(),
+            SignatureErr::IllegalType("uint".into())
+        );
+        assert_eq!(
+            resolver.selector("transfer(address, uint256)").unwrap_err(),
+            SignatureErr::IllegalType(" uint256".into())
+        );
+        assert_eq!(
+            resolver.selector("transfer(address to)").unwrap_err(),
+            SignatureErr::IllegalType("address to".into())
+        );
+        assert_eq!(
+            resolver.selector("hash(bytes33)").unwrap_err(),
+            SignatureErr::IllegalType("bytes33".into())
+        );
+    }
+}
@@ -1,5 +1,6 @@
 pub use frc42_hasher as hasher;
 pub use frc42_hasher::hash;
+pub use frc42_hasher::selector;
 pub use frc42_macros::method_hash;
 
 pub mod match_method;
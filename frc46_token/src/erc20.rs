@@ -0,0 +1,408 @@
+//! ERC-20 compatibility façade for FRC-46 tokens.
+//!
+//! Ethereum accounts and EVM contracts call native actors through the `InvokeEVM` method (see
+//! [`INVOKE_EVM_METHOD_NUM`][fvm_actor_utils::evm::INVOKE_EVM_METHOD_NUM]) with Solidity ABI
+//! calldata. A token actor can decode that calldata into an [`Erc20Call`] and invoke it against its
+//! [`Token`] to serve ERC-20 callers alongside native FRC-46 callers.
+//!
+//! Ethereum addresses are mapped to Filecoin addresses with
+//! [`eth_to_address`][fvm_actor_utils::evm::eth_to_address], after which the usual [`Token`]
+//! address resolution applies. Transfers still call the FRC-46 receiver hook on the recipient.
+#![allow(clippy::result_large_err)]
+
+use fvm_actor_utils::evm::{AbiDecoder, AbiEncoder, AbiError};
+use fvm_actor_utils::messaging::Messaging;
+use fvm_actor_utils::receiver::{ReceiverHook, ReceiverHookError};
+use fvm_actor_utils::syscalls::Syscalls;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use thiserror::Error;
+
+use crate::token::state::max_allowance;
+use crate::token::types::{TransferFromIntermediate, TransferIntermediate};
+use crate::token::{Token, TokenError};
+
+/// Number of decimals reported to ERC-20 callers, matching
+/// [`TOKEN_PRECISION`][crate::token::TOKEN_PRECISION].
+pub const DECIMALS: u64 = 18;
+
+/// Selectors of the supported ERC-20 functions.
+pub mod selectors {
+    use frc42_dispatch::selector::Selector;
+
+    /// `name()`
+    pub const NAME: Selector = [0x06, 0xfd, 0xde, 0x03];
+    /// `symbol()`
+    pub const SYMBOL: Selector = [0x95, 0xd8, 0x9b, 0x41];
+    /// `decimals()`
+    pub const DECIMALS: Selector = [0x31, 0x3c, 0xe5, 0x67];
+    /// `totalSupply()`
+    pub const TOTAL_SUPPLY: Selector = [0x18, 0x16, 0x0d, 0xdd];
+    /// `balanceOf(address)`
+    pub const BALANCE_OF: Selector = [0x70, 0xa0, 0x82, 0x31];
+    /// `allowance(address,address)`
+    pub const ALLOWANCE: Selector = [0xdd, 0x62, 0xed, 0x3e];
+    /// `transfer(address,uint256)`
+    pub const TRANSFER: Selector = [0xa9, 0x05, 0x9c, 0xbb];
+    /// `transferFrom(address,address,uint256)`
+    pub const TRANSFER_FROM: Selector = [0x23, 0xb8, 0x72, 0xdd];
+    /// `approve(address,uint256)`
+    pub const APPROVE: Selector = [0x09, 0x5e, 0xa7, 0xb3];
+}
+
+#[derive(Error, Debug)]
+pub enum Erc20Error {
+    #[error("abi error: {0}")]
+    Abi(#[from] AbiError),
+    #[error("token error: {0}")]
+    Token(#[from] TokenError),
+}
+
+impl From<&Erc20Error> for ExitCode {
+    fn from(error: &Erc20Error) -> Self {
+        match error {
+            Erc20Error::Abi(e) => e.into(),
+            Erc20Error::Token(e) => e.into(),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Erc20Error>;
+
+/// A decoded call to an ERC-20 function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Erc20Call {
+    Name,
+    Symbol,
+    Decimals,
+    TotalSupply,
+    BalanceOf { owner: Address },
+    Allowance { owner: Address, spender: Address },
+    Transfer { to: Address, amount: TokenAmount },
+    TransferFrom { from: Address, to: Address, amount: TokenAmount },
+    Approve { spender: Address, amount: TokenAmount },
+}
+
+impl Erc20Call {
+    /// Decodes Solidity ABI calldata into an ERC-20 call.
+    pub fn decode(calldata: &[u8]) -> std::result::Result<Self, AbiError> {
+        let (selector, args) = AbiDecoder::from_calldata(calldata)?;
+        let amount = |index| args.uint256(index).map(TokenAmount::from_atto);
+        Ok(match selector {
+            selectors::NAME => Erc20Call::Name,
+            selectors::SYMBOL => Erc20Call::Symbol,
+            selectors::DECIMALS => Erc20Call::Decimals,
+            selectors::TOTAL_SUPPLY => Erc20Call::TotalSupply,
+            selectors::BALANCE_OF => Erc20Call::BalanceOf { owner: args.address(0)? },
+            selectors::ALLOWANCE => {
+                Erc20Call::Allowance { owner: args.address(0)?, spender: args.address(1)? }
+            }
+            selectors::TRANSFER => Erc20Call::Transfer { to: args.address(0)?, amount: amount(1)? },
+            selectors::TRANSFER_FROM => Erc20Call::TransferFrom {
+                from: args.address(0)?,
+                to: args.address(1)?,
+                amount: amount(2)?,
+            },
+            selectors::APPROVE => {
+                Erc20Call::Approve { spender: args.address(0)?, amount: amount(1)? }
+            }
+            _ => return Err(AbiError::UnknownSelector(selector)),
+        })
+    }
+
+    /// Returns true if invoking this call modifies token state, which must then be flushed.
+    pub fn mutates_state(&self) -> bool {
+        matches!(
+            self,
+            Erc20Call::Transfer { .. } | Erc20Call::TransferFrom { .. } | Erc20Call::Approve { .. }
+        )
+    }
+
+    /// Invokes the call on a token, on behalf of `caller`.
+    ///
+    /// The `name` and `symbol` are not part of token state and must be supplied by the actor. If
+    /// [`Erc20Call::mutates_state`] is true, the token state must be flushed before the returned
+    /// [`Erc20Response`] is completed.
+    pub fn invoke<S: Syscalls, BS: Blockstore>(
+        self,
+        token: &mut Token<S, BS>,
+        caller: &Address,
+        name: &str,
+        symbol: &str,
+    ) -> Result<Erc20Response> {
+        let encoder = AbiEncoder::new();
+        let data = match self {
+            Erc20Call::Name => encoder.string(name),
+            Erc20Call::Symbol => encoder.string(symbol),
+            Erc20Call::Decimals => encoder.uint64(DECIMALS),
+            Erc20Call::TotalSupply => encoder.uint256(token.total_supply().atto())?,
+            Erc20Call::BalanceOf { owner } => encoder.uint256(token.balance_of(&owner)?.atto())?,
+            Erc20Call::Allowance { owner, spender } => {
+                // unlimited allowances are reported as the maximum, and larger ones are capped at it
+                let allowance = token.allowance(&owner, &spender)?.min(max_allowance());
+                encoder.uint256(allowance.atto())?
+            }
+            Erc20Call::Approve { spender, amount } => {
+                // approving the maximum sets an unlimited allowance
+                token.set_allowance(caller, &spender, &amount)?;
+                encoder.bool(true)
+            }
+            Erc20Call::Transfer { to, amount } => {
+                let hook = token.transfer(
+                    caller,
+                    &to,
+                    &amount,
+                    RawBytes::default(),
+                    RawBytes::default(),
+                )?;
+                return Ok(Erc20Response::Transfer(hook));
+            }
+            Erc20Call::TransferFrom { from, to, amount } => {
+                let hook = token.transfer_from(
+                    caller,
+                    &from,
+                    &to,
+                    &amount,
+                    RawBytes::default(),
+                    RawBytes::default(),
+                )?;
+                return Ok(Erc20Response::TransferFrom(hook));
+            }
+        };
+        Ok(Erc20Response::Complete(data.finish()))
+    }
+}
+
+/// The outcome of invoking an [`Erc20Call`].
+#[derive(Debug)]
+pub enum Erc20Response {
+    /// The call is complete, with the given ABI encoded return data.
+    Complete(Vec<u8>),
+    /// A transfer whose receiver hook must be called.
+    Transfer(ReceiverHook<TransferIntermediate>),
+    /// A delegated transfer whose receiver hook must be called.
+    TransferFrom(ReceiverHook<TransferFromIntermediate>),
+}
+
+impl Erc20Response {
+    /// Calls any pending receiver hook and returns the ABI encoded return data.
+    ///
+    /// Token state must be flushed before calling this, as the receiver hook may re-enter the token.
+    pub fn complete(self, msg: &dyn Messaging) -> std::result::Result<Vec<u8>, ReceiverHookError> {
+        match self {
+            Erc20Response::Complete(data) => Ok(data),
+            Erc20Response::Transfer(mut hook) => {
+                hook.call(msg)?;
+                Ok(AbiEncoder::new().bool(true).finish())
+            }
+            Erc20Response::TransferFrom(mut hook) => {
+                hook.call(msg)?;
+                Ok(AbiEncoder::new().bool(true).finish())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_actor_utils::evm::{eth_to_address, AbiDecoder, AbiEncoder, AbiError, EthAddress};
+    use fvm_actor_utils::syscalls::fake_syscalls::FakeSyscalls;
+    use fvm_actor_utils::util::ActorRuntime;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::address::Address;
+    use fvm_shared::bigint::BigInt;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
+
+    use super::{selectors, Erc20Call, Erc20Error, Erc20Response};
+    use crate::token::state::{max_allowance, unlimited_allowance, TokenState};
+    use crate::token::{Token, TokenError};
+
+    const TREASURY: Address = Address::new_id(2);
+    const ALICE: Address = Address::new_id(3);
+    const ETH_BOB: EthAddress = [0xb0; 20];
+
+    fn invoke(
+        token: &mut Token<FakeSyscalls, MemoryBlockstore>,
+        caller: &Address,
+        calldata: &[u8],
+    ) -> Result<Vec<u8>, Erc20Error> {
+        let call = Erc20Call::decode(calldata)?;
+        let res = call.invoke(token, caller, "Test Token", "TEST")?;
+        token.flush()?;
+        Ok(res.complete(token.runtime()).unwrap())
+    }
+
+    /// Creates a token where alice holds 5 whole tokens.
+    fn new_token_with_balance<'st>(
+        runtime: &'st ActorRuntime<FakeSyscalls, MemoryBlockstore>,
+        state: &'st mut TokenState,
+    ) -> Token<'st, FakeSyscalls, MemoryBlockstore> {
+        let mut token = Token::wrap(runtime, 1, state);
+        let mut hook = token
+            .mint(
+                &TREASURY,
+                &ALICE,
+                &TokenAmount::from_whole(5),
+                Default::default(),
+                Default::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(runtime).unwrap();
+        token
+    }
+
+    fn uint(data: &[u8]) -> BigInt {
+        AbiDecoder::new(data).uint256(0).unwrap()
+    }
+
+    #[test]
+    fn it_decodes_calls() {
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER_FROM)
+            .address(&ETH_BOB)
+            .address(&ETH_BOB)
+            .uint64(100)
+            .finish();
+        let call = Erc20Call::decode(&calldata).unwrap();
+        assert_eq!(
+            call,
+            Erc20Call::TransferFrom {
+                from: eth_to_address(&ETH_BOB),
+                to: eth_to_address(&ETH_BOB),
+                amount: TokenAmount::from_atto(100),
+            }
+        );
+        assert!(call.mutates_state());
+        assert!(!Erc20Call::TotalSupply.mutates_state());
+
+        assert_eq!(
+            Erc20Call::decode(&[0xde, 0xad, 0xbe, 0xef]).unwrap_err(),
+            AbiError::UnknownSelector([0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(Erc20Call::decode(&selectors::BALANCE_OF).unwrap_err(), AbiError::Truncated(0));
+    }
+
+    #[test]
+    fn it_serves_erc20_queries() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = TokenState::new(&runtime).unwrap();
+        let mut token = new_token_with_balance(&runtime, &mut state);
+
+        let name = invoke(&mut token, &ALICE, &selectors::NAME).unwrap();
+        assert_eq!(AbiDecoder::new(&name).bytes(0).unwrap(), b"Test Token");
+        let symbol = invoke(&mut token, &ALICE, &selectors::SYMBOL).unwrap();
+        assert_eq!(AbiDecoder::new(&symbol).bytes(0).unwrap(), b"TEST");
+        let decimals = invoke(&mut token, &ALICE, &selectors::DECIMALS).unwrap();
+        assert_eq!(uint(&decimals), BigInt::from(18));
+
+        let supply = invoke(&mut token, &ALICE, &selectors::TOTAL_SUPPLY).unwrap();
+        assert_eq!(uint(&supply), TokenAmount::from_whole(5).atto().clone());
+
+        // alice is queried through her masked ID address
+        let alice_eth = fvm_actor_utils::evm::id_to_eth(3);
+        let calldata =
+            AbiEncoder::with_selector(selectors::BALANCE_OF).address(&alice_eth).finish();
+        let balance = invoke(&mut token, &ALICE, &calldata).unwrap();
+        assert_eq!(uint(&balance), TokenAmount::from_whole(5).atto().clone());
+    }
+
+    #[test]
+    fn it_transfers_to_eth_addresses() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = TokenState::new(&runtime).unwrap();
+        let mut token = new_token_with_balance(&runtime, &mut state);
+
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER)
+            .address(&ETH_BOB)
+            .uint256(TokenAmount::from_whole(2).atto())
+            .unwrap()
+            .finish();
+        let ret = invoke(&mut token, &ALICE, &calldata).unwrap();
+        assert!(AbiDecoder::new(&ret).bool(0).unwrap());
+
+        // the f410 address of bob was initialised as an account
+        let bob = eth_to_address(&ETH_BOB);
+        assert_eq!(token.balance_of(&bob).unwrap(), TokenAmount::from_whole(2));
+        assert_eq!(token.balance_of(&ALICE).unwrap(), TokenAmount::from_whole(3));
+
+        // insufficient balance is reported as a token error
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER)
+            .address(&ETH_BOB)
+            .uint256(TokenAmount::from_whole(10).atto())
+            .unwrap()
+            .finish();
+        let err = invoke(&mut token, &ALICE, &calldata).unwrap_err();
+        assert!(matches!(err, Erc20Error::Token(TokenError::TokenState(_))));
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_INSUFFICIENT_FUNDS);
+        assert!(token.check_invariants().1.is_empty());
+    }
+
+    #[test]
+    fn it_approves_and_transfers_from() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = TokenState::new(&runtime).unwrap();
+        let mut token = new_token_with_balance(&runtime, &mut state);
+
+        let bob = eth_to_address(&ETH_BOB);
+        let calldata =
+            AbiEncoder::with_selector(selectors::APPROVE).address(&ETH_BOB).uint64(1_000).finish();
+        let ret = invoke(&mut token, &ALICE, &calldata).unwrap();
+        assert!(AbiDecoder::new(&ret).bool(0).unwrap());
+        // approve replaces rather than increases the allowance
+        invoke(&mut token, &ALICE, &calldata).unwrap();
+
+        let calldata = AbiEncoder::with_selector(selectors::ALLOWANCE)
+            .address(&fvm_actor_utils::evm::id_to_eth(3))
+            .address(&ETH_BOB)
+            .finish();
+        let allowance = invoke(&mut token, &ALICE, &calldata).unwrap();
+        assert_eq!(uint(&allowance), BigInt::from(1_000));
+
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER_FROM)
+            .address(&fvm_actor_utils::evm::id_to_eth(3))
+            .address(&ETH_BOB)
+            .uint64(400)
+            .finish();
+        let res = Erc20Call::decode(&calldata)
+            .unwrap()
+            .invoke(&mut token, &bob, "Test Token", "TEST")
+            .unwrap();
+        assert!(matches!(res, Erc20Response::TransferFrom(_)));
+        token.flush().unwrap();
+        let ret = res.complete(token.runtime()).unwrap();
+        assert!(AbiDecoder::new(&ret).bool(0).unwrap());
+
+        assert_eq!(token.balance_of(&bob).unwrap(), TokenAmount::from_atto(400));
+        assert_eq!(token.allowance(&ALICE, &bob).unwrap(), TokenAmount::from_atto(600));
+    }
+
+    #[test]
+    fn it_maps_max_approvals_to_unlimited_allowances() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = TokenState::new(&runtime).unwrap();
+        let mut token = new_token_with_balance(&runtime, &mut state);
+
+        let bob = eth_to_address(&ETH_BOB);
+        let calldata = AbiEncoder::with_selector(selectors::APPROVE)
+            .address(&ETH_BOB)
+            .uint256(max_allowance().atto())
+            .unwrap()
+            .finish();
+        invoke(&mut token, &ALICE, &calldata).unwrap();
+        let (alice, bob_id) = (ALICE.id().unwrap(), token.runtime().resolve_id(&bob).unwrap());
+        let stored = token.state().get_allowance_between(token.runtime(), alice, bob_id).unwrap();
+        assert_eq!(stored, unlimited_allowance());
+
+        let calldata = AbiEncoder::with_selector(selectors::ALLOWANCE)
+            .address(&fvm_actor_utils::evm::id_to_eth(3))
+            .address(&ETH_BOB)
+            .finish();
+        let allowance = invoke(&mut token, &ALICE, &calldata).unwrap();
+        assert_eq!(uint(&allowance), *max_allowance().atto());
+        assert_eq!(token.allowance(&ALICE, &bob).unwrap(), max_allowance());
+    }
+}
@@ -1,3 +1,4 @@
 // https://github.com/filecoin-project/actors-utils/issues/165
+pub mod erc20;
 pub mod receiver;
 pub mod token;
@@ -1,4 +1,6 @@
 use frc42_dispatch::method_hash;
+use frc42_dispatch::selector::Selector;
+use fvm_actor_utils::receiver::evm::{EvmArg, EvmCallback};
 use fvm_actor_utils::receiver::{ReceiverHook, ReceiverHookError, ReceiverType, RecipientData};
 use fvm_ipld_encoding::tuple::*;
 use fvm_ipld_encoding::RawBytes;
@@ -6,6 +8,14 @@ use fvm_shared::{address::Address, econ::TokenAmount, ActorID};
 
 pub const FRC46_TOKEN_TYPE: ReceiverType = method_hash!("FRC46") as u32;
 
+/// Selector of the Solidity callback used to deliver FRC46 tokens to EVM contracts:
+///
+/// `onFRC46TokenReceived(address from, address to, address operator, uint256 amount,
+/// bytes operatorData, bytes tokenData) returns (bytes4)`
+///
+/// The contract must return this selector to accept the tokens.
+pub const ON_FRC46_TOKEN_RECEIVED: Selector = [0xcd, 0xc0, 0x8b, 0xca];
+
 pub trait FRC46ReceiverHook<T: RecipientData> {
     fn new_frc46(
         address: Address,
@@ -21,12 +31,14 @@ impl<T: RecipientData> FRC46ReceiverHook<T> for ReceiverHook<T> {
         frc46_params: FRC46TokenReceived,
         result_data: T,
     ) -> std::result::Result<ReceiverHook<T>, ReceiverHookError> {
+        let callback = frc46_params.evm_callback();
         Ok(ReceiverHook::new(
             address,
             RawBytes::serialize(frc46_params)?,
             FRC46_TOKEN_TYPE,
             result_data,
-        ))
+        )
+        .with_evm_callback(callback))
     }
 }
 
@@ -46,3 +58,20 @@ pub struct FRC46TokenReceived {
     /// Additional data specified by the token-actor during transfer/mint.
     pub token_data: RawBytes,
 }
+
+impl FRC46TokenReceived {
+    /// Expresses the receive parameters as a call to [`ON_FRC46_TOKEN_RECEIVED`].
+    pub fn evm_callback(&self) -> EvmCallback {
+        EvmCallback {
+            selector: ON_FRC46_TOKEN_RECEIVED,
+            args: vec![
+                EvmArg::Actor(self.from),
+                EvmArg::Actor(self.to),
+                EvmArg::Actor(self.operator),
+                EvmArg::Uint256(self.amount.atto().clone()),
+                EvmArg::Bytes(self.operator_data.to_vec()),
+                EvmArg::Bytes(self.token_data.to_vec()),
+            ],
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use fvm_actor_utils::messaging::MessagingError;
+use fvm_actor_utils::pause::PauseError;
 use fvm_actor_utils::receiver::ReceiverHookError;
 use fvm_ipld_encoding::Error as SerializationError;
 use fvm_shared::address::{Address, Error as AddressError};
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use thiserror::Error;
 
+use crate::token::policy::PolicyError;
 use crate::token::state::StateError as TokenStateError;
 use crate::token::state::StateInvariantError;
 
@@ -33,6 +36,18 @@ pub enum TokenError {
     Serialization(#[from] SerializationError),
     #[error("error in state invariants {0}")]
     StateInvariant(#[from] StateInvariantError),
+    #[error("permit signature is not valid for {0}")]
+    InvalidSignature(Address),
+    #[error("permit expired at epoch {expiry:?}, current epoch is {epoch:?}")]
+    PermitExpired { expiry: ChainEpoch, epoch: ChainEpoch },
+    #[error("pause error: {0}")]
+    Pause(#[from] PauseError),
+    #[error("past votes can only be read for epochs before the current epoch {current:?}, not {epoch:?}")]
+    FutureVoteLookup { epoch: ChainEpoch, current: ChainEpoch },
+    #[error("{0}")]
+    Policy(#[from] PolicyError),
+    #[error("allowance expiry {expiry:?} is before the current epoch {epoch:?}")]
+    InvalidExpiry { expiry: ChainEpoch, epoch: ChainEpoch },
 }
 
 impl From<&TokenError> for ExitCode {
@@ -42,17 +57,25 @@ impl From<&TokenError> for ExitCode {
             TokenError::Serialization(_) => ExitCode::USR_SERIALIZATION,
             TokenError::InvalidOperator(_)
             | TokenError::InvalidGranularity { name: _, amount: _, granularity: _ }
-            | TokenError::InvalidNegative { name: _, amount: _ } => ExitCode::USR_ILLEGAL_ARGUMENT,
+            | TokenError::InvalidNegative { name: _, amount: _ }
+            | TokenError::FutureVoteLookup { epoch: _, current: _ }
+            | TokenError::InvalidExpiry { expiry: _, epoch: _ } => ExitCode::USR_ILLEGAL_ARGUMENT,
             TokenError::StateInvariant(_) => ExitCode::USR_ILLEGAL_STATE,
+            TokenError::InvalidSignature(_) | TokenError::PermitExpired { expiry: _, epoch: _ } => {
+                ExitCode::USR_FORBIDDEN
+            }
             TokenError::TokenState(state_error) => state_error.into(),
             TokenError::ReceiverHook(e) => e.into(),
             TokenError::Messaging(messaging_error) => messaging_error.into(),
+            TokenError::Pause(e) => e.into(),
+            TokenError::Policy(e) => e.into(),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use fvm_actor_utils::pause::{PauseError, ERR_PAUSED};
     use fvm_actor_utils::{messaging::MessagingError, receiver::ReceiverHookError};
     use fvm_ipld_encoding::{CodecProtocol, Error as SerializationError};
     use fvm_shared::{
@@ -117,6 +140,37 @@ mod test {
             String::from("error in state invariants total supply was negative: -1.0")
         );
 
+        let err = TokenError::InvalidSignature(Address::new_id(1));
+        assert_eq!(ExitCode::USR_FORBIDDEN, ExitCode::from(&err));
+        assert_eq!(err.to_string(), String::from("permit signature is not valid for f01"));
+
+        let err = TokenError::PermitExpired { expiry: 10, epoch: 11 };
+        assert_eq!(ExitCode::USR_FORBIDDEN, ExitCode::from(&err));
+        assert_eq!(
+            err.to_string(),
+            String::from("permit expired at epoch 10, current epoch is 11")
+        );
+
+        let err = TokenError::FutureVoteLookup { epoch: 12, current: 12 };
+        assert_eq!(ExitCode::USR_ILLEGAL_ARGUMENT, ExitCode::from(&err));
+        assert_eq!(
+            err.to_string(),
+            String::from(
+                "past votes can only be read for epochs before the current epoch 12, not 12"
+            )
+        );
+
+        let err = TokenError::InvalidExpiry { expiry: 9, epoch: 10 };
+        assert_eq!(ExitCode::USR_ILLEGAL_ARGUMENT, ExitCode::from(&err));
+        assert_eq!(
+            err.to_string(),
+            String::from("allowance expiry 9 is before the current epoch 10")
+        );
+
+        let err = TokenError::Pause(PauseError::Paused);
+        assert_eq!(ERR_PAUSED, ExitCode::from(&err));
+        assert_eq!(err.to_string(), String::from("pause error: operation rejected while paused"));
+
         let err = TokenError::Serialization(SerializationError {
             description: "test".into(),
             protocol: CodecProtocol::Cbor,
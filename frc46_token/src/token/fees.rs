@@ -0,0 +1,88 @@
+//! Fees charged on transfers and paid to a fee recipient.
+//!
+//! The fee recipient is credited without calling its receiver hook. It is nominated by the token
+//! rather than by the sender, so it has already agreed to hold the token, and calling its hook on
+//! every transfer would double the messaging cost and let it veto every transfer by aborting.
+//! Mints to the recipient, and transfers to it that aren't fees, still call its hook as usual.
+
+use fvm_ipld_encoding::tuple::*;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+/// Basis points in a whole, i.e. a fee of 10,000 basis points is the entire amount.
+pub const BASIS_POINTS: u32 = 10_000;
+
+/// Fee charged on each transfer and sent to the fee recipient.
+///
+/// The fee is a flat amount plus a proportion of the transferred amount, rounded down to the token
+/// granularity and capped at the transferred amount. Transfers to or from the recipient are exempt.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct FeeSchedule {
+    /// Account that receives fees.
+    pub recipient: ActorID,
+    /// Proportional fee in basis points of the transferred amount, at most [`BASIS_POINTS`].
+    pub basis_points: u32,
+    /// Flat fee charged on every transfer.
+    pub flat: TokenAmount,
+}
+
+impl FeeSchedule {
+    /// Returns whether the basis points are at most [`BASIS_POINTS`] and the flat fee is
+    /// non-negative.
+    pub fn is_valid(&self) -> bool {
+        self.basis_points <= BASIS_POINTS && !self.flat.is_negative()
+    }
+
+    /// Returns the fee charged for transferring an amount between two accounts.
+    pub fn fee_for(
+        &self,
+        from: ActorID,
+        to: ActorID,
+        amount: &TokenAmount,
+        granularity: u64,
+    ) -> TokenAmount {
+        if from == to || from == self.recipient || to == self.recipient {
+            return TokenAmount::zero();
+        }
+        self.fee_on(amount, granularity)
+    }
+
+    /// Returns the fee charged on an amount by a transfer that isn't exempt.
+    pub fn fee_on(&self, amount: &TokenAmount, granularity: u64) -> TokenAmount {
+        if amount.is_zero() {
+            return TokenAmount::zero();
+        }
+        let proportional = amount.atto() * self.basis_points / BASIS_POINTS;
+        let fee = self.flat.atto() + proportional;
+        let fee = TokenAmount::from_atto(&fee - &fee % granularity);
+        fee.min(amount.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_shared::econ::TokenAmount;
+
+    use super::FeeSchedule;
+
+    const RECIPIENT: u64 = 9;
+
+    #[test]
+    fn it_calculates_fees() {
+        let amount = TokenAmount::from_atto;
+        let fees = FeeSchedule { recipient: RECIPIENT, basis_points: 250, flat: amount(3) };
+        assert!(fees.is_valid());
+        assert_eq!(fees.fee_for(1, 2, &amount(1000), 1), amount(28));
+        // rounded down to the granularity
+        assert_eq!(fees.fee_for(1, 2, &amount(1000), 10), amount(20));
+        // capped at the amount
+        assert_eq!(fees.fee_for(1, 2, &amount(2), 1), amount(2));
+        // transfers to and from the recipient are exempt
+        assert_eq!(fees.fee_for(RECIPIENT, 2, &amount(1000), 1), amount(0));
+        assert_eq!(fees.fee_for(1, RECIPIENT, &amount(1000), 1), amount(0));
+
+        let too_high = FeeSchedule { recipient: RECIPIENT, basis_points: 10_001, flat: amount(0) };
+        assert!(!too_high.is_valid());
+    }
+}
@@ -1,34 +1,57 @@
 #![allow(clippy::result_large_err)]
 
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::ops::Neg;
 
 use cid::Cid;
 pub use error::TokenError;
 use fvm_actor_utils::messaging::{MessagingError, RECEIVER_HOOK_METHOD_NUM};
-use fvm_actor_utils::receiver::{ReceiverHook, ReceiverHookError};
+use fvm_actor_utils::pause::{PauseError, PauseState};
+use fvm_actor_utils::receiver::{BatchReceiverHook, ReceiverHook, ReceiverHookError};
 use fvm_actor_utils::syscalls::Syscalls;
 use fvm_actor_utils::util::ActorRuntime;
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
+use fvm_shared::ActorID;
 use num_traits::Zero;
 
-use self::state::{StateError as TokenStateError, StateInvariantError, StateSummary, TokenState};
+use self::fees::FeeSchedule;
+use self::policy::{TransferContext, TransferPolicy};
+use self::snapshot::SnapshotID;
+use self::state::{
+    is_unlimited_allowance, max_allowance, unlimited_allowance, AllowanceLayout, Cursor,
+    LoadedMaps, StateError as TokenStateError, StateInvariantError, StateSummary, TokenState,
+    TokenStats,
+};
+use self::types::AllowanceExpiryReturn;
+use self::types::PermitParams;
 use self::types::TransferFromIntermediate;
 use self::types::TransferFromReturn;
+use self::types::TransferParams;
 use self::types::TransferReturn;
+use self::types::{BatchMintReturn, BatchTransferReturn, MintParams};
 use self::types::{BurnFromReturn, MintIntermediate};
 use self::types::{BurnReturn, TransferIntermediate};
+use self::types::{ListAllowancesReturn, ListBalancesReturn, ListOperatorsReturn};
+use self::vesting::VestingSchedule;
 use crate::receiver::{FRC46ReceiverHook, FRC46TokenReceived};
 use crate::token::types::MintReturn;
 use crate::token::TokenError::InvalidGranularity;
 
 mod error;
+pub mod fees;
+pub mod policy;
+pub mod snapshot;
 pub mod state;
 pub mod types;
+pub mod vesting;
+pub mod votes;
 
 /// Ratio of integral units to interpretation as standard token units, as given by FRC-0046.
 /// Aka "18 decimals".
@@ -54,6 +77,14 @@ where
     /// Set to 1 for standard 18-dp precision, [`TOKEN_PRECISION`] for whole units only, or some
     /// value in between.
     granularity: u64,
+    /// Rules consulted before mints, transfers and burns are committed, if any.
+    policy: Option<&'st dyn TransferPolicy<BS>>,
+    /// Balance and allowance maps loaded by earlier operations, kept in memory so that later
+    /// operations don't load them again.
+    maps: RefCell<LoadedMaps<'st, ActorRuntime<S, BS>>>,
+    /// Whether changes to the loaded maps wait for [`Token::flush`] rather than being written into
+    /// the state after every operation.
+    deferred_flush: bool,
 }
 
 impl<'st, S, BS> Token<'st, S, BS>
@@ -77,20 +108,64 @@ where
         Ok(TokenState::new_with_bit_width(bs, hamt_bit_width)?)
     }
 
+    /// Creates a new clean token state instance, specifying the underlying Hamt bit widths and how
+    /// allowances are laid out.
+    ///
+    /// This should be wrapped in a [`Token`] handle for convenience. Must be flushed to the
+    /// blockstore explicitly to persist changes.
+    pub fn create_state_with_allowance_layout(
+        bs: &BS,
+        hamt_bit_width: u32,
+        allowance_layout: AllowanceLayout,
+    ) -> Result<TokenState> {
+        Ok(TokenState::new_with_allowance_layout(bs, hamt_bit_width, allowance_layout)?)
+    }
+
     /// Wrap an existing token state.
     pub fn wrap(
         runtime: &'st ActorRuntime<S, BS>,
         granularity: u64,
         state: &'st mut TokenState,
     ) -> Self {
-        Self { runtime, granularity, state }
+        Self {
+            runtime,
+            granularity,
+            state,
+            policy: None,
+            maps: RefCell::new(LoadedMaps::new()),
+            deferred_flush: false,
+        }
+    }
+
+    /// Consults a [`TransferPolicy`] for every mint, transfer and burn.
+    ///
+    /// The policy sees the state as it would be after the operation, and rejecting it discards the
+    /// whole transaction.
+    pub fn with_transfer_policy(mut self, policy: &'st dyn TransferPolicy<BS>) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Keeps balance and allowance changes in memory until [`Token::flush`], rather than writing
+    /// them into the state after every operation.
+    ///
+    /// A sequence of operations then writes each map once. Until the token is flushed, the
+    /// balance and allowance roots of [`Token::state`] are out of date, so flush before reading
+    /// them, setting the actor's state root or calling out to other actors, such as through a
+    /// receiver hook.
+    pub fn with_deferred_flush(mut self) -> Self {
+        self.deferred_flush = true;
+        self
     }
 
     /// Replace the current state with another.
     ///
-    /// The previous state is returned and can be safely dropped.
-    pub fn replace(&mut self, state: TokenState) -> TokenState {
-        std::mem::replace(self.state, state)
+    /// Changes deferred by [`Token::with_deferred_flush`] are written into the previous state
+    /// first. The previous state is returned and can be safely dropped.
+    pub fn replace(&mut self, state: TokenState) -> Result<TokenState> {
+        self.flush_maps()?;
+        *self.maps.get_mut() = LoadedMaps::new();
+        Ok(std::mem::replace(self.state, state))
     }
 
     /// For an already initialised state tree, loads the state tree from the blockstore at a Cid.
@@ -102,15 +177,21 @@ where
     /// state. The old state is returned to enable comparisons and the like but can be safely
     /// dropped otherwise.
     pub fn load_replace(&mut self, cid: &Cid) -> Result<TokenState> {
-        Ok(self.replace(Self::load_state(self.runtime.bs(), cid)?))
+        self.replace(Self::load_state(self.runtime.bs(), cid)?)
     }
 
     /// Flush state and return Cid for root.
+    ///
+    /// Changes deferred by [`Token::with_deferred_flush`] are written into the state first.
     pub fn flush(&mut self) -> Result<Cid> {
+        self.flush_maps()?;
         Ok(self.state.save(&self.runtime)?)
     }
 
     /// Get a reference to the wrapped state tree.
+    ///
+    /// With [`Token::with_deferred_flush`], its [`TokenState::balances`] and
+    /// [`TokenState::allowances`] roots are only brought up to date by [`Token::flush`].
     pub fn state(&self) -> &TokenState {
         self.state
     }
@@ -125,16 +206,88 @@ where
     ///
     /// If the closure returns an error, the transaction is dropped atomically and no change is
     /// observed on token state.
+    ///
+    /// Balances and allowances are changed through the loaded maps, whose changes are rolled back
+    /// along with the state. Unless flushing is deferred, the maps are written into the state
+    /// before it is saved.
     fn transaction<F, Res>(&mut self, f: F) -> Result<Res>
+    where
+        F: FnOnce(
+            &mut TokenState,
+            &mut LoadedMaps<'st, ActorRuntime<S, BS>>,
+            &'st ActorRuntime<S, BS>,
+        ) -> Result<Res>,
+    {
+        let mut mutable_state = self.state.clone();
+        let deferred_flush = self.deferred_flush;
+        let maps = self.maps.get_mut();
+        maps.checkpoint();
+        let res = f(&mut mutable_state, maps, self.runtime).and_then(|res| {
+            if !deferred_flush {
+                mutable_state.flush_maps(maps)?;
+            }
+            Ok(res)
+        });
+        match res {
+            Ok(res) => {
+                // if closure didn't error, save state
+                *self.state = mutable_state;
+                Ok(res)
+            }
+            Err(err) => {
+                maps.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Opens a transaction for operations that read or write balances and allowances through the
+    /// state's roots rather than the loaded maps.
+    ///
+    /// The maps are flushed into the state beforehand and reloaded afterwards.
+    fn flushed_transaction<F, Res>(&mut self, f: F) -> Result<Res>
     where
         F: FnOnce(&mut TokenState, &ActorRuntime<S, BS>) -> Result<Res>,
     {
+        self.flush_maps()?;
+        *self.maps.get_mut() = LoadedMaps::new();
         let mut mutable_state = self.state.clone();
         let res = f(&mut mutable_state, self.runtime)?;
-        // if closure didn't error, save state
         *self.state = mutable_state;
         Ok(res)
     }
+
+    /// Writes changes deferred by [`Token::with_deferred_flush`] into the state. Without deferral
+    /// the state is already up to date.
+    fn flush_maps(&mut self) -> Result<()> {
+        if self.deferred_flush {
+            self.state.flush_maps(self.maps.get_mut())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the state with the loaded maps flushed into it, for reads through the state's roots.
+    fn flushed_state(&self) -> std::result::Result<Cow<'_, TokenState>, TokenStateError> {
+        let mut maps = self.maps.borrow_mut();
+        if !self.deferred_flush || !maps.is_loaded() {
+            return Ok(Cow::Borrowed(self.state));
+        }
+        let mut state = self.state.clone();
+        state.flush_maps(&mut maps)?;
+        Ok(Cow::Owned(state))
+    }
+
+    /// Returns [`PauseError::Paused`] if the token is paused.
+    fn check_not_paused(&self) -> Result<()> {
+        match &self.state.pause {
+            Some(pause) => Ok(pause.check_not_paused()?),
+            None => Ok(()),
+        }
+    }
+
+    fn pause_state_mut(&mut self) -> Result<&mut PauseState> {
+        Ok(self.state.pause.as_mut().ok_or(PauseError::NotPausable)?)
+    }
 }
 
 impl<S, BS> Token<'_, S, BS>
@@ -168,6 +321,7 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<MintIntermediate>> {
+        self.check_not_paused()?;
         let amount = validate_amount_with_granularity(amount, "mint", self.granularity)?;
         // init the operator account so that its actor ID can be referenced in the receiver hook
         let operator_id = self.runtime.resolve_or_init(operator)?;
@@ -175,9 +329,14 @@ where
         let owner_id = self.runtime.resolve_or_init(initial_owner)?;
 
         // Increase the balance of the actor and increase total supply
-        let result = self.transaction(|state, bs| {
-            state.change_balance_by(&bs, owner_id, amount)?;
+        let policy = self.policy;
+        let result = self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            state.change_balance_by_in(bs, maps, owner_id, amount)?;
             state.change_supply_by(amount)?;
+            state.move_voting_power(bs, None, Some(owner_id), amount, epoch)?;
+            let ctx = TransferContext::mint(operator_id, owner_id, amount, epoch);
+            check_policy(policy, bs, state, maps, ctx)?;
             Ok(MintIntermediate { recipient: *initial_owner, recipient_data: RawBytes::default() })
         })?;
 
@@ -208,6 +367,86 @@ where
         })
     }
 
+    /// Mints tokens into several accounts in a single state transaction.
+    ///
+    /// Each mint follows the same rules as [`Token::mint`], and if any is invalid none of the
+    /// balances change. The same `token_data` is passed to every receiver hook.
+    ///
+    /// Returns a [`BatchReceiverHook`] to call each owner's receiver hook in order. It must be
+    /// called or it will panic and abort the transaction. If any hook fails, the actor must abort to
+    /// discard the whole batch.
+    ///
+    /// The hook call will return a [`MintIntermediate`] for each mint which must be passed to
+    /// [`Token::batch_mint_return`] to get the final return data.
+    pub fn batch_mint(
+        &mut self,
+        operator: &Address,
+        mints: &[MintParams],
+        token_data: RawBytes,
+    ) -> Result<BatchReceiverHook<MintIntermediate>> {
+        self.check_not_paused()?;
+        for mint in mints {
+            validate_amount_with_granularity(&mint.amount, "mint", self.granularity)?;
+        }
+        let operator_id = self.runtime.resolve_or_init(operator)?;
+        let owner_ids = mints
+            .iter()
+            .map(|mint| self.runtime.resolve_or_init(&mint.initial_owner))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let policy = self.policy;
+        self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            for (mint, owner_id) in mints.iter().zip(&owner_ids) {
+                state.change_balance_by_in(bs, maps, *owner_id, &mint.amount)?;
+                state.change_supply_by(&mint.amount)?;
+                state.move_voting_power(bs, None, Some(*owner_id), &mint.amount, epoch)?;
+                let ctx = TransferContext::mint(operator_id, *owner_id, &mint.amount, epoch);
+                check_policy(policy, bs, state, maps, ctx)?;
+            }
+            Ok(())
+        })?;
+
+        let hooks = mints
+            .iter()
+            .zip(owner_ids)
+            .map(|(mint, owner_id)| {
+                let params = FRC46TokenReceived {
+                    operator: operator_id,
+                    from: self.runtime.actor_id(),
+                    to: owner_id,
+                    amount: mint.amount.clone(),
+                    operator_data: mint.operator_data.clone(),
+                    token_data: token_data.clone(),
+                };
+                let result = MintIntermediate {
+                    recipient: mint.initial_owner,
+                    recipient_data: RawBytes::default(),
+                };
+                ReceiverHook::new_frc46(mint.initial_owner, params, result)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(BatchReceiverHook::new(hooks))
+    }
+
+    /// Finalise return data from the [`MintIntermediate`]s returned by calling the receiver hooks
+    /// after a batch mint.
+    pub fn batch_mint_return(
+        &self,
+        intermediates: Vec<MintIntermediate>,
+    ) -> Result<BatchMintReturn> {
+        let balances = intermediates
+            .iter()
+            .map(|intermediate| self.balance_of(&intermediate.recipient))
+            .collect::<Result<_>>()?;
+        Ok(BatchMintReturn {
+            balances,
+            supply: self.total_supply(),
+            recipient_data: intermediates.into_iter().map(|i| i.recipient_data).collect(),
+        })
+    }
+
     /// Gets the total number of tokens in existence.
     ///
     /// This equals the sum of [`Token::balance_of`] called on all addresses. This equals sum of all
@@ -217,6 +456,20 @@ where
         self.state.supply.clone()
     }
 
+    /// Gets the cap on the total supply, if any.
+    ///
+    /// Mints and other changes that would raise the supply above the cap fail.
+    pub fn max_supply(&self) -> Option<TokenAmount> {
+        self.state.max_supply.clone()
+    }
+
+    /// Returns the number of holders and allowances and the cumulative minted and burned amounts.
+    ///
+    /// These counters are maintained as the state changes, so reading them is cheap.
+    pub fn stats(&self) -> TokenStats {
+        self.state.stats.clone()
+    }
+
     /// Returns the balance associated with a particular address.
     ///
     /// Accounts that have never received transfers implicitly have a zero-balance.
@@ -224,7 +477,10 @@ where
         // Don't instantiate an account if unable to resolve to an ID address, as non-initialized
         // addresses have an implicit zero balance
         match self.runtime.resolve_id(owner) {
-            Ok(owner) => Ok(self.state.get_balance(&self.runtime, owner)?),
+            Ok(owner) => {
+                let mut maps = self.maps.borrow_mut();
+                Ok(self.state.get_balance_in(self.runtime, &mut maps, owner)?)
+            }
             Err(MessagingError::AddressNotResolved(_)) => {
                 // uninitialized address has implicit zero balance
                 Ok(TokenAmount::zero())
@@ -233,17 +489,249 @@ where
         }
     }
 
+    /// Enumerates a page of token holders and their balances.
+    pub fn list_balances(&self, cursor: RawBytes, limit: u64) -> Result<ListBalancesReturn> {
+        let cursor = Cursor::from_bytes(cursor)?;
+        let (balances, next_cursor) =
+            self.flushed_state()?.list_balances(&self.runtime, cursor, limit)?;
+        let next_cursor = next_cursor.map(|c| c.to_bytes()).transpose()?;
+        Ok(ListBalancesReturn { balances, next_cursor })
+    }
+
+    /// Starts recording snapshots of balances and the total supply.
+    ///
+    /// Until enabled, taking and querying snapshots fails. Does nothing if already enabled.
+    pub fn enable_snapshots(&mut self) -> Result<()> {
+        Ok(self.state.enable_snapshots(&self.runtime)?)
+    }
+
+    /// Takes a snapshot of all balances and the total supply, returning its ID for use with
+    /// [`Token::balance_of_at`] and [`Token::total_supply_at`].
+    pub fn snapshot(&mut self) -> Result<SnapshotID> {
+        Ok(self.state.snapshot(&self.runtime)?)
+    }
+
+    /// Returns the balance of an address as of a snapshot.
+    pub fn balance_of_at(&self, owner: &Address, id: SnapshotID) -> Result<TokenAmount> {
+        match self.runtime.resolve_id(owner) {
+            Ok(owner) => Ok(self.flushed_state()?.balance_of_at(&self.runtime, owner, id)?),
+            // uninitialized addresses have never held a balance, but the snapshot must still exist
+            Err(MessagingError::AddressNotResolved(_)) => {
+                self.state.total_supply_at(&self.runtime, id)?;
+                Ok(TokenAmount::zero())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the total supply as of a snapshot.
+    pub fn total_supply_at(&self, id: SnapshotID) -> Result<TokenAmount> {
+        Ok(self.state.total_supply_at(&self.runtime, id)?)
+    }
+
+    /// Charges a fee on every transfer, paid to the recipient.
+    ///
+    /// The fee is `flat` plus `basis_points` / 10,000 of the transferred amount, rounded down to
+    /// the granularity and capped at the amount. Recipients receive the amount net of the fee.
+    /// Transfers to or from the fee recipient are exempt, as are mints and burns.
+    pub fn set_transfer_fee(
+        &mut self,
+        recipient: &Address,
+        basis_points: u32,
+        flat: &TokenAmount,
+    ) -> Result<()> {
+        let recipient = self.runtime.resolve_or_init(recipient)?;
+        let fees = FeeSchedule { recipient, basis_points, flat: flat.clone() };
+        Ok(self.state.set_fee_schedule(Some(fees))?)
+    }
+
+    /// Stops charging fees on transfers.
+    pub fn remove_transfer_fee(&mut self) -> Result<()> {
+        Ok(self.state.set_fee_schedule(None)?)
+    }
+
+    /// Returns the fee charged on transfers, if any.
+    pub fn transfer_fee(&self) -> Option<&FeeSchedule> {
+        self.state.fees.as_ref()
+    }
+
+    /// Returns the amount the `to` address would receive from a transfer of `amount` from the
+    /// `from` address, net of the transfer fee.
+    ///
+    /// Transfer return values don't carry the net amount, so that they keep the standard layout.
+    /// The receiver hook is told the net amount.
+    pub fn net_transfer_amount(
+        &self,
+        from: &Address,
+        to: &Address,
+        amount: &TokenAmount,
+    ) -> Result<TokenAmount> {
+        let fees = match &self.state.fees {
+            Some(fees) if from != to => fees,
+            _ => return Ok(amount.clone()),
+        };
+        // uninitialized addresses can't be the fee recipient
+        let mut ids = Vec::with_capacity(2);
+        for address in [from, to] {
+            match self.runtime.resolve_id(address) {
+                Ok(id) => ids.push(Some(id)),
+                Err(MessagingError::AddressNotResolved(_)) => ids.push(None),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let exempt = (ids[0] == ids[1] && ids[0].is_some()) || ids.contains(&Some(fees.recipient));
+        if exempt {
+            return Ok(amount.clone());
+        }
+        Ok(amount - fees.fee_on(amount, self.granularity))
+    }
+
+    /// Starts tracking delegated voting power.
+    ///
+    /// Until enabled, delegating and querying votes fails. Existing balances carry no voting power
+    /// until their holders delegate. Does nothing if already enabled.
+    pub fn enable_votes(&mut self) -> Result<()> {
+        Ok(self.state.enable_votes(&self.runtime)?)
+    }
+
+    /// Delegates the voting power of a holder's balance to a delegatee, which may be the holder
+    /// itself.
+    ///
+    /// Voting power then follows the holder's balance through mints, burns and transfers until it
+    /// delegates again.
+    pub fn delegate(&mut self, holder: &Address, delegatee: &Address) -> Result<()> {
+        let holder = self.runtime.resolve_or_init(holder)?;
+        let delegatee = self.runtime.resolve_or_init(delegatee)?;
+        self.flushed_transaction(|state, bs| {
+            Ok(state.delegate(bs, holder, delegatee, bs.curr_epoch())?)
+        })
+    }
+
+    /// Returns the delegate of a holder, if it has delegated.
+    pub fn delegates(&self, holder: &Address) -> Result<Option<Address>> {
+        match self.runtime.resolve_id(holder) {
+            Ok(holder) => {
+                let delegate = self.state.get_delegate(&self.runtime, holder)?;
+                Ok(delegate.map(Address::new_id))
+            }
+            // uninitialized addresses have never delegated, but votes must still be enabled
+            Err(MessagingError::AddressNotResolved(_)) => {
+                self.check_votes_enabled()?;
+                Ok(None)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the current voting power delegated to an address.
+    pub fn get_votes(&self, account: &Address) -> Result<TokenAmount> {
+        match self.runtime.resolve_id(account) {
+            Ok(account) => Ok(self.state.get_votes(&self.runtime, account)?),
+            // uninitialized addresses have never been delegated to, but votes must still be enabled
+            Err(MessagingError::AddressNotResolved(_)) => {
+                self.check_votes_enabled()?;
+                Ok(TokenAmount::zero())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the voting power delegated to an address at the end of a past epoch.
+    ///
+    /// The epoch must be before the current epoch, as voting power may still change within it.
+    pub fn get_past_votes(&self, account: &Address, epoch: ChainEpoch) -> Result<TokenAmount> {
+        let current = self.runtime.curr_epoch();
+        if epoch >= current {
+            return Err(TokenError::FutureVoteLookup { epoch, current });
+        }
+        match self.runtime.resolve_id(account) {
+            Ok(account) => Ok(self.state.get_past_votes(&self.runtime, account, epoch)?),
+            // uninitialized addresses have never been delegated to, but votes must still be enabled
+            Err(MessagingError::AddressNotResolved(_)) => {
+                self.check_votes_enabled()?;
+                Ok(TokenAmount::zero())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn check_votes_enabled(&self) -> Result<()> {
+        match self.state.votes {
+            Some(_) => Ok(()),
+            None => Err(TokenStateError::VotesDisabled.into()),
+        }
+    }
+
+    /// Adds a vesting schedule to an account, locking the unvested part of its balance.
+    ///
+    /// Locked tokens can't be transferred or burned until they vest, measured against the current
+    /// epoch. The schedule can exceed the account's current balance, for example to lock tokens
+    /// that will be minted to it later. Callers are responsible for restricting who may lock
+    /// whose tokens.
+    pub fn add_vesting_schedule(
+        &mut self,
+        owner: &Address,
+        schedule: VestingSchedule,
+    ) -> Result<()> {
+        let owner = self.runtime.resolve_or_init(owner)?;
+        let granularity = self.granularity;
+        self.transaction(|state, _, bs| {
+            Ok(state.add_vesting_schedule(bs, owner, schedule, granularity, bs.curr_epoch())?)
+        })
+    }
+
+    /// Returns the vesting schedules of an address.
+    pub fn vesting_schedules(&self, owner: &Address) -> Result<Vec<VestingSchedule>> {
+        match self.runtime.resolve_id(owner) {
+            Ok(owner) => Ok(self.state.get_vesting_schedules(&self.runtime, owner)?),
+            // uninitialized addresses have no schedules
+            Err(MessagingError::AddressNotResolved(_)) => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the part of an address's balance that is locked at the current epoch.
+    pub fn locked_balance_of(&self, owner: &Address) -> Result<TokenAmount> {
+        match self.runtime.resolve_id(owner) {
+            Ok(owner) => {
+                let epoch = self.runtime.curr_epoch();
+                Ok(self.flushed_state()?.get_locked_balance(&self.runtime, owner, epoch)?)
+            }
+            // uninitialized addresses have no balance
+            Err(MessagingError::AddressNotResolved(_)) => Ok(TokenAmount::zero()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns the part of an address's balance that has vested at the current epoch and can be
+    /// transferred or burned.
+    pub fn vested_balance_of(&self, owner: &Address) -> Result<TokenAmount> {
+        Ok(self.balance_of(owner)? - self.locked_balance_of(owner)?)
+    }
+
     /// Gets the allowance between owner and operator.
     ///
     /// An allowance is the amount that the operator can transfer or burn out of the owner's account
-    /// via the [`Token::transfer`] and [`Token::burn`] methods.
+    /// via the [`Token::transfer`] and [`Token::burn`] methods. Expired allowances are zero, and
+    /// unlimited allowances are reported as [`max_allowance`].
     pub fn allowance(&self, owner: &Address, operator: &Address) -> Result<TokenAmount> {
+        Ok(self.allowance_with_expiry(owner, operator)?.allowance)
+    }
+
+    /// Gets the allowance between owner and operator along with the last epoch it can be used, if
+    /// it expires.
+    pub fn allowance_with_expiry(
+        &self,
+        owner: &Address,
+        operator: &Address,
+    ) -> Result<AllowanceExpiryReturn> {
+        let none = AllowanceExpiryReturn { allowance: TokenAmount::zero(), expiry: None };
         // Don't instantiate an account if unable to resolve owner-ID, as non-initialized addresses
         // give implicit zero allowances to all addresses
         let owner = match self.runtime.resolve_id(owner) {
             Ok(owner) => owner,
             Err(MessagingError::AddressNotResolved(_)) => {
-                return Ok(TokenAmount::zero());
+                return Ok(none);
             }
             Err(e) => return Err(e.into()),
         };
@@ -253,13 +741,83 @@ where
         let operator = match self.runtime.resolve_id(operator) {
             Ok(operator) => operator,
             Err(MessagingError::AddressNotResolved(_)) => {
-                return Ok(TokenAmount::zero());
+                return Ok(none);
             }
             Err(e) => return Err(e.into()),
         };
 
         // For concretely resolved accounts, retrieve the allowance from the map
-        Ok(self.state.get_allowance_between(&self.runtime, owner, operator)?)
+        let epoch = self.runtime.curr_epoch();
+        let mut maps = self.maps.borrow_mut();
+        let allowance =
+            self.state.get_allowance_at_in(self.runtime, &mut maps, owner, operator, epoch)?;
+        Ok(AllowanceExpiryReturn {
+            allowance: reported_allowance(allowance),
+            expiry: self.state.get_allowance_expiry(&self.runtime, owner, operator)?,
+        })
+    }
+
+    /// Rewrites every allowance into the given layout, such as when moving state written with the
+    /// default [`AllowanceLayout::Nested`] layout to [`AllowanceLayout::Flat`].
+    ///
+    /// Does nothing if the allowances are already in that layout.
+    pub fn migrate_allowances(&mut self, layout: AllowanceLayout) -> Result<()> {
+        self.flushed_transaction(|state, bs| Ok(state.migrate_allowances(bs, layout)?))
+    }
+
+    /// Enumerates a page of the operators an owner has set allowances for, along with their
+    /// remaining allowances.
+    ///
+    /// Expired allowances that haven't been pruned are listed as zero.
+    pub fn list_allowances_by_owner(
+        &self,
+        owner: &Address,
+        cursor: RawBytes,
+        limit: u64,
+    ) -> Result<ListAllowancesReturn> {
+        let owner = match self.runtime.resolve_id(owner) {
+            Ok(owner) => owner,
+            // uninitialized addresses have no allowances
+            Err(MessagingError::AddressNotResolved(_)) => {
+                return Ok(ListAllowancesReturn { allowances: vec![], next_cursor: None })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let cursor = Cursor::from_bytes(cursor)?;
+        let (mut allowances, next_cursor) =
+            self.flushed_state()?.list_allowances_by_owner(&self.runtime, owner, cursor, limit)?;
+        let epoch = self.runtime.curr_epoch();
+        for (operator, allowance) in allowances.iter_mut() {
+            let expiry = self.state.get_allowance_expiry(&self.runtime, owner, *operator)?;
+            if expiry.is_some_and(|expiry| epoch > expiry) {
+                *allowance = TokenAmount::zero();
+            }
+            *allowance = reported_allowance(std::mem::take(allowance));
+        }
+        let next_cursor = next_cursor.map(|c| c.to_bytes()).transpose()?;
+        Ok(ListAllowancesReturn { allowances, next_cursor })
+    }
+
+    /// Enumerates a page of the operators an owner has set allowances for.
+    pub fn list_operators_for_owner(
+        &self,
+        owner: &Address,
+        cursor: RawBytes,
+        limit: u64,
+    ) -> Result<ListOperatorsReturn> {
+        let owner = match self.runtime.resolve_id(owner) {
+            Ok(owner) => owner,
+            // uninitialized addresses have no allowances
+            Err(MessagingError::AddressNotResolved(_)) => {
+                return Ok(ListOperatorsReturn { operators: vec![], next_cursor: None })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let cursor = Cursor::from_bytes(cursor)?;
+        let (operators, next_cursor) =
+            self.flushed_state()?.list_operators_for_owner(&self.runtime, owner, cursor, limit)?;
+        let next_cursor = next_cursor.map(|c| c.to_bytes()).transpose()?;
+        Ok(ListOperatorsReturn { operators, next_cursor })
     }
 
     /// Increase the allowance that an operator can control of an owner's balance by the requested
@@ -268,7 +826,8 @@ where
     /// Returns an error if requested delta is negative or there are errors in (de)serialization of
     /// state. If either owner or operator addresses are not resolvable and cannot be initialised,
     /// this method returns [`MessagingError::AddressNotInitialized`]. Otherwise, it returns the new
-    /// allowance.
+    /// allowance. An expired allowance is first cleared, and an unexpired one keeps its expiry. An
+    /// unlimited allowance is left unchanged.
     pub fn increase_allowance(
         &mut self,
         owner: &Address,
@@ -280,9 +839,10 @@ where
         // Attempt to instantiate the accounts if they don't exist
         let owner = self.runtime.resolve_or_init(owner)?;
         let operator = self.runtime.resolve_or_init(operator)?;
-        let new_amount = self.state.change_allowance_by(&self.runtime, owner, operator, delta)?;
-
-        Ok(new_amount)
+        self.transaction(|state, maps, bs| {
+            state.prune_expired_allowance_in(bs, maps, owner, operator, bs.curr_epoch())?;
+            Ok(reported_allowance(state.change_allowance_by_in(bs, maps, owner, operator, delta)?))
+        })
     }
 
     /// Decrease the allowance that an operator controls of the owner's balance by the requested
@@ -291,7 +851,9 @@ where
     /// Returns an error if requested delta is negative or there are errors in (de)serialization of
     /// of state. If the resulting allowance would be negative, the allowance between owner and
     /// operator is set to zero. Returns an error if either the operator or owner addresses are not
-    /// resolvable and cannot be initialized. Otherwise it returns the new allowance.
+    /// resolvable and cannot be initialized. Otherwise it returns the new allowance. An expired
+    /// allowance is first cleared. An unlimited allowance is left unchanged, so must be revoked or
+    /// replaced with [`Token::set_allowance`] instead.
     pub fn decrease_allowance(
         &mut self,
         owner: &Address,
@@ -303,10 +865,80 @@ where
         // Attempt to instantiate the accounts if they don't exist
         let owner = self.runtime.resolve_or_init(owner)?;
         let operator = self.runtime.resolve_or_init(operator)?;
-        let new_allowance =
-            self.state.change_allowance_by(&self.runtime, owner, operator, &delta.neg())?;
+        self.transaction(|state, maps, bs| {
+            state.prune_expired_allowance_in(bs, maps, owner, operator, bs.curr_epoch())?;
+            let new_allowance =
+                state.change_allowance_by_in(bs, maps, owner, operator, &delta.neg())?;
+            Ok(reported_allowance(new_allowance))
+        })
+    }
+
+    /// Sets an allowance from a permit signed by the owner, returning the old allowance.
+    ///
+    /// Anyone may submit a permit. It must be signed by the owner over the
+    /// [`PermitPayload`](types::PermitPayload) for this token, must not have expired and must use
+    /// the owner's next nonce, which it consumes so the permit cannot be replayed.
+    pub fn permit(&mut self, params: &PermitParams) -> Result<TokenAmount> {
+        let amount = validate_allowance(&params.amount, "permit amount")?;
+
+        let epoch = self.runtime.curr_epoch();
+        if epoch > params.expiry {
+            return Err(TokenError::PermitExpired { expiry: params.expiry, epoch });
+        }
+
+        let plaintext = params.payload(self.runtime.actor_id()).signing_bytes()?;
+        if !self.runtime.verify_signature(&params.signature, &params.owner, &plaintext) {
+            return Err(TokenError::InvalidSignature(params.owner));
+        }
+
+        // the owner's nonce is recorded against its ID, even when revoking an allowance
+        let owner = self.runtime.resolve_or_init(&params.owner)?;
+        let operator = self.runtime.resolve_or_init(&params.operator)?;
+
+        let amount = stored_allowance(amount);
+        self.transaction(|state, maps, bs| {
+            state.use_permit_nonce(bs, owner, params.nonce)?;
+            let old_allowance =
+                state.set_allowance_with_expiry_in(bs, maps, owner, operator, &amount, None)?;
+            Ok(reported_allowance(old_allowance))
+        })
+    }
+
+    /// Returns the next permit nonce expected from the owner.
+    pub fn permit_nonce(&self, owner: &Address) -> Result<u64> {
+        match self.runtime.resolve_id(owner) {
+            Ok(owner) => Ok(self.state.get_permit_nonce(&self.runtime, owner)?),
+            // uninitialized addresses have never used a permit
+            Err(MessagingError::AddressNotResolved(_)) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns whether transfers, mints and burns are paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.pause.as_ref().is_some_and(|pause| pause.paused)
+    }
+
+    /// Pauses transfers, mints and burns until unpaused. Reads and allowance changes still work.
+    ///
+    /// Only the pauser may pause, and only if the token was given a pauser in
+    /// [`TokenState::pause`].
+    pub fn pause(&mut self, caller: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        Ok(self.pause_state_mut()?.pause(caller)?)
+    }
+
+    /// Resumes transfers, mints and burns. Only the pauser may unpause.
+    pub fn unpause(&mut self, caller: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        Ok(self.pause_state_mut()?.unpause(caller)?)
+    }
 
-        Ok(new_allowance)
+    /// Hands the pauser role to another address. Only the current pauser may do this.
+    pub fn set_pauser(&mut self, caller: &Address, pauser: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        let pauser = self.runtime.resolve_or_init(pauser)?;
+        Ok(self.pause_state_mut()?.set_pauser(caller, pauser)?)
     }
 
     /// Sets the allowance between owner and operator to zero, returning the old allowance.
@@ -328,17 +960,40 @@ where
             Err(e) => return Err(e.into()),
         };
         // if both accounts resolved, explicitly set allowance to zero
-        Ok(self.state.revoke_allowance(&self.runtime, owner, operator)?)
+        self.transaction(|state, maps, bs| {
+            Ok(reported_allowance(state.revoke_allowance_in(bs, maps, owner, operator)?))
+        })
     }
 
-    /// Sets the allowance to a specified amount, returning the old allowance.
+    /// Sets the allowance to a specified amount that never expires, returning the old allowance.
     pub fn set_allowance(
         &mut self,
         owner: &Address,
         operator: &Address,
         amount: &TokenAmount,
+    ) -> Result<TokenAmount> {
+        self.set_allowance_with_expiry(owner, operator, amount, None)
+    }
+
+    /// Sets the allowance to a specified amount, returning the old allowance.
+    ///
+    /// If an expiry is given, the allowance can be used up to and including that epoch and is
+    /// treated as zero afterwards. The expiry must not be in the past. An amount of
+    /// [`max_allowance`] sets an unlimited allowance.
+    pub fn set_allowance_with_expiry(
+        &mut self,
+        owner: &Address,
+        operator: &Address,
+        amount: &TokenAmount,
+        expiry: Option<ChainEpoch>,
     ) -> Result<TokenAmount> {
         let amount = validate_allowance(amount, "set allowance amount")?;
+        if let Some(expiry) = expiry {
+            let epoch = self.runtime.curr_epoch();
+            if expiry < epoch {
+                return Err(TokenError::InvalidExpiry { expiry, epoch });
+            }
+        }
 
         // Handle special revoke allowance case to avoid unnecessary account initialization
         if amount.is_zero() {
@@ -350,7 +1005,40 @@ where
         let operator = self.runtime.resolve_or_init(operator)?;
 
         // if both accounts resolved, explicitly set allowance
-        Ok(self.state.set_allowance(&self.runtime, owner, operator, amount)?)
+        let amount = stored_allowance(amount);
+        self.transaction(|state, maps, bs| {
+            let old_allowance =
+                state.set_allowance_with_expiry_in(bs, maps, owner, operator, &amount, expiry)?;
+            Ok(reported_allowance(old_allowance))
+        })
+    }
+
+    /// Allows the operator to spend any amount of the owner's tokens, returning the old allowance.
+    ///
+    /// This is the same as setting an allowance of [`max_allowance`]. The allowance is stored as
+    /// the [`unlimited_allowance`] sentinel, which transfers and burns never decrement, saving a
+    /// state write on each use.
+    pub fn set_unlimited_allowance(
+        &mut self,
+        owner: &Address,
+        operator: &Address,
+    ) -> Result<TokenAmount> {
+        self.set_allowance(owner, operator, &max_allowance())
+    }
+
+    /// Revokes all of an owner's expired allowances, returning how many were revoked.
+    ///
+    /// Expired allowances are already treated as zero, so this only reclaims their storage.
+    pub fn prune_expired_allowances(&mut self, owner: &Address) -> Result<usize> {
+        let owner = match self.runtime.resolve_id(owner) {
+            Ok(owner) => owner,
+            // uninitialized addresses have no allowances
+            Err(MessagingError::AddressNotResolved(_)) => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        self.flushed_transaction(|state, bs| {
+            Ok(state.prune_expired_allowances(bs, owner, bs.curr_epoch())?)
+        })
     }
 
     /// Burns an amount of token from the specified address, decreasing total token supply.
@@ -365,14 +1053,20 @@ where
     /// - The target's balance decreases by the requested value.
     /// - The total supply decreases by the requested value.
     pub fn burn(&mut self, owner: &Address, amount: &TokenAmount) -> Result<BurnReturn> {
+        self.check_not_paused()?;
         let amount = validate_amount_with_granularity(amount, "burn", self.granularity)?;
 
         let owner = self.runtime.resolve_or_init(owner)?;
-        self.transaction(|state, bs| {
+        let policy = self.policy;
+        self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            state.advance_vesting_epoch(epoch);
             // attempt to burn the requested amount
-            let new_amount = state.change_balance_by(&bs, owner, &amount.clone().neg())?;
+            let new_amount = state.change_balance_by_in(bs, maps, owner, &amount.clone().neg())?;
             // decrease total_supply
             state.change_supply_by(&amount.neg())?;
+            state.move_voting_power(bs, Some(owner), None, amount, epoch)?;
+            check_policy(policy, bs, state, maps, TransferContext::burn(owner, amount, epoch))?;
             Ok(BurnReturn { balance: new_amount })
         })
     }
@@ -398,6 +1092,7 @@ where
         owner: &Address,
         amount: &TokenAmount,
     ) -> Result<BurnFromReturn> {
+        self.check_not_paused()?;
         let amount = validate_amount_with_granularity(amount, "burn", self.granularity)?;
         if self.runtime.same_address(operator, owner) {
             return Err(TokenError::InvalidOperator(*operator));
@@ -435,13 +1130,23 @@ where
             Err(e) => return Err(e.into()),
         };
 
-        self.transaction(|state, bs| {
-            let new_allowance = state.attempt_use_allowance(&bs, operator, owner, amount)?;
+        let policy = self.policy;
+        self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            let new_allowance =
+                state.attempt_use_allowance_at_in(bs, maps, operator, owner, amount, epoch)?;
+            state.advance_vesting_epoch(epoch);
             // attempt to burn the requested amount
-            let new_balance = state.change_balance_by(&bs, owner, &amount.clone().neg())?;
+            let new_balance = state.change_balance_by_in(bs, maps, owner, &amount.clone().neg())?;
             // decrease total_supply
             state.change_supply_by(&amount.neg())?;
-            Ok(BurnFromReturn { balance: new_balance, allowance: new_allowance })
+            state.move_voting_power(bs, Some(owner), None, amount, epoch)?;
+            let ctx = TransferContext::burn_from(operator, owner, amount, epoch);
+            check_policy(policy, bs, state, maps, ctx)?;
+            Ok(BurnFromReturn {
+                balance: new_balance,
+                allowance: reported_allowance(new_allowance),
+            })
         })
     }
 
@@ -472,15 +1177,22 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<TransferIntermediate>> {
+        self.check_not_paused()?;
         let amount = validate_amount_with_granularity(amount, "transfer", self.granularity)?;
 
         // owner-initiated transfer
         let from_id = self.runtime.resolve_or_init(from)?;
         let to_id = self.runtime.resolve_or_init(to)?;
         // skip allowance check for self-managed transfers
-        self.transaction(|state, bs| {
-            state.make_transfer(&bs, from_id, to_id, amount)?;
-            Ok(())
+        let (policy, granularity) = (self.policy, self.granularity);
+        let received = self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            state.advance_vesting_epoch(epoch);
+            let received =
+                transfer_with_fee(state, bs, maps, from_id, to_id, amount, granularity, epoch)?;
+            let ctx = TransferContext::transfer(from_id, to_id, amount, epoch);
+            check_policy(policy, bs, state, maps, ctx)?;
+            Ok(received)
         })?;
 
         let res =
@@ -490,7 +1202,7 @@ where
             operator: from_id,
             from: from_id,
             to: to_id,
-            amount: amount.clone(),
+            amount: received,
             operator_data,
             token_data,
         };
@@ -507,16 +1219,111 @@ where
         })
     }
 
-    /// Transfers an amount from one address to another.
+    /// Transfers tokens from one address to several others in a single state transaction.
     ///
-    /// - The requested value MUST be non-negative.
-    /// - The requested value MUST NOT exceed the sender's balance.
-    /// - The receiving actor MUST implement a method called `tokens_received`, corresponding to the
-    ///   interface specified for FRC-0046 token receiver. If the receiving hook aborts, when called,
-    ///   the transfer is discarded and this method returns an error.
-    /// - The operator MUST be initialised AND have an allowance not less than the requested value.
+    /// Each transfer follows the same rules as [`Token::transfer`], and if any is invalid (e.g. the
+    /// balance is exhausted part way through the batch) none of the balances change. The same
+    /// `token_data` is passed to every receiver hook.
     ///
-    /// Upon successful transfer:
+    /// Returns a [`BatchReceiverHook`] to call each recipient's receiver hook in order. It must be
+    /// called or it will panic and abort the transaction. If any hook fails, the actor must abort to
+    /// discard the whole batch.
+    ///
+    /// Return data from the hooks should be passed to [`Token::batch_transfer_return`] which will
+    /// generate the [`BatchTransferReturn`].
+    pub fn batch_transfer(
+        &mut self,
+        from: &Address,
+        transfers: &[TransferParams],
+        token_data: RawBytes,
+    ) -> Result<BatchReceiverHook<TransferIntermediate>> {
+        self.check_not_paused()?;
+        for transfer in transfers {
+            validate_amount_with_granularity(&transfer.amount, "transfer", self.granularity)?;
+        }
+        let from_id = self.runtime.resolve_or_init(from)?;
+        let to_ids = transfers
+            .iter()
+            .map(|transfer| self.runtime.resolve_or_init(&transfer.to))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        // skip allowance check for self-managed transfers
+        let (policy, granularity) = (self.policy, self.granularity);
+        let received = self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            state.advance_vesting_epoch(epoch);
+            let mut received = Vec::with_capacity(transfers.len());
+            for (transfer, to_id) in transfers.iter().zip(&to_ids) {
+                let amount = &transfer.amount;
+                received.push(transfer_with_fee(
+                    state,
+                    bs,
+                    maps,
+                    from_id,
+                    *to_id,
+                    amount,
+                    granularity,
+                    epoch,
+                )?);
+                let ctx = TransferContext::transfer(from_id, *to_id, amount, epoch);
+                check_policy(policy, bs, state, maps, ctx)?;
+            }
+            Ok(received)
+        })?;
+
+        let hooks = transfers
+            .iter()
+            .zip(to_ids)
+            .zip(received)
+            .map(|((transfer, to_id), received)| {
+                let params = FRC46TokenReceived {
+                    operator: from_id,
+                    from: from_id,
+                    to: to_id,
+                    amount: received,
+                    operator_data: transfer.operator_data.clone(),
+                    token_data: token_data.clone(),
+                };
+                let result = TransferIntermediate {
+                    from: *from,
+                    to: transfer.to,
+                    recipient_data: RawBytes::default(),
+                };
+                ReceiverHook::new_frc46(transfer.to, params, result)
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        Ok(BatchReceiverHook::new(hooks))
+    }
+
+    /// Generate [`BatchTransferReturn`] from the intermediate data returned by the receiver hooks
+    /// of a batch transfer from `from`.
+    pub fn batch_transfer_return(
+        &self,
+        from: &Address,
+        intermediates: Vec<TransferIntermediate>,
+    ) -> Result<BatchTransferReturn> {
+        let to_balances = intermediates
+            .iter()
+            .map(|intermediate| self.balance_of(&intermediate.to))
+            .collect::<Result<_>>()?;
+        Ok(BatchTransferReturn {
+            from_balance: self.balance_of(from)?,
+            to_balances,
+            recipient_data: intermediates.into_iter().map(|i| i.recipient_data).collect(),
+        })
+    }
+
+    /// Transfers an amount from one address to another.
+    ///
+    /// - The requested value MUST be non-negative.
+    /// - The requested value MUST NOT exceed the sender's balance.
+    /// - The receiving actor MUST implement a method called `tokens_received`, corresponding to the
+    ///   interface specified for FRC-0046 token receiver. If the receiving hook aborts, when called,
+    ///   the transfer is discarded and this method returns an error.
+    /// - The operator MUST be initialised AND have an allowance not less than the requested value.
+    ///
+    /// Upon successful transfer:
     ///
     /// - The from balance decreases by the requested value.
     /// - The to balance increases by the requested value.
@@ -537,6 +1344,7 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<TransferFromIntermediate>> {
+        self.check_not_paused()?;
         let amount = validate_amount_with_granularity(amount, "transfer", self.granularity)?;
         if self.runtime.same_address(operator, from) {
             return Err(TokenError::InvalidOperator(*operator));
@@ -576,10 +1384,16 @@ where
         let to_id = self.runtime.resolve_or_init(to)?;
 
         // update token state
-        self.transaction(|state, bs| {
-            state.attempt_use_allowance(&bs, operator_id, from_id, amount)?;
-            state.make_transfer(&bs, from_id, to_id, amount)?;
-            Ok(())
+        let (policy, granularity) = (self.policy, self.granularity);
+        let received = self.transaction(|state, maps, bs| {
+            let epoch = bs.curr_epoch();
+            state.attempt_use_allowance_at_in(bs, maps, operator_id, from_id, amount, epoch)?;
+            state.advance_vesting_epoch(epoch);
+            let received =
+                transfer_with_fee(state, bs, maps, from_id, to_id, amount, granularity, epoch)?;
+            let ctx = TransferContext::transfer_from(operator_id, from_id, to_id, amount, epoch);
+            check_policy(policy, bs, state, maps, ctx)?;
+            Ok(received)
         })?;
 
         let res = TransferFromIntermediate {
@@ -593,7 +1407,7 @@ where
             operator: operator_id,
             from: from_id,
             to: to_id,
-            amount: amount.clone(),
+            amount: received,
             operator_data,
             token_data,
         };
@@ -622,12 +1436,19 @@ where
         let amount = validate_amount_with_granularity(amount, "set_balance", self.granularity)?;
 
         let owner = self.runtime.resolve_or_init(owner)?;
-        let old_balance = self.transaction(|state, bs| {
+        let old_balance = self.transaction(|state, maps, bs| {
             // update the account's balance
-            let old_balance = state.set_balance(bs, owner, amount)?;
+            let old_balance = state.set_balance_in(bs, maps, owner, amount)?;
             // update the total supply accordingly
             let supply_change = amount - old_balance.clone();
-            state.supply += supply_change;
+            state.change_supply_by(&supply_change)?;
+            // move voting power as if the change were minted or burned
+            let epoch = bs.curr_epoch();
+            if supply_change.is_positive() {
+                state.move_voting_power(bs, None, Some(owner), &supply_change, epoch)?;
+            } else {
+                state.move_voting_power(bs, Some(owner), None, &supply_change.neg(), epoch)?;
+            }
             Ok(old_balance)
         })?;
 
@@ -675,7 +1496,76 @@ where
 
     /// Checks the state invariants, returning a state summary and list of errors.
     pub fn check_invariants(&self) -> (StateSummary, Vec<StateInvariantError>) {
-        self.state.check_invariants(&self.runtime, self.granularity)
+        match self.flushed_state() {
+            Ok(state) => state.check_invariants(&self.runtime, self.granularity),
+            Err(err) => {
+                let summary = StateSummary {
+                    balance_map: None,
+                    allowance_map: None,
+                    total_supply: self.state.supply.clone(),
+                };
+                (summary, vec![StateInvariantError::State(err)])
+            }
+        }
+    }
+}
+
+impl Cursor {
+    /// Generates a cursor from an opaque representation.
+    pub fn from_bytes(bytes: RawBytes) -> Result<Option<Cursor>> {
+        if bytes.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(fvm_ipld_encoding::from_slice(&bytes)?))
+        }
+    }
+
+    /// Generates an opaque representation of the cursor that can be used to resume enumeration.
+    pub fn to_bytes(&self) -> Result<RawBytes> {
+        Ok(RawBytes::from(fvm_ipld_encoding::to_vec(self)?))
+    }
+}
+
+/// Moves tokens and their voting power between two accounts, charging the transfer fee if any.
+///
+/// Returns the amount received, net of the fee.
+#[allow(clippy::too_many_arguments)]
+fn transfer_with_fee<'bs, BS: Blockstore>(
+    state: &mut TokenState,
+    bs: &'bs BS,
+    maps: &mut LoadedMaps<'bs, BS>,
+    from: ActorID,
+    to: ActorID,
+    amount: &TokenAmount,
+    granularity: u64,
+    epoch: ChainEpoch,
+) -> Result<TokenAmount> {
+    let fee = state.make_transfer_with_fee_in(bs, maps, from, to, amount, granularity)?;
+    let received = amount - &fee;
+    state.move_voting_power(bs, Some(from), Some(to), &received, epoch)?;
+    if let Some(recipient) = state.fees.as_ref().map(|fees| fees.recipient) {
+        state.move_voting_power(bs, Some(from), Some(recipient), &fee, epoch)?;
+    }
+    Ok(received)
+}
+
+/// Consults the transfer policy, if any, about an operation already applied to the state.
+///
+/// The policy reads the resulting balances through the loaded maps, which haven't been written
+/// into the state yet.
+fn check_policy<'bs, S: Syscalls, BS: Blockstore>(
+    policy: Option<&dyn TransferPolicy<BS>>,
+    runtime: &'bs ActorRuntime<S, BS>,
+    state: &TokenState,
+    maps: &mut LoadedMaps<'bs, ActorRuntime<S, BS>>,
+    ctx: TransferContext,
+) -> Result<()> {
+    match policy {
+        Some(policy) => {
+            let mut balance_of = |owner| state.get_balance_in(runtime, maps, owner);
+            Ok(policy.check(&ctx, state, runtime.bs(), &mut balance_of)?)
+        }
+        None => Ok(()),
     }
 }
 
@@ -709,24 +1599,56 @@ pub fn validate_allowance<'a>(a: &'a TokenAmount, name: &'static str) -> Result<
     Ok(a)
 }
 
+/// Converts a stored allowance to the amount reported to callers, which never see the
+/// [`unlimited_allowance`] sentinel.
+fn reported_allowance(allowance: TokenAmount) -> TokenAmount {
+    if is_unlimited_allowance(&allowance) {
+        max_allowance()
+    } else {
+        allowance
+    }
+}
+
+/// Converts an allowance given by a caller to the amount stored, so that [`max_allowance`] is
+/// stored as the [`unlimited_allowance`] sentinel.
+fn stored_allowance(allowance: &TokenAmount) -> TokenAmount {
+    if *allowance == max_allowance() {
+        unlimited_allowance()
+    } else {
+        allowance.clone()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::ops::Neg;
 
+    use fvm_actor_utils::evm::{
+        calldata_from_params, return_data_block, AbiDecoder, AbiEncoder, EVM_ACTOR_TYPE,
+        INVOKE_EVM_METHOD_NUM,
+    };
     use fvm_actor_utils::messaging::{MessagingError, RECEIVER_HOOK_METHOD_NUM};
+    use fvm_actor_utils::pause::{PauseError, PauseState};
     use fvm_actor_utils::receiver::{ReceiverHookError, UniversalReceiverParams};
     use fvm_actor_utils::syscalls::fake_syscalls::FakeSyscalls;
     use fvm_actor_utils::util::ActorRuntime;
+    use fvm_ipld_blockstore::tracking::{BSStats, TrackingBlockstore};
     use fvm_ipld_blockstore::MemoryBlockstore;
     use fvm_ipld_encoding::RawBytes;
     use fvm_sdk::sys::ErrorNumber;
     use fvm_shared::address::{Address, BLS_PUB_LEN};
+    use fvm_shared::crypto::signature::Signature;
     use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
     use num_traits::Zero;
 
-    use crate::receiver::{FRC46TokenReceived, FRC46_TOKEN_TYPE};
+    use crate::receiver::{FRC46TokenReceived, FRC46_TOKEN_TYPE, ON_FRC46_TOKEN_RECEIVED};
+    use crate::token::policy::Denylist;
     use crate::token::state::StateError;
     use crate::token::state::TokenState;
+    use crate::token::state::{max_allowance, unlimited_allowance};
+    use crate::token::types::{AllowanceExpiryReturn, MintParams, PermitParams, TransferParams};
+    use crate::token::vesting::VestingSchedule;
     use crate::token::Token;
     use crate::token::TokenError;
 
@@ -907,7 +1829,7 @@ mod test {
 
         // entire transaction succeeds
         token
-            .transaction(|state, _bs| {
+            .transaction(|state, _maps, _bs| {
                 state.change_supply_by(&TokenAmount::from_atto(100))?;
                 state.change_supply_by(&TokenAmount::from_atto(100))?;
                 Ok(())
@@ -917,7 +1839,7 @@ mod test {
 
         // entire transaction fails
         token
-            .transaction(|state, _bs| {
+            .transaction(|state, _maps, _bs| {
                 state.change_supply_by(&TokenAmount::from_atto(-100))?;
                 state.change_supply_by(&TokenAmount::from_atto(-100))?;
                 // this makes supply negative and should revert the entire transaction
@@ -1196,7 +2118,7 @@ mod test {
 
         // restore original pre-mint state
         // in actor code, we'd just abort and let the VM handle this
-        token.replace(original_state);
+        token.replace(original_state).unwrap();
 
         // state remained unchanged
         assert_eq!(token.balance_of(TREASURY).unwrap(), TokenAmount::zero());
@@ -1204,6 +2126,53 @@ mod test {
         token.assert_invariants().unwrap();
     }
 
+    #[test]
+    fn it_mints_to_evm_contracts() {
+        let mut helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        helper.syscalls.actor_id = TOKEN_ACTOR.id().unwrap();
+        helper.syscalls.set_actor_type(CAROL.id().unwrap(), EVM_ACTOR_TYPE);
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        // the contract accepts by returning the callback selector
+        let accept = AbiEncoder::new().bytes4(ON_FRC46_TOKEN_RECEIVED).finish();
+        helper.syscalls.next_return_data.replace(Some(return_data_block(&accept).unwrap()));
+        let mut hook = token
+            .mint(
+                TOKEN_ACTOR,
+                CAROL,
+                &TokenAmount::from_atto(1_000),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        let last_message = helper.syscalls.last_message.borrow().clone().unwrap();
+        assert_eq!(last_message.method, INVOKE_EVM_METHOD_NUM);
+        let calldata = calldata_from_params(last_message.params).unwrap();
+        let (selector, args) = AbiDecoder::from_calldata(&calldata).unwrap();
+        assert_eq!(selector, ON_FRC46_TOKEN_RECEIVED);
+        assert_eq!(args.address(1).unwrap(), *CAROL);
+        assert_eq!(args.uint64(3).unwrap(), 1_000);
+
+        // any other return rejects the tokens
+        let mut hook = token
+            .mint(
+                TOKEN_ACTOR,
+                CAROL,
+                &TokenAmount::from_atto(1_000),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        let err = hook.call(token.runtime).unwrap_err();
+        assert!(matches!(err, ReceiverHookError::EvmRejected { address: _ }));
+    }
+
     #[test]
     fn it_burns() {
         let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
@@ -1421,6 +2390,866 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_batch_mints() {
+        let mut helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        helper.syscalls.actor_id = TOKEN_ACTOR.id().unwrap();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let mint = |owner: &Address, amount| MintParams {
+            initial_owner: *owner,
+            amount: TokenAmount::from_atto(amount),
+            operator_data: RawBytes::default(),
+        };
+        let secp = secp_address();
+        let mut hook = token
+            .batch_mint(
+                TOKEN_ACTOR,
+                &[mint(ALICE, 100), mint(&secp, 50), mint(ALICE, 10)],
+                RawBytes::default(),
+            )
+            .unwrap();
+        assert_eq!(hook.len(), 3);
+        token.flush().unwrap();
+        let intermediates = hook.call(token.runtime).unwrap();
+        let ret = token.batch_mint_return(intermediates).unwrap();
+
+        assert_eq!(
+            ret.balances,
+            vec![
+                TokenAmount::from_atto(110),
+                TokenAmount::from_atto(50),
+                TokenAmount::from_atto(110)
+            ]
+        );
+        assert_eq!(ret.supply, TokenAmount::from_atto(160));
+        assert_eq!(ret.recipient_data.len(), 3);
+
+        // a single invalid mint fails the whole batch
+        token
+            .batch_mint(TOKEN_ACTOR, &[mint(BOB, 10), mint(BOB, -1)], RawBytes::default())
+            .unwrap_err();
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::zero());
+        assert_eq!(token.total_supply(), TokenAmount::from_atto(160));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_reads_balances_at_snapshots() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+        let err = token.snapshot().unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::SnapshotsDisabled)));
+        token.enable_snapshots().unwrap();
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        let first = token.snapshot().unwrap();
+
+        let mut hook = token
+            .transfer(ALICE, BOB, &amount(30), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+        token.burn(ALICE, &amount(10)).unwrap();
+        let second = token.snapshot().unwrap();
+        // unchanged accounts and supply between snapshots are read from later checkpoints
+        let third = token.snapshot().unwrap();
+
+        token.set_balance(CAROL, &amount(5)).unwrap();
+        token.burn(BOB, &amount(30)).unwrap();
+
+        assert_eq!(token.balance_of_at(ALICE, first).unwrap(), amount(100));
+        assert_eq!(token.balance_of_at(BOB, first).unwrap(), amount(0));
+        assert_eq!(token.total_supply_at(first).unwrap(), amount(100));
+
+        for id in [second, third] {
+            assert_eq!(token.balance_of_at(ALICE, id).unwrap(), amount(60));
+            assert_eq!(token.balance_of_at(BOB, id).unwrap(), amount(30));
+            assert_eq!(token.balance_of_at(CAROL, id).unwrap(), amount(0));
+            assert_eq!(token.total_supply_at(id).unwrap(), amount(90));
+        }
+
+        // current values are unaffected
+        assert_eq!(token.balance_of(BOB).unwrap(), amount(0));
+        assert_eq!(token.total_supply(), amount(65));
+        assert_eq!(token.balance_of_at(&secp_address(), third).unwrap(), amount(0));
+
+        // snapshots must exist
+        let err = token.balance_of_at(ALICE, third + 1).unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::InvalidSnapshot { .. })));
+        let err = token.total_supply_at(0).unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::InvalidSnapshot { .. })));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_tracks_delegated_votes() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+        let err = token.delegate(ALICE, ALICE).unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::VotesDisabled)));
+        token.enable_votes().unwrap();
+
+        let amount = TokenAmount::from_atto;
+        helper.syscalls.set_epoch(1);
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        // balances carry no votes until delegated
+        assert_eq!(token.get_votes(ALICE).unwrap(), amount(0));
+        token.delegate(ALICE, CAROL).unwrap();
+        token.delegate(BOB, BOB).unwrap();
+        assert_eq!(token.delegates(ALICE).unwrap(), Some(*CAROL));
+        assert_eq!(token.delegates(CAROL).unwrap(), None);
+        assert_eq!(token.get_votes(CAROL).unwrap(), amount(100));
+
+        helper.syscalls.set_epoch(2);
+        let mut hook = token
+            .mint(TOKEN_ACTOR, BOB, &amount(50), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+        let mut hook = token
+            .transfer(ALICE, BOB, &amount(30), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        helper.syscalls.set_epoch(3);
+        token.burn(BOB, &amount(20)).unwrap();
+        // redelegating moves the whole balance
+        token.delegate(BOB, CAROL).unwrap();
+        assert_eq!(token.get_votes(BOB).unwrap(), amount(0));
+        assert_eq!(token.get_votes(CAROL).unwrap(), amount(130));
+
+        helper.syscalls.set_epoch(4);
+        assert_eq!(token.get_past_votes(CAROL, 0).unwrap(), amount(0));
+        assert_eq!(token.get_past_votes(CAROL, 1).unwrap(), amount(100));
+        assert_eq!(token.get_past_votes(CAROL, 2).unwrap(), amount(70));
+        assert_eq!(token.get_past_votes(BOB, 2).unwrap(), amount(80));
+        assert_eq!(token.get_past_votes(BOB, 3).unwrap(), amount(0));
+        assert_eq!(token.get_past_votes(&secp_address(), 3).unwrap(), amount(0));
+
+        // the current epoch may still change
+        let err = token.get_past_votes(CAROL, 4).unwrap_err();
+        assert!(matches!(err, TokenError::FutureVoteLookup { epoch: 4, current: 4 }));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_locks_unvested_balances() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        helper.syscalls.set_epoch(10);
+        token.set_balance(ALICE, &amount(150)).unwrap();
+        token
+            .add_vesting_schedule(ALICE, VestingSchedule::linear(amount(100), 10, 10, 40))
+            .unwrap();
+        token.add_vesting_schedule(ALICE, VestingSchedule::cliff(amount(20), 30)).unwrap();
+        assert_eq!(token.vesting_schedules(ALICE).unwrap().len(), 2);
+        assert_eq!(token.locked_balance_of(ALICE).unwrap(), amount(120));
+        assert_eq!(token.vested_balance_of(ALICE).unwrap(), amount(30));
+
+        // only the unlocked part can be transferred or burned
+        let err = token
+            .transfer(ALICE, BOB, &amount(31), RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_INSUFFICIENT_FUNDS);
+        assert!(matches!(err, TokenError::TokenState(StateError::LockedBalance { .. })));
+        let err = token.burn(ALICE, &amount(31)).unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::LockedBalance { .. })));
+        token.burn(ALICE, &amount(30)).unwrap();
+        assert_eq!(token.balance_of(ALICE).unwrap(), amount(120));
+
+        // a quarter of the linear schedule vests at epoch 20, and the cliff at epoch 30
+        helper.syscalls.set_epoch(20);
+        assert_eq!(token.vested_balance_of(ALICE).unwrap(), amount(25));
+        helper.syscalls.set_epoch(30);
+        assert_eq!(token.vested_balance_of(ALICE).unwrap(), amount(70));
+        let mut hook = token
+            .transfer(ALICE, BOB, &amount(70), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+        assert_eq!(token.locked_balance_of(ALICE).unwrap(), amount(50));
+
+        // invalid schedules are rejected
+        let err = token
+            .add_vesting_schedule(ALICE, VestingSchedule::linear(amount(1), 0, 10, 5))
+            .unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_consults_the_transfer_policy() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let alice = ALICE.id().unwrap();
+        let policy = Denylist::new([alice]);
+        let mut token = new_token(&helper, &mut token_state).with_transfer_policy(&policy);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        token.set_balance(BOB, &amount(100)).unwrap();
+
+        // rejected operations leave the state unchanged
+        let err = token
+            .transfer(ALICE, BOB, &amount(10), RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        assert!(matches!(err, TokenError::Policy(_)));
+        token
+            .transfer(BOB, ALICE, &amount(10), RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        token.burn(ALICE, &amount(10)).unwrap_err();
+        token
+            .mint(TOKEN_ACTOR, ALICE, &amount(10), RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert_eq!(token.balance_of(ALICE).unwrap(), amount(100));
+        assert_eq!(token.total_supply(), amount(200));
+
+        // other accounts are unaffected
+        let mut hook = token
+            .transfer(BOB, CAROL, &amount(10), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+        token.burn(BOB, &amount(10)).unwrap();
+        assert_eq!(token.balance_of(BOB).unwrap(), amount(80));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_charges_transfer_fees() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(1000)).unwrap();
+        let err = token.set_transfer_fee(TREASURY, 10_001, &amount(0)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+        token.set_transfer_fee(TREASURY, 100, &amount(1)).unwrap();
+
+        // 1 flat plus 1% of 100 goes to the treasury, and the receiver is told of the net amount
+        let mut hook = token
+            .transfer(ALICE, BOB, &amount(100), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        let intermediate = hook.call(token.runtime).unwrap();
+        assert_last_hook_call_eq(
+            &helper,
+            FRC46TokenReceived {
+                operator: ALICE.id().unwrap(),
+                from: ALICE.id().unwrap(),
+                to: BOB.id().unwrap(),
+                amount: amount(98),
+                operator_data: RawBytes::default(),
+                token_data: RawBytes::default(),
+            },
+        );
+        let ret = token.transfer_return(intermediate).unwrap();
+        assert_eq!(token.net_transfer_amount(ALICE, BOB, &amount(100)).unwrap(), amount(98));
+        assert_eq!(ret.from_balance, amount(900));
+        assert_eq!(ret.to_balance, amount(98));
+        assert_eq!(token.balance_of(TREASURY).unwrap(), amount(2));
+
+        // operators spend the gross amount from the allowance
+        token.increase_allowance(ALICE, CAROL, &amount(200)).unwrap();
+        let mut hook = token
+            .transfer_from(
+                CAROL,
+                ALICE,
+                BOB,
+                &amount(200),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        let intermediate = hook.call(token.runtime).unwrap();
+        let ret = token.transfer_from_return(intermediate).unwrap();
+        assert_eq!(token.net_transfer_amount(ALICE, BOB, &amount(200)).unwrap(), amount(197));
+        assert_eq!(token.net_transfer_amount(ALICE, ALICE, &amount(200)).unwrap(), amount(200));
+        assert_eq!(token.net_transfer_amount(BOB, TREASURY, &amount(200)).unwrap(), amount(200));
+        assert_eq!(ret.allowance, amount(0));
+        assert_eq!(token.balance_of(TREASURY).unwrap(), amount(5));
+
+        // transfers to the fee recipient are exempt
+        let mut hook = token
+            .transfer(ALICE, TREASURY, &amount(100), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+        assert_eq!(token.balance_of(TREASURY).unwrap(), amount(105));
+
+        // fees don't change the total supply
+        assert_eq!(token.balance_of(BOB).unwrap(), amount(295));
+        assert_eq!(token.total_supply(), amount(1000));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_expires_allowances() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        helper.syscalls.set_epoch(10);
+        let err = token.set_allowance_with_expiry(ALICE, CAROL, &amount(50), Some(9)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+        token.set_allowance_with_expiry(ALICE, CAROL, &amount(50), Some(20)).unwrap();
+        token.set_allowance_with_expiry(ALICE, BOB, &amount(50), Some(15)).unwrap();
+
+        // the allowance can be used up to and including the expiry epoch, which it keeps
+        helper.syscalls.set_epoch(20);
+        token.burn_from(CAROL, ALICE, &amount(10)).unwrap();
+        token.increase_allowance(ALICE, CAROL, &amount(5)).unwrap();
+        let ret = token.allowance_with_expiry(ALICE, CAROL).unwrap();
+        assert_eq!(ret, AllowanceExpiryReturn { allowance: amount(45), expiry: Some(20) });
+
+        // expired allowances read as zero and can't be spent
+        helper.syscalls.set_epoch(21);
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), amount(0));
+        let err = token.burn_from(CAROL, ALICE, &amount(10)).unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::InsufficientAllowance { .. })));
+
+        // increasing an expired allowance starts afresh with no expiry
+        assert_eq!(token.increase_allowance(ALICE, CAROL, &amount(5)).unwrap(), amount(5));
+        let ret = token.allowance_with_expiry(ALICE, CAROL).unwrap();
+        assert_eq!(ret, AllowanceExpiryReturn { allowance: amount(5), expiry: None });
+
+        // pruning revokes the remaining expired allowance
+        assert_eq!(token.prune_expired_allowances(ALICE).unwrap(), 1);
+        assert_eq!(token.prune_expired_allowances(ALICE).unwrap(), 0);
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), amount(5));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_never_decrements_unlimited_allowances() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        assert_eq!(token.set_unlimited_allowance(ALICE, CAROL).unwrap(), amount(0));
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), max_allowance());
+        // the sentinel is only visible in state
+        let (alice, carol) = (ALICE.id().unwrap(), CAROL.id().unwrap());
+        let stored = token.state().get_allowance_between(helper.bs(), alice, carol).unwrap();
+        assert_eq!(stored, unlimited_allowance());
+
+        // spending doesn't rewrite the allowance
+        let allowances = token.state().allowances;
+        let mut hook = token
+            .transfer_from(CAROL, ALICE, BOB, &amount(60), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        let intermediate = hook.call(token.runtime).unwrap();
+        let ret = token.transfer_from_return(intermediate).unwrap();
+        assert_eq!(ret.allowance, max_allowance());
+        let ret = token.burn_from(CAROL, ALICE, &amount(40)).unwrap();
+        assert_eq!(ret.allowance, max_allowance());
+        assert_eq!(token.state().allowances, allowances);
+
+        // changes by a delta leave it unlimited
+        assert_eq!(token.increase_allowance(ALICE, CAROL, &amount(5)).unwrap(), max_allowance());
+        assert_eq!(token.decrease_allowance(ALICE, CAROL, &amount(5)).unwrap(), max_allowance());
+        token.assert_invariants().unwrap();
+
+        // setting an amount replaces it
+        assert_eq!(token.set_allowance(ALICE, CAROL, &amount(5)).unwrap(), max_allowance());
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), amount(5));
+    }
+
+    #[test]
+    fn it_lists_allowances_by_owner() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_allowance(ALICE, BOB, &amount(10)).unwrap();
+        token.set_allowance_with_expiry(ALICE, CAROL, &amount(20), Some(5)).unwrap();
+        token.set_balance(ALICE, &amount(100)).unwrap();
+
+        let page = token.list_allowances_by_owner(ALICE, RawBytes::default(), 1).unwrap();
+        assert_eq!(page.allowances.len(), 1);
+        let cursor = page.next_cursor.unwrap();
+        let last = token.list_allowances_by_owner(ALICE, cursor.clone(), 1).unwrap();
+        assert!(last.next_cursor.is_none());
+
+        // expired allowances are listed as zero
+        helper.syscalls.set_epoch(6);
+        let mut allowances = page.allowances;
+        allowances.extend(last.allowances);
+        allowances.sort();
+        let page = token.list_allowances_by_owner(ALICE, RawBytes::default(), 10).unwrap();
+        let mut listed = page.allowances;
+        listed.sort();
+        let (bob, carol) = (BOB.id().unwrap(), CAROL.id().unwrap());
+        assert_eq!(allowances, vec![(bob, amount(10)), (carol, amount(20))]);
+        assert_eq!(listed, vec![(bob, amount(10)), (carol, amount(0))]);
+
+        let operators = token.list_operators_for_owner(ALICE, RawBytes::default(), 10).unwrap();
+        assert_eq!(operators.operators.len(), 2);
+        let balances = token.list_balances(RawBytes::default(), 10).unwrap();
+        assert_eq!(balances.balances, vec![(ALICE.id().unwrap(), amount(100))]);
+
+        // stale cursors are rejected
+        token.revoke_allowance(ALICE, BOB).unwrap();
+        let err = token.list_allowances_by_owner(ALICE, cursor, 1).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+    }
+
+    #[test]
+    fn it_reports_stats() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        token.set_balance(BOB, &amount(50)).unwrap();
+        token.burn(ALICE, &amount(30)).unwrap();
+        token.set_balance(BOB, &amount(0)).unwrap();
+        token.increase_allowance(ALICE, CAROL, &amount(10)).unwrap();
+
+        let stats = token.stats();
+        assert_eq!(stats.holders, 1);
+        assert_eq!(stats.minted, amount(150));
+        assert_eq!(stats.burned, amount(80));
+        assert_eq!(stats.allowances, 1);
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_writes_maps_into_state_after_each_operation() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        token.increase_allowance(ALICE, CAROL, &amount(150)).unwrap();
+
+        // without deferred flushing the roots are up to date after every operation
+        let (alice, carol) = (ALICE.id().unwrap(), CAROL.id().unwrap());
+        let state = token.state();
+        assert_eq!(state.get_balance(helper.bs(), alice).unwrap(), amount(100));
+        assert_eq!(state.get_allowance_between(helper.bs(), alice, carol).unwrap(), amount(150));
+    }
+
+    #[test]
+    fn it_keeps_maps_loaded_until_flushed() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let unflushed = token_state.clone();
+        let mut token = new_token(&helper, &mut token_state).with_deferred_flush();
+
+        let amount = TokenAmount::from_atto;
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        token.increase_allowance(ALICE, CAROL, &amount(150)).unwrap();
+
+        // changes are read back through the loaded maps before the roots are updated
+        assert_eq!(token.state().balances, unflushed.balances);
+        assert_eq!(token.state().allowances, unflushed.allowances);
+        assert_eq!(token.balance_of(ALICE).unwrap(), amount(100));
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), amount(150));
+        token.assert_invariants().unwrap();
+
+        // the allowance is used before the balance runs out, and both are rolled back
+        let err = token
+            .transfer_from(
+                CAROL,
+                ALICE,
+                BOB,
+                &amount(120),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, TokenError::TokenState(StateError::InsufficientBalance { .. })));
+        assert_eq!(token.balance_of(ALICE).unwrap(), amount(100));
+        assert_eq!(token.allowance(ALICE, CAROL).unwrap(), amount(150));
+
+        let mut hook = token
+            .transfer_from(CAROL, ALICE, BOB, &amount(30), RawBytes::default(), RawBytes::default())
+            .unwrap();
+        let cid = token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        let state = TokenState::load(&helper, &cid).unwrap();
+        assert_eq!(state.get_balance(&helper, ALICE.id().unwrap()).unwrap(), amount(70));
+        assert_eq!(state.get_balance(&helper, BOB.id().unwrap()).unwrap(), amount(30));
+        assert_eq!(
+            state.get_allowance_between(&helper, ALICE.id().unwrap(), CAROL.id().unwrap()).unwrap(),
+            amount(120)
+        );
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_writes_loaded_maps_once_per_flush() {
+        let amount = TokenAmount::from_atto;
+        let runtime = || {
+            ActorRuntime::new(
+                FakeSyscalls::default(),
+                TrackingBlockstore::new(MemoryBlockstore::new()),
+            )
+        };
+        let alice = ALICE.id().unwrap();
+
+        // burning through a token handle with deferred flushing writes the balance map once
+        let token_helper = runtime();
+        let mut token_state = TokenState::new(&token_helper).unwrap();
+        let mut token = Token::wrap(&token_helper, 1, &mut token_state).with_deferred_flush();
+        token.set_balance(ALICE, &amount(100)).unwrap();
+        token.flush().unwrap();
+        *token_helper.blockstore.stats.borrow_mut() = BSStats::default();
+        for _ in 0..5 {
+            token.burn(ALICE, &amount(10)).unwrap();
+        }
+        token.flush().unwrap();
+        let token_stats = *token_helper.blockstore.stats.borrow();
+
+        // while the state methods flush it after every burn
+        let helper = runtime();
+        let mut state = TokenState::new(&helper).unwrap();
+        state.set_balance(&helper, alice, &amount(100)).unwrap();
+        state.change_supply_by(&amount(100)).unwrap();
+        state.save(&helper).unwrap();
+        *helper.blockstore.stats.borrow_mut() = BSStats::default();
+        for _ in 0..5 {
+            state.change_balance_by(&helper, alice, &amount(-10)).unwrap();
+            state.change_supply_by(&amount(-10)).unwrap();
+        }
+        state.save(&helper).unwrap();
+        let state_stats = *helper.blockstore.stats.borrow();
+
+        assert_eq!(token_state.get_balance(&token_helper, alice).unwrap(), amount(50));
+        assert_eq!(state.get_balance(&helper, alice).unwrap(), amount(50));
+        assert!(token_stats.w < state_stats.w, "{token_stats:?} vs {state_stats:?}");
+        assert!(token_stats.r < state_stats.r, "{token_stats:?} vs {state_stats:?}");
+    }
+
+    #[test]
+    fn it_caps_supply_at_max_supply() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        token_state.set_max_supply(Some(TokenAmount::from_atto(100))).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+        assert_eq!(token.max_supply(), Some(TokenAmount::from_atto(100)));
+
+        let mut hook = token
+            .mint(
+                TOKEN_ACTOR,
+                ALICE,
+                &TokenAmount::from_atto(60),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        let capped = |err: TokenError| {
+            matches!(err, TokenError::TokenState(StateError::SupplyCapExceeded { .. }))
+        };
+        let err = token
+            .mint(
+                TOKEN_ACTOR,
+                BOB,
+                &TokenAmount::from_atto(41),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap_err();
+        assert!(capped(err));
+
+        // batch mints are checked as a whole
+        let mint = |to: &Address, amount| MintParams {
+            initial_owner: *to,
+            amount: TokenAmount::from_atto(amount),
+            operator_data: RawBytes::default(),
+        };
+        let err = token
+            .batch_mint(TOKEN_ACTOR, &[mint(BOB, 30), mint(CAROL, 11)], RawBytes::default())
+            .unwrap_err();
+        assert!(capped(err));
+
+        // setting balances directly can't bypass the cap
+        let err = token.set_balance(BOB, &TokenAmount::from_atto(41)).unwrap_err();
+        assert!(capped(err));
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::zero());
+
+        token.set_balance(BOB, &TokenAmount::from_atto(40)).unwrap();
+        assert_eq!(token.total_supply(), TokenAmount::from_atto(100));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_pauses_transfers_mints_and_burns() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        token_state.pause = Some(PauseState::new(TREASURY.id().unwrap()));
+        let mut token = new_token(&helper, &mut token_state);
+
+        let mut hook = token
+            .mint(
+                TOKEN_ACTOR,
+                ALICE,
+                &TokenAmount::from_atto(100),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        // only the pauser can pause
+        let err = token.pause(ALICE).unwrap_err();
+        assert!(matches!(err, TokenError::Pause(PauseError::NotPauser { .. })));
+        assert!(!token.is_paused());
+        token.pause(TREASURY).unwrap();
+        assert!(token.is_paused());
+
+        let one = &TokenAmount::from_atto(1);
+        let paused = |err: TokenError| matches!(err, TokenError::Pause(PauseError::Paused));
+        let err = token
+            .mint(TOKEN_ACTOR, ALICE, one, RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert!(paused(err));
+        let err =
+            token.transfer(ALICE, BOB, one, RawBytes::default(), RawBytes::default()).unwrap_err();
+        assert!(paused(err));
+        let err = token.burn(ALICE, one).unwrap_err();
+        assert!(paused(err));
+
+        // allowances and reads still work
+        token.increase_allowance(ALICE, BOB, one).unwrap();
+        let err = token
+            .transfer_from(BOB, ALICE, CAROL, one, RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert!(paused(err));
+        let err = token.burn_from(BOB, ALICE, one).unwrap_err();
+        assert!(paused(err));
+        assert_eq!(token.balance_of(ALICE).unwrap(), TokenAmount::from_atto(100));
+
+        token.unpause(TREASURY).unwrap();
+        token.burn(ALICE, one).unwrap();
+        assert_eq!(token.balance_of(ALICE).unwrap(), TokenAmount::from_atto(99));
+    }
+
+    #[test]
+    fn it_cannot_pause_without_a_pauser() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let err = token.pause(TREASURY).unwrap_err();
+        assert!(matches!(err, TokenError::Pause(PauseError::NotPausable)));
+        assert!(!token.is_paused());
+    }
+
+    fn signed_permit(
+        runtime: &ActorRuntime<FakeSyscalls, MemoryBlockstore>,
+        owner: &Address,
+        amount: u64,
+        nonce: u64,
+    ) -> PermitParams {
+        let mut params = PermitParams {
+            owner: *owner,
+            operator: *BOB,
+            amount: TokenAmount::from_atto(amount),
+            nonce,
+            expiry: 10,
+            signature: Signature::new_secp256k1(vec![]),
+        };
+        let plaintext = params.payload(runtime.actor_id()).signing_bytes().unwrap();
+        params.signature = FakeSyscalls::sign(owner, &plaintext);
+        params
+    }
+
+    #[test]
+    fn it_sets_allowances_from_permits() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+        let owner = &secp_address();
+        assert_eq!(token.permit_nonce(owner).unwrap(), 0);
+
+        // anyone can submit the permit
+        helper.syscalls.set_caller_id(CAROL.id().unwrap());
+        let old_allowance = token.permit(&signed_permit(&helper, owner, 100, 0)).unwrap();
+        assert_eq!(old_allowance, TokenAmount::zero());
+        assert_eq!(token.allowance(owner, BOB).unwrap(), TokenAmount::from_atto(100));
+        assert_eq!(token.permit_nonce(owner).unwrap(), 1);
+
+        // permits set rather than increase the allowance
+        helper.syscalls.set_epoch(10);
+        let old_allowance = token.permit(&signed_permit(&helper, owner, 40, 1)).unwrap();
+        assert_eq!(old_allowance, TokenAmount::from_atto(100));
+        assert_eq!(token.allowance(owner, BOB).unwrap(), TokenAmount::from_atto(40));
+
+        // permits cannot be replayed
+        let err = token.permit(&signed_permit(&helper, owner, 100, 0)).unwrap_err();
+        if let TokenError::TokenState(StateError::InvalidNonce { owner: _, expected, nonce }) = err
+        {
+            assert_eq!(expected, 2);
+            assert_eq!(nonce, 0);
+        } else {
+            panic!("unexpected error {err:?}");
+        }
+        assert_eq!(token.allowance(owner, BOB).unwrap(), TokenAmount::from_atto(40));
+        token.assert_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_rejects_invalid_permits() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+        let owner = &bls_address();
+
+        // the signed terms cannot be changed
+        let mut params = signed_permit(&helper, owner, 100, 0);
+        params.amount = TokenAmount::from_atto(1000);
+        let err = token.permit(&params).unwrap_err();
+        assert!(matches!(err, TokenError::InvalidSignature(a) if a == *owner));
+
+        // nor can the permit be used by another owner
+        let mut params = signed_permit(&helper, owner, 100, 0);
+        params.owner = secp_address();
+        let err = token.permit(&params).unwrap_err();
+        assert!(matches!(err, TokenError::InvalidSignature(_)));
+
+        // nor on another token
+        let mut params = signed_permit(&helper, owner, 100, 0);
+        let plaintext = params.payload(helper.actor_id() + 1).signing_bytes().unwrap();
+        params.signature = FakeSyscalls::sign(owner, &plaintext);
+        let err = token.permit(&params).unwrap_err();
+        assert!(matches!(err, TokenError::InvalidSignature(_)));
+
+        // ID addresses cannot sign
+        let err = token.permit(&signed_permit(&helper, ALICE, 100, 0)).unwrap_err();
+        assert!(matches!(err, TokenError::InvalidSignature(_)));
+
+        // negative amounts are invalid
+        let mut params = signed_permit(&helper, owner, 0, 0);
+        params.amount = TokenAmount::from_atto(-1);
+        let err = token.permit(&params).unwrap_err();
+        assert!(matches!(err, TokenError::InvalidNegative { .. }));
+
+        // permits expire after their expiry epoch
+        helper.syscalls.set_epoch(11);
+        let err = token.permit(&signed_permit(&helper, owner, 100, 0)).unwrap_err();
+        assert!(matches!(err, TokenError::PermitExpired { expiry: 10, epoch: 11 }));
+
+        // no nonce was used and the owner was never initialized
+        assert_eq!(token.permit_nonce(owner).unwrap(), 0);
+        assert_eq!(token.allowance(owner, BOB).unwrap(), TokenAmount::zero());
+    }
+
+    #[test]
+    fn it_batch_transfers() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut token_state =
+            Token::<FakeSyscalls, MemoryBlockstore>::create_state(helper.bs()).unwrap();
+        let mut token = new_token(&helper, &mut token_state);
+
+        let mut hook = token
+            .mint(
+                TOKEN_ACTOR,
+                ALICE,
+                &TokenAmount::from_atto(100),
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        token.flush().unwrap();
+        hook.call(token.runtime).unwrap();
+
+        let transfer = |to: &Address, amount| TransferParams {
+            to: *to,
+            amount: TokenAmount::from_atto(amount),
+            operator_data: RawBytes::from(vec![amount as u8]),
+        };
+        let mut hook = token
+            .batch_transfer(ALICE, &[transfer(BOB, 30), transfer(CAROL, 20)], RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        let intermediates = hook.call(token.runtime).unwrap();
+        let ret = token.batch_transfer_return(ALICE, intermediates).unwrap();
+
+        assert_eq!(ret.from_balance, TokenAmount::from_atto(50));
+        assert_eq!(ret.to_balances, vec![TokenAmount::from_atto(30), TokenAmount::from_atto(20)]);
+        assert_eq!(token.total_supply(), TokenAmount::from_atto(100));
+        // hooks were called in order
+        assert_last_hook_call_eq(
+            token.runtime,
+            FRC46TokenReceived {
+                operator: ALICE.id().unwrap(),
+                from: ALICE.id().unwrap(),
+                amount: TokenAmount::from_atto(20),
+                to: CAROL.id().unwrap(),
+                operator_data: RawBytes::from(vec![20]),
+                token_data: Default::default(),
+            },
+        );
+
+        // exhausting the balance part way through the batch fails the whole batch
+        let err = token
+            .batch_transfer(ALICE, &[transfer(BOB, 40), transfer(CAROL, 20)], RawBytes::default())
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TokenError::TokenState(StateError::InsufficientBalance {
+                owner: _,
+                balance: _,
+                delta: _
+            })
+        ));
+        assert_eq!(token.balance_of(ALICE).unwrap(), TokenAmount::from_atto(50));
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::from_atto(30));
+
+        // a failing hook fails the batch
+        let mut hook = token
+            .batch_transfer(ALICE, &[transfer(BOB, 10), transfer(CAROL, 10)], RawBytes::default())
+            .unwrap();
+        token.flush().unwrap();
+        token.runtime.syscalls.abort_next_send.replace(true);
+        hook.call(token.runtime).unwrap_err();
+        token.assert_invariants().unwrap();
+    }
+
     #[test]
     fn it_transfers_to_self() {
         let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
@@ -1663,7 +3492,7 @@ mod test {
 
         // restore original pre-mint state
         // in actor code, we'd just abort and let the VM handle this
-        token.replace(pre_transfer_state);
+        token.replace(pre_transfer_state).unwrap();
 
         // balances unchanged
         assert_eq!(token.balance_of(ALICE).unwrap(), TokenAmount::from_atto(100));
@@ -1686,7 +3515,7 @@ mod test {
 
         // restore original pre-mint state
         // in actor code, we'd just abort and let the VM handle this
-        token.replace(pre_transfer_state);
+        token.replace(pre_transfer_state).unwrap();
 
         // balances unchanged
         assert_eq!(token.balance_of(ALICE).unwrap(), TokenAmount::from_atto(100));
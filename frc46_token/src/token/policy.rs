@@ -0,0 +1,266 @@
+//! Pluggable rules that can reject token movements, such as allowlists or frozen accounts.
+//!
+//! A [`Token`](crate::token::Token) given a [`TransferPolicy`] consults it inside the same
+//! transaction as each mint, transfer and burn, after the state has been updated. Rejecting the
+//! operation discards the whole transaction.
+
+use std::collections::BTreeSet;
+
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::ActorID;
+use thiserror::Error;
+
+use crate::token::state::{StateError, TokenState};
+
+/// An operation rejected by a [`TransferPolicy`], with the exit code the actor should abort with.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[error("rejected by transfer policy: {reason}")]
+pub struct PolicyError {
+    pub exit_code: ExitCode,
+    pub reason: String,
+}
+
+impl PolicyError {
+    pub fn new(exit_code: ExitCode, reason: impl Into<String>) -> Self {
+        Self { exit_code, reason: reason.into() }
+    }
+}
+
+impl From<&PolicyError> for ExitCode {
+    fn from(error: &PolicyError) -> Self {
+        error.exit_code
+    }
+}
+
+type Result<T> = std::result::Result<T, PolicyError>;
+
+/// The kind of operation being checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferKind {
+    Mint,
+    Transfer,
+    TransferFrom,
+    Burn,
+    BurnFrom,
+}
+
+/// A movement of tokens presented to a [`TransferPolicy`].
+#[derive(Clone, Debug)]
+pub struct TransferContext<'a> {
+    pub kind: TransferKind,
+    /// The actor that initiated the operation. For mints this is the minting operator.
+    pub operator: ActorID,
+    /// The debited account, or None for mints.
+    pub from: Option<ActorID>,
+    /// The credited account, or None for burns.
+    pub to: Option<ActorID>,
+    pub amount: &'a TokenAmount,
+    /// The current epoch.
+    pub epoch: ChainEpoch,
+}
+
+impl<'a> TransferContext<'a> {
+    pub fn mint(
+        operator: ActorID,
+        to: ActorID,
+        amount: &'a TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Self {
+        Self { kind: TransferKind::Mint, operator, from: None, to: Some(to), amount, epoch }
+    }
+
+    pub fn transfer(
+        from: ActorID,
+        to: ActorID,
+        amount: &'a TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Self {
+        let kind = TransferKind::Transfer;
+        Self { kind, operator: from, from: Some(from), to: Some(to), amount, epoch }
+    }
+
+    pub fn transfer_from(
+        operator: ActorID,
+        from: ActorID,
+        to: ActorID,
+        amount: &'a TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Self {
+        let kind = TransferKind::TransferFrom;
+        Self { kind, operator, from: Some(from), to: Some(to), amount, epoch }
+    }
+
+    pub fn burn(owner: ActorID, amount: &'a TokenAmount, epoch: ChainEpoch) -> Self {
+        Self {
+            kind: TransferKind::Burn,
+            operator: owner,
+            from: Some(owner),
+            to: None,
+            amount,
+            epoch,
+        }
+    }
+
+    pub fn burn_from(
+        operator: ActorID,
+        owner: ActorID,
+        amount: &'a TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Self {
+        Self { kind: TransferKind::BurnFrom, operator, from: Some(owner), to: None, amount, epoch }
+    }
+
+    /// Returns the accounts whose balances change.
+    pub fn holders(&self) -> impl Iterator<Item = ActorID> {
+        self.from.into_iter().chain(self.to)
+    }
+}
+
+/// Looks up an account's balance as it stands after the operation being checked.
+pub type BalanceOf<'a> = dyn FnMut(ActorID) -> std::result::Result<TokenAmount, StateError> + 'a;
+
+/// Rules consulted before a mint, transfer or burn is committed.
+///
+/// The state passed in already reflects the operation. [`Token`](crate::token::Token) keeps
+/// changed balances in memory, so the balance and allowance roots of the state may be out of date
+/// and balances must be read through `balance_of`.
+pub trait TransferPolicy<BS: Blockstore> {
+    fn check(
+        &self,
+        ctx: &TransferContext,
+        state: &TokenState,
+        bs: &BS,
+        balance_of: &mut BalanceOf,
+    ) -> Result<()>;
+}
+
+/// Only allows listed accounts to send or receive tokens. Operators needn't be listed.
+#[derive(Clone, Debug, Default)]
+pub struct Allowlist {
+    pub allowed: BTreeSet<ActorID>,
+}
+
+impl Allowlist {
+    pub fn new(allowed: impl IntoIterator<Item = ActorID>) -> Self {
+        Self { allowed: allowed.into_iter().collect() }
+    }
+}
+
+impl<BS: Blockstore> TransferPolicy<BS> for Allowlist {
+    fn check(
+        &self,
+        ctx: &TransferContext,
+        _state: &TokenState,
+        _bs: &BS,
+        _balance_of: &mut BalanceOf,
+    ) -> Result<()> {
+        match ctx.holders().find(|holder| !self.allowed.contains(holder)) {
+            Some(holder) => Err(PolicyError::new(
+                ExitCode::USR_FORBIDDEN,
+                format!("{holder} is not on the allowlist"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Stops listed accounts from sending, receiving or operating on tokens, e.g. to freeze them.
+#[derive(Clone, Debug, Default)]
+pub struct Denylist {
+    pub denied: BTreeSet<ActorID>,
+}
+
+impl Denylist {
+    pub fn new(denied: impl IntoIterator<Item = ActorID>) -> Self {
+        Self { denied: denied.into_iter().collect() }
+    }
+}
+
+impl<BS: Blockstore> TransferPolicy<BS> for Denylist {
+    fn check(
+        &self,
+        ctx: &TransferContext,
+        _state: &TokenState,
+        _bs: &BS,
+        _balance_of: &mut BalanceOf,
+    ) -> Result<()> {
+        let mut accounts = std::iter::once(ctx.operator).chain(ctx.holders());
+        match accounts.find(|account| self.denied.contains(account)) {
+            Some(account) => Err(PolicyError::new(
+                ExitCode::USR_FORBIDDEN,
+                format!("{account} is on the denylist"),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Caps the balance any account can reach by receiving tokens.
+#[derive(Clone, Debug)]
+pub struct MaxBalance {
+    pub max_balance: TokenAmount,
+}
+
+impl MaxBalance {
+    pub fn new(max_balance: TokenAmount) -> Self {
+        Self { max_balance }
+    }
+}
+
+impl<BS: Blockstore> TransferPolicy<BS> for MaxBalance {
+    fn check(
+        &self,
+        ctx: &TransferContext,
+        _state: &TokenState,
+        _bs: &BS,
+        balance_of: &mut BalanceOf,
+    ) -> Result<()> {
+        let to = match ctx.to {
+            Some(to) => to,
+            None => return Ok(()),
+        };
+        let balance =
+            balance_of(to).map_err(|e| PolicyError::new(ExitCode::from(&e), e.to_string()))?;
+        if balance > self.max_balance {
+            return Err(PolicyError::new(
+                ExitCode::USR_FORBIDDEN,
+                format!("{to}'s balance of {balance} exceeds the maximum {}", self.max_balance),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
+
+    use super::{Allowlist, Denylist, MaxBalance, TransferContext, TransferPolicy};
+    use crate::token::state::TokenState;
+
+    #[test]
+    fn stock_policies_check_accounts() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        state.change_balance_by(bs, 2, &TokenAmount::from_atto(10)).unwrap();
+        let amount = TokenAmount::from_atto(10);
+        let ctx = TransferContext::transfer_from(3, 1, 2, &amount, 0);
+        let balance_of = &mut |owner| state.get_balance(bs, owner);
+
+        Allowlist::new([1, 2]).check(&ctx, &state, bs, balance_of).unwrap();
+        let err = Allowlist::new([1]).check(&ctx, &state, bs, balance_of).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+
+        Denylist::new([4]).check(&ctx, &state, bs, balance_of).unwrap();
+        // operators are checked too
+        Denylist::new([3]).check(&ctx, &state, bs, balance_of).unwrap_err();
+
+        MaxBalance::new(TokenAmount::from_atto(10)).check(&ctx, &state, bs, balance_of).unwrap();
+        MaxBalance::new(TokenAmount::from_atto(9)).check(&ctx, &state, bs, balance_of).unwrap_err();
+    }
+}
@@ -0,0 +1,222 @@
+//! Balance and total supply snapshots, for reading historical balances on-chain.
+//!
+//! Taking a snapshot is cheap: it only increments the snapshot ID. The first time an account's
+//! balance (or the total supply) changes after a snapshot, its value before the change is recorded
+//! as a checkpoint against the current snapshot ID. Accounts that don't change between snapshots
+//! cost nothing to snapshot.
+//!
+//! Supply checkpoints are archived to an [`Amt`] in ascending ID order. The checkpoint for the
+//! current snapshot is held inline until the next snapshot is taken, so recording it doesn't need
+//! a blockstore.
+
+use cid::Cid;
+use fvm_ipld_amt::Amt;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+
+use crate::token::state::{actor_id_key, StateError};
+
+type Result<T> = std::result::Result<T, StateError>;
+
+type CheckpointMap<'bs, BS> = Hamt<&'bs BS, Vec<Checkpoint>, BytesKey>;
+type CheckpointArray<'bs, BS> = Amt<Checkpoint, &'bs BS>;
+
+const AMT_BIT_WIDTH: u32 = 5;
+
+/// Identifies a snapshot. IDs start from 1 and increase with each snapshot.
+pub type SnapshotID = u64;
+
+/// A value as of a snapshot.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct Checkpoint {
+    pub id: SnapshotID,
+    pub value: TokenAmount,
+}
+
+/// Snapshot state IPLD structure, stored in [`TokenState`](crate::token::state::TokenState) once
+/// snapshots are enabled.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct SnapshotState {
+    /// ID of the latest snapshot, or zero before the first snapshot.
+    pub current_id: SnapshotID,
+    /// `Map<ActorId, Vec<Checkpoint>>` of balance checkpoints in ascending ID order, as a
+    /// [`Hamt`].
+    pub balances: Cid,
+    /// `Array<Checkpoint>` of archived total supply checkpoints in ascending ID order, as an
+    /// [`Amt`].
+    pub supply: Cid,
+    /// Total supply checkpoint for the current snapshot, archived when the next is taken.
+    pub pending_supply: Option<Checkpoint>,
+}
+
+impl SnapshotState {
+    pub fn new<BS: Blockstore>(bs: &BS, hamt_bit_width: u32) -> Result<Self> {
+        let balances = CheckpointMap::new_with_bit_width(bs, hamt_bit_width).flush()?;
+        let supply = CheckpointArray::new_with_bit_width(bs, AMT_BIT_WIDTH).flush()?;
+        Ok(Self { current_id: 0, balances, supply, pending_supply: None })
+    }
+
+    /// Takes a new snapshot, returning its ID.
+    pub fn snapshot<BS: Blockstore>(&mut self, bs: &BS) -> Result<SnapshotID> {
+        if let Some(checkpoint) = self.pending_supply.take() {
+            let mut checkpoints = CheckpointArray::load(&self.supply, bs)?;
+            checkpoints.set(checkpoints.count(), checkpoint)?;
+            self.supply = checkpoints.flush()?;
+        }
+        self.current_id += 1;
+        Ok(self.current_id)
+    }
+
+    /// Records an account's balance before it changes, if not already recorded for the current
+    /// snapshot.
+    pub fn record_balance<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        owner: ActorID,
+        balance: &TokenAmount,
+    ) -> Result<()> {
+        if self.current_id == 0 {
+            return Ok(());
+        }
+        let mut checkpoint_map =
+            CheckpointMap::load_with_bit_width(&self.balances, bs, hamt_bit_width)?;
+        let owner_key = actor_id_key(owner);
+        let mut checkpoints = checkpoint_map.get(&owner_key)?.cloned().unwrap_or_default();
+        if record(&mut checkpoints, self.current_id, balance) {
+            checkpoint_map.set(owner_key, checkpoints)?;
+            self.balances = checkpoint_map.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records the total supply before it changes, if not already recorded for the current
+    /// snapshot.
+    pub fn record_supply(&mut self, supply: &TokenAmount) {
+        if self.current_id != 0 && self.pending_supply.is_none() {
+            self.pending_supply = Some(Checkpoint { id: self.current_id, value: supply.clone() });
+        }
+    }
+
+    /// Returns an account's balance as of a snapshot, given its current balance.
+    pub fn balance_at<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        owner: ActorID,
+        id: SnapshotID,
+        balance: TokenAmount,
+    ) -> Result<TokenAmount> {
+        self.check_id(id)?;
+        let checkpoint_map =
+            CheckpointMap::load_with_bit_width(&self.balances, bs, hamt_bit_width)?;
+        let checkpoints = checkpoint_map.get(&actor_id_key(owner))?;
+        Ok(value_at(checkpoints.map(Vec::as_slice).unwrap_or_default(), id).unwrap_or(balance))
+    }
+
+    /// Returns the total supply as of a snapshot, given the current supply.
+    pub fn supply_at<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        id: SnapshotID,
+        supply: TokenAmount,
+    ) -> Result<TokenAmount> {
+        self.check_id(id)?;
+        let archived = array_value_at(bs, &self.supply, id)?;
+        let pending = self.pending_supply.as_ref().map(|checkpoint| checkpoint.value.clone());
+        Ok(archived.or(pending).unwrap_or(supply))
+    }
+
+    fn check_id(&self, id: SnapshotID) -> Result<()> {
+        if id == 0 || id > self.current_id {
+            return Err(StateError::InvalidSnapshot { id, current_id: self.current_id });
+        }
+        Ok(())
+    }
+}
+
+/// Appends a checkpoint unless one already exists for the snapshot, returning whether it did.
+fn record(checkpoints: &mut Vec<Checkpoint>, id: SnapshotID, value: &TokenAmount) -> bool {
+    if checkpoints.last().is_some_and(|last| last.id == id) {
+        return false;
+    }
+    checkpoints.push(Checkpoint { id, value: value.clone() });
+    true
+}
+
+/// Returns the value as of a snapshot, or None if it hasn't changed since.
+///
+/// The value as of snapshot `id` is held by the earliest checkpoint at or after `id`, as that
+/// records the value before the first change following the snapshot.
+fn value_at(checkpoints: &[Checkpoint], id: SnapshotID) -> Option<TokenAmount> {
+    let index = checkpoints.partition_point(|checkpoint| checkpoint.id < id);
+    checkpoints.get(index).map(|checkpoint| checkpoint.value.clone())
+}
+
+/// [`value_at`] for checkpoints stored in an [`Amt`], binary searching by index.
+fn array_value_at<BS: Blockstore>(
+    bs: &BS,
+    root: &Cid,
+    id: SnapshotID,
+) -> Result<Option<TokenAmount>> {
+    let checkpoints = CheckpointArray::load(root, bs)?;
+    let (mut low, mut high) = (0, checkpoints.count());
+    let mut found = None;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let checkpoint = checkpoints.get(mid)?.ok_or(StateError::MissingState(*root))?;
+        if checkpoint.id < id {
+            low = mid + 1;
+        } else {
+            found = Some(checkpoint.value.clone());
+            high = mid;
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::econ::TokenAmount;
+
+    use super::{array_value_at, record, value_at, Checkpoint, CheckpointArray};
+
+    #[test]
+    fn it_finds_values_at_snapshots() {
+        let amount = TokenAmount::from_atto;
+        let mut checkpoints: Vec<Checkpoint> = Vec::new();
+        // changed after snapshots 2 and 5, only the first change is recorded
+        assert!(record(&mut checkpoints, 2, &amount(10)));
+        assert!(!record(&mut checkpoints, 2, &amount(20)));
+        assert!(record(&mut checkpoints, 5, &amount(30)));
+
+        assert_eq!(value_at(&checkpoints, 1), Some(amount(10)));
+        assert_eq!(value_at(&checkpoints, 2), Some(amount(10)));
+        assert_eq!(value_at(&checkpoints, 3), Some(amount(30)));
+        assert_eq!(value_at(&checkpoints, 5), Some(amount(30)));
+        // unchanged since snapshot 6
+        assert_eq!(value_at(&checkpoints, 6), None);
+    }
+
+    #[test]
+    fn it_binary_searches_archived_checkpoints() {
+        let bs = &MemoryBlockstore::new();
+        let amount = TokenAmount::from_atto;
+        let mut checkpoints = CheckpointArray::new(bs);
+        for (index, id) in [2, 5, 6, 9].into_iter().enumerate() {
+            let checkpoint = Checkpoint { id, value: amount(id as i64 * 10) };
+            checkpoints.set(index as u64, checkpoint).unwrap();
+        }
+        let checkpoints = &checkpoints.flush().unwrap();
+
+        assert_eq!(array_value_at(bs, checkpoints, 1).unwrap(), Some(amount(20)));
+        assert_eq!(array_value_at(bs, checkpoints, 3).unwrap(), Some(amount(50)));
+        assert_eq!(array_value_at(bs, checkpoints, 6).unwrap(), Some(amount(60)));
+        assert_eq!(array_value_at(bs, checkpoints, 9).unwrap(), Some(amount(90)));
+        assert_eq!(array_value_at(bs, checkpoints, 10).unwrap(), None);
+    }
+}
@@ -1,31 +1,78 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Neg;
 
 use cid::Cid;
+use fvm_actor_utils::pause::PauseState;
+use fvm_ipld_amt::Error as AmtError;
 use fvm_ipld_blockstore::Block;
 use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::repr::{Deserialize_repr, Serialize_repr};
 use fvm_ipld_encoding::tuple::*;
 use fvm_ipld_encoding::CborStore;
 use fvm_ipld_encoding::DAG_CBOR;
 use fvm_ipld_hamt::Hamt;
 use fvm_ipld_hamt::{BytesKey, Error as HamtError};
 use fvm_shared::address::Address;
-use fvm_shared::bigint::Zero;
+use fvm_shared::bigint::{BigInt, Zero};
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ExitCode;
 use fvm_shared::ActorID;
 use integer_encoding::VarInt;
 use multihash_codetable::Code;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::token::fees::FeeSchedule;
+use crate::token::snapshot::{SnapshotID, SnapshotState};
+use crate::token::vesting::{VestingSchedule, VestingState};
+use crate::token::votes::VotesState;
+
 /// This value has been chosen to optimise to reduce gas-costs when accessing the balances map. Non-
 /// standard use cases of the token library might find a different value to be more efficient.
 pub const DEFAULT_HAMT_BIT_WIDTH: u32 = 3;
 
+/// Version of the [`TokenState`] layout written by this version of the library.
+///
+/// State written before the layout was versioned decodes as version zero, and is brought up to date
+/// by [`TokenState::upgrade`].
+pub const STATE_VERSION: u64 = 1;
+
+/// Exit code for supply increases rejected by the maximum supply.
+///
+/// This is an actor-specific exit code, following [`fvm_actor_utils::pause::ERR_PAUSED`].
+pub const ERR_SUPPLY_CAP_EXCEEDED: ExitCode = ExitCode::new(33);
+
+/// Returns the allowance stored for an operator that may spend any amount of an owner's tokens.
+///
+/// Allowances are otherwise never negative, so the sentinel can't be mistaken for a real amount.
+/// Unlimited allowances are never decremented when used. The sentinel only appears in state:
+/// [`Token`](crate::token::Token) reports unlimited allowances as [`max_allowance`].
+pub fn unlimited_allowance() -> TokenAmount {
+    TokenAmount::from_atto(-1)
+}
+
+/// Returns the allowance that [`Token`](crate::token::Token) reports for, and accepts as, an
+/// unlimited allowance.
+///
+/// This is 2^256 - 1 atto, the `type(uint256).max` that ERC-20 callers approve for unlimited
+/// spending.
+pub fn max_allowance() -> TokenAmount {
+    TokenAmount::from_atto((BigInt::from(1) << 256) - 1)
+}
+
+/// Returns whether an allowance is the [`unlimited_allowance`] sentinel.
+pub fn is_unlimited_allowance(allowance: &TokenAmount) -> bool {
+    *allowance == unlimited_allowance()
+}
+
 #[derive(Error, Debug)]
 pub enum StateError {
     #[error("ipld hamt error: {0}")]
     IpldHamt(#[from] HamtError),
+    #[error("ipld amt error: {0}")]
+    IpldAmt(#[from] AmtError),
     #[error("missing state at cid: {0}")]
     MissingState(Cid),
     #[error("underlying serialization error: {0}")]
@@ -49,20 +96,63 @@ pub enum StateError {
     NegativeAllowance { amount: TokenAmount, owner: ActorID, operator: ActorID },
     #[error("balance cannot be negative, cannot set balance of {owner:?} to {amount:?}")]
     NegativeBalance { amount: TokenAmount, owner: ActorID },
+    #[error("total supply {supply:?} would exceed the maximum supply {max_supply:?}")]
+    SupplyCapExceeded { supply: TokenAmount, max_supply: TokenAmount },
+    #[error("snapshots are not enabled")]
+    SnapshotsDisabled,
+    #[error("snapshot {id:?} does not exist, the latest snapshot is {current_id:?}")]
+    InvalidSnapshot { id: SnapshotID, current_id: SnapshotID },
+    #[error("votes are not enabled")]
+    VotesDisabled,
+    #[error("negative votes caused by changing {delegate:?}'s votes of {votes:?} by {delta:?}")]
+    NegativeVotes { delegate: ActorID, votes: TokenAmount, delta: TokenAmount },
+    #[error("invalid vesting schedule {0:?}")]
+    InvalidVestingSchedule(Box<VestingSchedule>),
+    #[error("vesting over {periods:?} periods of {period:?} epochs overflows")]
+    VestingDurationOverflow { period: ChainEpoch, periods: i64 },
+    #[error("{owner:?}'s balance of {balance:?} would be less than its locked amount {locked:?}")]
+    LockedBalance { owner: ActorID, balance: TokenAmount, locked: TokenAmount },
+    #[error("invalid fee schedule {0:?}")]
+    InvalidFeeSchedule(Box<FeeSchedule>),
+    #[error(
+        "permit nonce {nonce:?} for {owner:?} was already used or skips ahead of {expected:?}"
+    )]
+    InvalidNonce { owner: ActorID, expected: u64, nonce: u64 },
+    #[error("invalid cursor")]
+    InvalidCursor,
+    #[error("allowances are stored in the {actual:?} layout, not {expected:?}")]
+    WrongAllowanceLayout { expected: AllowanceLayout, actual: AllowanceLayout },
 }
 
 impl From<&StateError> for ExitCode {
     fn from(error: &StateError) -> Self {
         match error {
-            StateError::IpldHamt(_) | StateError::Serialization(_) => ExitCode::USR_SERIALIZATION,
+            StateError::IpldHamt(_) | StateError::IpldAmt(_) | StateError::Serialization(_) => {
+                ExitCode::USR_SERIALIZATION
+            }
             StateError::NegativeBalance { amount: _, owner: _ }
             | StateError::NegativeAllowance { amount: _, owner: _, operator: _ }
             | StateError::NegativeTotalSupply { supply: _, delta: _ }
-            | StateError::MissingState(_) => ExitCode::USR_ILLEGAL_STATE,
+            | StateError::NegativeVotes { delegate: _, votes: _, delta: _ }
+            | StateError::MissingState(_)
+            | StateError::WrongAllowanceLayout { expected: _, actual: _ } => {
+                ExitCode::USR_ILLEGAL_STATE
+            }
             StateError::InsufficientBalance { balance: _, delta: _, owner: _ }
-            | StateError::InsufficientAllowance { owner: _, operator: _, allowance: _, delta: _ } => {
+            | StateError::InsufficientAllowance { owner: _, operator: _, allowance: _, delta: _ }
+            | StateError::LockedBalance { owner: _, balance: _, locked: _ } => {
                 ExitCode::USR_INSUFFICIENT_FUNDS
             }
+            StateError::SupplyCapExceeded { supply: _, max_supply: _ } => ERR_SUPPLY_CAP_EXCEEDED,
+            StateError::SnapshotsDisabled | StateError::VotesDisabled => {
+                ExitCode::USR_ILLEGAL_STATE
+            }
+            StateError::InvalidNonce { owner: _, expected: _, nonce: _ }
+            | StateError::InvalidSnapshot { id: _, current_id: _ }
+            | StateError::InvalidVestingSchedule(_)
+            | StateError::VestingDurationOverflow { period: _, periods: _ }
+            | StateError::InvalidFeeSchedule(_)
+            | StateError::InvalidCursor => ExitCode::USR_ILLEGAL_ARGUMENT,
         }
     }
 }
@@ -71,6 +161,20 @@ impl From<&StateError> for ExitCode {
 pub enum StateInvariantError {
     #[error("total supply was negative: {0}")]
     SupplyNegative(TokenAmount),
+    #[error("total supply {supply:?} exceeds the maximum supply {max_supply:?}")]
+    SupplyExceedsMax { supply: TokenAmount, max_supply: TokenAmount },
+    #[error("total delegated votes {votes:?} exceed the total supply {supply:?}")]
+    VotesExceedSupply { votes: TokenAmount, supply: TokenAmount },
+    #[error("stored an invalid fee schedule {0:?}")]
+    InvalidFeeSchedule(Box<FeeSchedule>),
+    #[error("counted {counted:?} holders but the stats record {holders:?}")]
+    HolderCountMismatch { holders: u64, counted: u64 },
+    #[error("counted {counted:?} allowances but the stats record {allowances:?}")]
+    AllowanceCountMismatch { allowances: u64, counted: u64 },
+    #[error("total supply {supply:?} does not match {minted:?} minted less {burned:?} burned")]
+    MintedBurnedMismatch { supply: TokenAmount, minted: TokenAmount, burned: TokenAmount },
+    #[error("stored an expiry for a missing allowance between {owner:?} and {operator:?}")]
+    OrphanedAllowanceExpiry { owner: ActorID, operator: ActorID },
     #[error("the account for {account:?} had a negative balance of {balance:?}")]
     BalanceNegative { account: ActorID, balance: TokenAmount },
     #[error("the total supply {supply:?} does not match the sum of all balances {balance_sum:?}")]
@@ -105,19 +209,112 @@ type Map<'bs, BS, K, V> = Hamt<&'bs BS, V, K>;
 type BalanceMap<'bs, BS> = Map<'bs, BS, BytesKey, TokenAmount>;
 type AllowanceMap<'bs, BS> = Map<'bs, BS, BytesKey, Cid>;
 type OwnerAllowanceMap<'bs, BS> = Map<'bs, BS, BytesKey, TokenAmount>;
+type NonceMap<'bs, BS> = Map<'bs, BS, BytesKey, u64>;
+type FlatAllowanceMap<'bs, BS> = Map<'bs, BS, BytesKey, TokenAmount>;
+type ExpiryMap<'bs, BS> = Map<'bs, BS, BytesKey, ChainEpoch>;
+
+/// Accounts and their amounts, as listed by a page of balances or allowances.
+pub type AccountAmounts = Vec<(ActorID, TokenAmount)>;
+
+/// Opaque cursor to resume iterating over a Hamt in [`TokenState`].
+///
+/// A cursor is only valid for the Hamt it was created from, so it is invalidated by any change to
+/// the data being listed.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct Cursor {
+    pub root: Cid,
+    pub key: BytesKey,
+}
+
+impl Cursor {
+    fn new(root: Cid, key: BytesKey) -> Self {
+        Self { root, key }
+    }
+}
+
+/// Counters kept up to date as the state changes, so they can be read without walking the Hamts.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug, Default)]
+pub struct TokenStats {
+    /// Number of accounts with a non-zero balance.
+    pub holders: u64,
+    /// Cumulative increases of the total supply, by minting or setting balances.
+    pub minted: TokenAmount,
+    /// Cumulative decreases of the total supply, by burning or setting balances.
+    pub burned: TokenAmount,
+    /// Number of owner-operator pairs with a stored allowance.
+    pub allowances: u64,
+}
+
+/// How allowances are stored in [`TokenState::allowances`], encoded as an integer.
+#[derive(Serialize_repr, Deserialize_repr, PartialEq, Eq, Clone, Copy, Debug, Default)]
+#[repr(u8)]
+pub enum AllowanceLayout {
+    /// A Hamt of owners to the Cid of a Hamt of that owner's operators to allowances.
+    ///
+    /// Each allowance update rewrites both levels, but an owner's allowances can be listed cheaply.
+    #[default]
+    Nested = 0,
+    /// A single Hamt of allowances keyed by [`allowance_key`].
+    ///
+    /// Each allowance update rewrites one Hamt, but listing an owner's allowances scans them all.
+    Flat = 1,
+}
 
 /// Token state IPLD structure.
+///
+/// The first four fields are the original layout of the state. Fields added since are appended
+/// after them and default when absent, so state written by earlier versions of the library can
+/// still be decoded.
 #[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
 pub struct TokenState {
     /// Total supply of token.
     pub supply: TokenAmount,
     /// `Map<ActorId, TokenAmount>` of balances as a [`Hamt`].
     pub balances: Cid,
-    /// `Map<ActorId, Map<ActorId, TokenAmount>>` as a [`Hamt`]. Allowances are stored
-    /// `balances[owner][operator]`.
+    /// Allowances as a [`Hamt`], laid out according to `allowance_layout`. Under the nested layout
+    /// this is a `Map<ActorId, Map<ActorId, TokenAmount>>` with allowances stored
+    /// `allowances[owner][operator]`. Under the flat layout it is a
+    /// `Map<(ActorId, ActorId), TokenAmount>` keyed by [`allowance_key`].
     pub allowances: Cid,
     /// Bit-width to use when loading Hamts.
     pub hamt_bit_width: u32,
+    /// Layout version of the state, see [`STATE_VERSION`].
+    #[serde(default)]
+    pub version: u64,
+    /// Cap on the total supply, if any.
+    #[serde(default)]
+    pub max_supply: Option<TokenAmount>,
+    /// Layout of the allowances Hamt.
+    #[serde(default)]
+    pub allowance_layout: AllowanceLayout,
+    /// `Map<(ActorId, ActorId), ChainEpoch>` of the last epoch each expiring allowance can be used,
+    /// as a [`Hamt`] keyed by [`allowance_key`]. Allowances without an entry never expire, and the
+    /// map is None while no allowance expires.
+    #[serde(default)]
+    pub allowance_expiries: Option<Cid>,
+    /// `Map<ActorId, u64>` of the next permit nonce for each owner as a [`Hamt`]. Owners that have
+    /// never used a permit are absent and start at zero, and the map is None until the first
+    /// permit is used.
+    #[serde(default)]
+    pub permit_nonces: Option<Cid>,
+    /// Pause state, if the token is pausable. Tokens are created without a pauser.
+    #[serde(default)]
+    pub pause: Option<PauseState>,
+    /// Balance and supply snapshots, once enabled with [`TokenState::enable_snapshots`].
+    #[serde(default)]
+    pub snapshots: Option<SnapshotState>,
+    /// Vote delegation, once enabled with [`TokenState::enable_votes`].
+    #[serde(default)]
+    pub votes: Option<VotesState>,
+    /// Vesting schedules, created when the first schedule is added.
+    #[serde(default)]
+    pub vesting: Option<VestingState>,
+    /// Fee charged on transfers, if any.
+    #[serde(default)]
+    pub fees: Option<FeeSchedule>,
+    /// Holder, supply and allowance counters.
+    #[serde(default)]
+    pub stats: TokenStats,
 }
 
 /// An abstraction over the IPLD layer to get and modify token state without dealing with HAMTs etc.
@@ -137,31 +334,85 @@ impl TokenState {
     /// Explicitly sets the bit width of underlying Hamt structures. Caller must ensure
     /// `1 <= hamt_bit_width <= 8`.
     pub fn new_with_bit_width<BS: Blockstore>(store: &BS, hamt_bit_width: u32) -> Result<Self> {
+        Self::new_with_allowance_layout(store, hamt_bit_width, AllowanceLayout::Nested)
+    }
+
+    /// Create a new token state-tree, without committing it (the root cid) to a blockstore.
+    ///
+    /// Explicitly sets the bit width of underlying Hamt structures and how allowances are laid out.
+    /// Caller must ensure `1 <= hamt_bit_width <= 8`.
+    pub fn new_with_allowance_layout<BS: Blockstore>(
+        store: &BS,
+        hamt_bit_width: u32,
+        allowance_layout: AllowanceLayout,
+    ) -> Result<Self> {
         // Blockstore is still needed to create valid Cids for the Hamts
         let empty_balance_map = BalanceMap::new_with_bit_width(store, hamt_bit_width).flush()?;
-        let empty_allowances_map =
-            AllowanceMap::new_with_bit_width(store, hamt_bit_width).flush()?;
+        let empty_allowances_map = match allowance_layout {
+            AllowanceLayout::Nested => {
+                AllowanceMap::new_with_bit_width(store, hamt_bit_width).flush()?
+            }
+            AllowanceLayout::Flat => {
+                FlatAllowanceMap::new_with_bit_width(store, hamt_bit_width).flush()?
+            }
+        };
 
         Ok(Self {
             supply: Default::default(),
             balances: empty_balance_map,
             allowances: empty_allowances_map,
             hamt_bit_width,
+            version: STATE_VERSION,
+            max_supply: None,
+            allowance_layout,
+            allowance_expiries: None,
+            permit_nonces: None,
+            pause: None,
+            snapshots: None,
+            votes: None,
+            vesting: None,
+            fees: None,
+            stats: TokenStats::default(),
         })
     }
 
     /// Loads a fresh copy of the state from a blockstore from a given cid.
+    ///
+    /// State written by an earlier version of the library is upgraded as it is loaded.
     pub fn load<BS: Blockstore>(bs: &BS, cid: &Cid) -> Result<Self> {
         // Load the actor state from the state tree.
-        let state = match bs.get_cbor::<Self>(cid) {
+        let mut state = match bs.get_cbor::<Self>(cid) {
             Ok(Some(state)) => Ok(state),
             Ok(None) => Err(StateError::MissingState(*cid)),
             Err(err) => Err(StateError::Serialization(err.to_string())),
         }?;
+        state.upgrade(bs)?;
 
         Ok(state)
     }
 
+    /// Brings state written by an earlier version of the library up to [`STATE_VERSION`].
+    ///
+    /// [`TokenState::load`] does this automatically. Actors that embed the state in their own state
+    /// object must call it after decoding. Does nothing if the state is already up to date.
+    ///
+    /// State written before versioning didn't keep [`TokenStats`], so they are seeded from the
+    /// balances and allowances, with the whole supply counted as minted.
+    pub fn upgrade<BS: Blockstore>(&mut self, bs: &BS) -> Result<()> {
+        if self.version == 0 {
+            self.stats = TokenStats {
+                holders: self.count_balances(bs)? as u64,
+                minted: self.supply.clone(),
+                burned: TokenAmount::zero(),
+                allowances: self.count_nested_allowances(bs)?,
+            };
+        }
+        if self.version < STATE_VERSION {
+            self.version = STATE_VERSION;
+        }
+        Ok(())
+    }
+
     /// Saves the current state to the blockstore, returning the cid.
     pub fn save<BS: Blockstore>(&self, bs: &BS) -> Result<Cid> {
         let serialized = match fvm_ipld_encoding::to_vec(self) {
@@ -178,57 +429,81 @@ impl TokenState {
 
     /// Get the balance of an [`ActorID`] from the currently stored state.
     pub fn get_balance<BS: Blockstore>(&self, bs: &BS, owner: ActorID) -> Result<TokenAmount> {
-        let balances = self.get_balance_map(bs)?;
-
-        let balance = match balances.get(&actor_id_key(owner))? {
-            Some(amount) => amount.clone(),
-            None => TokenAmount::zero(),
-        };
+        self.get_balance_in(bs, &mut LoadedMaps::new(), owner)
+    }
 
-        Ok(balance)
+    /// Like [`TokenState::get_balance`], reading through loaded maps.
+    pub(crate) fn get_balance_in<'bs, BS: Blockstore>(
+        &self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+    ) -> Result<TokenAmount> {
+        maps.get_balance(self, bs, owner)
     }
 
     /// Changes the balance of the specified account by the delta.
     ///
     /// Caller must ensure that the sign of of the delta is consistent with token rules (i.e.
     /// negative transfers, burns etc. are not allowed). Returns the new balance of the account.
+    ///
+    /// A debit fails with [`StateError::LockedBalance`] if it would leave less than the account's
+    /// locked amount, as of the epoch last given to [`TokenState::advance_vesting_epoch`].
     pub fn change_balance_by<BS: Blockstore>(
         &mut self,
         bs: &BS,
         owner: ActorID,
         delta: &TokenAmount,
     ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| state.change_balance_by_in(bs, maps, owner, delta))
+    }
+
+    /// Like [`TokenState::change_balance_by`], leaving the change in the loaded maps.
+    pub(crate) fn change_balance_by_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        delta: &TokenAmount,
+    ) -> Result<TokenAmount> {
+        let balance = maps.get_balance(self, bs, owner)?;
         if delta.is_zero() {
             // This is a no-op as far as mutating state
-            return self.get_balance(bs, owner);
+            return Ok(balance);
         }
 
-        let mut balance_map = self.get_balance_map(bs)?;
-        let owner_key = actor_id_key(owner);
-        let balance = balance_map.get(&owner_key)?;
-        let balance = match balance {
-            Some(amount) => amount.clone(),
-            None => TokenAmount::zero(),
-        };
-
         let new_balance = &balance + delta;
 
         // if the new_balance is negative, return an error
         if new_balance.is_negative() {
             return Err(StateError::InsufficientBalance { balance, delta: delta.clone(), owner });
         }
-
-        if new_balance.is_zero() {
-            balance_map.delete(&owner_key)?;
-        } else {
-            balance_map.set(owner_key, new_balance.clone())?;
+        if delta.is_negative() {
+            self.check_debit(bs, owner, &new_balance)?;
         }
-
-        self.balances = balance_map.flush()?;
+        self.record_balance_snapshot(bs, owner, &balance)?;
+        self.count_holder(&balance, &new_balance);
+        maps.set_balance(self, bs, owner, &new_balance)?;
 
         Ok(new_balance)
     }
 
+    /// Sets or removes the cap on the total supply.
+    ///
+    /// Fails if the current supply already exceeds the new cap.
+    pub fn set_max_supply(&mut self, max_supply: Option<TokenAmount>) -> Result<()> {
+        if let Some(max_supply) = &max_supply {
+            if self.supply > *max_supply {
+                return Err(StateError::SupplyCapExceeded {
+                    supply: self.supply.clone(),
+                    max_supply: max_supply.clone(),
+                });
+            }
+        }
+        self.max_supply = max_supply;
+        Ok(())
+    }
+
     /// Set the balance of the account returning the old balance.
     ///
     /// Consistent with [`TokenState::change_balance_by`], this method does not change the total
@@ -239,37 +514,78 @@ impl TokenState {
         bs: &BS,
         owner: ActorID,
         new_balance: &TokenAmount,
+    ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| state.set_balance_in(bs, maps, owner, new_balance))
+    }
+
+    /// Like [`TokenState::set_balance`], leaving the change in the loaded maps.
+    pub(crate) fn set_balance_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        new_balance: &TokenAmount,
     ) -> Result<TokenAmount> {
         // if the new balance is negative, return an error
         if new_balance.is_negative() {
             return Err(StateError::NegativeBalance { amount: new_balance.clone(), owner });
         }
 
-        let mut balance_map = self.get_balance_map(bs)?;
-        let owner_key = actor_id_key(owner);
-        let old_balance = match balance_map.get(&owner_key)? {
-            Some(amount) => amount.clone(),
-            None => TokenAmount::zero(),
-        };
-
-        // if the new balance is zero, remove from balance map
-        if new_balance.is_zero() {
-            balance_map.delete(&owner_key)?;
-            self.balances = balance_map.flush()?;
-            return Ok(old_balance);
+        let old_balance = maps.get_balance(self, bs, owner)?;
+        if old_balance != *new_balance {
+            self.record_balance_snapshot(bs, owner, &old_balance)?;
         }
+        self.count_holder(&old_balance, new_balance);
 
-        // else, set the new balance
-        balance_map.set(owner_key, new_balance.clone())?;
-        self.balances = balance_map.flush()?;
+        // a zero balance is removed from the balance map
+        maps.set_balance(self, bs, owner, new_balance)?;
         Ok(old_balance)
     }
 
+    /// Updates the holder count for an account's balance changing from `old` to `new`.
+    fn count_holder(&mut self, old: &TokenAmount, new: &TokenAmount) {
+        match (old.is_zero(), new.is_zero()) {
+            (true, false) => self.stats.holders += 1,
+            (false, true) => self.stats.holders = self.stats.holders.saturating_sub(1),
+            _ => {}
+        }
+    }
+
     /// Retrieve the balance map as a HAMT.
     pub fn get_balance_map<'bs, BS: Blockstore>(&self, bs: &'bs BS) -> Result<BalanceMap<'bs, BS>> {
         Ok(BalanceMap::load_with_bit_width(&self.balances, bs, self.hamt_bit_width)?)
     }
 
+    /// Runs an operation against maps loaded for it alone, then flushes them into the state.
+    fn with_maps<'bs, BS: Blockstore + 'bs, R>(
+        &mut self,
+        f: impl FnOnce(&mut Self, &mut LoadedMaps<'bs, BS>) -> Result<R>,
+    ) -> Result<R> {
+        let mut maps = LoadedMaps::new();
+        let res = f(self, &mut maps)?;
+        self.flush_maps(&mut maps)?;
+        Ok(res)
+    }
+
+    /// Writes the balance and allowance maps loaded through the `_in` methods back to
+    /// [`TokenState::balances`] and [`TokenState::allowances`].
+    ///
+    /// The maps stay loaded, and flushing them again without further changes writes nothing.
+    pub(crate) fn flush_maps<BS: Blockstore>(
+        &mut self,
+        maps: &mut LoadedMaps<'_, BS>,
+    ) -> Result<()> {
+        if let Some(balances) = &mut maps.balances {
+            self.balances = balances.flush()?;
+        }
+        match &mut maps.allowances {
+            Some(LoadedAllowances::Nested(allowances)) => self.allowances = allowances.flush()?,
+            Some(LoadedAllowances::Flat(allowances)) => self.allowances = allowances.flush()?,
+            None => {}
+        }
+        Ok(())
+    }
+
     /// Record a transfer of an amount between two accounts.
     ///
     /// It is the caller's responsibility to ensure that allowance invariants are upheld. The caller
@@ -280,11 +596,23 @@ impl TokenState {
         from: ActorID,
         to: ActorID,
         amount: &TokenAmount,
+    ) -> Result<()> {
+        self.with_maps(|state, maps| state.make_transfer_in(bs, maps, from, to, amount))
+    }
+
+    /// Like [`TokenState::make_transfer`], leaving the changes in the loaded maps.
+    pub(crate) fn make_transfer_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        from: ActorID,
+        to: ActorID,
+        amount: &TokenAmount,
     ) -> Result<()> {
         if from == to {
             // balance transfers are a no-op if the from and to are the same but should still error
             // if the requested amount exceeds the account's balance
-            let balance = self.get_balance(&bs, from)?;
+            let balance = self.get_balance_in(bs, maps, from)?;
             if balance.lt(amount) {
                 return Err(StateError::InsufficientBalance {
                     owner: from,
@@ -292,14 +620,277 @@ impl TokenState {
                     delta: amount.clone().neg(),
                 });
             }
+            // nor may it move locked tokens
+            self.check_debit(bs, from, &(balance - amount))?;
         } else {
-            self.change_balance_by(&bs, from, &amount.neg())?;
-            self.change_balance_by(&bs, to, amount)?;
+            self.change_balance_by_in(bs, maps, from, &amount.neg())?;
+            self.change_balance_by_in(bs, maps, to, amount)?;
         }
 
         Ok(())
     }
 
+    /// Record a transfer of an amount between two accounts, charging the transfer fee if any.
+    ///
+    /// The fee is debited from `from` along with the amount and paid to the fee recipient, so `to`
+    /// receives the amount net of the fee. Returns the fee charged. The same caller checks apply as
+    /// for [`TokenState::make_transfer`], which never charges a fee.
+    pub fn make_transfer_with_fee<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        from: ActorID,
+        to: ActorID,
+        amount: &TokenAmount,
+        granularity: u64,
+    ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| {
+            state.make_transfer_with_fee_in(bs, maps, from, to, amount, granularity)
+        })
+    }
+
+    /// Like [`TokenState::make_transfer_with_fee`], leaving the changes in the loaded maps.
+    pub(crate) fn make_transfer_with_fee_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        from: ActorID,
+        to: ActorID,
+        amount: &TokenAmount,
+        granularity: u64,
+    ) -> Result<TokenAmount> {
+        let (recipient, fee) = match &self.fees {
+            Some(fees) => (fees.recipient, fees.fee_for(from, to, amount, granularity)),
+            None => (from, TokenAmount::zero()),
+        };
+        self.make_transfer_in(bs, maps, from, to, &(amount - &fee))?;
+        if !fee.is_zero() {
+            self.make_transfer_in(bs, maps, from, recipient, &fee)?;
+        }
+        Ok(fee)
+    }
+
+    /// Sets or removes the fee charged on transfers.
+    pub fn set_fee_schedule(&mut self, fees: Option<FeeSchedule>) -> Result<()> {
+        if let Some(fees) = &fees {
+            if !fees.is_valid() {
+                return Err(StateError::InvalidFeeSchedule(Box::new(fees.clone())));
+            }
+        }
+        self.fees = fees;
+        Ok(())
+    }
+
+    /// Starts recording balance and supply snapshots. Does nothing if already enabled.
+    pub fn enable_snapshots<BS: Blockstore>(&mut self, bs: &BS) -> Result<()> {
+        if self.snapshots.is_none() {
+            self.snapshots = Some(SnapshotState::new(bs, self.hamt_bit_width)?);
+        }
+        Ok(())
+    }
+
+    /// Takes a snapshot of all balances and the total supply, returning its ID.
+    pub fn snapshot<BS: Blockstore>(&mut self, bs: &BS) -> Result<SnapshotID> {
+        let snapshots = self.snapshots.as_mut().ok_or(StateError::SnapshotsDisabled)?;
+        snapshots.snapshot(bs)
+    }
+
+    /// Get the balance of an [`ActorID`] as of a snapshot.
+    pub fn balance_of_at<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        id: SnapshotID,
+    ) -> Result<TokenAmount> {
+        let snapshots = self.snapshots.as_ref().ok_or(StateError::SnapshotsDisabled)?;
+        let balance = self.get_balance(bs, owner)?;
+        snapshots.balance_at(bs, self.hamt_bit_width, owner, id, balance)
+    }
+
+    /// Get the total supply as of a snapshot.
+    pub fn total_supply_at<BS: Blockstore>(&self, bs: &BS, id: SnapshotID) -> Result<TokenAmount> {
+        let snapshots = self.snapshots.as_ref().ok_or(StateError::SnapshotsDisabled)?;
+        snapshots.supply_at(bs, id, self.supply.clone())
+    }
+
+    /// Records a balance that is about to change, if snapshots are enabled.
+    fn record_balance_snapshot<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        balance: &TokenAmount,
+    ) -> Result<()> {
+        let hamt_bit_width = self.hamt_bit_width;
+        match &mut self.snapshots {
+            Some(snapshots) => snapshots.record_balance(bs, hamt_bit_width, owner, balance),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts tracking delegated voting power. Does nothing if already enabled.
+    ///
+    /// Existing balances carry no voting power until their holders delegate.
+    pub fn enable_votes<BS: Blockstore>(&mut self, bs: &BS) -> Result<()> {
+        if self.votes.is_none() {
+            self.votes = Some(VotesState::new(bs, self.hamt_bit_width)?);
+        }
+        Ok(())
+    }
+
+    /// Delegates the voting power of a holder's balance to a delegate, which may be the holder.
+    ///
+    /// Votes for the holder's current balance move from any previous delegate at the given epoch.
+    pub fn delegate<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        holder: ActorID,
+        delegate: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        let balance = self.get_balance(bs, holder)?;
+        let hamt_bit_width = self.hamt_bit_width;
+        let votes = self.votes.as_mut().ok_or(StateError::VotesDisabled)?;
+        votes.delegate(bs, hamt_bit_width, holder, delegate, &balance, epoch)
+    }
+
+    /// Get the delegate of a holder, if it has delegated.
+    pub fn get_delegate<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        holder: ActorID,
+    ) -> Result<Option<ActorID>> {
+        let votes = self.votes.as_ref().ok_or(StateError::VotesDisabled)?;
+        votes.delegate_of(bs, self.hamt_bit_width, holder)
+    }
+
+    /// Get the current voting power of a delegate.
+    pub fn get_votes<BS: Blockstore>(&self, bs: &BS, delegate: ActorID) -> Result<TokenAmount> {
+        let votes = self.votes.as_ref().ok_or(StateError::VotesDisabled)?;
+        votes.votes(bs, self.hamt_bit_width, delegate)
+    }
+
+    /// Get the voting power of a delegate at the end of an epoch.
+    pub fn get_past_votes<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        delegate: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        let votes = self.votes.as_ref().ok_or(StateError::VotesDisabled)?;
+        votes.past_votes(bs, self.hamt_bit_width, delegate, epoch)
+    }
+
+    /// Moves voting power after tokens move from one holder to another, if votes are enabled.
+    ///
+    /// A holder of None stands for tokens being minted or burned.
+    pub fn move_voting_power<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        from: Option<ActorID>,
+        to: Option<ActorID>,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        let hamt_bit_width = self.hamt_bit_width;
+        match &mut self.votes {
+            Some(votes) => votes.move_holder_votes(bs, hamt_bit_width, from, to, amount, epoch),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a vesting schedule to an account, locking the unvested part of its balance.
+    ///
+    /// The schedule's amount must be a multiple of the granularity, which vested amounts are
+    /// rounded down to. The granularity is fixed by the first schedule added.
+    pub fn add_vesting_schedule<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        schedule: VestingSchedule,
+        granularity: u64,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        let hamt_bit_width = self.hamt_bit_width;
+        let vesting = match &mut self.vesting {
+            Some(vesting) => vesting,
+            None => self.vesting.insert(VestingState::new(bs, hamt_bit_width, granularity, epoch)?),
+        };
+        vesting.add_schedule(bs, hamt_bit_width, owner, schedule, epoch)
+    }
+
+    /// Moves the epoch at which locks are enforced on debits forward, if vesting is in use.
+    ///
+    /// Debits are checked against the latest epoch given, so this should be called with the
+    /// current epoch before moving tokens.
+    pub fn advance_vesting_epoch(&mut self, epoch: ChainEpoch) {
+        if let Some(vesting) = &mut self.vesting {
+            vesting.advance_epoch(epoch);
+        }
+    }
+
+    /// Get the vesting schedules of an [`ActorID`].
+    pub fn get_vesting_schedules<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+    ) -> Result<Vec<VestingSchedule>> {
+        match &self.vesting {
+            Some(vesting) => vesting.schedules(bs, self.hamt_bit_width, owner),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Get the part of an [`ActorID`]'s balance that is locked at an epoch.
+    pub fn get_locked_balance<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        let locked = match &self.vesting {
+            Some(vesting) => vesting.locked_at(bs, self.hamt_bit_width, owner, epoch)?,
+            None => return Ok(TokenAmount::zero()),
+        };
+        Ok(locked.min(self.get_balance(bs, owner)?))
+    }
+
+    /// Checks that an account's balance still covers its locked amount at an epoch.
+    ///
+    /// Debits already enforce this as of the latest vesting epoch, see
+    /// [`TokenState::advance_vesting_epoch`].
+    pub fn check_unlocked<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        let locked = match &self.vesting {
+            Some(vesting) => vesting.locked_at(bs, self.hamt_bit_width, owner, epoch)?,
+            None => return Ok(()),
+        };
+        let balance = self.get_balance(bs, owner)?;
+        if balance < locked {
+            return Err(StateError::LockedBalance { owner, balance, locked });
+        }
+        Ok(())
+    }
+
+    /// Checks that a debit leaving `balance` doesn't dip into the account's locked amount.
+    fn check_debit<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        balance: &TokenAmount,
+    ) -> Result<()> {
+        let locked = match &self.vesting {
+            Some(vesting) => vesting.locked_at(bs, self.hamt_bit_width, owner, vesting.epoch)?,
+            None => return Ok(()),
+        };
+        if *balance < locked {
+            return Err(StateError::LockedBalance { owner, balance: balance.clone(), locked });
+        }
+        Ok(())
+    }
+
     /// Retrieve the number of token holders.
     ///
     /// This involves iterating through the entire HAMT.
@@ -315,9 +906,44 @@ impl TokenState {
         Ok(count)
     }
 
+    /// Counts the allowances stored in the [`AllowanceLayout::Nested`] layout.
+    ///
+    /// This involves iterating through every owner's allowance HAMT.
+    fn count_nested_allowances<BS: Blockstore>(&self, bs: &BS) -> Result<u64> {
+        let mut count = 0;
+        self.get_allowances_map(bs)?.for_each(|_, root| {
+            let owner_map = OwnerAllowanceMap::load_with_bit_width(root, bs, self.hamt_bit_width)?;
+            owner_map.for_each(|_, _| {
+                count += 1;
+                Ok(())
+            })?;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// List a page of at most `limit` token holders and their balances, starting from the cursor.
+    ///
+    /// Returns a cursor to the next page, or None if this is the last page.
+    pub fn list_balances<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        cursor: Option<Cursor>,
+        limit: u64,
+    ) -> Result<(AccountAmounts, Option<Cursor>)> {
+        if cursor.as_ref().is_some_and(|cursor| cursor.root != self.balances) {
+            return Err(StateError::InvalidCursor);
+        }
+        let balance_map = self.get_balance_map(bs)?;
+        let (balances, next_key) = list_map_entries(&balance_map, cursor, limit)?;
+        let next_cursor = next_key.map(|key| Cursor::new(self.balances, key));
+        Ok((balances, next_cursor))
+    }
+
     /// Increase/decrease the total supply by the specified value.
     ///
-    /// Returns the new total supply.
+    /// Fails if the new supply would be negative or exceed the maximum supply. Returns the new total
+    /// supply.
     pub fn change_supply_by(&mut self, delta: &TokenAmount) -> Result<&TokenAmount> {
         let new_supply = &self.supply + delta;
         if new_supply.is_negative() {
@@ -326,6 +952,24 @@ impl TokenState {
                 delta: delta.clone(),
             });
         }
+        if let Some(max_supply) = &self.max_supply {
+            if delta.is_positive() && new_supply > *max_supply {
+                return Err(StateError::SupplyCapExceeded {
+                    supply: new_supply,
+                    max_supply: max_supply.clone(),
+                });
+            }
+        }
+        if let Some(snapshots) = &mut self.snapshots {
+            if !delta.is_zero() {
+                snapshots.record_supply(&self.supply);
+            }
+        }
+        if delta.is_positive() {
+            self.stats.minted += delta;
+        } else {
+            self.stats.burned -= delta;
+        }
 
         self.supply = new_supply;
         Ok(&self.supply)
@@ -340,20 +984,23 @@ impl TokenState {
         owner: ActorID,
         operator: ActorID,
     ) -> Result<TokenAmount> {
-        let owner_allowances = self.get_owner_allowance_map(bs, owner)?;
-        match owner_allowances {
-            Some(map) => {
-                let maybe_allowance = map.get(&actor_id_key(operator))?;
-                if let Some(allowance) = maybe_allowance {
-                    return Ok(allowance.clone());
-                }
-                Ok(TokenAmount::zero())
-            }
-            None => Ok(TokenAmount::zero()),
-        }
+        self.get_allowance_between_in(bs, &mut LoadedMaps::new(), owner, operator)
+    }
+
+    /// Like [`TokenState::get_allowance_between`], reading through loaded maps.
+    pub(crate) fn get_allowance_between_in<'bs, BS: Blockstore>(
+        &self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+    ) -> Result<TokenAmount> {
+        Ok(maps.get_allowance(self, bs, owner, operator)?.unwrap_or_default())
     }
 
     /// Change the allowance between owner and operator by the specified delta.
+    ///
+    /// An [`unlimited_allowance`] is left unchanged.
     pub fn change_allowance_by<BS: Blockstore>(
         &mut self,
         bs: &BS,
@@ -361,97 +1008,72 @@ impl TokenState {
         operator: ActorID,
         delta: &TokenAmount,
     ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| state.change_allowance_by_in(bs, maps, owner, operator, delta))
+    }
+
+    /// Like [`TokenState::change_allowance_by`], leaving the change in the loaded maps.
+    pub(crate) fn change_allowance_by_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+        delta: &TokenAmount,
+    ) -> Result<TokenAmount> {
+        // calculate new allowance (max with zero)
+        let existing_allowance = maps.get_allowance(self, bs, owner, operator)?;
         if delta.is_zero() {
             // This is a no-op as far as mutating state
-            return self.get_allowance_between(bs, owner, operator);
+            return Ok(existing_allowance.unwrap_or_default());
         }
-
-        let mut global_allowances_map = self.get_allowances_map(bs)?;
-
-        // get or create the owner's allowance map
-        let owner_key = actor_id_key(owner);
-        let mut allowance_map = match global_allowances_map.get(&owner_key)? {
-            Some(cid) => OwnerAllowanceMap::load_with_bit_width(cid, bs, self.hamt_bit_width)?,
-            None => {
-                // the owner doesn't have any allowances, and the delta is negative, this is a no-op
-                if delta.is_negative() {
-                    return Ok(TokenAmount::zero());
-                }
-
-                // else create a new map for the owner
-                OwnerAllowanceMap::new_with_bit_width(bs, self.hamt_bit_width)
-            }
-        };
-
-        // calculate new allowance (max with zero)
-        let operator_key = actor_id_key(operator);
-        let new_allowance = match allowance_map.get(&operator_key)? {
-            Some(existing_allowance) => existing_allowance + delta,
-            None => (*delta).clone(),
+        let new_allowance = match &existing_allowance {
+            Some(existing) if is_unlimited_allowance(existing) => return Ok(existing.clone()),
+            Some(existing) => existing + delta,
+            None => delta.clone(),
         }
         .max(TokenAmount::zero());
 
         // if the new allowance is zero, we can remove the entry from the state tree
         if new_allowance.is_zero() {
-            allowance_map.delete(&operator_key)?;
-        } else {
-            allowance_map.set(operator_key, new_allowance.clone())?;
-        }
-
-        // if the owner-allowance map is empty, remove it from the global allowances map
-        if allowance_map.is_empty() {
-            global_allowances_map.delete(&owner_key)?;
+            if existing_allowance.is_some() {
+                self.write_allowance_in(bs, maps, owner, operator, None)?;
+                self.set_allowance_expiry(bs, owner, operator, None)?;
+            }
         } else {
-            // else update the global-allowance map
-            global_allowances_map.set(owner_key, allowance_map.flush()?)?;
+            self.write_allowance_in(bs, maps, owner, operator, Some(new_allowance.clone()))?;
         }
 
-        // update the state with the updated global map
-        self.allowances = global_allowances_map.flush()?;
-
         Ok(new_allowance)
     }
 
-    /// Revokes an approved allowance by removing the entry from the owner-operator map.
+    /// Revokes an approved allowance by removing its entry.
     ///
-    /// If that map becomes empty, it is removed from the root map. Returns the old allowance.
+    /// Under the nested layout, an owner's map is removed from the root map once it becomes empty.
+    /// Returns the old allowance.
     pub fn revoke_allowance<BS: Blockstore>(
         &mut self,
         bs: &BS,
         owner: ActorID,
         operator: ActorID,
     ) -> Result<TokenAmount> {
-        let allowance_map = self.get_owner_allowance_map(bs, owner)?;
-        if let Some(mut map) = allowance_map {
-            // revoke the allowance
-            let operator_key = actor_id_key(operator);
-            let old_allowance = match map.delete(&operator_key)? {
-                Some((_, amount)) => amount,
-                None => TokenAmount::zero(),
-            };
-
-            // if the allowance map has become empty it can be dropped entirely
-            let owner_key = actor_id_key(owner);
-            if map.is_empty() {
-                let mut root_allowance_map = self.get_allowances_map(bs)?;
-                root_allowance_map.delete(&owner_key)?;
-                self.allowances = root_allowance_map.flush()?;
-            } else {
-                let new_cid = map.flush()?;
-                let mut root_allowance_map = self.get_allowances_map(bs)?;
-                root_allowance_map.set(owner_key, new_cid)?;
-                self.allowances = root_allowance_map.flush()?;
-            }
+        self.with_maps(|state, maps| state.revoke_allowance_in(bs, maps, owner, operator))
+    }
 
-            Ok(old_allowance)
-        } else {
-            // no allowance map exists, there is nothing to do
-            Ok(TokenAmount::zero())
-        }
+    /// Like [`TokenState::revoke_allowance`], leaving the change in the loaded maps.
+    pub(crate) fn revoke_allowance_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+    ) -> Result<TokenAmount> {
+        let old_allowance = self.write_allowance_in(bs, maps, owner, operator, None)?;
+        self.set_allowance_expiry(bs, owner, operator, None)?;
+        Ok(old_allowance.unwrap_or_default())
     }
 
-    /// Set the allowance between owner and operator to a specific amount, returning the old
-    /// allowance.
+    /// Set the allowance between owner and operator to a specific amount that never expires,
+    /// returning the old allowance.
     pub fn set_allowance<BS: Blockstore>(
         &mut self,
         bs: &BS,
@@ -459,45 +1081,151 @@ impl TokenState {
         operator: ActorID,
         amount: &TokenAmount,
     ) -> Result<TokenAmount> {
-        if amount.is_negative() {
+        self.set_allowance_with_expiry(bs, owner, operator, amount, None)
+    }
+
+    /// Set the allowance between owner and operator to a specific amount, usable up to and
+    /// including the expiry epoch if given. Returns the old allowance.
+    ///
+    /// The amount may be the [`unlimited_allowance`] sentinel.
+    pub fn set_allowance_with_expiry<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        operator: ActorID,
+        amount: &TokenAmount,
+        expiry: Option<ChainEpoch>,
+    ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| {
+            state.set_allowance_with_expiry_in(bs, maps, owner, operator, amount, expiry)
+        })
+    }
+
+    /// Like [`TokenState::set_allowance_with_expiry`], leaving the change in the loaded maps.
+    pub(crate) fn set_allowance_with_expiry_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+        amount: &TokenAmount,
+        expiry: Option<ChainEpoch>,
+    ) -> Result<TokenAmount> {
+        if amount.is_negative() && !is_unlimited_allowance(amount) {
             return Err(StateError::NegativeAllowance { owner, operator, amount: amount.clone() });
         }
 
-        let mut root_allowances_map = self.get_allowances_map(bs)?;
+        if amount.is_zero() {
+            // zero allowance may have special handling for cleaning up
+            return self.revoke_allowance_in(bs, maps, owner, operator);
+        }
 
-        // get or create the owner's allowance map
-        let owner_key = actor_id_key(owner);
-        let mut allowance_map = match root_allowances_map.get(&owner_key)? {
-            Some(cid) => OwnerAllowanceMap::load_with_bit_width(cid, bs, self.hamt_bit_width)?,
-            None => OwnerAllowanceMap::new_with_bit_width(bs, self.hamt_bit_width),
-        };
+        let old_allowance =
+            self.write_allowance_in(bs, maps, owner, operator, Some(amount.clone()))?;
+        self.set_allowance_expiry(bs, owner, operator, expiry)?;
 
-        // determine the existing allowance
-        let operator_key = actor_id_key(operator);
-        let old_allowance = match allowance_map.get(&operator_key)? {
-            Some(a) => a.clone(),
-            None => TokenAmount::zero(),
-        };
+        Ok(old_allowance.unwrap_or_default())
+    }
 
-        if amount.is_zero() {
-            // zero allowance may have special handling for cleaning up
-            self.revoke_allowance(bs, owner, operator)?;
-            return Ok(old_allowance);
+    /// Moves all allowances to the given layout, e.g. when upgrading an actor.
+    ///
+    /// Every allowance is rewritten, so the cost grows with the number of allowances. Does nothing
+    /// if the state already uses the layout.
+    pub fn migrate_allowances<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        layout: AllowanceLayout,
+    ) -> Result<()> {
+        if layout == self.allowance_layout {
+            return Ok(());
         }
 
-        // set the new allowance
-        allowance_map.set(operator_key, amount.clone())?;
-        // update the root map
-        root_allowances_map.set(owner_key, allowance_map.flush()?)?;
-        // update the state with the updated global map
-        self.allowances = root_allowances_map.flush()?;
+        // collect every allowance, grouped by owner
+        let mut allowances: BTreeMap<ActorID, Vec<(ActorID, TokenAmount)>> = BTreeMap::new();
+        match self.allowance_layout {
+            AllowanceLayout::Nested => {
+                let mut owner_maps = vec![];
+                self.get_allowances_map(bs)?.for_each(|owner_key, cid| {
+                    owner_maps.push((owner_key.clone(), *cid));
+                    Ok(())
+                })?;
+                for (owner_key, cid) in owner_maps {
+                    let owner = decode_actor_id(&owner_key).ok_or_else(invalid_key)?;
+                    let owner_map =
+                        OwnerAllowanceMap::load_with_bit_width(&cid, bs, self.hamt_bit_width)?;
+                    let (entries, _) = list_map_entries(&owner_map, None, u64::MAX)?;
+                    allowances.insert(owner, entries);
+                }
+            }
+            AllowanceLayout::Flat => {
+                let mut entries = vec![];
+                self.get_flat_allowance_map(bs)?.for_each(|key, allowance| {
+                    entries.push((key.clone(), allowance.clone()));
+                    Ok(())
+                })?;
+                for (key, allowance) in entries {
+                    let (owner, operator) = decode_allowance_key(&key).ok_or_else(invalid_key)?;
+                    allowances.entry(owner).or_default().push((operator, allowance));
+                }
+            }
+        }
 
+        // write them out in the new layout
+        self.allowances = match layout {
+            AllowanceLayout::Nested => {
+                let mut root_map = AllowanceMap::new_with_bit_width(bs, self.hamt_bit_width);
+                for (owner, entries) in allowances {
+                    let mut owner_map =
+                        OwnerAllowanceMap::new_with_bit_width(bs, self.hamt_bit_width);
+                    for (operator, allowance) in entries {
+                        owner_map.set(actor_id_key(operator), allowance)?;
+                    }
+                    root_map.set(actor_id_key(owner), owner_map.flush()?)?;
+                }
+                root_map.flush()?
+            }
+            AllowanceLayout::Flat => {
+                let mut flat_map = FlatAllowanceMap::new_with_bit_width(bs, self.hamt_bit_width);
+                for (owner, entries) in allowances {
+                    for (operator, allowance) in entries {
+                        flat_map.set(allowance_key(owner, operator), allowance)?;
+                    }
+                }
+                flat_map.flush()?
+            }
+        };
+        self.allowance_layout = layout;
+        Ok(())
+    }
+
+    /// Stores the allowance between owner and operator in the loaded maps, or removes it if None,
+    /// returning the previously stored allowance.
+    fn write_allowance_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+        allowance: Option<TokenAmount>,
+    ) -> Result<Option<TokenAmount>> {
+        let storing = allowance.is_some();
+        let old_allowance = maps.set_allowance(self, bs, owner, operator, allowance)?;
+
+        match (old_allowance.is_some(), storing) {
+            (false, true) => self.stats.allowances += 1,
+            (true, false) => self.stats.allowances = self.stats.allowances.saturating_sub(1),
+            _ => {}
+        }
         Ok(old_allowance)
     }
 
     /// Atomically checks if value is less than the allowance and deducts it if so.
     ///
     /// Returns new allowance if successful, else returns an error and the allowance is unchanged.
+    /// Without an epoch to measure against, an allowance with an expiry is treated as expired.
+    #[deprecated(
+        note = "use `attempt_use_allowance_at`, which can spend allowances with an expiry"
+    )]
     pub fn attempt_use_allowance<BS: Blockstore>(
         &mut self,
         bs: &BS,
@@ -505,7 +1233,41 @@ impl TokenState {
         owner: u64,
         amount: &TokenAmount,
     ) -> Result<TokenAmount> {
-        let current_allowance = self.get_allowance_between(bs, owner, operator)?;
+        self.attempt_use_allowance_at(bs, operator, owner, amount, ChainEpoch::MAX)
+    }
+
+    /// Atomically checks if value is less than the allowance at an epoch and deducts it if so.
+    ///
+    /// An allowance that has expired by the given epoch is treated as zero, and an
+    /// [`unlimited_allowance`] covers any amount without being written. Returns new allowance if
+    /// successful, else returns an error and the allowance is unchanged.
+    pub fn attempt_use_allowance_at<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        operator: u64,
+        owner: u64,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        self.with_maps(|state, maps| {
+            state.attempt_use_allowance_at_in(bs, maps, operator, owner, amount, epoch)
+        })
+    }
+
+    /// Like [`TokenState::attempt_use_allowance_at`], leaving the change in the loaded maps.
+    pub(crate) fn attempt_use_allowance_at_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        operator: u64,
+        owner: u64,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        let current_allowance = self.get_allowance_at_in(bs, maps, owner, operator, epoch)?;
+        if is_unlimited_allowance(&current_allowance) {
+            return Ok(current_allowance);
+        }
 
         // defensive check for operator != owner, really allowance should never be checked here
         if (current_allowance.is_zero() && operator != owner) || current_allowance.lt(amount) {
@@ -517,14 +1279,244 @@ impl TokenState {
             });
         }
 
-        if amount.is_zero() {
-            return Ok(current_allowance);
+        if amount.is_zero() {
+            return Ok(current_allowance);
+        }
+
+        // let new_allowance = current_allowance - amount;
+        let new_allowance =
+            self.change_allowance_by_in(bs, maps, owner, operator, &amount.neg())?;
+
+        Ok(new_allowance)
+    }
+
+    /// List a page of at most `limit` of an owner's operators and the allowances stored for them,
+    /// starting from the cursor.
+    ///
+    /// Allowances are listed as stored, so expired allowances that haven't been pruned are
+    /// included. Returns a cursor to the next page, or None if this is the last page.
+    pub fn list_allowances_by_owner<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        cursor: Option<Cursor>,
+        limit: u64,
+    ) -> Result<(AccountAmounts, Option<Cursor>)> {
+        if self.allowance_layout == AllowanceLayout::Flat {
+            return self.list_flat_allowances_by_owner(bs, owner, cursor, limit);
+        }
+
+        let root = self.get_allowances_map(bs)?.get(&actor_id_key(owner))?.copied();
+        let root = match (root, &cursor) {
+            (Some(root), Some(cursor)) if cursor.root != root => {
+                return Err(StateError::InvalidCursor)
+            }
+            (None, Some(_)) => return Err(StateError::InvalidCursor),
+            (None, None) => return Ok((vec![], None)),
+            (Some(root), _) => root,
+        };
+        let allowance_map = OwnerAllowanceMap::load_with_bit_width(&root, bs, self.hamt_bit_width)?;
+        let (allowances, next_key) = list_map_entries(&allowance_map, cursor, limit)?;
+        let next_cursor = next_key.map(|key| Cursor::new(root, key));
+        Ok((allowances, next_cursor))
+    }
+
+    /// Under the flat layout, pages are taken from the allowances of all owners and filtered to the
+    /// owner's, so a page may be short or even empty while there are more to come.
+    fn list_flat_allowances_by_owner<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        cursor: Option<Cursor>,
+        limit: u64,
+    ) -> Result<(AccountAmounts, Option<Cursor>)> {
+        if cursor.as_ref().is_some_and(|cursor| cursor.root != self.allowances) {
+            return Err(StateError::InvalidCursor);
+        }
+        let flat_map = self.get_flat_allowance_map(bs)?;
+        let mut allowances = vec![];
+        let (_, next_key) = flat_map.for_each_ranged(
+            cursor.as_ref().map(|cursor| &cursor.key),
+            Some(limit as usize),
+            |key, allowance| {
+                if let Some((entry_owner, operator)) = decode_allowance_key(key) {
+                    if entry_owner == owner {
+                        allowances.push((operator, allowance.clone()));
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        let next_cursor = next_key.map(|key| Cursor::new(self.allowances, key));
+        Ok((allowances, next_cursor))
+    }
+
+    /// List a page of at most `limit` operators an owner has stored allowances for, starting from
+    /// the cursor.
+    ///
+    /// Returns a cursor to the next page, or None if this is the last page.
+    pub fn list_operators_for_owner<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        cursor: Option<Cursor>,
+        limit: u64,
+    ) -> Result<(Vec<ActorID>, Option<Cursor>)> {
+        let (allowances, next_cursor) = self.list_allowances_by_owner(bs, owner, cursor, limit)?;
+        Ok((allowances.into_iter().map(|(operator, _)| operator).collect(), next_cursor))
+    }
+
+    /// Get the allowance that an owner has approved for an operator, as usable at an epoch.
+    ///
+    /// Expired allowances are zero, even if they haven't been pruned yet.
+    pub fn get_allowance_at<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        operator: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        self.get_allowance_at_in(bs, &mut LoadedMaps::new(), owner, operator, epoch)
+    }
+
+    /// Like [`TokenState::get_allowance_at`], reading through loaded maps.
+    pub(crate) fn get_allowance_at_in<'bs, BS: Blockstore>(
+        &self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        match self.get_allowance_expiry(bs, owner, operator)? {
+            Some(expiry) if epoch > expiry => Ok(TokenAmount::zero()),
+            _ => self.get_allowance_between_in(bs, maps, owner, operator),
+        }
+    }
+
+    /// Get the last epoch at which an allowance can be used, or None if it never expires.
+    pub fn get_allowance_expiry<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        operator: ActorID,
+    ) -> Result<Option<ChainEpoch>> {
+        // most allowances never expire, so there is usually no map to look in
+        if self.allowance_expiries.is_none() {
+            return Ok(None);
+        }
+        let expiry_map = self.get_expiry_map(bs)?;
+        Ok(expiry_map.get(&allowance_key(owner, operator))?.copied())
+    }
+
+    /// Revokes the allowance between owner and operator if it has expired by the given epoch.
+    ///
+    /// Returns whether it was revoked.
+    pub fn prune_expired_allowance<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        operator: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<bool> {
+        self.with_maps(|state, maps| {
+            state.prune_expired_allowance_in(bs, maps, owner, operator, epoch)
+        })
+    }
+
+    /// Like [`TokenState::prune_expired_allowance`], leaving the change in the loaded maps.
+    pub(crate) fn prune_expired_allowance_in<'bs, BS: Blockstore>(
+        &mut self,
+        bs: &'bs BS,
+        maps: &mut LoadedMaps<'bs, BS>,
+        owner: ActorID,
+        operator: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<bool> {
+        match self.get_allowance_expiry(bs, owner, operator)? {
+            Some(expiry) if epoch > expiry => {
+                self.revoke_allowance_in(bs, maps, owner, operator)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Revokes all of an owner's allowances that have expired by the given epoch.
+    ///
+    /// Returns the number of allowances revoked.
+    pub fn prune_expired_allowances<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<usize> {
+        let mut operators = Vec::new();
+        match self.allowance_layout {
+            AllowanceLayout::Nested => {
+                if let Some(allowance_map) = self.get_owner_allowance_map(bs, owner)? {
+                    allowance_map.for_each(|operator, _| {
+                        operators.extend(decode_actor_id(operator));
+                        Ok(())
+                    })?;
+                }
+            }
+            AllowanceLayout::Flat => {
+                self.get_flat_allowance_map(bs)?.for_each(|key, _| {
+                    match decode_allowance_key(key) {
+                        Some((entry_owner, operator)) if entry_owner == owner => {
+                            operators.push(operator)
+                        }
+                        _ => {}
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        let mut pruned = 0;
+        for operator in operators {
+            if self.prune_expired_allowance(bs, owner, operator, epoch)? {
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Sets or clears the expiry of an allowance.
+    fn set_allowance_expiry<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        operator: ActorID,
+        expiry: Option<ChainEpoch>,
+    ) -> Result<()> {
+        if expiry.is_none() && self.allowance_expiries.is_none() {
+            return Ok(());
+        }
+        let mut expiry_map = self.get_expiry_map(bs)?;
+        let key = allowance_key(owner, operator);
+        match expiry {
+            Some(expiry) => {
+                expiry_map.set(key, expiry)?;
+            }
+            None => {
+                if expiry_map.delete(&key)?.is_none() {
+                    return Ok(());
+                }
+            }
+        }
+        self.allowance_expiries =
+            if expiry_map.is_empty() { None } else { Some(expiry_map.flush()?) };
+        Ok(())
+    }
+
+    /// Get the allowance expiry map as a HAMT, which is empty if no allowance expires.
+    fn get_expiry_map<'bs, BS: Blockstore>(&self, bs: &'bs BS) -> Result<ExpiryMap<'bs, BS>> {
+        match &self.allowance_expiries {
+            Some(root) => Ok(ExpiryMap::load_with_bit_width(root, bs, self.hamt_bit_width)?),
+            None => Ok(ExpiryMap::new_with_bit_width(bs, self.hamt_bit_width)),
         }
-
-        // let new_allowance = current_allowance - amount;
-        let new_allowance = self.change_allowance_by(bs, owner, operator, &amount.neg())?;
-
-        Ok(new_allowance)
     }
 
     /// Get the allowances map of a specific actor, resolving the CID link to a Hamt.
@@ -533,7 +1525,7 @@ impl TokenState {
     ///
     /// - `Ok(Some)` if the owner has allocated allowances to other actors..
     /// - `Ok(None)` if the owner has no current non-zero allowances to other actors.
-    /// - `Err` if operations on the underlying Hamt failed.
+    /// - `Err` if operations on the underlying Hamt failed, or the state uses the flat layout.
     pub fn get_owner_allowance_map<'bs, BS: Blockstore>(
         &self,
         bs: &'bs BS,
@@ -551,22 +1543,83 @@ impl TokenState {
 
     /// Get the global allowances map.
     ///
-    /// Gets a HAMT with CIDs linking to other HAMTs.
+    /// Gets a HAMT with CIDs linking to other HAMTs. Fails unless the state uses the nested layout.
     pub fn get_allowances_map<'bs, BS: Blockstore>(
         &self,
         bs: &'bs BS,
     ) -> Result<AllowanceMap<'bs, BS>> {
+        self.require_allowance_layout(AllowanceLayout::Nested)?;
         Ok(AllowanceMap::load_with_bit_width(&self.allowances, bs, self.hamt_bit_width)?)
     }
+
+    /// Get the allowances map keyed by [`allowance_key`]. Fails unless the state uses the flat
+    /// layout.
+    pub fn get_flat_allowance_map<'bs, BS: Blockstore>(
+        &self,
+        bs: &'bs BS,
+    ) -> Result<FlatAllowanceMap<'bs, BS>> {
+        self.require_allowance_layout(AllowanceLayout::Flat)?;
+        Ok(FlatAllowanceMap::load_with_bit_width(&self.allowances, bs, self.hamt_bit_width)?)
+    }
+
+    fn require_allowance_layout(&self, expected: AllowanceLayout) -> Result<()> {
+        if self.allowance_layout != expected {
+            return Err(StateError::WrongAllowanceLayout {
+                expected,
+                actual: self.allowance_layout,
+            });
+        }
+        Ok(())
+    }
+
+    /// Get the next permit nonce expected from an owner.
+    pub fn get_permit_nonce<BS: Blockstore>(&self, bs: &BS, owner: ActorID) -> Result<u64> {
+        let nonce_map = self.get_nonce_map(bs)?;
+        Ok(nonce_map.get(&actor_id_key(owner))?.copied().unwrap_or_default())
+    }
+
+    /// Consumes a permit nonce for an owner, returning the next nonce.
+    ///
+    /// Nonces must be used in sequence, so each permit can only be used once.
+    pub fn use_permit_nonce<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        owner: ActorID,
+        nonce: u64,
+    ) -> Result<u64> {
+        let mut nonce_map = self.get_nonce_map(bs)?;
+        let owner_key = actor_id_key(owner);
+        let expected = nonce_map.get(&owner_key)?.copied().unwrap_or_default();
+        if nonce != expected {
+            return Err(StateError::InvalidNonce { owner, expected, nonce });
+        }
+
+        let next = expected + 1;
+        nonce_map.set(owner_key, next)?;
+        self.permit_nonces = Some(nonce_map.flush()?);
+        Ok(next)
+    }
+
+    /// Get the permit nonce map as a HAMT, which is empty if no permit has been used.
+    fn get_nonce_map<'bs, BS: Blockstore>(&self, bs: &'bs BS) -> Result<NonceMap<'bs, BS>> {
+        match &self.permit_nonces {
+            Some(root) => Ok(NonceMap::load_with_bit_width(root, bs, self.hamt_bit_width)?),
+            None => Ok(NonceMap::new_with_bit_width(bs, self.hamt_bit_width)),
+        }
+    }
 }
 
 impl TokenState {
     /// Checks that the current state obeys all system invariants.
     ///
     /// Checks that there are no zero balances, zero allowances or empty allowance maps explicitly
-    /// stored in the blockstore. Checks that balances, total supply, allowances are never negative.
+    /// stored in the blockstore. Checks that balances, total supply, allowances are never negative,
+    /// other than the [`unlimited_allowance`] sentinel.
     /// Checks that sum of all balances matches `total_supply`. Checks that no allowances are stored
-    /// where operator == owner. Checks that all balances are a multiple of the granularity.
+    /// where operator == owner. Checks that all balances are a multiple of the granularity. Checks that
+    /// total delegated votes never exceed the total supply. Checks that allowance expiries belong to
+    /// stored allowances. Checks that the [`TokenStats`] agree with the balances, allowances and
+    /// total supply.
     ///
     /// Returns a state summary that can be used to check application specific invariants and a list
     /// of errors that were found.
@@ -582,6 +1635,35 @@ impl TokenState {
         if self.supply.is_negative() {
             errors.push(StateInvariantError::SupplyNegative(self.supply.clone()));
         }
+        if let Some(max_supply) = &self.max_supply {
+            if self.supply > *max_supply {
+                errors.push(StateInvariantError::SupplyExceedsMax {
+                    supply: self.supply.clone(),
+                    max_supply: max_supply.clone(),
+                });
+            }
+        }
+
+        // check the fee schedule
+        if let Some(fees) = &self.fees {
+            if !fees.is_valid() {
+                errors.push(StateInvariantError::InvalidFeeSchedule(Box::new(fees.clone())));
+            }
+        }
+
+        // check delegated votes
+        if let Some(votes) = &self.votes {
+            match votes.total_votes(bs, self.hamt_bit_width) {
+                Ok(total) if total > self.supply => {
+                    errors.push(StateInvariantError::VotesExceedSupply {
+                        votes: total,
+                        supply: self.supply.clone(),
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => errors.push(StateInvariantError::State(e)),
+            }
+        }
 
         // check balances
         let balance_summary = match self.get_balance_map(bs) {
@@ -597,9 +1679,16 @@ impl TokenState {
         };
 
         // check allowances
-        let allowance_summary = match self.get_allowances_map(bs) {
-            Ok(hamt) => {
-                let (allowance_summary, mut allowance_errors) = self.check_allowances(bs, hamt);
+        let checked_allowances = match self.allowance_layout {
+            AllowanceLayout::Nested => {
+                self.get_allowances_map(bs).map(|hamt| self.check_allowances(bs, hamt))
+            }
+            AllowanceLayout::Flat => {
+                self.get_flat_allowance_map(bs).map(|hamt| self.check_flat_allowances(hamt))
+            }
+        };
+        let allowance_summary = match checked_allowances {
+            Ok((allowance_summary, mut allowance_errors)) => {
                 errors.append(&mut allowance_errors);
                 Some(allowance_summary)
             }
@@ -609,6 +1698,64 @@ impl TokenState {
             }
         };
 
+        // check allowance expiries, which may outlive their expiry but not their allowance
+        if let Some(allowance_summary) = &allowance_summary {
+            match self.get_expiry_map(bs) {
+                Ok(hamt) => {
+                    hamt.for_each(|key, _| {
+                        match decode_allowance_key(key) {
+                            Some((owner, operator)) => {
+                                let allowance = allowance_summary
+                                    .get(&owner)
+                                    .and_then(|allowances| allowances.get(&operator));
+                                if allowance.is_none() {
+                                    errors.push(StateInvariantError::OrphanedAllowanceExpiry {
+                                        owner,
+                                        operator,
+                                    });
+                                }
+                            }
+                            None => errors.push(StateInvariantError::InvalidBytesKey(key.clone())),
+                        }
+                        Ok(())
+                    })
+                    .unwrap();
+                }
+                Err(e) => errors.push(StateInvariantError::State(e)),
+            }
+        }
+
+        // check the stats against the recorded state
+        if self.supply != &self.stats.minted - &self.stats.burned {
+            errors.push(StateInvariantError::MintedBurnedMismatch {
+                supply: self.supply.clone(),
+                minted: self.stats.minted.clone(),
+                burned: self.stats.burned.clone(),
+            });
+        }
+        if let Some(balance_summary) = &balance_summary {
+            let counted = balance_summary.values().filter(|b| b.is_positive()).count() as u64;
+            if counted != self.stats.holders {
+                errors.push(StateInvariantError::HolderCountMismatch {
+                    holders: self.stats.holders,
+                    counted,
+                });
+            }
+        }
+        if let Some(allowance_summary) = &allowance_summary {
+            let counted = allowance_summary
+                .values()
+                .flat_map(|allowances| allowances.values())
+                .filter(|a| a.is_positive() || is_unlimited_allowance(a))
+                .count() as u64;
+            if counted != self.stats.allowances {
+                errors.push(StateInvariantError::AllowanceCountMismatch {
+                    allowances: self.stats.allowances,
+                    counted,
+                });
+            }
+        }
+
         (
             StateSummary {
                 balance_map: balance_summary,
@@ -658,31 +1805,7 @@ impl TokenState {
                             allowance_map.for_each(|operator, allowance| {
                                 if let Some(operator) = Self::decode_key_addr(operator, &mut errors)
                                 {
-                                    // check there's no stored self-stored allowance
-                                    if owner == operator {
-                                        errors.push(StateInvariantError::ExplicitSelfAllowance {
-                                            account: owner,
-                                            allowance: allowance.clone(),
-                                        });
-                                    }
-
-                                    // check the allowance isn't negative
-                                    if allowance.is_negative() {
-                                        errors.push(StateInvariantError::NegativeAllowance {
-                                            owner,
-                                            operator,
-                                            allowance: allowance.clone(),
-                                        });
-                                    }
-
-                                    // check there's no explicit zero allowance
-                                    if allowance.is_zero() {
-                                        errors.push(StateInvariantError::ExplicitZeroAllowance {
-                                            owner,
-                                            operator,
-                                        });
-                                    }
-
+                                    Self::check_allowance(owner, operator, allowance, &mut errors);
                                     allowances_map.insert(operator, allowance.clone());
                                 }
 
@@ -700,6 +1823,64 @@ impl TokenState {
         (allowance_summary, errors)
     }
 
+    /// Checks a flat allowance Hamt for any consistency errors.
+    ///
+    /// Returns a summary of the allowances and a list of errors.
+    fn check_flat_allowances<BS: Blockstore>(
+        &self,
+        allowances_hamt: FlatAllowanceMap<BS>,
+    ) -> (HashMap<u64, HashMap<u64, TokenAmount>>, Vec<StateInvariantError>) {
+        let mut errors: Vec<StateInvariantError> = vec![];
+        let mut allowance_summary: HashMap<ActorID, HashMap<ActorID, TokenAmount>> = HashMap::new();
+
+        allowances_hamt
+            .for_each(|key, allowance| {
+                match decode_allowance_key(key) {
+                    Some((owner, operator)) => {
+                        Self::check_allowance(owner, operator, allowance, &mut errors);
+                        allowance_summary
+                            .entry(owner)
+                            .or_default()
+                            .insert(operator, allowance.clone());
+                    }
+                    None => errors.push(StateInvariantError::InvalidBytesKey(key.clone())),
+                }
+                Ok(())
+            })
+            .unwrap();
+        (allowance_summary, errors)
+    }
+
+    /// Checks a single stored allowance, recording any errors.
+    fn check_allowance(
+        owner: ActorID,
+        operator: ActorID,
+        allowance: &TokenAmount,
+        errors: &mut Vec<StateInvariantError>,
+    ) {
+        // check there's no stored self-stored allowance
+        if owner == operator {
+            errors.push(StateInvariantError::ExplicitSelfAllowance {
+                account: owner,
+                allowance: allowance.clone(),
+            });
+        }
+
+        // check the allowance isn't negative, other than the sentinel
+        if allowance.is_negative() && !is_unlimited_allowance(allowance) {
+            errors.push(StateInvariantError::NegativeAllowance {
+                owner,
+                operator,
+                allowance: allowance.clone(),
+            });
+        }
+
+        // check there's no explicit zero allowance
+        if allowance.is_zero() {
+            errors.push(StateInvariantError::ExplicitZeroAllowance { owner, operator });
+        }
+    }
+
     /// Checks a balance Hamt for any consistency errors.
     ///
     /// Returns a summary of the balances and a list of errors.
@@ -770,10 +1951,271 @@ impl TokenState {
     }
 }
 
+/// The balance and allowance Hamts of a [`TokenState`], loaded on first use and kept in memory
+/// across operations.
+///
+/// Most [`TokenState`] methods load these Hamts from their roots and flush them after every change.
+/// Their `_in` variants, used by [`Token`](crate::token::Token), go through a `LoadedMaps` instead,
+/// so a sequence of operations loads and flushes each Hamt once. [`TokenState::balances`] and
+/// [`TokenState::allowances`] are stale until [`TokenState::flush_maps`]. Changes since the last
+/// [`LoadedMaps::checkpoint`] can be undone with [`LoadedMaps::rollback`].
+pub(crate) struct LoadedMaps<'bs, BS: Blockstore> {
+    balances: Option<BalanceMap<'bs, BS>>,
+    allowances: Option<LoadedAllowances<'bs, BS>>,
+    /// Previous values of the entries changed since the last checkpoint, oldest first.
+    changes: Vec<MapChange>,
+}
+
+/// The allowances Hamt in the [`AllowanceLayout`] of the state it was loaded from.
+enum LoadedAllowances<'bs, BS: Blockstore> {
+    Nested(AllowanceMap<'bs, BS>),
+    Flat(FlatAllowanceMap<'bs, BS>),
+}
+
+/// The previous value of an entry changed through [`LoadedMaps`].
+enum MapChange {
+    Balance(BytesKey, Option<TokenAmount>),
+    OwnerAllowances(BytesKey, Option<Cid>),
+    FlatAllowance(BytesKey, Option<TokenAmount>),
+}
+
+impl<'bs, BS: Blockstore> LoadedMaps<'bs, BS> {
+    pub(crate) fn new() -> Self {
+        Self { balances: None, allowances: None, changes: Vec::new() }
+    }
+
+    /// Returns whether either Hamt has been loaded.
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.balances.is_some() || self.allowances.is_some()
+    }
+
+    /// Keeps the changes made so far, so that a later rollback leaves them in place.
+    pub(crate) fn checkpoint(&mut self) {
+        self.changes.clear();
+    }
+
+    /// Undoes the changes made since the last checkpoint.
+    pub(crate) fn rollback(&mut self) -> Result<()> {
+        while let Some(change) = self.changes.pop() {
+            match (change, &mut self.balances, &mut self.allowances) {
+                (MapChange::Balance(key, old), Some(balances), _) => {
+                    restore_entry(balances, key, old)?
+                }
+                (
+                    MapChange::OwnerAllowances(key, old),
+                    _,
+                    Some(LoadedAllowances::Nested(allowances)),
+                ) => restore_entry(allowances, key, old)?,
+                (
+                    MapChange::FlatAllowance(key, old),
+                    _,
+                    Some(LoadedAllowances::Flat(allowances)),
+                ) => restore_entry(allowances, key, old)?,
+                // changes are only recorded against loaded maps
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn balances(&mut self, state: &TokenState, bs: &'bs BS) -> Result<&mut BalanceMap<'bs, BS>> {
+        let balances = match self.balances.take() {
+            Some(balances) => balances,
+            None => state.get_balance_map(bs)?,
+        };
+        Ok(self.balances.insert(balances))
+    }
+
+    fn allowances(
+        &mut self,
+        state: &TokenState,
+        bs: &'bs BS,
+    ) -> Result<&mut LoadedAllowances<'bs, BS>> {
+        let allowances = match self.allowances.take() {
+            Some(allowances) => allowances,
+            None => match state.allowance_layout {
+                AllowanceLayout::Nested => LoadedAllowances::Nested(state.get_allowances_map(bs)?),
+                AllowanceLayout::Flat => LoadedAllowances::Flat(state.get_flat_allowance_map(bs)?),
+            },
+        };
+        Ok(self.allowances.insert(allowances))
+    }
+
+    fn get_balance(
+        &mut self,
+        state: &TokenState,
+        bs: &'bs BS,
+        owner: ActorID,
+    ) -> Result<TokenAmount> {
+        let balance = self.balances(state, bs)?.get(&actor_id_key(owner))?;
+        Ok(balance.cloned().unwrap_or_default())
+    }
+
+    /// Stores a balance, removing the entry if it is zero.
+    fn set_balance(
+        &mut self,
+        state: &TokenState,
+        bs: &'bs BS,
+        owner: ActorID,
+        balance: &TokenAmount,
+    ) -> Result<()> {
+        let key = actor_id_key(owner);
+        let balances = self.balances(state, bs)?;
+        let old = match balance.is_zero() {
+            true => balances.delete(&key)?.map(|(_, old)| old),
+            false => balances.set(key.clone(), balance.clone())?,
+        };
+        self.changes.push(MapChange::Balance(key, old));
+        Ok(())
+    }
+
+    fn get_allowance(
+        &mut self,
+        state: &TokenState,
+        bs: &'bs BS,
+        owner: ActorID,
+        operator: ActorID,
+    ) -> Result<Option<TokenAmount>> {
+        let hamt_bit_width = state.hamt_bit_width;
+        match self.allowances(state, bs)? {
+            LoadedAllowances::Nested(allowances) => match allowances.get(&actor_id_key(owner))? {
+                Some(root) => {
+                    let owner_map =
+                        OwnerAllowanceMap::load_with_bit_width(root, bs, hamt_bit_width)?;
+                    Ok(owner_map.get(&actor_id_key(operator))?.cloned())
+                }
+                None => Ok(None),
+            },
+            LoadedAllowances::Flat(allowances) => {
+                Ok(allowances.get(&allowance_key(owner, operator))?.cloned())
+            }
+        }
+    }
+
+    /// Stores the allowance between owner and operator, or removes it if None, returning the
+    /// previously stored allowance.
+    ///
+    /// Under the nested layout, the owner's Hamt is flushed and an empty one is removed.
+    fn set_allowance(
+        &mut self,
+        state: &TokenState,
+        bs: &'bs BS,
+        owner: ActorID,
+        operator: ActorID,
+        allowance: Option<TokenAmount>,
+    ) -> Result<Option<TokenAmount>> {
+        let hamt_bit_width = state.hamt_bit_width;
+        let storing = allowance.is_some();
+        let (change, old_allowance) = match self.allowances(state, bs)? {
+            LoadedAllowances::Nested(allowances) => {
+                // get or create the owner's allowance map
+                let owner_key = actor_id_key(owner);
+                let old_root = allowances.get(&owner_key)?.copied();
+                let mut owner_map = match &old_root {
+                    Some(root) => OwnerAllowanceMap::load_with_bit_width(root, bs, hamt_bit_width)?,
+                    // the owner doesn't have any allowances, so there is nothing to remove
+                    None if !storing => return Ok(None),
+                    None => OwnerAllowanceMap::new_with_bit_width(bs, hamt_bit_width),
+                };
+
+                let operator_key = actor_id_key(operator);
+                let old_allowance = match allowance {
+                    Some(allowance) => owner_map.set(operator_key, allowance)?,
+                    None => owner_map.delete(&operator_key)?.map(|(_, old)| old),
+                };
+
+                // if the owner's map is empty, remove it from the root map
+                if owner_map.is_empty() {
+                    allowances.delete(&owner_key)?;
+                } else {
+                    allowances.set(owner_key.clone(), owner_map.flush()?)?;
+                }
+                (MapChange::OwnerAllowances(owner_key, old_root), old_allowance)
+            }
+            LoadedAllowances::Flat(allowances) => {
+                let key = allowance_key(owner, operator);
+                let old_allowance = match allowance {
+                    Some(allowance) => allowances.set(key.clone(), allowance)?,
+                    None => allowances.delete(&key)?.map(|(_, old)| old),
+                };
+                (MapChange::FlatAllowance(key, old_allowance.clone()), old_allowance)
+            }
+        };
+        self.changes.push(change);
+        Ok(old_allowance)
+    }
+}
+
+/// Sets an entry back to a previous value, removing it if there was none.
+fn restore_entry<BS, V>(
+    map: &mut Map<BS, BytesKey, V>,
+    key: BytesKey,
+    value: Option<V>,
+) -> Result<()>
+where
+    BS: Blockstore,
+    V: Serialize + DeserializeOwned + PartialEq,
+{
+    match value {
+        Some(value) => {
+            map.set(key, value)?;
+        }
+        None => {
+            map.delete(&key)?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists a page of at most `limit` entries of a Hamt keyed by actor ID, starting from the cursor.
+///
+/// Returns the key of the first entry of the next page, if any.
+fn list_map_entries<BS: Blockstore>(
+    map: &Map<BS, BytesKey, TokenAmount>,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> Result<(AccountAmounts, Option<BytesKey>)> {
+    let mut entries = Vec::new();
+    let mut invalid_key = false;
+    let (_, next_key) = map.for_each_ranged(
+        cursor.as_ref().map(|cursor| &cursor.key),
+        Some(limit as usize),
+        |key, amount| {
+            match decode_actor_id(key) {
+                Some(id) => entries.push((id, amount.clone())),
+                None => invalid_key = true,
+            }
+            Ok(())
+        },
+    )?;
+    if invalid_key {
+        return Err(self::invalid_key());
+    }
+    Ok((entries, next_key))
+}
+
+fn invalid_key() -> StateError {
+    StateError::Serialization("invalid actor id key".into())
+}
+
 pub fn actor_id_key(a: ActorID) -> BytesKey {
     a.encode_var_vec().into()
 }
 
+/// Key for an owner-operator pair, the owner's varint followed by the operator's.
+pub fn allowance_key(owner: ActorID, operator: ActorID) -> BytesKey {
+    let mut key = owner.encode_var_vec();
+    key.extend(operator.encode_var_vec());
+    key.into()
+}
+
+/// Decodes an [`allowance_key`] into the owner and operator.
+pub fn decode_allowance_key(key: &BytesKey) -> Option<(ActorID, ActorID)> {
+    let (owner, len) = u64::decode_var(key.0.as_slice())?;
+    let (operator, rest) = u64::decode_var(&key.0[len..])?;
+    (len + rest == key.0.len()).then_some((owner, operator))
+}
+
 pub fn decode_actor_id(key: &BytesKey) -> Option<ActorID> {
     u64::decode_var(key.0.as_slice()).map(|a| a.0)
 }
@@ -789,13 +2231,18 @@ pub struct StateSummary {
 #[cfg(test)]
 mod test {
     use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
-    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_ipld_encoding::{CborStore, DAG_CBOR};
     use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
     use fvm_shared::{bigint::Zero, ActorID};
     use multihash_codetable::Code;
 
-    use super::TokenState;
+    use super::{
+        allowance_key, AllowanceLayout, TokenState, TokenStats, DEFAULT_HAMT_BIT_WIDTH,
+        ERR_SUPPLY_CAP_EXCEEDED, STATE_VERSION,
+    };
     use crate::token::state::{actor_id_key, OwnerAllowanceMap, StateError, StateInvariantError};
+    use crate::token::vesting::VestingSchedule;
 
     #[test]
     fn it_instantiates() {
@@ -806,6 +2253,78 @@ mod test {
         assert_eq!(state, saved_state);
     }
 
+    #[test]
+    fn it_enforces_max_supply() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(60)).unwrap();
+
+        // the cap can't be set below the current supply
+        let err = state.set_max_supply(Some(TokenAmount::from_atto(50))).unwrap_err();
+        assert!(matches!(err, StateError::SupplyCapExceeded { .. }));
+        state.set_max_supply(Some(TokenAmount::from_atto(100))).unwrap();
+
+        state.change_supply_by(&TokenAmount::from_atto(40)).unwrap();
+        let err = state.change_supply_by(&TokenAmount::from_atto(1)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ERR_SUPPLY_CAP_EXCEEDED);
+        if let StateError::SupplyCapExceeded { supply, max_supply } = err {
+            assert_eq!(supply, TokenAmount::from_atto(101));
+            assert_eq!(max_supply, TokenAmount::from_atto(100));
+        } else {
+            panic!("unexpected error {err:?}");
+        }
+        assert_eq!(state.supply, TokenAmount::from_atto(100));
+        // supply can still decrease
+        state.change_supply_by(&TokenAmount::from_atto(-10)).unwrap();
+
+        // the cap is checked as an invariant
+        state.max_supply = Some(TokenAmount::from_atto(80));
+        let (_summary, errors) = state.check_invariants(bs, 1);
+        assert!(errors.iter().any(|e| matches!(e, StateInvariantError::SupplyExceedsMax { .. })));
+    }
+
+    #[test]
+    fn it_checks_votes_against_supply() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        state.enable_votes(bs).unwrap();
+        state.change_balance_by(bs, 1, &TokenAmount::from_atto(60)).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(60)).unwrap();
+        state.delegate(bs, 1, 2, 0).unwrap();
+        assert_eq!(state.get_votes(bs, 2).unwrap(), TokenAmount::from_atto(60));
+        let (_summary, errors) = state.check_invariants(bs, 1);
+        assert!(errors.is_empty());
+
+        // votes that weren't moved along with a burn exceed the supply
+        state.change_balance_by(bs, 1, &TokenAmount::from_atto(-10)).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(-10)).unwrap();
+        let (_summary, errors) = state.check_invariants(bs, 1);
+        assert!(errors.iter().any(|e| matches!(e, StateInvariantError::VotesExceedSupply { .. })));
+    }
+
+    #[test]
+    fn it_uses_permit_nonces_in_sequence() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        assert_eq!(state.get_permit_nonce(bs, 1).unwrap(), 0);
+
+        assert_eq!(state.use_permit_nonce(bs, 1, 0).unwrap(), 1);
+        assert_eq!(state.get_permit_nonce(bs, 1).unwrap(), 1);
+        // other owners are unaffected
+        assert_eq!(state.get_permit_nonce(bs, 2).unwrap(), 0);
+
+        // nonces cannot be replayed or skipped
+        for nonce in [0, 2] {
+            match state.use_permit_nonce(bs, 1, nonce).unwrap_err() {
+                StateError::InvalidNonce { owner: 1, expected: 1, nonce: n } => {
+                    assert_eq!(n, nonce)
+                }
+                e => panic!("unexpected error {e:?}"),
+            }
+        }
+        assert_eq!(state.get_permit_nonce(bs, 1).unwrap(), 1);
+    }
+
     #[test]
     fn it_handles_missing_data_load() {
         // try to load from an empty blockstore (and default Cid)
@@ -819,6 +2338,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn it_loads_state_written_before_versioning() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        state.change_balance_by(bs, 1, &TokenAmount::from_atto(100)).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(100)).unwrap();
+        state.set_allowance(bs, 1, 2, &TokenAmount::from_atto(10)).unwrap();
+
+        // the original layout is a tuple of supply, balances, allowances and bit width
+        let original = (&state.supply, state.balances, state.allowances, state.hamt_bit_width);
+        let cid = bs.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        let mut loaded = TokenState::load(bs, &cid).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        assert_eq!(loaded.supply, TokenAmount::from_atto(100));
+        // stats are seeded from the existing state, so the invariants hold
+        let expected = TokenStats {
+            holders: 1,
+            minted: TokenAmount::from_atto(100),
+            burned: TokenAmount::zero(),
+            allowances: 1,
+        };
+        assert_eq!(loaded.stats, expected);
+        assert!(loaded.check_invariants(bs, 1).1.is_empty());
+        assert_eq!(loaded.allowance_layout, AllowanceLayout::Nested);
+        assert_eq!(loaded.get_balance(bs, 1).unwrap(), TokenAmount::from_atto(100));
+        assert_eq!(loaded.get_allowance_between(bs, 1, 2).unwrap(), TokenAmount::from_atto(10));
+
+        // extensions start out empty and can be used straight away
+        assert_eq!(loaded.max_supply, None);
+        assert_eq!(loaded.get_permit_nonce(bs, 1).unwrap(), 0);
+        assert_eq!(loaded.use_permit_nonce(bs, 1, 0).unwrap(), 1);
+        loaded.set_allowance_with_expiry(bs, 1, 3, &TokenAmount::from_atto(5), Some(10)).unwrap();
+        assert_eq!(loaded.get_allowance_expiry(bs, 1, 3).unwrap(), Some(10));
+
+        // and the upgraded state round trips in the current layout
+        let cid = loaded.save(bs).unwrap();
+        assert_eq!(TokenState::load(bs, &cid).unwrap(), loaded);
+    }
+
     #[test]
     fn it_handles_invalid_data_load() {
         let bs = &MemoryBlockstore::new();
@@ -987,6 +2546,45 @@ mod test {
         assert_eq!(allowance_4, TokenAmount::zero());
     }
 
+    #[test]
+    fn it_lists_balances_and_allowances_in_pages() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(&bs).unwrap();
+        for holder in 1..=5 {
+            state.change_balance_by(bs, holder, &TokenAmount::from_atto(holder * 10)).unwrap();
+            state.set_allowance(bs, 1, holder + 1, &TokenAmount::from_atto(holder)).unwrap();
+        }
+
+        // pages cover every holder exactly once
+        let mut listed = vec![];
+        let mut cursor = None;
+        loop {
+            let (page, next_cursor) = state.list_balances(bs, cursor, 2).unwrap();
+            assert!(page.len() <= 2);
+            listed.extend(page);
+            cursor = next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        listed.sort();
+        let expected: Vec<_> = (1..=5).map(|h| (h, TokenAmount::from_atto(h * 10))).collect();
+        assert_eq!(listed, expected);
+
+        let (operators, cursor) = state.list_operators_for_owner(bs, 1, None, 10).unwrap();
+        assert_eq!(operators.len(), 5);
+        assert!(cursor.is_none());
+        let (allowances, cursor) = state.list_allowances_by_owner(bs, 2, None, 10).unwrap();
+        assert!(allowances.is_empty() && cursor.is_none());
+
+        // cursors are invalidated by changes to the listed data
+        let (_, cursor) = state.list_balances(bs, None, 2).unwrap();
+        state.change_balance_by(bs, 6, &TokenAmount::from_atto(1)).unwrap();
+        let err = state.list_balances(bs, cursor, 2).unwrap_err();
+        assert!(matches!(err, StateError::InvalidCursor));
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+    }
+
     #[test]
     fn it_sets_allowances_between_actors() {
         let bs = &MemoryBlockstore::new();
@@ -1043,19 +2641,38 @@ mod test {
         state.change_allowance_by(bs, owner, operator, &delta).unwrap();
 
         // can consume an allowance
-        let new_allowance =
-            state.attempt_use_allowance(bs, operator, owner, &TokenAmount::from_atto(60)).unwrap();
+        let new_allowance = state
+            .attempt_use_allowance_at(bs, operator, owner, &TokenAmount::from_atto(60), 0)
+            .unwrap();
         assert_eq!(new_allowance, TokenAmount::from_atto(40));
         let new_allowance = state.get_allowance_between(bs, owner, operator).unwrap();
         assert_eq!(new_allowance, TokenAmount::from_atto(40));
 
         // cannot consume more allowance than approved
-        state.attempt_use_allowance(bs, operator, owner, &TokenAmount::from_atto(50)).unwrap_err();
+        state
+            .attempt_use_allowance_at(bs, operator, owner, &TokenAmount::from_atto(50), 0)
+            .unwrap_err();
         // allowance was unchanged
         let new_allowance = state.get_allowance_between(bs, owner, operator).unwrap();
         assert_eq!(new_allowance, TokenAmount::from_atto(40));
     }
 
+    #[test]
+    #[allow(deprecated)]
+    fn it_consumes_expiring_allowances_at_an_epoch() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        let amount = TokenAmount::from_atto;
+        state.set_allowance_with_expiry(bs, 1, 2, &amount(100), Some(10)).unwrap();
+
+        state.attempt_use_allowance_at(bs, 2, 1, &amount(40), 10).unwrap();
+        let err = state.attempt_use_allowance_at(bs, 2, 1, &amount(40), 11).unwrap_err();
+        assert!(matches!(err, StateError::InsufficientAllowance { .. }));
+        // without an epoch, an allowance with an expiry can't be used
+        state.attempt_use_allowance(bs, 2, 1, &amount(40)).unwrap_err();
+        assert_eq!(state.get_allowance_between(bs, 1, 2).unwrap(), amount(60));
+    }
+
     #[test]
     fn it_revokes_allowances() {
         let bs = &MemoryBlockstore::new();
@@ -1098,6 +2715,106 @@ mod test {
         }
     }
 
+    #[test]
+    fn it_stores_flat_allowances() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new_with_allowance_layout(
+            bs,
+            DEFAULT_HAMT_BIT_WIDTH,
+            AllowanceLayout::Flat,
+        )
+        .unwrap();
+        let amount = TokenAmount::from_atto;
+
+        state.set_allowance(bs, 1, 2, &amount(100)).unwrap();
+        state.change_allowance_by(bs, 1, 3, &amount(50)).unwrap();
+        state.change_allowance_by(bs, 4, 2, &amount(10)).unwrap();
+        state.attempt_use_allowance_at(bs, 2, 1, &amount(40), 0).unwrap();
+        assert_eq!(state.get_allowance_between(bs, 1, 2).unwrap(), amount(60));
+        assert_eq!(state.revoke_allowance(bs, 1, 3).unwrap(), amount(50));
+        assert_eq!(state.get_allowance_between(bs, 1, 3).unwrap(), amount(0));
+        assert_eq!(state.stats.allowances, 2);
+
+        // an owner's allowances are found amongst everyone's
+        let (allowances, _) = state.list_allowances_by_owner(bs, 1, None, 10).unwrap();
+        assert_eq!(allowances, vec![(2, amount(60))]);
+        let (summary, errors) = state.check_invariants(bs, 1);
+        assert!(errors.is_empty());
+        assert_eq!(summary.allowance_map.unwrap().len(), 2);
+
+        // explicit zero allowances are caught as in the nested layout
+        let mut flat_map = state.get_flat_allowance_map(bs).unwrap();
+        flat_map.set(allowance_key(5, 5), amount(0)).unwrap();
+        state.allowances = flat_map.flush().unwrap();
+        let (_summary, errors) = state.check_invariants(bs, 1);
+        assert!(matches!(
+            errors[..],
+            [
+                StateInvariantError::ExplicitSelfAllowance { account: 5, .. },
+                StateInvariantError::ExplicitZeroAllowance { owner: 5, operator: 5 }
+            ]
+        ));
+    }
+
+    #[test]
+    fn it_migrates_allowance_layouts() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        let amount = TokenAmount::from_atto;
+        for owner in 1..=3 {
+            for operator in 4..=5 {
+                state.set_allowance(bs, owner, operator, &amount(owner * operator)).unwrap();
+            }
+        }
+        let nested = state.allowances;
+
+        state.migrate_allowances(bs, AllowanceLayout::Flat).unwrap();
+        assert_eq!(state.allowance_layout, AllowanceLayout::Flat);
+        assert_eq!(state.get_allowance_between(bs, 3, 5).unwrap(), amount(15));
+        assert!(state.check_invariants(bs, 1).1.is_empty());
+
+        // migrating back restores the original allowances Hamt
+        state.migrate_allowances(bs, AllowanceLayout::Nested).unwrap();
+        assert_eq!(state.allowances, nested);
+        assert!(state.check_invariants(bs, 1).1.is_empty());
+    }
+
+    #[test]
+    fn it_migrates_allowances_of_state_written_before_versioning() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        state.set_allowance(bs, 1, 2, &TokenAmount::from_atto(10)).unwrap();
+        state.set_allowance(bs, 3, 2, &TokenAmount::from_atto(20)).unwrap();
+        let original = (&state.supply, state.balances, state.allowances, state.hamt_bit_width);
+        let cid = bs.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        let mut loaded = TokenState::load(bs, &cid).unwrap();
+        loaded.migrate_allowances(bs, AllowanceLayout::Flat).unwrap();
+        assert_eq!(loaded.get_allowance_between(bs, 3, 2).unwrap(), TokenAmount::from_atto(20));
+        assert!(loaded.check_invariants(bs, 1).1.is_empty());
+
+        // the nested accessors refuse to read the flat Hamt
+        let err = loaded.get_owner_allowance_map(bs, 1).err().unwrap();
+        assert!(matches!(
+            err,
+            StateError::WrongAllowanceLayout {
+                expected: AllowanceLayout::Nested,
+                actual: AllowanceLayout::Flat
+            }
+        ));
+        loaded.get_allowances_map(bs).err().unwrap();
+
+        // the layout is stored as an integer and survives a round trip
+        let cid = loaded.save(bs).unwrap();
+        let reloaded = TokenState::load(bs, &cid).unwrap();
+        assert_eq!(reloaded.allowance_layout, AllowanceLayout::Flat);
+        assert_eq!(fvm_ipld_encoding::to_vec(&AllowanceLayout::Flat).unwrap(), vec![0x01]);
+
+        loaded.migrate_allowances(bs, AllowanceLayout::Nested).unwrap();
+        assert_eq!(loaded.allowances, state.allowances);
+        loaded.get_flat_allowance_map(bs).err().unwrap();
+    }
+
     #[test]
     fn it_allows_variable_bit_width() {
         let bs = &MemoryBlockstore::new();
@@ -1117,6 +2834,66 @@ mod test {
         }
     }
 
+    #[test]
+    fn it_enforces_vesting_locks_on_debits() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        let amount = TokenAmount::from_atto;
+
+        state.set_balance(bs, 1, &amount(100)).unwrap();
+        let schedule = VestingSchedule::linear(amount(60), 10, 0, 10);
+        state.add_vesting_schedule(bs, 1, schedule, 1, 10).unwrap();
+
+        // transfers, self-transfers and burns can't touch the locked amount
+        let err = state.make_transfer(bs, 1, 2, &amount(41)).unwrap_err();
+        assert!(matches!(err, StateError::LockedBalance { owner: 1, .. }));
+        state.make_transfer(bs, 1, 1, &amount(41)).unwrap_err();
+        state.change_balance_by(bs, 1, &amount(-41)).unwrap_err();
+        state.make_transfer(bs, 1, 2, &amount(40)).unwrap();
+
+        // half has vested by epoch 15, and earlier epochs are ignored
+        state.advance_vesting_epoch(15);
+        state.advance_vesting_epoch(12);
+        state.change_balance_by(bs, 1, &amount(-30)).unwrap();
+        assert_eq!(state.get_balance(bs, 1).unwrap(), amount(30));
+        state.change_balance_by(bs, 1, &amount(-1)).unwrap_err();
+    }
+
+    #[test]
+    fn it_maintains_stats() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = TokenState::new(bs).unwrap();
+        let amount = TokenAmount::from_atto;
+
+        state.change_supply_by(&amount(100)).unwrap();
+        state.change_balance_by(bs, 1, &amount(60)).unwrap();
+        state.set_balance(bs, 2, &amount(40)).unwrap();
+        state.make_transfer(bs, 2, 3, &amount(40)).unwrap();
+        state.change_supply_by(&amount(-10)).unwrap();
+        state.change_balance_by(bs, 1, &amount(-10)).unwrap();
+        state.set_allowance(bs, 1, 2, &amount(5)).unwrap();
+        state.change_allowance_by(bs, 1, 3, &amount(5)).unwrap();
+        state.change_allowance_by(bs, 1, 3, &amount(5)).unwrap();
+        state.revoke_allowance(bs, 1, 2).unwrap();
+
+        let expected =
+            TokenStats { holders: 2, minted: amount(100), burned: amount(10), allowances: 1 };
+        assert_eq!(state.stats, expected);
+        assert!(state.check_invariants(bs, 1).1.is_empty());
+
+        // counters that drift from the Hamts are caught
+        state.stats.holders = 3;
+        state.stats.allowances = 0;
+        let (_summary, errors) = state.check_invariants(bs, 1);
+        assert!(matches!(
+            errors[..],
+            [
+                StateInvariantError::HolderCountMismatch { holders: 3, counted: 2 },
+                StateInvariantError::AllowanceCountMismatch { allowances: 0, counted: 1 }
+            ]
+        ));
+    }
+
     #[test]
     fn check_invariants_accumulates_errors() {
         let bs = &MemoryBlockstore::new();
@@ -1160,15 +2937,16 @@ mod test {
         // add a different type of error
         state.supply = TokenAmount::from_atto(5);
 
-        // it accumulates errors
+        // it accumulates errors, including the supply no longer matching the stats
         let (_summary, errors) = state.check_invariants(bs, granularity);
-        assert_eq!(errors.len(), 3);
+        assert_eq!(errors.len(), 4);
         if let StateInvariantError::BalanceSupplyMismatch { balance_sum, supply } = &errors[2] {
             assert_eq!(*balance_sum, TokenAmount::from_atto(0));
             assert_eq!(*supply, TokenAmount::from_atto(5));
         } else {
             panic!("unexpected error");
         }
+        assert!(matches!(errors[3], StateInvariantError::MintedBurnedMismatch { .. }));
     }
 
     #[test]
@@ -1215,15 +2993,16 @@ mod test {
         // add a different type of error
         state.supply = TokenAmount::from_atto(5);
 
-        // it accumulates errors
+        // it accumulates errors, including the supply no longer matching the stats
         let (_summary, errors) = state.check_invariants(bs, granularity);
-        assert_eq!(errors.len(), 4);
+        assert_eq!(errors.len(), 5);
         if let StateInvariantError::BalanceSupplyMismatch { balance_sum, supply } = &errors[3] {
             assert_eq!(*balance_sum, TokenAmount::from_atto(-1));
             assert_eq!(*supply, TokenAmount::from_atto(5));
         } else {
             panic!("unexpected error");
         }
+        assert!(matches!(errors[4], StateInvariantError::MintedBurnedMismatch { .. }));
     }
 
     #[test]
@@ -2,7 +2,16 @@ use fvm_actor_utils::receiver::RecipientData;
 use fvm_ipld_encoding::tuple::*;
 use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+
+use crate::token::state::AccountAmounts;
+
+/// Prefix of the bytes signed for a [`PermitPayload`], separating permits from other messages the
+/// owner's key may sign.
+pub const PERMIT_DOMAIN: &[u8] = b"frc46-permit:";
 
 /// A standard fungible token interface allowing for on-chain transactions that implements the
 /// FRC-0046 standard. This represents the external interface exposed to other on-chain actors.
@@ -138,6 +147,36 @@ impl RecipientData for MintIntermediate {
     }
 }
 
+/// Instruction to mint tokens to an address.
+///
+/// The mint method is not standardised, so this is merely a useful library-level type for batch
+/// mints.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct MintParams {
+    pub initial_owner: Address,
+    /// A non-negative amount to mint.
+    pub amount: TokenAmount,
+    /// Arbitrary data to pass on via the receiver hook.
+    pub operator_data: RawBytes,
+}
+
+/// Instruction to mint tokens to several addresses at once.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct BatchMintParams {
+    pub mints: Vec<MintParams>,
+}
+
+/// Return value after a successful batch mint.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct BatchMintReturn {
+    /// The new balance of each owner address, in order.
+    pub balances: Vec<TokenAmount>,
+    /// The new total supply.
+    pub supply: TokenAmount,
+    /// (Optional) data returned from each receiver hook, in order.
+    pub recipient_data: Vec<RawBytes>,
+}
+
 /// Instruction to transfer tokens to another address.
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct TransferParams {
@@ -174,6 +213,23 @@ impl RecipientData for TransferIntermediate {
     }
 }
 
+/// Instruction to transfer tokens from the caller to several addresses at once.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct BatchTransferParams {
+    pub transfers: Vec<TransferParams>,
+}
+
+/// Return value after a successful batch transfer.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct BatchTransferReturn {
+    /// The new balance of the `from` address.
+    pub from_balance: TokenAmount,
+    /// The new balance of each `to` address, in order.
+    pub to_balances: Vec<TokenAmount>,
+    /// (Optional) data returned from each receiver hook, in order.
+    pub recipient_data: Vec<RawBytes>,
+}
+
 /// Instruction to transfer tokens between two addresses as an operator.
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct TransferFromParams {
@@ -236,6 +292,55 @@ pub struct RevokeAllowanceParams {
     pub operator: Address,
 }
 
+/// An allowance signed off-chain by the owner, which anyone may submit to set the allowance.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct PermitParams {
+    /// The signer, which must be a key (f1/f3) or eth (f410) address.
+    pub owner: Address,
+    pub operator: Address,
+    /// A non-negative amount to set the allowance to.
+    pub amount: TokenAmount,
+    /// The owner's next permit nonce. Each nonce can only be used once, in sequence.
+    pub nonce: u64,
+    /// The last epoch at which the permit can be used.
+    pub expiry: ChainEpoch,
+    /// The owner's signature over the [`PermitPayload`].
+    pub signature: Signature,
+}
+
+impl PermitParams {
+    /// Returns the payload the owner signs for this permit on the given token actor.
+    pub fn payload(&self, token: ActorID) -> PermitPayload {
+        PermitPayload {
+            token: Address::new_id(token),
+            operator: self.operator,
+            amount: self.amount.clone(),
+            nonce: self.nonce,
+            expiry: self.expiry,
+        }
+    }
+}
+
+/// The terms of a permit, signed by the owner.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct PermitPayload {
+    /// ID address of the token actor, so the permit can't be replayed on other tokens.
+    pub token: Address,
+    pub operator: Address,
+    pub amount: TokenAmount,
+    pub nonce: u64,
+    pub expiry: ChainEpoch,
+}
+
+impl PermitPayload {
+    /// Returns the bytes to sign: [`PERMIT_DOMAIN`] followed by the CBOR encoded payload.
+    pub fn signing_bytes(&self) -> Result<Vec<u8>, fvm_ipld_encoding::Error> {
+        let mut bytes = PERMIT_DOMAIN.to_vec();
+        bytes.extend(fvm_ipld_encoding::to_vec(self)?);
+        Ok(bytes)
+    }
+}
+
 /// Params to get allowance between to addresses.
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct GetAllowanceParams {
@@ -243,6 +348,15 @@ pub struct GetAllowanceParams {
     pub operator: Address,
 }
 
+/// An allowance and when it expires.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug, PartialEq, Eq)]
+pub struct AllowanceExpiryReturn {
+    /// The remaining allowance, or zero if it has expired.
+    pub allowance: TokenAmount,
+    /// The last epoch the allowance can be used, or None if it never expires.
+    pub expiry: Option<ChainEpoch>,
+}
+
 /// Instruction to burn an amount of tokens.
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct BurnParams {
@@ -273,3 +387,70 @@ pub struct BurnFromReturn {
     /// New remaining allowance between the owner and operator (caller).
     pub allowance: TokenAmount,
 }
+
+/// Instruction to delegate the caller's voting power.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct DelegateParams {
+    /// The address to receive the caller's voting power, which may be the caller itself.
+    pub delegatee: Address,
+}
+
+/// Params to get the current voting power of an address.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct GetVotesParams {
+    pub account: Address,
+}
+
+/// Params to get the voting power of an address at the end of a past epoch.
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct GetPastVotesParams {
+    pub account: Address,
+    /// An epoch before the current epoch.
+    pub epoch: ChainEpoch,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListBalancesParams {
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), with empty cursor meaning start of list.
+    pub cursor: RawBytes,
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListBalancesReturn {
+    /// Token holders and their balances.
+    pub balances: AccountAmounts,
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), or None if this is the last page.
+    pub next_cursor: Option<RawBytes>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListAllowancesParams {
+    pub owner: Address,
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), with empty cursor meaning start of list.
+    pub cursor: RawBytes,
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListAllowancesReturn {
+    /// Operators and their remaining allowances, which are zero if expired.
+    pub allowances: AccountAmounts,
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), or None if this is the last page.
+    pub next_cursor: Option<RawBytes>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListOperatorsParams {
+    pub owner: Address,
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), with empty cursor meaning start of list.
+    pub cursor: RawBytes,
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListOperatorsReturn {
+    pub operators: Vec<ActorID>,
+    /// Opaque serialisation of [`Cursor`](crate::token::state::Cursor), or None if this is the last page.
+    pub next_cursor: Option<RawBytes>,
+}
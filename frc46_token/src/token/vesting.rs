@@ -0,0 +1,232 @@
+//! Vesting schedules that lock part of an account's balance until it vests.
+//!
+//! An account may have several schedules. The locked part of its balance is the sum of the unvested
+//! amounts of all its schedules, capped at its balance. Only the rest of the balance can be
+//! transferred or burned.
+//!
+//! Locks are enforced by [`TokenState`](crate::token::state::TokenState) whenever a balance is
+//! debited, as of the latest epoch it has been given. Locks only shrink over time, so a stale epoch
+//! can hold back tokens that have since vested but never releases tokens early.
+
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+use crate::token::state::{actor_id_key, StateError};
+
+type Result<T> = std::result::Result<T, StateError>;
+
+type ScheduleMap<'bs, BS> = Hamt<&'bs BS, Vec<VestingSchedule>, BytesKey>;
+
+/// An amount that vests over time.
+///
+/// Nothing vests before `start + cliff`. After that the amount vests linearly from `start`, in
+/// whole periods, until all of it has vested at `start + duration`. Vested amounts are rounded
+/// down to the token's granularity:
+///
+/// - a cliff schedule unlocks everything at once, see [`VestingSchedule::cliff`]
+/// - a linear schedule vests every epoch, see [`VestingSchedule::linear`]
+/// - an epoch-based schedule vests in fixed steps, see [`VestingSchedule::periodic`]
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct VestingSchedule {
+    /// Total amount to vest.
+    pub amount: TokenAmount,
+    /// Epoch from which vesting is measured.
+    pub start: ChainEpoch,
+    /// Epochs after `start` before anything vests.
+    pub cliff: ChainEpoch,
+    /// Epochs after `start` until the whole amount has vested.
+    pub duration: ChainEpoch,
+    /// Epochs between each vesting step.
+    pub period: ChainEpoch,
+}
+
+impl VestingSchedule {
+    /// Vests the whole amount at the given epoch.
+    pub fn cliff(amount: TokenAmount, epoch: ChainEpoch) -> Self {
+        Self { amount, start: epoch, cliff: 0, duration: 0, period: 1 }
+    }
+
+    /// Vests the amount a little every epoch over `duration` epochs from `start`, with nothing
+    /// vesting for the first `cliff` epochs.
+    pub fn linear(
+        amount: TokenAmount,
+        start: ChainEpoch,
+        cliff: ChainEpoch,
+        duration: ChainEpoch,
+    ) -> Self {
+        Self { amount, start, cliff, duration, period: 1 }
+    }
+
+    /// Vests the amount in `periods` equal steps, one at the end of each period from `start`.
+    ///
+    /// Fails if the total duration overflows.
+    pub fn periodic(
+        amount: TokenAmount,
+        start: ChainEpoch,
+        period: ChainEpoch,
+        periods: i64,
+    ) -> Result<Self> {
+        let duration = period
+            .checked_mul(periods)
+            .ok_or(StateError::VestingDurationOverflow { period, periods })?;
+        Ok(Self { amount, start, cliff: 0, duration, period })
+    }
+
+    /// Returns the amount vested by the given epoch, rounded down to a multiple of `granularity`.
+    pub fn vested_at(&self, epoch: ChainEpoch, granularity: u64) -> TokenAmount {
+        let elapsed = epoch.saturating_sub(self.start);
+        if elapsed < self.cliff {
+            return TokenAmount::zero();
+        }
+        if elapsed >= self.duration {
+            return self.amount.clone();
+        }
+        let elapsed = elapsed - elapsed % self.period;
+        let vested = self.amount.atto() * elapsed / self.duration;
+        TokenAmount::from_atto(&vested - &vested % granularity)
+    }
+
+    /// Returns the amount still locked at the given epoch.
+    pub fn locked_at(&self, epoch: ChainEpoch, granularity: u64) -> TokenAmount {
+        &self.amount - self.vested_at(epoch, granularity)
+    }
+
+    fn validate(&self, granularity: u64) -> Result<()> {
+        if self.amount.is_negative()
+            || !(self.amount.atto() % granularity).is_zero()
+            || self.cliff < 0
+            || self.duration < self.cliff
+            || self.period <= 0
+        {
+            return Err(StateError::InvalidVestingSchedule(Box::new(self.clone())));
+        }
+        Ok(())
+    }
+}
+
+/// Vesting IPLD structure, stored in [`TokenState`](crate::token::state::TokenState) once the first
+/// schedule is added.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct VestingState {
+    /// `Map<ActorId, Vec<VestingSchedule>>` of each account's schedules as a [`Hamt`].
+    pub schedules: Cid,
+    /// Granularity that vested amounts are rounded down to.
+    pub granularity: u64,
+    /// Latest epoch given to the token state, at which locks are enforced on debits.
+    pub epoch: ChainEpoch,
+}
+
+impl VestingState {
+    pub fn new<BS: Blockstore>(
+        bs: &BS,
+        hamt_bit_width: u32,
+        granularity: u64,
+        epoch: ChainEpoch,
+    ) -> Result<Self> {
+        let schedules = ScheduleMap::new_with_bit_width(bs, hamt_bit_width).flush()?;
+        Ok(Self { schedules, granularity, epoch })
+    }
+
+    /// Moves the epoch at which locks are enforced forward. Earlier epochs are ignored.
+    pub fn advance_epoch(&mut self, epoch: ChainEpoch) {
+        self.epoch = self.epoch.max(epoch);
+    }
+
+    /// Adds a schedule to an account, dropping any of its schedules that have fully vested.
+    pub fn add_schedule<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        owner: ActorID,
+        schedule: VestingSchedule,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        schedule.validate(self.granularity)?;
+        self.advance_epoch(epoch);
+        let mut schedule_map =
+            ScheduleMap::load_with_bit_width(&self.schedules, bs, hamt_bit_width)?;
+        let owner_key = actor_id_key(owner);
+        let mut schedules = schedule_map.get(&owner_key)?.cloned().unwrap_or_default();
+        schedules.retain(|schedule| !schedule.locked_at(epoch, self.granularity).is_zero());
+        schedules.push(schedule);
+        schedule_map.set(owner_key, schedules)?;
+        self.schedules = schedule_map.flush()?;
+        Ok(())
+    }
+
+    /// Returns an account's schedules.
+    pub fn schedules<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        owner: ActorID,
+    ) -> Result<Vec<VestingSchedule>> {
+        let schedule_map = ScheduleMap::load_with_bit_width(&self.schedules, bs, hamt_bit_width)?;
+        Ok(schedule_map.get(&actor_id_key(owner))?.cloned().unwrap_or_default())
+    }
+
+    /// Returns the total amount of an account's schedules still locked at an epoch.
+    ///
+    /// This is not capped at the account's balance.
+    pub fn locked_at<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        owner: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        let schedules = self.schedules(bs, hamt_bit_width, owner)?;
+        Ok(schedules.iter().map(|schedule| schedule.locked_at(epoch, self.granularity)).sum())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_shared::clock::ChainEpoch;
+    use fvm_shared::econ::TokenAmount;
+
+    use super::VestingSchedule;
+    use crate::token::state::StateError;
+
+    #[test]
+    fn it_vests_schedules() {
+        let amount = TokenAmount::from_atto;
+
+        let cliff = VestingSchedule::cliff(amount(100), 10);
+        assert_eq!(cliff.vested_at(9, 1), amount(0));
+        assert_eq!(cliff.vested_at(10, 1), amount(100));
+
+        let linear = VestingSchedule::linear(amount(100), 10, 5, 20);
+        assert_eq!(linear.vested_at(14, 1), amount(0));
+        assert_eq!(linear.vested_at(15, 1), amount(25));
+        assert_eq!(linear.vested_at(16, 1), amount(30));
+        assert_eq!(linear.vested_at(30, 1), amount(100));
+        assert_eq!(linear.locked_at(16, 1), amount(70));
+        // vested amounts are rounded down to the granularity
+        assert_eq!(linear.vested_at(15, 10), amount(20));
+        assert_eq!(linear.locked_at(15, 10), amount(80));
+
+        let periodic = VestingSchedule::periodic(amount(90), 10, 4, 3).unwrap();
+        assert_eq!(periodic.vested_at(13, 1), amount(0));
+        assert_eq!(periodic.vested_at(14, 1), amount(30));
+        assert_eq!(periodic.vested_at(21, 1), amount(60));
+        assert_eq!(periodic.vested_at(22, 1), amount(90));
+        // epochs long before the start don't overflow
+        assert_eq!(periodic.vested_at(ChainEpoch::MIN, 1), amount(0));
+    }
+
+    #[test]
+    fn it_rejects_overflowing_periodic_schedules() {
+        let err = VestingSchedule::periodic(TokenAmount::from_atto(1), 0, i64::MAX, 2).unwrap_err();
+        assert!(matches!(
+            err,
+            StateError::VestingDurationOverflow { period: i64::MAX, periods: 2 }
+        ));
+    }
+}
@@ -0,0 +1,278 @@
+//! Vote delegation with checkpointed voting power, in the style of ERC20Votes.
+//!
+//! Holders delegate the voting power of their whole balance to a delegate, which may be
+//! themselves. Balances of holders who haven't delegated carry no voting power. Each delegate's
+//! voting power is checkpointed at the epochs it changes, so past voting power can be read on-chain.
+//!
+//! Each delegate's checkpoints are kept in their own [`Amt`] in ascending epoch order, so moving
+//! votes only writes the latest checkpoint and past voting power is found by binary search.
+
+use cid::Cid;
+use fvm_ipld_amt::Amt;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_hamt::{BytesKey, Hamt};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+use crate::token::state::{actor_id_key, StateError};
+
+type Result<T> = std::result::Result<T, StateError>;
+
+type DelegateMap<'bs, BS> = Hamt<&'bs BS, ActorID, BytesKey>;
+type VoteCheckpointMap<'bs, BS> = Hamt<&'bs BS, Cid, BytesKey>;
+type VoteCheckpointArray<'bs, BS> = Amt<VoteCheckpoint, &'bs BS>;
+
+const AMT_BIT_WIDTH: u32 = 5;
+
+/// A delegate's voting power from an epoch onwards.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct VoteCheckpoint {
+    pub epoch: ChainEpoch,
+    pub votes: TokenAmount,
+}
+
+/// Vote delegation IPLD structure, stored in [`TokenState`](crate::token::state::TokenState) once
+/// votes are enabled.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct VotesState {
+    /// `Map<ActorId, ActorId>` of holders to their delegates, as a [`Hamt`].
+    pub delegates: Cid,
+    /// `Map<ActorId, Cid>` of each delegate's voting power as a [`Hamt`]. Each value is the root
+    /// of an `Array<VoteCheckpoint>` in ascending epoch order, as an [`Amt`].
+    pub checkpoints: Cid,
+}
+
+impl VotesState {
+    pub fn new<BS: Blockstore>(bs: &BS, hamt_bit_width: u32) -> Result<Self> {
+        let delegates = DelegateMap::new_with_bit_width(bs, hamt_bit_width).flush()?;
+        let checkpoints = VoteCheckpointMap::new_with_bit_width(bs, hamt_bit_width).flush()?;
+        Ok(Self { delegates, checkpoints })
+    }
+
+    /// Returns the delegate of a holder, if it has delegated.
+    pub fn delegate_of<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        holder: ActorID,
+    ) -> Result<Option<ActorID>> {
+        let delegate_map = DelegateMap::load_with_bit_width(&self.delegates, bs, hamt_bit_width)?;
+        Ok(delegate_map.get(&actor_id_key(holder))?.copied())
+    }
+
+    /// Delegates a holder's voting power, moving its balance's votes from any previous delegate.
+    pub fn delegate<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        holder: ActorID,
+        delegate: ActorID,
+        balance: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        let mut delegate_map =
+            DelegateMap::load_with_bit_width(&self.delegates, bs, hamt_bit_width)?;
+        let previous = delegate_map.set(actor_id_key(holder), delegate)?;
+        self.delegates = delegate_map.flush()?;
+        self.move_votes(bs, hamt_bit_width, previous, Some(delegate), balance, epoch)
+    }
+
+    /// Moves votes between the delegates of two holders after tokens move between them.
+    ///
+    /// A holder of None stands for tokens being minted or burned.
+    pub fn move_holder_votes<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        from: Option<ActorID>,
+        to: Option<ActorID>,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        if from == to || amount.is_zero() {
+            return Ok(());
+        }
+        let from = match from {
+            Some(holder) => self.delegate_of(bs, hamt_bit_width, holder)?,
+            None => None,
+        };
+        let to = match to {
+            Some(holder) => self.delegate_of(bs, hamt_bit_width, holder)?,
+            None => None,
+        };
+        self.move_votes(bs, hamt_bit_width, from, to, amount, epoch)
+    }
+
+    /// Returns a delegate's current voting power.
+    pub fn votes<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        delegate: ActorID,
+    ) -> Result<TokenAmount> {
+        let checkpoints = match self.get_checkpoints(bs, hamt_bit_width, delegate)? {
+            Some(root) => VoteCheckpointArray::load(&root, bs)?,
+            None => return Ok(TokenAmount::zero()),
+        };
+        Ok(latest_checkpoint(&checkpoints)?.map(|c| c.votes).unwrap_or_default())
+    }
+
+    /// Returns a delegate's voting power at the end of an epoch.
+    pub fn past_votes<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        delegate: ActorID,
+        epoch: ChainEpoch,
+    ) -> Result<TokenAmount> {
+        match self.get_checkpoints(bs, hamt_bit_width, delegate)? {
+            Some(root) => Ok(array_votes_at(bs, &root, epoch)?.unwrap_or_default()),
+            None => Ok(TokenAmount::zero()),
+        }
+    }
+
+    /// Returns the sum of all delegates' current voting power.
+    pub fn total_votes<BS: Blockstore>(&self, bs: &BS, hamt_bit_width: u32) -> Result<TokenAmount> {
+        let checkpoint_map =
+            VoteCheckpointMap::load_with_bit_width(&self.checkpoints, bs, hamt_bit_width)?;
+        let mut roots = Vec::new();
+        checkpoint_map.for_each(|_, root| {
+            roots.push(*root);
+            Ok(())
+        })?;
+        let mut total = TokenAmount::zero();
+        for root in roots {
+            let checkpoints = VoteCheckpointArray::load(&root, bs)?;
+            if let Some(latest) = latest_checkpoint(&checkpoints)? {
+                total += latest.votes;
+            }
+        }
+        Ok(total)
+    }
+
+    fn move_votes<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        from: Option<ActorID>,
+        to: Option<ActorID>,
+        amount: &TokenAmount,
+        epoch: ChainEpoch,
+    ) -> Result<()> {
+        if from == to || amount.is_zero() {
+            return Ok(());
+        }
+        let mut checkpoint_map =
+            VoteCheckpointMap::load_with_bit_width(&self.checkpoints, bs, hamt_bit_width)?;
+        if let Some(delegate) = from {
+            write_checkpoint(bs, &mut checkpoint_map, delegate, &-amount, epoch)?;
+        }
+        if let Some(delegate) = to {
+            write_checkpoint(bs, &mut checkpoint_map, delegate, amount, epoch)?;
+        }
+        self.checkpoints = checkpoint_map.flush()?;
+        Ok(())
+    }
+
+    fn get_checkpoints<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        hamt_bit_width: u32,
+        delegate: ActorID,
+    ) -> Result<Option<Cid>> {
+        let checkpoint_map =
+            VoteCheckpointMap::load_with_bit_width(&self.checkpoints, bs, hamt_bit_width)?;
+        Ok(checkpoint_map.get(&actor_id_key(delegate))?.copied())
+    }
+}
+
+/// Changes a delegate's voting power at an epoch, replacing any checkpoint already at that epoch.
+fn write_checkpoint<BS: Blockstore>(
+    bs: &BS,
+    checkpoint_map: &mut VoteCheckpointMap<BS>,
+    delegate: ActorID,
+    delta: &TokenAmount,
+    epoch: ChainEpoch,
+) -> Result<()> {
+    let key = actor_id_key(delegate);
+    let mut checkpoints = match checkpoint_map.get(&key)? {
+        Some(root) => VoteCheckpointArray::load(root, bs)?,
+        None => VoteCheckpointArray::new_with_bit_width(bs, AMT_BIT_WIDTH),
+    };
+    let latest = latest_checkpoint(&checkpoints)?;
+    let votes = latest.as_ref().map(|c| c.votes.clone()).unwrap_or_default();
+    let new_votes = &votes + delta;
+    if new_votes.is_negative() {
+        return Err(StateError::NegativeVotes { delegate, votes, delta: delta.clone() });
+    }
+
+    let index = match latest {
+        Some(latest) if latest.epoch == epoch => checkpoints.count() - 1,
+        _ => checkpoints.count(),
+    };
+    checkpoints.set(index, VoteCheckpoint { epoch, votes: new_votes })?;
+    checkpoint_map.set(key, checkpoints.flush()?)?;
+    Ok(())
+}
+
+/// Returns the last checkpoint in a delegate's checkpoint array.
+fn latest_checkpoint<BS: Blockstore>(
+    checkpoints: &VoteCheckpointArray<BS>,
+) -> Result<Option<VoteCheckpoint>> {
+    match checkpoints.count().checked_sub(1) {
+        Some(index) => Ok(checkpoints.get(index)?.cloned()),
+        None => Ok(None),
+    }
+}
+
+/// Returns the voting power at the end of an epoch from a delegate's checkpoint array, binary
+/// searching by index, or None if it has no checkpoint at or before the epoch.
+fn array_votes_at<BS: Blockstore>(
+    bs: &BS,
+    root: &Cid,
+    epoch: ChainEpoch,
+) -> Result<Option<TokenAmount>> {
+    let checkpoints = VoteCheckpointArray::load(root, bs)?;
+    let (mut low, mut high) = (0, checkpoints.count());
+    let mut found = None;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let checkpoint = checkpoints.get(mid)?.ok_or(StateError::MissingState(*root))?;
+        if checkpoint.epoch <= epoch {
+            found = Some(checkpoint.votes.clone());
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::econ::TokenAmount;
+
+    use super::{array_votes_at, VoteCheckpoint, VoteCheckpointArray};
+
+    #[test]
+    fn it_binary_searches_vote_checkpoints() {
+        let bs = &MemoryBlockstore::new();
+        let amount = TokenAmount::from_atto;
+        let mut checkpoints = VoteCheckpointArray::new(bs);
+        for (index, epoch) in [2, 5, 6, 9].into_iter().enumerate() {
+            let checkpoint = VoteCheckpoint { epoch, votes: amount(epoch * 10) };
+            checkpoints.set(index as u64, checkpoint).unwrap();
+        }
+        let checkpoints = &checkpoints.flush().unwrap();
+
+        assert_eq!(array_votes_at(bs, checkpoints, 1).unwrap(), None);
+        assert_eq!(array_votes_at(bs, checkpoints, 2).unwrap(), Some(amount(20)));
+        assert_eq!(array_votes_at(bs, checkpoints, 4).unwrap(), Some(amount(20)));
+        assert_eq!(array_votes_at(bs, checkpoints, 6).unwrap(), Some(amount(60)));
+        assert_eq!(array_votes_at(bs, checkpoints, 100).unwrap(), Some(amount(90)));
+    }
+}
@@ -0,0 +1,529 @@
+//! ERC-721 compatibility façade for FRC-53 collections.
+//!
+//! Ethereum accounts and EVM contracts call native actors through the `InvokeEVM` method (see
+//! [`INVOKE_EVM_METHOD_NUM`][fvm_actor_utils::evm::INVOKE_EVM_METHOD_NUM]) with Solidity ABI
+//! calldata. An NFT actor can decode that calldata into an [`Erc721Call`] and invoke it against its
+//! [`NFT`] to serve ERC-721 callers, such as FEVM marketplaces, alongside native FRC-53 callers.
+//!
+//! Token IDs are passed as `uint256` and must fit in a [`TokenID`]. Owners and operators are
+//! reported by their f410 address where they have one, otherwise by their masked ID address.
+//!
+//! Unlike ERC-721, every transfer calls the FRC-53 receiver hook on the recipient, so
+//! `transferFrom` behaves like `safeTransferFrom`. The `bytes` passed to `safeTransferFrom` are
+//! forwarded to the hook as operator data.
+#![allow(clippy::result_large_err)]
+
+use fvm_actor_utils::evm::{actor_eth_address, eth_to_address, AbiDecoder, AbiEncoder, AbiError};
+use fvm_actor_utils::messaging::Messaging;
+use fvm_actor_utils::receiver::{ReceiverHook, ReceiverHookError};
+use fvm_actor_utils::syscalls::Syscalls;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::error::ExitCode;
+use thiserror::Error;
+
+use crate::types::{TokenID, TransferIntermediate};
+use crate::{NFTError, NFT};
+
+/// Selectors of the supported ERC-721 functions.
+pub mod selectors {
+    use frc42_dispatch::selector::Selector;
+
+    /// `balanceOf(address)`
+    pub const BALANCE_OF: Selector = [0x70, 0xa0, 0x82, 0x31];
+    /// `ownerOf(uint256)`
+    pub const OWNER_OF: Selector = [0x63, 0x52, 0x21, 0x1e];
+    /// `transferFrom(address,address,uint256)`
+    pub const TRANSFER_FROM: Selector = [0x23, 0xb8, 0x72, 0xdd];
+    /// `safeTransferFrom(address,address,uint256)`
+    pub const SAFE_TRANSFER_FROM: Selector = [0x42, 0x84, 0x2e, 0x0e];
+    /// `safeTransferFrom(address,address,uint256,bytes)`
+    pub const SAFE_TRANSFER_FROM_WITH_DATA: Selector = [0xb8, 0x8d, 0x4f, 0xde];
+    /// `approve(address,uint256)`
+    pub const APPROVE: Selector = [0x09, 0x5e, 0xa7, 0xb3];
+    /// `setApprovalForAll(address,bool)`
+    pub const SET_APPROVAL_FOR_ALL: Selector = [0xa2, 0x2c, 0xb4, 0x65];
+    /// `isApprovedForAll(address,address)`
+    pub const IS_APPROVED_FOR_ALL: Selector = [0xe9, 0x85, 0xe9, 0xc5];
+    /// `getApproved(uint256)`
+    pub const GET_APPROVED: Selector = [0x08, 0x18, 0x12, 0xfc];
+    /// `tokenURI(uint256)`
+    pub const TOKEN_URI: Selector = [0xc8, 0x7b, 0x56, 0xdd];
+    /// `supportsInterface(bytes4)`
+    pub const SUPPORTS_INTERFACE: Selector = [0x01, 0xff, 0xc9, 0xa7];
+}
+
+/// ERC-165 interface IDs reported by `supportsInterface`.
+pub mod interfaces {
+    use frc42_dispatch::selector::Selector;
+
+    /// ERC-165 itself.
+    pub const ERC165: Selector = [0x01, 0xff, 0xc9, 0xa7];
+    /// The core ERC-721 interface.
+    pub const ERC721: Selector = [0x80, 0xac, 0x58, 0xcd];
+}
+
+/// The zero address, used by ERC-721 to signify "no address".
+const ZERO_ADDRESS: [u8; 20] = [0; 20];
+
+#[derive(Error, Debug)]
+pub enum Erc721Error {
+    #[error("abi error: {0}")]
+    Abi(#[from] AbiError),
+    #[error("nft error: {0}")]
+    NFT(#[from] NFTError),
+}
+
+impl From<&Erc721Error> for ExitCode {
+    fn from(error: &Erc721Error) -> Self {
+        match error {
+            Erc721Error::Abi(e) => e.into(),
+            Erc721Error::NFT(e) => e.into(),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Erc721Error>;
+
+/// A decoded call to an ERC-721 function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Erc721Call {
+    BalanceOf {
+        owner: Address,
+    },
+    OwnerOf {
+        token_id: TokenID,
+    },
+    /// Any of `transferFrom` and the `safeTransferFrom` overloads.
+    TransferFrom {
+        from: Address,
+        to: Address,
+        token_id: TokenID,
+        data: Vec<u8>,
+    },
+    /// Approves `operator`, or clears the approval if it is the zero address.
+    Approve {
+        operator: Option<Address>,
+        token_id: TokenID,
+    },
+    SetApprovalForAll {
+        operator: Address,
+        approved: bool,
+    },
+    IsApprovedForAll {
+        owner: Address,
+        operator: Address,
+    },
+    GetApproved {
+        token_id: TokenID,
+    },
+    TokenUri {
+        token_id: TokenID,
+    },
+    SupportsInterface {
+        interface_id: [u8; 4],
+    },
+}
+
+impl Erc721Call {
+    /// Decodes Solidity ABI calldata into an ERC-721 call.
+    pub fn decode(calldata: &[u8]) -> std::result::Result<Self, AbiError> {
+        let (selector, args) = AbiDecoder::from_calldata(calldata)?;
+        let zero = eth_to_address(&ZERO_ADDRESS);
+        // ERC-721 forbids transfers to the zero address
+        let recipient = |index| match args.address(index)? {
+            to if to == zero => Err(AbiError::InvalidArgument { index, kind: "recipient" }),
+            to => Ok(to),
+        };
+        Ok(match selector {
+            selectors::BALANCE_OF => Erc721Call::BalanceOf { owner: args.address(0)? },
+            selectors::OWNER_OF => Erc721Call::OwnerOf { token_id: args.uint64(0)? },
+            selectors::TRANSFER_FROM | selectors::SAFE_TRANSFER_FROM => Erc721Call::TransferFrom {
+                from: args.address(0)?,
+                to: recipient(1)?,
+                token_id: args.uint64(2)?,
+                data: Vec::new(),
+            },
+            selectors::SAFE_TRANSFER_FROM_WITH_DATA => Erc721Call::TransferFrom {
+                from: args.address(0)?,
+                to: recipient(1)?,
+                token_id: args.uint64(2)?,
+                data: args.bytes(3)?.to_vec(),
+            },
+            selectors::APPROVE => Erc721Call::Approve {
+                operator: Some(args.address(0)?).filter(|operator| *operator != zero),
+                token_id: args.uint64(1)?,
+            },
+            selectors::SET_APPROVAL_FOR_ALL => Erc721Call::SetApprovalForAll {
+                operator: args.address(0)?,
+                approved: args.bool(1)?,
+            },
+            selectors::IS_APPROVED_FOR_ALL => {
+                Erc721Call::IsApprovedForAll { owner: args.address(0)?, operator: args.address(1)? }
+            }
+            selectors::GET_APPROVED => Erc721Call::GetApproved { token_id: args.uint64(0)? },
+            selectors::TOKEN_URI => Erc721Call::TokenUri { token_id: args.uint64(0)? },
+            selectors::SUPPORTS_INTERFACE => {
+                Erc721Call::SupportsInterface { interface_id: args.bytes4(0)? }
+            }
+            _ => return Err(AbiError::UnknownSelector(selector)),
+        })
+    }
+
+    /// Returns true if invoking this call modifies NFT state, which must then be flushed.
+    pub fn mutates_state(&self) -> bool {
+        matches!(
+            self,
+            Erc721Call::TransferFrom { .. }
+                | Erc721Call::Approve { .. }
+                | Erc721Call::SetApprovalForAll { .. }
+        )
+    }
+
+    /// Invokes the call on a collection, on behalf of `caller`.
+    ///
+    /// If [`Erc721Call::mutates_state`] is true, the NFT state must be flushed before the returned
+    /// [`Erc721Response`] is completed.
+    pub fn invoke<S: Syscalls, BS: Blockstore>(
+        self,
+        nft: &mut NFT<S, BS>,
+        caller: &Address,
+    ) -> Result<Erc721Response> {
+        let encoder = AbiEncoder::new();
+        let data = match self {
+            Erc721Call::BalanceOf { owner } => encoder.uint64(nft.balance_of(&owner)?),
+            Erc721Call::OwnerOf { token_id } => {
+                let owner = nft.owner_of(token_id)?;
+                encoder.address(&actor_eth_address(nft.runtime(), owner))
+            }
+            Erc721Call::TransferFrom { from, to, token_id, data } => {
+                let hook = if nft.runtime().same_address(caller, &from) {
+                    nft.transfer(caller, &to, &[token_id], data.into(), RawBytes::default())?
+                } else {
+                    nft.transfer_from(
+                        &from,
+                        caller,
+                        &to,
+                        &[token_id],
+                        data.into(),
+                        RawBytes::default(),
+                    )?
+                };
+                return Ok(Erc721Response::Transfer(hook));
+            }
+            Erc721Call::Approve { operator, token_id } => {
+                nft.set_approved(caller, operator.as_ref(), token_id)?;
+                encoder
+            }
+            Erc721Call::SetApprovalForAll { operator, approved } => {
+                if approved {
+                    nft.approve_for_owner(caller, &operator)?;
+                } else {
+                    nft.revoke_for_all(caller, &operator)?;
+                }
+                encoder
+            }
+            Erc721Call::IsApprovedForAll { owner, operator } => {
+                encoder.bool(nft.is_account_operator(&owner, &operator)?)
+            }
+            Erc721Call::GetApproved { token_id } => {
+                let operators = nft.list_token_operators(token_id, RawBytes::default(), 1)?;
+                let approved = match operators.operators.iter().next() {
+                    Some(operator) => actor_eth_address(nft.runtime(), operator),
+                    None => ZERO_ADDRESS,
+                };
+                encoder.address(&approved)
+            }
+            Erc721Call::TokenUri { token_id } => encoder.string(&nft.metadata(token_id)?),
+            Erc721Call::SupportsInterface { interface_id } => {
+                encoder.bool(matches!(interface_id, interfaces::ERC165 | interfaces::ERC721))
+            }
+        };
+        Ok(Erc721Response::Complete(data.finish()))
+    }
+}
+
+/// The outcome of invoking an [`Erc721Call`].
+#[derive(Debug)]
+pub enum Erc721Response {
+    /// The call is complete, with the given ABI encoded return data.
+    Complete(Vec<u8>),
+    /// A transfer whose receiver hook must be called.
+    Transfer(ReceiverHook<TransferIntermediate>),
+}
+
+impl Erc721Response {
+    /// Calls any pending receiver hook and returns the ABI encoded return data.
+    ///
+    /// NFT state must be flushed before calling this, as the receiver hook may re-enter the actor.
+    pub fn complete(self, msg: &dyn Messaging) -> std::result::Result<Vec<u8>, ReceiverHookError> {
+        match self {
+            Erc721Response::Complete(data) => Ok(data),
+            Erc721Response::Transfer(mut hook) => {
+                hook.call(msg)?;
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_actor_utils::evm::{
+        eth_to_address, id_to_eth, AbiDecoder, AbiEncoder, AbiError, EthAddress,
+    };
+    use fvm_actor_utils::syscalls::fake_syscalls::FakeSyscalls;
+    use fvm_actor_utils::util::ActorRuntime;
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::address::Address;
+    use fvm_shared::error::ExitCode;
+
+    use super::{interfaces, selectors, Erc721Call, Erc721Error, Erc721Response, ZERO_ADDRESS};
+    use crate::state::NFTState;
+    use crate::NFT;
+
+    const ALICE: Address = Address::new_id(2);
+    const OPERATOR: Address = Address::new_id(3);
+    const ETH_BOB: EthAddress = [0xb0; 20];
+
+    fn invoke(
+        nft: &mut NFT<FakeSyscalls, MemoryBlockstore>,
+        caller: &Address,
+        calldata: &[u8],
+    ) -> Result<Vec<u8>, Erc721Error> {
+        let call = Erc721Call::decode(calldata)?;
+        let res = call.invoke(nft, caller)?;
+        nft.flush()?;
+        Ok(res.complete(nft.runtime()).unwrap())
+    }
+
+    /// Creates a collection where alice holds tokens 0 and 1.
+    fn new_nft_with_tokens(
+        runtime: ActorRuntime<FakeSyscalls, MemoryBlockstore>,
+        state: &mut NFTState,
+    ) -> NFT<'_, FakeSyscalls, MemoryBlockstore> {
+        let mut nft = NFT::wrap(runtime, state);
+        let mut hook = nft
+            .mint(
+                &ALICE,
+                &ALICE,
+                vec!["ipfs://0".into(), "ipfs://1".into()],
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap();
+        nft.flush().unwrap();
+        hook.call(nft.runtime()).unwrap();
+        nft
+    }
+
+    fn address(data: &[u8]) -> EthAddress {
+        AbiDecoder::new(data).word(0).unwrap()[12..].try_into().unwrap()
+    }
+
+    #[test]
+    fn it_decodes_calls() {
+        let calldata = AbiEncoder::with_selector(selectors::SAFE_TRANSFER_FROM_WITH_DATA)
+            .address(&id_to_eth(2))
+            .address(&ETH_BOB)
+            .uint64(7)
+            .bytes(b"hello")
+            .finish();
+        let call = Erc721Call::decode(&calldata).unwrap();
+        assert_eq!(
+            call,
+            Erc721Call::TransferFrom {
+                from: ALICE,
+                to: eth_to_address(&ETH_BOB),
+                token_id: 7,
+                data: b"hello".to_vec(),
+            }
+        );
+        assert!(call.mutates_state());
+
+        // approving the zero address clears the approval
+        let calldata =
+            AbiEncoder::with_selector(selectors::APPROVE).address(&ZERO_ADDRESS).uint64(1).finish();
+        assert_eq!(
+            Erc721Call::decode(&calldata).unwrap(),
+            Erc721Call::Approve { operator: None, token_id: 1 }
+        );
+
+        // transfers to the zero address are rejected
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER_FROM)
+            .address(&id_to_eth(2))
+            .address(&ZERO_ADDRESS)
+            .uint64(0)
+            .finish();
+        assert_eq!(
+            Erc721Call::decode(&calldata).unwrap_err(),
+            AbiError::InvalidArgument { index: 1, kind: "recipient" }
+        );
+
+        // token ids must fit in a u64
+        let mut calldata = AbiEncoder::with_selector(selectors::OWNER_OF).uint64(0).finish();
+        calldata[4] = 1;
+        assert_eq!(
+            Erc721Call::decode(&calldata).unwrap_err(),
+            AbiError::InvalidArgument { index: 0, kind: "uint64" }
+        );
+    }
+
+    #[test]
+    fn it_serves_erc721_queries() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&runtime).unwrap();
+        let mut nft = new_nft_with_tokens(runtime, &mut state);
+
+        let calldata = AbiEncoder::with_selector(selectors::BALANCE_OF).address(&id_to_eth(2));
+        let balance = invoke(&mut nft, &ALICE, &calldata.finish()).unwrap();
+        assert_eq!(AbiDecoder::new(&balance).uint64(0).unwrap(), 2);
+
+        let calldata = AbiEncoder::with_selector(selectors::OWNER_OF).uint64(1).finish();
+        let owner = invoke(&mut nft, &ALICE, &calldata).unwrap();
+        assert_eq!(address(&owner), id_to_eth(2));
+
+        let calldata = AbiEncoder::with_selector(selectors::TOKEN_URI).uint64(1).finish();
+        let uri = invoke(&mut nft, &ALICE, &calldata).unwrap();
+        assert_eq!(AbiDecoder::new(&uri).bytes(0).unwrap(), b"ipfs://1");
+
+        for (interface_id, supported) in
+            [(interfaces::ERC165, true), (interfaces::ERC721, true), ([0xff; 4], false)]
+        {
+            let calldata =
+                AbiEncoder::with_selector(selectors::SUPPORTS_INTERFACE).bytes4(interface_id);
+            let ret = invoke(&mut nft, &ALICE, &calldata.finish()).unwrap();
+            assert_eq!(AbiDecoder::new(&ret).bool(0).unwrap(), supported);
+        }
+
+        // querying a token that doesn't exist fails
+        let calldata = AbiEncoder::with_selector(selectors::OWNER_OF).uint64(5).finish();
+        let err = invoke(&mut nft, &ALICE, &calldata).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_NOT_FOUND);
+    }
+
+    #[test]
+    fn it_transfers_to_eth_addresses() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&runtime).unwrap();
+        let mut nft = new_nft_with_tokens(runtime, &mut state);
+
+        let calldata = AbiEncoder::with_selector(selectors::SAFE_TRANSFER_FROM)
+            .address(&id_to_eth(2))
+            .address(&ETH_BOB)
+            .uint64(0)
+            .finish();
+        let ret = invoke(&mut nft, &ALICE, &calldata).unwrap();
+        assert!(ret.is_empty());
+
+        // the new owner is reported by its f410 address
+        let calldata = AbiEncoder::with_selector(selectors::OWNER_OF).uint64(0).finish();
+        let owner = invoke(&mut nft, &ALICE, &calldata).unwrap();
+        assert_eq!(address(&owner), ETH_BOB);
+        assert_eq!(nft.balance_of(&eth_to_address(&ETH_BOB)).unwrap(), 1);
+
+        // alice can no longer transfer the token
+        let err = invoke(&mut nft, &ALICE, &calldata_transfer(2, ETH_BOB, 0)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        nft.check_invariants().unwrap();
+    }
+
+    fn calldata_transfer(from: u64, to: EthAddress, token_id: u64) -> Vec<u8> {
+        AbiEncoder::with_selector(selectors::TRANSFER_FROM)
+            .address(&id_to_eth(from))
+            .address(&to)
+            .uint64(token_id)
+            .finish()
+    }
+
+    #[test]
+    fn it_approves_single_operators() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&runtime).unwrap();
+        let mut nft = new_nft_with_tokens(runtime, &mut state);
+        let bob = eth_to_address(&ETH_BOB);
+
+        let approve = |operator: &EthAddress| {
+            AbiEncoder::with_selector(selectors::APPROVE).address(operator).uint64(0).finish()
+        };
+        let get_approved = AbiEncoder::with_selector(selectors::GET_APPROVED).uint64(0).finish();
+
+        let ret = invoke(&mut nft, &ALICE, &get_approved).unwrap();
+        assert_eq!(address(&ret), ZERO_ADDRESS);
+
+        // a new approval replaces the previous one
+        invoke(&mut nft, &ALICE, &approve(&id_to_eth(3))).unwrap();
+        invoke(&mut nft, &ALICE, &approve(&ETH_BOB)).unwrap();
+        let ret = invoke(&mut nft, &ALICE, &get_approved).unwrap();
+        assert_eq!(address(&ret), ETH_BOB);
+        let err = invoke(&mut nft, &OPERATOR, &calldata_transfer(2, ETH_BOB, 0)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+
+        // only the owner or its account-level operators may approve
+        let err = invoke(&mut nft, &OPERATOR, &approve(&id_to_eth(3))).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+
+        // the approved operator transfers the token to itself, clearing the approval
+        let bob_id = nft.runtime().resolve_id(&bob).unwrap();
+        invoke(&mut nft, &bob, &calldata_transfer(2, ETH_BOB, 0)).unwrap();
+        assert_eq!(nft.owner_of(0).unwrap(), bob_id);
+        let ret = invoke(&mut nft, &bob, &get_approved).unwrap();
+        assert_eq!(address(&ret), ZERO_ADDRESS);
+
+        // approving the zero address clears the approval
+        let calldata =
+            AbiEncoder::with_selector(selectors::APPROVE).address(&id_to_eth(3)).uint64(1).finish();
+        invoke(&mut nft, &ALICE, &calldata).unwrap();
+        let calldata =
+            AbiEncoder::with_selector(selectors::APPROVE).address(&ZERO_ADDRESS).uint64(1).finish();
+        invoke(&mut nft, &ALICE, &calldata).unwrap();
+        let calldata = AbiEncoder::with_selector(selectors::GET_APPROVED).uint64(1).finish();
+        let ret = invoke(&mut nft, &ALICE, &calldata).unwrap();
+        assert_eq!(address(&ret), ZERO_ADDRESS);
+        nft.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_approves_account_operators() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&runtime).unwrap();
+        let mut nft = new_nft_with_tokens(runtime, &mut state);
+
+        let set_approval = |approved| {
+            AbiEncoder::with_selector(selectors::SET_APPROVAL_FOR_ALL)
+                .address(&id_to_eth(3))
+                .bool(approved)
+                .finish()
+        };
+        let is_approved = AbiEncoder::with_selector(selectors::IS_APPROVED_FOR_ALL)
+            .address(&id_to_eth(2))
+            .address(&id_to_eth(3))
+            .finish();
+
+        let ret = invoke(&mut nft, &ALICE, &is_approved).unwrap();
+        assert!(!AbiDecoder::new(&ret).bool(0).unwrap());
+
+        invoke(&mut nft, &ALICE, &set_approval(true)).unwrap();
+        let ret = invoke(&mut nft, &ALICE, &is_approved).unwrap();
+        assert!(AbiDecoder::new(&ret).bool(0).unwrap());
+
+        // the operator can transfer any of alice's tokens
+        let res = Erc721Call::decode(&calldata_transfer(2, ETH_BOB, 1))
+            .unwrap()
+            .invoke(&mut nft, &OPERATOR)
+            .unwrap();
+        assert!(matches!(res, Erc721Response::Transfer(_)));
+        nft.flush().unwrap();
+        res.complete(nft.runtime()).unwrap();
+        assert_eq!(nft.balance_of(&ALICE).unwrap(), 1);
+
+        invoke(&mut nft, &ALICE, &set_approval(false)).unwrap();
+        let ret = invoke(&mut nft, &ALICE, &is_approved).unwrap();
+        assert!(!AbiDecoder::new(&ret).bool(0).unwrap());
+        let err = invoke(&mut nft, &OPERATOR, &calldata_transfer(2, ETH_BOB, 0)).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        nft.check_invariants().unwrap();
+    }
+}
@@ -9,23 +9,30 @@
 use cid::Cid;
 use fvm_actor_utils::{
     messaging::MessagingError,
+    pause::{PauseError, PauseState},
     receiver::ReceiverHook,
     syscalls::Syscalls,
     util::{ActorError, ActorRuntime},
 };
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::{Error as EncodingError, RawBytes};
-use fvm_shared::{address::Address, ActorID};
+use fvm_shared::{address::Address, error::ExitCode, ActorID};
 use receiver::{FRC53ReceiverHook, FRC53TokenReceived};
 use state::{Cursor, StateError, StateInvariantError, StateSummary};
 use thiserror::Error;
 use types::{
-    ListAccountOperatorsReturn, ListOperatorTokensReturn, ListTokenOperatorsReturn,
-    ListTokensReturn, MintIntermediate, MintReturn, TokenID, TransferIntermediate, TransferReturn,
+    ListAccountOperatorsReturn, ListOperatorOwnersReturn, ListOperatorTokensReturn,
+    ListTokenOperatorsReturn, ListTokensReturn, MintIntermediate, MintReturn, TokenID,
+    TokenMetadata, TransferIntermediate, TransferReturn,
 };
+use util::OperatorSet;
 
 use self::state::NFTState;
 
+#[cfg(doc)]
+use fvm_actor_utils::access_control::METADATA_EDITOR_ROLE;
+
+pub mod erc721;
 pub mod receiver;
 pub mod state;
 pub mod types;
@@ -41,6 +48,20 @@ pub enum NFTError {
     Actor(#[from] ActorError),
     #[error("error encoding ipld value: {0}")]
     Encoding(#[from] EncodingError),
+    #[error("pause error: {0}")]
+    Pause(#[from] PauseError),
+}
+
+impl From<&NFTError> for ExitCode {
+    fn from(error: &NFTError) -> Self {
+        match error {
+            NFTError::NFTState(e) => e.into(),
+            NFTError::Messaging(e) => e.into(),
+            NFTError::Actor(e) => e.into(),
+            NFTError::Encoding(_) => ExitCode::USR_SERIALIZATION,
+            NFTError::Pause(e) => e.into(),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, NFTError>;
@@ -65,6 +86,11 @@ where
         Self { runtime, state }
     }
 
+    /// Returns a reference to the actor runtime.
+    pub fn runtime(&self) -> &ActorRuntime<S, BS> {
+        &self.runtime
+    }
+
     /// Flush state and return Cid for root.
     pub fn flush(&mut self) -> Result<Cid> {
         Ok(self.state.save(&self.runtime)?)
@@ -94,12 +120,35 @@ where
         F: FnOnce(&mut NFTState, &ActorRuntime<S, BS>) -> Result<Res>,
     {
         let mut mutable_state = self.state.clone();
+        // bring older state up to date so the upgrade is saved with this change
+        mutable_state.upgrade(&self.runtime)?;
         let res = f(&mut mutable_state, &self.runtime)?;
         // if closure didn't error save state
         *self.state = mutable_state;
         Ok(res)
     }
 
+    /// Brings state written by an earlier version of the library up to date.
+    ///
+    /// Mutating calls do this automatically. Call it when the owner token lists or operator
+    /// indexes of an older collection must be read before anything has changed it; the upgrade is
+    /// persisted when the actor next saves its state.
+    pub fn upgrade(&mut self) -> Result<()> {
+        self.transaction(|_, _| Ok(()))
+    }
+
+    /// Returns [`PauseError::Paused`] if the collection is paused.
+    fn check_not_paused(&self) -> Result<()> {
+        match &self.state.pause {
+            Some(pause) => Ok(pause.check_not_paused()?),
+            None => Ok(()),
+        }
+    }
+
+    fn pause_state_mut(&mut self) -> Result<&mut PauseState> {
+        Ok(self.state.pause.as_mut().ok_or(PauseError::NotPausable)?)
+    }
+
     /// Check the underlying state for consistency errors.
     pub fn check_invariants(&self) -> std::result::Result<StateSummary, Vec<StateInvariantError>> {
         let (summary, errors) = self.state.check_invariants(&self.runtime);
@@ -136,11 +185,96 @@ where
         Ok(self.state.get_owner(&self.runtime, token_id)?)
     }
 
-    /// Return the metadata for an NFT.
+    /// Return the metadata URI for an NFT.
+    ///
+    /// String metadata is returned as is and links as `ipfs://` URIs. Structured records have no
+    /// URI, so an empty string is returned for them, and their content is only available from
+    /// [`NFT::token_metadata`].
     pub fn metadata(&self, token_id: TokenID) -> Result<String> {
+        let metadata = self.state.get_metadata(&self.runtime, token_id)?;
+        Ok(metadata.uri().unwrap_or_default())
+    }
+
+    /// Return the metadata for an NFT in the form it was stored.
+    pub fn token_metadata(&self, token_id: TokenID) -> Result<TokenMetadata> {
         Ok(self.state.get_metadata(&self.runtime, token_id)?)
     }
 
+    /// Return the CID of an NFT's metadata, if it is a link or a structured record.
+    pub fn metadata_cid(&self, token_id: TokenID) -> Result<Option<Cid>> {
+        Ok(self.state.get_metadata(&self.runtime, token_id)?.cid())
+    }
+
+    /// Return whether an NFT's metadata is frozen.
+    pub fn is_metadata_frozen(&self, token_id: TokenID) -> Result<bool> {
+        Ok(self.state.is_metadata_frozen(&self.runtime, token_id)?)
+    }
+
+    /// Replaces the metadata of an NFT.
+    ///
+    /// Only holders of [`METADATA_EDITOR_ROLE`] in [`NFTState::access_control`] may update
+    /// metadata, and only until the NFT's metadata is frozen.
+    pub fn update_metadata(
+        &mut self,
+        caller: &Address,
+        token_id: TokenID,
+        metadata: TokenMetadata,
+    ) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        self.transaction(|state, bs| Ok(state.update_metadata(&bs, caller, token_id, metadata)?))
+    }
+
+    /// Permanently freezes the metadata of an NFT. Only holders of [`METADATA_EDITOR_ROLE`] may do
+    /// this.
+    pub fn freeze_metadata(&mut self, caller: &Address, token_id: TokenID) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        self.transaction(|state, bs| Ok(state.freeze_metadata(&bs, caller, token_id)?))
+    }
+
+    /// Return whether an operator is approved at account level to act for an owner.
+    pub fn is_account_operator(&self, owner: &Address, operator: &Address) -> Result<bool> {
+        let (owner, operator) =
+            match (self.runtime.resolve_id(owner), self.runtime.resolve_id(operator)) {
+                (Ok(owner), Ok(operator)) => (owner, operator),
+                (Err(MessagingError::AddressNotResolved(_)), _)
+                | (_, Err(MessagingError::AddressNotResolved(_))) => return Ok(false),
+                (Err(e), _) | (_, Err(e)) => return Err(e.into()),
+            };
+        let owner_map = self.state.get_owner_data_hamt(&self.runtime)?;
+        let approved = owner_map
+            .get(&state::actor_id_key(owner))
+            .map_err(StateError::from)?
+            .is_some_and(|data| data.operators.contains_actor(&operator));
+        Ok(approved)
+    }
+
+    /// Returns whether transfers, mints and burns are paused.
+    pub fn is_paused(&self) -> bool {
+        self.state.pause.as_ref().is_some_and(|pause| pause.paused)
+    }
+
+    /// Pauses transfers, mints and burns until unpaused. Reads and approvals still work.
+    ///
+    /// Only the pauser may pause, and only if the collection was given a pauser in
+    /// [`NFTState::pause`].
+    pub fn pause(&mut self, caller: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        Ok(self.pause_state_mut()?.pause(caller)?)
+    }
+
+    /// Resumes transfers, mints and burns. Only the pauser may unpause.
+    pub fn unpause(&mut self, caller: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        Ok(self.pause_state_mut()?.unpause(caller)?)
+    }
+
+    /// Hands the pauser role to another address. Only the current pauser may do this.
+    pub fn set_pauser(&mut self, caller: &Address, pauser: &Address) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        let pauser = self.runtime.resolve_or_init(pauser)?;
+        Ok(self.pause_state_mut()?.set_pauser(caller, pauser)?)
+    }
+
     /// Create new NFTs belonging to the initial_owner. The mint method is not standardised
     /// as part of the actor's interface but this is a useful method at the library level to
     /// generate new tokens that will maintain the necessary state invariants.
@@ -156,11 +290,25 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<MintIntermediate>> {
+        let metadata_array = metadata_array.into_iter().map(TokenMetadata::String).collect();
+        self.mint_with_metadata(operator, initial_owner, metadata_array, operator_data, token_data)
+    }
+
+    /// Like [`NFT::mint`], but with metadata in any of the forms of [`TokenMetadata`].
+    pub fn mint_with_metadata(
+        &mut self,
+        operator: &Address,
+        initial_owner: &Address,
+        metadata_array: Vec<TokenMetadata>,
+        operator_data: RawBytes,
+        token_data: RawBytes,
+    ) -> Result<ReceiverHook<MintIntermediate>> {
+        self.check_not_paused()?;
         let operator = self.runtime.resolve_id(operator)?;
         let initial_owner_id = self.runtime.resolve_or_init(initial_owner)?;
 
         let mint_intermediate = self.transaction(|state, bs| {
-            Ok(state.mint_tokens(&bs, initial_owner_id, metadata_array)?)
+            Ok(state.mint_tokens_with_metadata(&bs, initial_owner_id, metadata_array)?)
         })?;
 
         // params we'll send to the receiver hook
@@ -193,6 +341,7 @@ where
     ///
     /// A burnt [`TokenID`] can never be minted again.
     pub fn burn(&mut self, owner: &Address, token_ids: &[TokenID]) -> Result<u64> {
+        self.check_not_paused()?;
         let owner = self.runtime.resolve_id(owner)?;
 
         let balance = self.transaction(|state, helper| {
@@ -213,6 +362,7 @@ where
         operator: &Address,
         token_ids: &[TokenID],
     ) -> Result<u64> {
+        self.check_not_paused()?;
         let operator = self.runtime.resolve_id(operator)?;
         let owner = self.runtime.resolve_or_init(owner)?;
 
@@ -260,6 +410,33 @@ where
         Ok(())
     }
 
+    /// Set the sole approved operator of an NFT, revoking any others.
+    ///
+    /// - `caller` may be an account-level operator or owner of the NFT.
+    /// - `operator` is the new approved operator, or None to clear all approvals.
+    pub fn set_approved(
+        &mut self,
+        caller: &Address,
+        operator: Option<&Address>,
+        token_id: TokenID,
+    ) -> Result<()> {
+        let caller = self.runtime.resolve_id(caller)?;
+        let operator = operator.map(|op| self.runtime.resolve_or_init(op)).transpose()?;
+
+        self.transaction(|state, bs| {
+            let owner_map = state.get_owner_data_hamt(bs)?;
+            Ok(state.set_token_operator(bs, operator, token_id, |token_data, token_id| {
+                if NFTState::is_account_operator(&owner_map, token_data.owner, caller)? {
+                    Ok(())
+                } else {
+                    NFTState::assert_owns_token(token_data, token_id, caller)
+                }
+            })?)
+        })?;
+
+        Ok(())
+    }
+
     /// Revoke the approval of an operator to transfer a particular NFT.
     ///
     /// - `caller` may be an account-level operator or owner of the NFT.
@@ -325,6 +502,7 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<TransferIntermediate>> {
+        self.check_not_paused()?;
         // Attempt to instantiate the accounts if they don't exist
         let owner_id = self.runtime.resolve_or_init(owner)?;
         let recipient_id = self.runtime.resolve_or_init(recipient)?;
@@ -369,6 +547,7 @@ where
         operator_data: RawBytes,
         token_data: RawBytes,
     ) -> Result<ReceiverHook<TransferIntermediate>> {
+        self.check_not_paused()?;
         // Attempt to instantiate the accounts if they don't exist
         let owner_id = self.runtime.resolve_id(owner)?;
         let operator_id = self.runtime.resolve_id(operator)?;
@@ -442,6 +621,15 @@ where
         Ok(ListTokensReturn { tokens, next_cursor })
     }
 
+    /// Returns the token at a position in the list of tokens owned by an address.
+    ///
+    /// Positions run from zero up to the owner's balance. The order of the list is unspecified and
+    /// changes when a token leaves the owner, as the owner's last token moves into its position.
+    pub fn token_of_owner_by_index(&self, owner: &Address, index: u64) -> Result<TokenID> {
+        let owner_id = self.runtime.resolve_id(owner)?;
+        Ok(self.state.token_of_owner_by_index(&self.runtime, owner_id, index)?)
+    }
+
     /// Returns all the operators approved by an owner for a token.
     pub fn list_token_operators(
         &self,
@@ -471,6 +659,21 @@ where
         Ok(ListOperatorTokensReturn { tokens, next_cursor })
     }
 
+    /// Enumerates the owners that approved an address as an account-level operator.
+    pub fn list_operator_owners(
+        &self,
+        operator: &Address,
+        cursor: RawBytes,
+        limit: u64,
+    ) -> Result<ListOperatorOwnersReturn> {
+        let operator_id = self.runtime.resolve_id(operator)?;
+        let cursor = Cursor::from_bytes(cursor)?;
+        let (owners, next_cursor) =
+            self.state.list_operator_owners(&self.runtime, operator_id, cursor, limit)?;
+        let next_cursor = next_cursor.map(|c| c.to_bytes()).transpose()?;
+        Ok(ListOperatorOwnersReturn { owners, next_cursor })
+    }
+
     /// Returns all the account-level operators approved by an owner.
     pub fn list_account_operators(
         &self,
@@ -522,12 +725,21 @@ impl Cursor {
 mod test {
 
     use fvm_actor_utils::{syscalls::fake_syscalls::FakeSyscalls, util::ActorRuntime};
-    use fvm_ipld_bitfield::bitfield;
+    use fvm_ipld_amt::Amt;
+    use fvm_ipld_bitfield::{bitfield, BitField};
     use fvm_ipld_blockstore::MemoryBlockstore;
-    use fvm_ipld_encoding::RawBytes;
+    use fvm_ipld_encoding::{CborStore, RawBytes};
     use fvm_shared::{address::Address, ActorID};
+    use multihash_codetable::Code;
 
-    use crate::{state::StateError, types::TokenID, NFTError, NFTState, NFT};
+    use fvm_actor_utils::access_control::{
+        AccessControlError, AccessControlState, METADATA_EDITOR_ROLE,
+    };
+    use fvm_actor_utils::pause::{PauseError, PauseState};
+
+    use crate::state::{actor_id_key, StateError, StateInvariantError, STATE_VERSION};
+    use crate::types::{TokenID, TokenMetadata};
+    use crate::{NFTError, NFTState, NFT};
 
     const ALICE_ID: ActorID = 1;
     const ALICE: Address = Address::new_id(ALICE_ID);
@@ -599,6 +811,198 @@ mod test {
         nft.check_invariants().unwrap();
     }
 
+    #[test]
+    fn it_transfers_batches_atomically() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 3]).unwrap();
+        state.mint_tokens(&bs, BOB_ID, vec![String::new(); 1]).unwrap();
+        let (token_data, owner_data) = (state.token_data, state.owner_data);
+
+        // the last token isn't alice's, so none of the batch is transferred
+        let err = state
+            .transfer(&bs, &[0, 1, 3], ALICE_ID, CHARLIE_ID, &|token_data, token_id| {
+                NFTState::assert_owns_token(token_data, token_id, ALICE_ID)
+            })
+            .unwrap_err();
+        assert!(matches!(err, StateError::NotOwner { actor: ALICE_ID, token_id: 3 }));
+        assert_eq!(state.token_data, token_data);
+        assert_eq!(state.owner_data, owner_data);
+
+        state
+            .transfer(&bs, &[0, 1, 2], ALICE_ID, CHARLIE_ID, &|token_data, token_id| {
+                NFTState::assert_owns_token(token_data, token_id, ALICE_ID)
+            })
+            .unwrap();
+        assert_eq!(state.get_balance(&bs, ALICE_ID).unwrap(), 0);
+        assert_eq!(state.get_balance(&bs, CHARLIE_ID).unwrap(), 3);
+        assert_eq!(state.get_owner(&bs, 2).unwrap(), CHARLIE_ID);
+        assert!(state.check_invariants(&bs).1.is_empty());
+    }
+
+    #[test]
+    fn it_checks_owner_token_indexes() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 2]).unwrap();
+        assert!(state.check_invariants(&bs).1.is_empty());
+
+        // drop the last token from alice's list
+        let mut owner_tokens = state.get_owner_tokens_hamt(&bs).unwrap();
+        let root = *owner_tokens.get(&actor_id_key(ALICE_ID)).unwrap().unwrap();
+        let mut alice = Amt::<TokenID, _>::load(&root, &bs).unwrap();
+        alice.delete(1).unwrap();
+        owner_tokens.set(actor_id_key(ALICE_ID), alice.flush().unwrap()).unwrap();
+        state.owner_tokens = owner_tokens.flush().unwrap();
+
+        let (_, errors) = state.check_invariants(&bs);
+        assert!(matches!(
+            errors[..],
+            [StateInvariantError::TokenIndexMismatch { owner: ALICE_ID, .. }]
+        ));
+    }
+
+    #[test]
+    fn it_keeps_owner_token_lists_dense() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 4]).unwrap();
+        let owned_by = |state: &NFTState, owner| {
+            let balance = state.get_balance(&bs, owner).unwrap();
+            (0..balance)
+                .map(|index| state.token_of_owner_by_index(&bs, owner, index).unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        // removing a token moves the owner's last token into its position
+        state
+            .transfer(&bs, &[1], ALICE_ID, BOB_ID, &|token_data, token_id| {
+                NFTState::assert_owns_token(token_data, token_id, ALICE_ID)
+            })
+            .unwrap();
+        assert_eq!(owned_by(&state, ALICE_ID), vec![0, 3, 2]);
+        assert_eq!(owned_by(&state, BOB_ID), vec![1]);
+
+        state
+            .burn_tokens(&bs, ALICE_ID, &[2], |token_data, token_id| {
+                NFTState::assert_owns_token(token_data, token_id, ALICE_ID)
+            })
+            .unwrap();
+        assert_eq!(owned_by(&state, ALICE_ID), vec![0, 3]);
+        let err = state.token_of_owner_by_index(&bs, ALICE_ID, 2).unwrap_err();
+        assert!(matches!(err, StateError::OwnerIndexOutOfBounds { owner: ALICE_ID, index: 2 }));
+        assert!(state.check_invariants(&bs).1.is_empty());
+    }
+
+    #[test]
+    fn it_rebuilds_operator_indexes_of_state_written_before_versioning() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 3]).unwrap();
+        state.approve_for_tokens(&bs, BOB_ID, &[0, 2], |_, _| Ok(())).unwrap();
+        state.approve_for_owner(&bs, ALICE_ID, CHARLIE_ID).unwrap();
+        state.approve_for_owner(&bs, BOB_ID, CHARLIE_ID).unwrap();
+        let original = (state.token_data, state.owner_data, state.next_token, state.total_supply);
+        let cid = bs.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        let mut loaded = NFTState::load(&bs, &cid).unwrap();
+        loaded.upgrade(&bs).unwrap();
+        assert_eq!(loaded.operator_tokens, state.operator_tokens);
+        assert_eq!(loaded.operator_owners, state.operator_owners);
+        let (tokens, _) = loaded.list_operator_tokens(&bs, BOB_ID, None, u64::MAX).unwrap();
+        assert_eq!(tokens, bitfield![1, 0, 1]);
+        let (owners, _) = loaded.list_operator_owners(&bs, CHARLIE_ID, None, u64::MAX).unwrap();
+        assert_eq!(owners.iter().collect::<Vec<_>>(), vec![ALICE_ID, BOB_ID]);
+        assert!(loaded.check_invariants(&bs).1.is_empty());
+
+        // the rebuilt indexes are kept up to date from then on
+        loaded.revoke_for_all(&bs, ALICE_ID, CHARLIE_ID).unwrap();
+        let (owners, _) = loaded.list_operator_owners(&bs, CHARLIE_ID, None, u64::MAX).unwrap();
+        assert_eq!(owners.iter().collect::<Vec<_>>(), vec![BOB_ID]);
+        assert!(loaded.check_invariants(&bs).1.is_empty());
+    }
+
+    #[test]
+    fn it_loads_state_written_before_versioning() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 2]).unwrap();
+        state.mint_tokens(&bs, BOB_ID, vec![String::new(); 1]).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 1]).unwrap();
+
+        // the original layout is a tuple of token data, owner data, next token and total supply
+        let original = (state.token_data, state.owner_data, state.next_token, state.total_supply);
+        let cid = bs.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        // loading leaves the state as it was written, without its indexes
+        let mut loaded = NFTState::load(&bs, &cid).unwrap();
+        assert_eq!(loaded.version, 0);
+        assert_eq!(loaded.get_balance(&bs, ALICE_ID).unwrap(), 3);
+        let err = loaded.token_of_owner_by_index(&bs, ALICE_ID, 0).unwrap_err();
+        assert!(matches!(err, StateError::NotUpgraded { version: 0 }));
+        assert!(loaded.check_invariants(&bs).1.is_empty());
+
+        loaded.upgrade(&bs).unwrap();
+        assert_eq!(loaded.version, STATE_VERSION);
+        // owner token lists are rebuilt from the token array, which lists tokens in ascending order
+        // until they change hands
+        assert_eq!(loaded.token_of_owner_by_index(&bs, ALICE_ID, 2).unwrap(), 3);
+        assert_eq!(loaded.token_of_owner_by_index(&bs, BOB_ID, 0).unwrap(), 2);
+        assert_eq!(loaded.owner_tokens, state.owner_tokens);
+        assert!(loaded.pause.is_none());
+
+        // the owner data keeps its original layout
+        let owner_map = loaded.get_owner_data_hamt(&bs).unwrap();
+        let bob = owner_map.get(&actor_id_key(BOB_ID)).unwrap().unwrap();
+        assert_eq!(fvm_ipld_encoding::to_vec(bob).unwrap(), vec![0x82, 0x01, 0x40]);
+    }
+
+    #[test]
+    fn it_upgrades_state_on_the_first_mutating_call() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&helper).unwrap();
+        state.mint_tokens(&helper, ALICE_ID, vec![String::new(); 2]).unwrap();
+        let original = (state.token_data, state.owner_data, state.next_token, state.total_supply);
+        let cid = helper.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        let mut loaded = NFTState::load(&helper, &cid).unwrap();
+        let mut nft = NFT::wrap(helper, &mut loaded);
+        // queries leave the state as it was loaded
+        assert_eq!(nft.balance_of(&ALICE).unwrap(), 2);
+        assert_eq!(nft.state.version, 0);
+
+        nft.approve_for_owner(&ALICE, &BOB).unwrap();
+        assert_eq!(nft.state.version, STATE_VERSION);
+        assert_eq!(nft.token_of_owner_by_index(&ALICE, 1).unwrap(), 1);
+        let cid = nft.flush().unwrap();
+        assert_eq!(NFTState::load(&nft.runtime, &cid).unwrap().version, STATE_VERSION);
+        nft.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_checks_reverse_operator_indexes() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 2]).unwrap();
+        state.approve_for_tokens(&bs, BOB_ID, &[0, 1], |_, _| Ok(())).unwrap();
+        state.approve_for_owner(&bs, ALICE_ID, CHARLIE_ID).unwrap();
+        assert!(state.check_invariants(&bs).1.is_empty());
+
+        // drop both operators from the indexes
+        let empty_index = NFTState::new(&bs).unwrap().operator_tokens;
+        state.operator_tokens = empty_index;
+        state.operator_owners = empty_index;
+
+        let (_, errors) = state.check_invariants(&bs);
+        assert!(matches!(
+            errors[..],
+            [
+                StateInvariantError::OperatorTokensMismatch { operator: BOB_ID, .. },
+                StateInvariantError::OperatorOwnersMismatch { operator: CHARLIE_ID, .. }
+            ]
+        ));
+    }
+
     #[test]
     fn it_transfers_tokens() {
         let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
@@ -1255,6 +1659,22 @@ mod test {
             let res = nft.list_owned_tokens(&CHARLIE, RawBytes::default(), u64::MAX).unwrap();
             assert_eq!(res.tokens, bitfield![]);
             assert!(res.next_cursor.is_none());
+
+            // Page through alice's tokens
+            let res = nft.list_owned_tokens(&ALICE, RawBytes::default(), 3).unwrap();
+            assert_eq!(res.tokens, bitfield![1, 1, 1]);
+            let res = nft.list_owned_tokens(&ALICE, res.next_cursor.unwrap(), 3).unwrap();
+            assert_eq!(res.tokens, bitfield![0, 0, 0, 1]);
+            assert!(res.next_cursor.is_none());
+
+            // Look up tokens by their position amongst an owner's tokens
+            assert_eq!(nft.token_of_owner_by_index(&BOB, 0).unwrap(), 4);
+            assert_eq!(nft.token_of_owner_by_index(&BOB, 1).unwrap(), 7);
+            let err = nft.token_of_owner_by_index(&BOB, 2).unwrap_err();
+            assert!(matches!(
+                err,
+                NFTError::NFTState(StateError::OwnerIndexOutOfBounds { owner: BOB_ID, index: 2 })
+            ));
         }
 
         // List token operators
@@ -1322,5 +1742,233 @@ mod test {
             let res = nft.list_account_operators(&CHARLIE, RawBytes::default(), u64::MAX).unwrap();
             assert!(res.operators.is_empty());
         }
+
+        // List operator owners
+        {
+            // Alice and bob approved charlie at account level
+            let res = nft.list_operator_owners(&CHARLIE, RawBytes::default(), 1).unwrap();
+            assert_eq!(res.owners, bitfield![0, 1]);
+            let res = nft.list_operator_owners(&CHARLIE, res.next_cursor.unwrap(), 1).unwrap();
+            assert!(res.owners.get(BOB_ID));
+            assert_eq!(res.owners.len(), 1);
+            assert!(res.next_cursor.is_none());
+
+            let res = nft.list_operator_owners(&BOB, RawBytes::default(), u64::MAX).unwrap();
+            assert!(res.owners.is_empty());
+        }
+    }
+
+    #[test]
+    fn it_maintains_reverse_operator_indexes() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&helper).unwrap();
+        let mut nft = NFT::wrap(helper, &mut state);
+
+        let mut hook = nft
+            .mint(&ALICE, &ALICE, vec![String::new(); 4], RawBytes::default(), RawBytes::default())
+            .unwrap();
+        hook.call(&nft.runtime).unwrap();
+        nft.approve(&ALICE, &BOB, &[0, 1, 2, 3]).unwrap();
+        nft.approve(&ALICE, &CHARLIE, &[0]).unwrap();
+        nft.approve_for_owner(&ALICE, &CHARLIE).unwrap();
+        nft.revoke(&ALICE, &BOB, &[3]).unwrap();
+
+        let res = nft.list_operator_tokens(&BOB, RawBytes::default(), u64::MAX).unwrap();
+        assert_eq!(res.tokens, bitfield![1, 1, 1]);
+
+        // transferring and burning tokens clears their approvals from the index
+        let mut hook =
+            nft.transfer(&ALICE, &CHARLIE, &[0], RawBytes::default(), RawBytes::default()).unwrap();
+        hook.call(&nft.runtime).unwrap();
+        nft.burn(&ALICE, &[1]).unwrap();
+        let res = nft.list_operator_tokens(&BOB, RawBytes::default(), u64::MAX).unwrap();
+        assert_eq!(res.tokens, bitfield![0, 0, 1]);
+        let res = nft.list_operator_tokens(&CHARLIE, RawBytes::default(), u64::MAX).unwrap();
+        assert!(res.tokens.is_empty());
+
+        // revoking an account-level operator removes the owner from the index
+        let res = nft.list_operator_owners(&CHARLIE, RawBytes::default(), u64::MAX).unwrap();
+        assert!(res.owners.get(ALICE_ID));
+        nft.revoke_for_all(&ALICE, &CHARLIE).unwrap();
+        let res = nft.list_operator_owners(&CHARLIE, RawBytes::default(), u64::MAX).unwrap();
+        assert!(res.owners.is_empty());
+
+        nft.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn it_pauses_transfers_mints_and_burns() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&helper).unwrap();
+        state.pause = Some(PauseState::new(CHARLIE_ID));
+        let mut nft = NFT::wrap(helper, &mut state);
+
+        let mut hook = nft
+            .mint(&ALICE, &ALICE, vec![String::new(); 2], RawBytes::default(), RawBytes::default())
+            .unwrap();
+        hook.call(&nft.runtime).unwrap();
+
+        // only the pauser can pause
+        let err = nft.pause(&ALICE).unwrap_err();
+        assert!(matches!(err, NFTError::Pause(PauseError::NotPauser { .. })));
+        nft.pause(&CHARLIE).unwrap();
+        assert!(nft.is_paused());
+
+        let paused = |err: NFTError| matches!(err, NFTError::Pause(PauseError::Paused));
+        let err = nft
+            .mint(&ALICE, &ALICE, vec![String::new()], RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert!(paused(err));
+        let err =
+            nft.transfer(&ALICE, &BOB, &[0], RawBytes::default(), RawBytes::default()).unwrap_err();
+        assert!(paused(err));
+        let err = nft.burn(&ALICE, &[0]).unwrap_err();
+        assert!(paused(err));
+
+        // approvals and reads still work
+        nft.approve(&ALICE, &BOB, &[0]).unwrap();
+        let err = nft
+            .transfer_from(&ALICE, &BOB, &BOB, &[0], RawBytes::default(), RawBytes::default())
+            .unwrap_err();
+        assert!(paused(err));
+        let err = nft.burn_from(&ALICE, &BOB, &[0]).unwrap_err();
+        assert!(paused(err));
+        assert_eq!(nft.balance_of(&ALICE).unwrap(), 2);
+
+        nft.unpause(&CHARLIE).unwrap();
+        nft.burn(&ALICE, &[1]).unwrap();
+        assert_eq!(nft.balance_of(&ALICE).unwrap(), 1);
+
+        // collections without a pauser can't be paused
+        nft.state.pause = None;
+        let err = nft.pause(&CHARLIE).unwrap_err();
+        assert!(matches!(err, NFTError::Pause(PauseError::NotPausable)));
+    }
+
+    #[test]
+    fn it_updates_and_freezes_metadata() {
+        let helper = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut state = NFTState::new(&helper).unwrap();
+        let mut nft = NFT::wrap(helper, &mut state);
+
+        let link = TokenMetadata::Link(NFTState::new(&nft.runtime).unwrap().token_data);
+        let record = TokenMetadata::Record(RawBytes::serialize(("name", 42)).unwrap());
+        let metadata = vec![TokenMetadata::String("uri".into()), link.clone(), record.clone()];
+        let mut hook = nft
+            .mint_with_metadata(&ALICE, &ALICE, metadata, RawBytes::default(), RawBytes::default())
+            .unwrap();
+        hook.call(&nft.runtime).unwrap();
+
+        // each form is returned as stored, with a URI where one exists
+        assert_eq!(nft.token_metadata(2).unwrap(), record);
+        assert_eq!(nft.metadata(0).unwrap(), "uri");
+        assert_eq!(nft.metadata(1).unwrap(), format!("ipfs://{}", link.cid().unwrap()));
+        // records have no URI, which isn't an error
+        assert_eq!(nft.metadata(2).unwrap(), "");
+        assert_eq!(nft.metadata_cid(0).unwrap(), None);
+        assert_eq!(nft.metadata_cid(1).unwrap(), link.cid());
+        assert!(nft.metadata_cid(2).unwrap().is_some());
+
+        // records must be valid DAG-CBOR
+        let invalid = TokenMetadata::Record(RawBytes::new(vec![0xff, 0x00]));
+        let err = nft
+            .mint_with_metadata(
+                &ALICE,
+                &ALICE,
+                vec![invalid.clone()],
+                RawBytes::default(),
+                RawBytes::default(),
+            )
+            .unwrap_err();
+        assert!(matches!(err, NFTError::NFTState(StateError::InvalidMetadata)));
+
+        // collections without access control can't update metadata
+        let err = nft.update_metadata(&ALICE, 0, link.clone()).unwrap_err();
+        assert!(missing_editor_role(err, ALICE_ID));
+
+        let mut access_control = AccessControlState::new(&nft.runtime, CHARLIE_ID).unwrap();
+        access_control
+            .grant_role(&nft.runtime, CHARLIE_ID, METADATA_EDITOR_ROLE, CHARLIE_ID)
+            .unwrap();
+        nft.state.access_control = Some(access_control);
+        // owning a token doesn't allow updating its metadata
+        let err = nft.update_metadata(&ALICE, 0, link.clone()).unwrap_err();
+        assert!(missing_editor_role(err, ALICE_ID));
+        let err = nft.update_metadata(&CHARLIE, 0, invalid).unwrap_err();
+        assert!(matches!(err, NFTError::NFTState(StateError::InvalidMetadata)));
+        nft.update_metadata(&CHARLIE, 0, link.clone()).unwrap();
+        assert_eq!(nft.token_metadata(0).unwrap(), link);
+
+        // frozen metadata can't change
+        let err = nft.freeze_metadata(&BOB, 0).unwrap_err();
+        assert!(missing_editor_role(err, BOB_ID));
+        nft.freeze_metadata(&CHARLIE, 0).unwrap();
+        assert!(nft.is_metadata_frozen(0).unwrap());
+        assert!(!nft.is_metadata_frozen(1).unwrap());
+        let err = nft.update_metadata(&CHARLIE, 0, record).unwrap_err();
+        assert!(matches!(err, NFTError::NFTState(StateError::MetadataFrozen(0))));
+        let err = nft.freeze_metadata(&CHARLIE, 0).unwrap_err();
+        assert!(matches!(err, NFTError::NFTState(StateError::MetadataFrozen(0))));
+        assert_eq!(nft.token_metadata(0).unwrap(), link);
+
+        // the role can be granted and revoked by the admin
+        let access_control = nft.state.access_control.as_mut().unwrap();
+        access_control.grant_role(&nft.runtime, CHARLIE_ID, METADATA_EDITOR_ROLE, BOB_ID).unwrap();
+        nft.update_metadata(&BOB, 1, TokenMetadata::String("uri".into())).unwrap();
+        let access_control = nft.state.access_control.as_mut().unwrap();
+        access_control.revoke_role(&nft.runtime, CHARLIE_ID, METADATA_EDITOR_ROLE, BOB_ID).unwrap();
+        let err = nft.update_metadata(&BOB, 1, link).unwrap_err();
+        assert!(missing_editor_role(err, BOB_ID));
+        assert_eq!(nft.metadata(1).unwrap(), "uri");
+        nft.check_invariants().unwrap();
+    }
+
+    fn missing_editor_role(err: NFTError, actor: ActorID) -> bool {
+        matches!(
+            err,
+            NFTError::NFTState(StateError::AccessControl(AccessControlError::MissingRole {
+                account,
+                role,
+            })) if account == actor && role == METADATA_EDITOR_ROLE
+        )
+    }
+
+    #[test]
+    fn it_decodes_token_data_written_before_metadata_forms() {
+        let bs = MemoryBlockstore::new();
+        let mut state = NFTState::new(&bs).unwrap();
+        state.mint_tokens(&bs, ALICE_ID, vec![String::new(); 2]).unwrap();
+
+        // token data used to be a tuple of owner, operators and a plain string
+        let mut token_array = Amt::<(ActorID, BitField, String), _>::new(&bs);
+        token_array.set(0, (ALICE_ID, BitField::new(), "uri".into())).unwrap();
+        token_array.set(1, (ALICE_ID, BitField::new(), "legacy".into())).unwrap();
+        let token_data = token_array.flush().unwrap();
+        let original = (token_data, state.owner_data, state.next_token, state.total_supply);
+        let cid = bs.put_cbor(&original, Code::Blake2b256).unwrap();
+
+        let mut loaded = NFTState::load(&bs, &cid).unwrap();
+        loaded.upgrade(&bs).unwrap();
+        assert_eq!(loaded.get_metadata(&bs, 1).unwrap(), TokenMetadata::String("legacy".into()));
+        assert!(!loaded.is_metadata_frozen(&bs, 1).unwrap());
+        assert!(loaded.check_invariants(&bs).1.is_empty());
+
+        // each form is stored as its bare value
+        let link = TokenMetadata::Link(state.owner_data);
+        let record = TokenMetadata::Record(RawBytes::serialize(42).unwrap());
+        assert_eq!(
+            fvm_ipld_encoding::to_vec(&TokenMetadata::String("a".into())).unwrap(),
+            b"\x61a"
+        );
+        assert_eq!(
+            fvm_ipld_encoding::to_vec(&link).unwrap(),
+            fvm_ipld_encoding::to_vec(&state.owner_data).unwrap()
+        );
+        assert_eq!(fvm_ipld_encoding::to_vec(&record).unwrap(), vec![0x42, 0x18, 0x2a]);
+        for metadata in [link, record] {
+            let bytes = fvm_ipld_encoding::to_vec(&metadata).unwrap();
+            assert_eq!(fvm_ipld_encoding::from_slice::<TokenMetadata>(&bytes).unwrap(), metadata);
+        }
+        fvm_ipld_encoding::from_slice::<TokenMetadata>(&[0x18, 0x2a]).unwrap_err();
     }
 }
@@ -1,4 +1,6 @@
 use frc42_dispatch::method_hash;
+use frc42_dispatch::selector::Selector;
+use fvm_actor_utils::receiver::evm::{EvmArg, EvmCallback};
 use fvm_actor_utils::receiver::{ReceiverHook, ReceiverHookError, ReceiverType, RecipientData};
 use fvm_ipld_encoding::tuple::*;
 use fvm_ipld_encoding::RawBytes;
@@ -8,6 +10,14 @@ use crate::types::TokenID;
 
 pub const FRC53_TOKEN_TYPE: ReceiverType = method_hash!("FRC53") as u32;
 
+/// Selector of the Solidity callback used to deliver FRC53 tokens to EVM contracts:
+///
+/// `onFRC53TokensReceived(address to, address operator, uint256[] tokenIds, bytes operatorData,
+/// bytes tokenData) returns (bytes4)`
+///
+/// The contract must return this selector to accept the tokens.
+pub const ON_FRC53_TOKENS_RECEIVED: Selector = [0x1f, 0x9c, 0xdf, 0xa0];
+
 pub trait FRC53ReceiverHook<T: RecipientData> {
     fn new_frc53(
         address: Address,
@@ -23,12 +33,14 @@ impl<T: RecipientData> FRC53ReceiverHook<T> for ReceiverHook<T> {
         frc53_params: FRC53TokenReceived,
         result_data: T,
     ) -> std::result::Result<ReceiverHook<T>, ReceiverHookError> {
+        let callback = frc53_params.evm_callback();
         Ok(ReceiverHook::new(
             address,
             RawBytes::serialize(frc53_params)?,
             FRC53_TOKEN_TYPE,
             result_data,
-        ))
+        )
+        .with_evm_callback(callback))
     }
 }
 
@@ -46,3 +58,19 @@ pub struct FRC53TokenReceived {
     /// Additional data specified by the token-actor during transfer/mint.
     pub token_data: RawBytes,
 }
+
+impl FRC53TokenReceived {
+    /// Expresses the receive parameters as a call to [`ON_FRC53_TOKENS_RECEIVED`].
+    pub fn evm_callback(&self) -> EvmCallback {
+        EvmCallback {
+            selector: ON_FRC53_TOKENS_RECEIVED,
+            args: vec![
+                EvmArg::Actor(self.to),
+                EvmArg::Actor(self.operator),
+                EvmArg::Uint64Array(self.token_ids.clone()),
+                EvmArg::Bytes(self.operator_data.to_vec()),
+                EvmArg::Bytes(self.token_data.to_vec()),
+            ],
+        }
+    }
+}
@@ -1,9 +1,13 @@
 //! Abstraction of the on-chain state related to NFT accounting
+use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::mem;
 use std::vec;
 
 use cid::Cid;
+use fvm_actor_utils::access_control::{
+    AccessControlError, AccessControlState, METADATA_EDITOR_ROLE,
+};
+use fvm_actor_utils::pause::PauseState;
 use fvm_actor_utils::receiver::ReceiverHookError;
 use fvm_ipld_amt::Amt;
 use fvm_ipld_amt::Error as AmtError;
@@ -17,6 +21,7 @@ use fvm_ipld_encoding::DAG_CBOR;
 use fvm_ipld_hamt::BytesKey;
 use fvm_ipld_hamt::Error as HamtError;
 use fvm_ipld_hamt::Hamt;
+use fvm_shared::error::ExitCode;
 use fvm_shared::ActorID;
 use integer_encoding::VarInt;
 use multihash_codetable::Code;
@@ -26,11 +31,18 @@ use crate::types::ActorIDSet;
 use crate::types::MintIntermediate;
 use crate::types::MintReturn;
 use crate::types::TokenID;
+use crate::types::TokenMetadata;
 use crate::types::TokenSet;
 use crate::types::TransferIntermediate;
 use crate::types::TransferReturn;
 use crate::util::OperatorSet;
 
+/// Version of the [`NFTState`] layout written by this version of the library.
+///
+/// State written before the layout was versioned decodes as version zero. [`NFTState::upgrade`]
+/// builds the indexes it lacks.
+pub const STATE_VERSION: u64 = 1;
+
 /// Opaque cursor to iterate over internal data structures.
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct Cursor {
@@ -50,7 +62,11 @@ pub struct TokenData {
     pub owner: ActorID,
     // operators on this token
     pub operators: BitField, // or maybe as a Cid to an Amt
-    pub metadata: String,
+    pub metadata: TokenMetadata,
+    /// Frozen metadata can no longer be updated, even by a metadata editor. Absent in token data
+    /// written before metadata could be frozen.
+    #[serde(default)]
+    pub metadata_frozen: bool,
 }
 
 /// Each owner stores their own balance and other indexed data.
@@ -73,6 +89,34 @@ pub struct NFTState {
     pub next_token: TokenID,
     /// The number of minted tokens less the number of burned tokens.
     pub total_supply: u64,
+    // Fields below were added after the layout above was deployed, so they must stay at the end
+    // and decode to a default when missing.
+    /// Layout version of this state. See [`STATE_VERSION`].
+    #[serde(default)]
+    pub version: u64,
+    /// [`Hamt<ActorID, Cid>`] of the root of an [`Amt<u64, TokenID>`] per owner, listing the tokens
+    /// it owns so that they can be read by position.
+    #[serde(default)]
+    pub owner_tokens: Cid,
+    /// [`Amt<TokenID, u64>`] of the position of each token in its owner's list.
+    #[serde(default)]
+    pub token_positions: Cid,
+    /// [`Hamt<ActorID, TokenSet>`] reverse index of the tokens each operator is approved for at
+    /// token level.
+    #[serde(default)]
+    pub operator_tokens: Cid,
+    /// [`Hamt<ActorID, ActorIDSet>`] reverse index of the owners that approved each operator at
+    /// account level.
+    #[serde(default)]
+    pub operator_owners: Cid,
+    /// Pause state, if the collection is pausable. Collections are created without a pauser.
+    #[serde(default)]
+    pub pause: Option<PauseState>,
+    /// Roles of the collection. Holders of [`METADATA_EDITOR_ROLE`] may update and freeze token
+    /// metadata. Collections are created without access control, so their metadata can't change
+    /// after minting.
+    #[serde(default)]
+    pub access_control: Option<AccessControlState>,
 }
 
 // TODO: benchmark and tune these values
@@ -83,6 +127,8 @@ type Result<T> = std::result::Result<T, StateError>;
 
 type Map<'bs, BS, K, V> = Hamt<&'bs BS, V, K>;
 type OwnerMap<'bs, BS> = Map<'bs, BS, BytesKey, OwnerData>;
+type OwnerTokensMap<'bs, BS> = Map<'bs, BS, BytesKey, Cid>;
+type OperatorIndex<'bs, BS> = Map<'bs, BS, BytesKey, BitField>;
 
 #[derive(Error, Debug)]
 pub enum StateError {
@@ -100,11 +146,40 @@ pub enum StateError {
     ReceiverHook(#[from] ReceiverHookError),
     #[error("invalid cursor")]
     InvalidCursor,
+    #[error("actor {owner:?} owns no token at index {index:?}")]
+    OwnerIndexOutOfBounds { owner: ActorID, index: u64 },
+    #[error("access control error: {0}")]
+    AccessControl(#[from] AccessControlError),
+    #[error("metadata of token {0:?} is frozen")]
+    MetadataFrozen(TokenID),
+    #[error("metadata is not a valid DAG-CBOR record")]
+    InvalidMetadata,
+    #[error("state version {version:?} must be upgraded to version {STATE_VERSION:?}")]
+    NotUpgraded { version: u64 },
     /// This error is returned for errors that should never happen.
     #[error("invariant failed: {0}")]
     InvariantFailed(String),
 }
 
+impl From<&StateError> for ExitCode {
+    fn from(error: &StateError) -> Self {
+        match error {
+            StateError::IpldAmt(_) | StateError::IpldHamt(_) => ExitCode::USR_SERIALIZATION,
+            StateError::TokenNotFound(_) => ExitCode::USR_NOT_FOUND,
+            StateError::NotOwner { actor: _, token_id: _ }
+            | StateError::NotAuthorized { actor: _, token_id: _ } => ExitCode::USR_FORBIDDEN,
+            StateError::ReceiverHook(e) => e.into(),
+            StateError::InvalidCursor => ExitCode::USR_ILLEGAL_ARGUMENT,
+            StateError::OwnerIndexOutOfBounds { owner: _, index: _ } => ExitCode::USR_NOT_FOUND,
+            StateError::AccessControl(e) => e.into(),
+            StateError::MetadataFrozen(_) => ExitCode::USR_FORBIDDEN,
+            StateError::InvalidMetadata => ExitCode::USR_ILLEGAL_ARGUMENT,
+            StateError::NotUpgraded { version: _ } => ExitCode::USR_ILLEGAL_STATE,
+            StateError::InvariantFailed(_) => ExitCode::USR_ILLEGAL_STATE,
+        }
+    }
+}
+
 impl NFTState {
     /// Create a new NFT state-tree, without committing it (the root Cid) to a blockstore.
     pub fn new<BS: Blockstore>(store: &BS) -> Result<Self> {
@@ -114,15 +189,32 @@ impl NFTState {
         // Blockstore is still needed to create valid Cids for the Hamts
         let empty_owner_map =
             Hamt::<&BS, OwnerData, ActorID>::new_with_bit_width(store, HAMT_BIT_WIDTH).flush()?;
+        let empty_owner_tokens =
+            OwnerTokensMap::new_with_bit_width(store, HAMT_BIT_WIDTH).flush()?;
+        let empty_token_positions =
+            Amt::<u64, &BS>::new_with_bit_width(store, AMT_BIT_WIDTH).flush()?;
+        let empty_operator_index =
+            OperatorIndex::new_with_bit_width(store, HAMT_BIT_WIDTH).flush()?;
 
         Ok(Self {
             token_data: empty_token_array,
             owner_data: empty_owner_map,
             next_token: 0,
             total_supply: 0,
+            version: STATE_VERSION,
+            owner_tokens: empty_owner_tokens,
+            token_positions: empty_token_positions,
+            operator_tokens: empty_operator_index,
+            operator_owners: empty_operator_index,
+            pause: None,
+            access_control: None,
         })
     }
 
+    /// Loads the state from a blockstore.
+    ///
+    /// State written by an earlier version of the library is returned as it was written, and must
+    /// be brought up to date with [`NFTState::upgrade`] before its indexes can be used.
     pub fn load<BS: Blockstore>(store: &BS, root: &Cid) -> Result<Self> {
         match store.get_cbor::<Self>(root) {
             Ok(Some(state)) => Ok(state),
@@ -131,6 +223,59 @@ impl NFTState {
         }
     }
 
+    /// Brings state written by an earlier version of the library up to [`STATE_VERSION`].
+    ///
+    /// Until then, reading or updating the owner token lists or reverse operator indexes fails
+    /// with [`StateError::NotUpgraded`]. [`NFT`](crate::NFT) upgrades the state before every
+    /// mutating call, so the upgrade is saved along with the first change. Up to date state is left
+    /// unchanged.
+    ///
+    /// Unversioned state has no owner token lists or reverse operator indexes, so they are built
+    /// from the token array and owner map, with each owner's tokens listed in ascending order.
+    /// This reads the whole collection, so it should be saved rather than repeated on every load.
+    pub fn upgrade<BS: Blockstore>(&mut self, store: &BS) -> Result<()> {
+        if self.version == 0 {
+            let mut owned = BTreeMap::<ActorID, Vec<TokenID>>::new();
+            let mut approved_tokens = BTreeMap::<ActorID, TokenSet>::new();
+            self.get_token_data_amt(store)?.for_each(|token_id, data| {
+                owned.entry(data.owner).or_default().push(token_id);
+                for operator in data.operators.iter() {
+                    approved_tokens.entry(operator).or_default().set(token_id);
+                }
+                Ok(())
+            })?;
+            let mut approved_owners = BTreeMap::<ActorID, ActorIDSet>::new();
+            self.get_owner_data_hamt(store)?.for_each(|owner_key, data| {
+                // invalid keys are left for check_invariants to report
+                if let Some(owner) = decode_actor_id(owner_key) {
+                    for operator in data.operators.iter() {
+                        approved_owners.entry(operator).or_default().set(owner);
+                    }
+                }
+                Ok(())
+            })?;
+
+            self.owner_tokens =
+                OwnerTokensMap::new_with_bit_width(store, HAMT_BIT_WIDTH).flush()?;
+            self.token_positions =
+                Amt::<u64, &BS>::new_with_bit_width(store, AMT_BIT_WIDTH).flush()?;
+            // the indexes exist from here on, so they can be filled in
+            self.version = 1;
+            let mut owner_tokens = OwnerTokenIndex::load(self, store)?;
+            for (owner, token_ids) in owned {
+                owner_tokens.push(owner, token_ids)?;
+            }
+            owner_tokens.flush(self)?;
+
+            self.operator_tokens = Self::build_operator_index(store, approved_tokens)?;
+            self.operator_owners = Self::build_operator_index(store, approved_owners)?;
+        }
+        if self.version < STATE_VERSION {
+            self.version = STATE_VERSION;
+        }
+        Ok(())
+    }
+
     pub fn save<BS: Blockstore>(&self, store: &BS) -> Result<Cid> {
         let serialized = match fvm_ipld_encoding::to_vec(self) {
             Ok(s) => s,
@@ -160,6 +305,93 @@ impl NFTState {
         Ok(res)
     }
 
+    /// Returns [`StateError::NotUpgraded`] if the state predates the current version.
+    fn check_upgraded(&self) -> Result<()> {
+        if self.version < STATE_VERSION {
+            return Err(StateError::NotUpgraded { version: self.version });
+        }
+        Ok(())
+    }
+
+    pub fn get_owner_tokens_hamt<'bs, BS: Blockstore>(
+        &self,
+        store: &'bs BS,
+    ) -> Result<OwnerTokensMap<'bs, BS>> {
+        self.check_upgraded()?;
+        let res = OwnerTokensMap::load_with_bit_width(&self.owner_tokens, store, HAMT_BIT_WIDTH)?;
+        Ok(res)
+    }
+
+    pub fn get_token_positions_amt<'bs, BS: Blockstore>(
+        &self,
+        store: &'bs BS,
+    ) -> Result<Amt<u64, &'bs BS>> {
+        self.check_upgraded()?;
+        let res = Amt::load(&self.token_positions, store)?;
+        Ok(res)
+    }
+
+    /// Loads the list of tokens owned by an actor along with its root, if the actor owns any.
+    fn get_owner_token_list<'bs, BS: Blockstore>(
+        &self,
+        store: &'bs BS,
+        owner: ActorID,
+    ) -> Result<Option<(Cid, Amt<TokenID, &'bs BS>)>> {
+        let owner_tokens = self.get_owner_tokens_hamt(store)?;
+        match owner_tokens.get(&actor_id_key(owner))? {
+            Some(root) => Ok(Some((*root, Amt::load(root, store)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_operator_tokens_hamt<'bs, BS: Blockstore>(
+        &self,
+        store: &'bs BS,
+    ) -> Result<OperatorIndex<'bs, BS>> {
+        self.check_upgraded()?;
+        let res = OperatorIndex::load_with_bit_width(&self.operator_tokens, store, HAMT_BIT_WIDTH)?;
+        Ok(res)
+    }
+
+    pub fn get_operator_owners_hamt<'bs, BS: Blockstore>(
+        &self,
+        store: &'bs BS,
+    ) -> Result<OperatorIndex<'bs, BS>> {
+        self.check_upgraded()?;
+        let res = OperatorIndex::load_with_bit_width(&self.operator_owners, store, HAMT_BIT_WIDTH)?;
+        Ok(res)
+    }
+
+    /// Builds one of the reverse operator indexes from each operator's entry.
+    fn build_operator_index<BS: Blockstore>(
+        store: &BS,
+        entries: BTreeMap<ActorID, BitField>,
+    ) -> Result<Cid> {
+        let mut index = OperatorIndex::new_with_bit_width(store, HAMT_BIT_WIDTH);
+        for (operator, entry) in entries {
+            index.set(actor_id_key(operator), entry)?;
+        }
+        Ok(index.flush()?)
+    }
+
+    /// Updates an operator's entry in one of the reverse operator indexes, removing the entry if it
+    /// becomes empty.
+    fn update_operator_index<BS: Blockstore>(
+        index: &mut OperatorIndex<'_, BS>,
+        operator: ActorID,
+        update: impl FnOnce(&mut BitField),
+    ) -> Result<()> {
+        let key = actor_id_key(operator);
+        let mut entry = index.get(&key)?.cloned().unwrap_or_default();
+        update(&mut entry);
+        if entry.is_empty() {
+            index.delete(&key)?;
+        } else {
+            index.set(key, entry)?;
+        }
+        Ok(())
+    }
+
     /// Retrieves the token data amt, asserting that the cursor is valid for the current state. If
     /// the root cid has changed since the cursor was created, the data has mutated and the cursor
     /// is invalid.
@@ -177,6 +409,76 @@ impl NFTState {
     }
 }
 
+/// The owner token lists and token positions of an [`NFTState`], loaded to be updated together.
+///
+/// Each owner's tokens are listed densely in an [`Amt`] so that they can be read by position. A
+/// token is removed by moving the owner's last token into its position.
+struct OwnerTokenIndex<'bs, BS: Blockstore> {
+    store: &'bs BS,
+    lists: OwnerTokensMap<'bs, BS>,
+    positions: Amt<u64, &'bs BS>,
+}
+
+impl<'bs, BS: Blockstore> OwnerTokenIndex<'bs, BS> {
+    fn load(state: &NFTState, store: &'bs BS) -> Result<Self> {
+        Ok(Self {
+            store,
+            lists: state.get_owner_tokens_hamt(store)?,
+            positions: state.get_token_positions_amt(store)?,
+        })
+    }
+
+    fn load_list(&self, owner: ActorID) -> Result<Amt<TokenID, &'bs BS>> {
+        let list = match self.lists.get(&actor_id_key(owner))? {
+            Some(root) => Amt::load(root, self.store)?,
+            None => Amt::new_with_bit_width(self.store, AMT_BIT_WIDTH),
+        };
+        Ok(list)
+    }
+
+    fn store_list(&mut self, owner: ActorID, mut list: Amt<TokenID, &'bs BS>) -> Result<()> {
+        let key = actor_id_key(owner);
+        if list.count() == 0 {
+            self.lists.delete(&key)?;
+        } else {
+            self.lists.set(key, list.flush()?)?;
+        }
+        Ok(())
+    }
+
+    /// Appends tokens to the end of an owner's list.
+    fn push(&mut self, owner: ActorID, token_ids: impl IntoIterator<Item = TokenID>) -> Result<()> {
+        let mut list = self.load_list(owner)?;
+        for token_id in token_ids {
+            let position = list.count();
+            list.set(position, token_id)?;
+            self.positions.set(token_id, position)?;
+        }
+        self.store_list(owner, list)
+    }
+
+    /// Removes a token from its owner's list, moving the owner's last token into its position.
+    fn remove(&mut self, owner: ActorID, token_id: TokenID) -> Result<()> {
+        let not_listed =
+            || StateError::InvariantFailed(format!("token {token_id} not listed for {owner}"));
+        let position = self.positions.delete(token_id)?.ok_or_else(not_listed)?;
+        let mut list = self.load_list(owner)?;
+        let last = list.count().saturating_sub(1);
+        let last_token = list.delete(last)?.ok_or_else(not_listed)?;
+        if position != last {
+            list.set(position, last_token)?;
+            self.positions.set(last_token, position)?;
+        }
+        self.store_list(owner, list)
+    }
+
+    fn flush(mut self, state: &mut NFTState) -> Result<()> {
+        state.owner_tokens = self.lists.flush()?;
+        state.token_positions = self.positions.flush()?;
+        Ok(())
+    }
+}
+
 impl NFTState {
     /// Mint a new token to the specified address.
     pub fn mint_tokens<BS: Blockstore>(
@@ -185,11 +487,27 @@ impl NFTState {
         initial_owner: ActorID,
         metadatas: Vec<String>,
     ) -> Result<MintIntermediate> {
+        let metadatas = metadatas.into_iter().map(TokenMetadata::String).collect();
+        self.mint_tokens_with_metadata(bs, initial_owner, metadatas)
+    }
+
+    /// Mint new tokens to the specified address, with metadata in any of the forms of
+    /// [`TokenMetadata`].
+    pub fn mint_tokens_with_metadata<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        initial_owner: ActorID,
+        metadatas: Vec<TokenMetadata>,
+    ) -> Result<MintIntermediate> {
+        if !metadatas.iter().all(TokenMetadata::is_valid) {
+            return Err(StateError::InvalidMetadata);
+        }
         let first_token_id = self.next_token;
         let num_to_mint = metadatas.len();
 
         let mut token_array = self.get_token_data_amt(bs)?;
         let mut owner_map = self.get_owner_data_hamt(bs)?;
+        let mut owner_tokens = OwnerTokenIndex::load(self, bs)?;
 
         // update owner data map
         let new_owner_data = match owner_map.get(&actor_id_key(initial_owner)) {
@@ -207,16 +525,18 @@ impl NFTState {
             Err(e) => return Err(e.into()),
         };
         owner_map.set(actor_id_key(initial_owner), new_owner_data)?;
+        owner_tokens.push(initial_owner, first_token_id..first_token_id + num_to_mint as u64)?;
 
         // update token data array
-        for mut metadata in metadatas {
+        for metadata in metadatas {
             let token_id = self.next_token;
             token_array.set(
                 token_id,
                 TokenData {
                     owner: initial_owner,
                     operators: BitField::default(),
-                    metadata: mem::take(&mut metadata),
+                    metadata,
+                    metadata_frozen: false,
                 },
             )?;
             self.next_token += 1;
@@ -226,6 +546,7 @@ impl NFTState {
         self.total_supply += num_to_mint as u64;
         self.token_data = token_array.flush()?;
         self.owner_data = owner_map.flush()?;
+        owner_tokens.flush(self)?;
 
         // params for constructing our return value
         Ok(MintIntermediate {
@@ -260,6 +581,7 @@ impl NFTState {
         F: Fn(&TokenData, TokenID) -> Result<()>,
     {
         let mut token_array = self.get_token_data_amt(bs)?;
+        let mut operator_tokens = self.get_operator_tokens_hamt(bs)?;
 
         for &token_id in token_ids {
             let mut token_data =
@@ -268,8 +590,12 @@ impl NFTState {
             token_data.operators.add_operator(operator);
             token_array.set(token_id, token_data)?;
         }
+        Self::update_operator_index(&mut operator_tokens, operator, |tokens| {
+            token_ids.iter().for_each(|&token_id| tokens.set(token_id))
+        })?;
 
         self.token_data = token_array.flush()?;
+        self.operator_tokens = operator_tokens.flush()?;
 
         Ok(())
     }
@@ -288,6 +614,7 @@ impl NFTState {
         F: Fn(&TokenData, TokenID) -> Result<()>,
     {
         let mut token_array = self.get_token_data_amt(bs)?;
+        let mut operator_tokens = self.get_operator_tokens_hamt(bs)?;
         for &token_id in token_ids {
             let mut token_data =
                 token_array.get(token_id)?.ok_or(StateError::TokenNotFound(token_id))?.clone();
@@ -295,8 +622,51 @@ impl NFTState {
             token_data.operators.remove_operator(&operator);
             token_array.set(token_id, token_data)?;
         }
+        Self::update_operator_index(&mut operator_tokens, operator, |tokens| {
+            token_ids.iter().for_each(|&token_id| tokens.unset(token_id))
+        })?;
 
         self.token_data = token_array.flush()?;
+        self.operator_tokens = operator_tokens.flush()?;
+
+        Ok(())
+    }
+
+    /// Replaces the operators of a token with at most one operator.
+    ///
+    /// This is the single-approval model of ERC-721, where approving an operator revokes any
+    /// previous approval and approving no operator clears it.
+    pub fn set_token_operator<F, BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        operator: Option<ActorID>,
+        token_id: TokenID,
+        approve_predicate: F,
+    ) -> Result<()>
+    where
+        F: Fn(&TokenData, TokenID) -> Result<()>,
+    {
+        let mut token_array = self.get_token_data_amt(bs)?;
+        let mut operator_tokens = self.get_operator_tokens_hamt(bs)?;
+        let mut token_data =
+            token_array.get(token_id)?.ok_or(StateError::TokenNotFound(token_id))?.clone();
+        approve_predicate(&token_data, token_id)?;
+        for previous in token_data.operators.iter() {
+            Self::update_operator_index(&mut operator_tokens, previous, |tokens| {
+                tokens.unset(token_id)
+            })?;
+        }
+        token_data.operators = BitField::default();
+        if let Some(operator) = operator {
+            token_data.operators.add_operator(operator);
+            Self::update_operator_index(&mut operator_tokens, operator, |tokens| {
+                tokens.set(token_id)
+            })?;
+        }
+        token_array.set(token_id, token_data)?;
+
+        self.token_data = token_array.flush()?;
+        self.operator_tokens = operator_tokens.flush()?;
 
         Ok(())
     }
@@ -315,18 +685,18 @@ impl NFTState {
         operator: ActorID,
     ) -> Result<()> {
         let mut owner_map = self.get_owner_data_hamt(bs)?;
+        let mut operator_owners = self.get_operator_owners_hamt(bs)?;
 
-        let new_owner_data = match owner_map.get(&actor_id_key(owner))? {
-            Some(data) => {
-                let mut operators = data.operators.clone();
-                operators.add_operator(operator);
-                OwnerData { operators, balance: data.balance }
-            }
+        let mut new_owner_data = match owner_map.get(&actor_id_key(owner))? {
+            Some(data) => data.clone(),
             None => OwnerData { balance: 0, operators: BitField::default() },
         };
+        new_owner_data.operators.add_operator(operator);
         owner_map.set(actor_id_key(owner), new_owner_data)?;
+        Self::update_operator_index(&mut operator_owners, operator, |owners| owners.set(owner))?;
 
         self.owner_data = owner_map.flush()?;
+        self.operator_owners = operator_owners.flush()?;
 
         Ok(())
     }
@@ -341,6 +711,7 @@ impl NFTState {
         operator: ActorID,
     ) -> Result<()> {
         let mut owner_map = self.get_owner_data_hamt(bs)?;
+        let mut operator_owners = self.get_operator_owners_hamt(bs)?;
 
         let new_owner_data = owner_map.get(&actor_id_key(owner))?.map(|existing_data| {
             let mut operators = existing_data.operators.clone();
@@ -356,8 +727,10 @@ impl NFTState {
                 owner_map.set(actor_key, data)?;
             }
         }
+        Self::update_operator_index(&mut operator_owners, operator, |owners| owners.unset(owner))?;
 
         self.owner_data = owner_map.flush()?;
+        self.operator_owners = operator_owners.flush()?;
 
         Ok(())
     }
@@ -382,11 +755,19 @@ impl NFTState {
     {
         let mut token_array = self.get_token_data_amt(bs)?;
         let mut owner_map = self.get_owner_data_hamt(bs)?;
+        let mut owner_tokens = OwnerTokenIndex::load(self, bs)?;
+        let mut operator_tokens = self.get_operator_tokens_hamt(bs)?;
 
         for &token_id in token_ids {
             let token_data =
                 token_array.delete(token_id)?.ok_or(StateError::TokenNotFound(token_id))?;
             burn_predicate(&token_data, token_id)?;
+            owner_tokens.remove(token_data.owner, token_id)?;
+            for operator in token_data.operators.iter() {
+                Self::update_operator_index(&mut operator_tokens, operator, |tokens| {
+                    tokens.unset(token_id)
+                })?;
+            }
         }
 
         // we only reach here if all tokens were burned successfully so assume the caller is valid
@@ -408,6 +789,8 @@ impl NFTState {
         self.total_supply -= token_ids.len() as u64;
         self.token_data = token_array.flush()?;
         self.owner_data = owner_map.flush()?;
+        owner_tokens.flush(self)?;
+        self.operator_tokens = operator_tokens.flush()?;
 
         Ok(new_balance)
     }
@@ -417,6 +800,9 @@ impl NFTState {
     /// The predicate is checked for each token to be transferred, and the entire transfer is
     /// aborted if the predicate fails. It is the caller's responsibility to check that the
     /// actor using this method is permitted to do so.
+    ///
+    /// The token array, owner map and indexes are updated in memory and flushed once for the whole
+    /// batch.
     pub fn transfer<F, BS: Blockstore>(
         &mut self,
         bs: &BS,
@@ -430,18 +816,27 @@ impl NFTState {
     {
         let mut token_array = self.get_token_data_amt(bs)?;
         let mut owner_map = self.get_owner_data_hamt(bs)?;
+        let mut owner_tokens = OwnerTokenIndex::load(self, bs)?;
+        let mut operator_tokens = self.get_operator_tokens_hamt(bs)?;
 
         for &token_id in token_ids {
             // update the token_data to reflect the new owner and clear approved operators
-            self.make_transfer(
+            Self::make_transfer(
                 &mut token_array,
                 &mut owner_map,
+                &mut owner_tokens,
+                &mut operator_tokens,
                 token_id,
                 receiver,
                 transfer_predicate,
             )?;
         }
 
+        self.token_data = token_array.flush()?;
+        self.owner_data = owner_map.flush()?;
+        owner_tokens.flush(self)?;
+        self.operator_tokens = operator_tokens.flush()?;
+
         Ok(TransferIntermediate {
             token_ids: token_ids.into(),
             from: owner,
@@ -451,11 +846,12 @@ impl NFTState {
     }
 
     /// Makes a transfer of a token from one address to another. The caller must verify that such a
-    /// transfer is allowed.
+    /// transfer is allowed, and flush the token array, owner map and indexes afterwards.
     fn make_transfer<F, BS: Blockstore>(
-        &mut self,
         token_array: &mut Amt<TokenData, &BS>,
         owner_map: &mut Hamt<&BS, OwnerData>,
+        owner_tokens: &mut OwnerTokenIndex<'_, BS>,
+        operator_tokens: &mut OperatorIndex<'_, BS>,
         token_id: TokenID,
         receiver: ActorID,
         transfer_predicate: &F,
@@ -468,6 +864,11 @@ impl NFTState {
         // check the transfer against business rules
         transfer_predicate(&old_token_data, token_id)?;
 
+        for operator in old_token_data.operators.iter() {
+            Self::update_operator_index(operator_tokens, operator, |tokens| {
+                tokens.unset(token_id)
+            })?;
+        }
         let new_token_data =
             TokenData { owner: receiver, operators: BitField::default(), ..old_token_data };
         token_array.set(token_id, new_token_data)?;
@@ -495,8 +896,8 @@ impl NFTState {
         };
         owner_map.set(new_owner_key, new_owner_data)?;
 
-        self.token_data = token_array.flush()?;
-        self.owner_data = owner_map.flush()?;
+        owner_tokens.remove(old_token_data.owner, token_id)?;
+        owner_tokens.push(receiver, [token_id])?;
 
         Ok(())
     }
@@ -587,12 +988,87 @@ impl NFTState {
     }
 
     /// Get the metadata for a token.
-    pub fn get_metadata<BS: Blockstore>(&self, bs: &BS, token_id: TokenID) -> Result<String> {
+    pub fn get_metadata<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        token_id: TokenID,
+    ) -> Result<TokenMetadata> {
         let token_data_array = self.get_token_data_amt(bs)?;
         let token = token_data_array.get(token_id)?.ok_or(StateError::TokenNotFound(token_id))?;
         Ok(token.metadata.clone())
     }
 
+    /// Get whether a token's metadata is frozen.
+    pub fn is_metadata_frozen<BS: Blockstore>(&self, bs: &BS, token_id: TokenID) -> Result<bool> {
+        let token_data_array = self.get_token_data_amt(bs)?;
+        let token = token_data_array.get(token_id)?.ok_or(StateError::TokenNotFound(token_id))?;
+        Ok(token.metadata_frozen)
+    }
+
+    /// Replaces the metadata of a token.
+    ///
+    /// Only holders of [`METADATA_EDITOR_ROLE`] may update metadata, and only while the token's
+    /// metadata isn't frozen.
+    pub fn update_metadata<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        token_id: TokenID,
+        metadata: TokenMetadata,
+    ) -> Result<()> {
+        self.check_metadata_editor(bs, caller)?;
+        if !metadata.is_valid() {
+            return Err(StateError::InvalidMetadata);
+        }
+        self.modify_metadata(bs, token_id, |token| token.metadata = metadata)
+    }
+
+    /// Permanently freezes the metadata of a token.
+    ///
+    /// Only holders of [`METADATA_EDITOR_ROLE`] may freeze metadata. Freezing frozen metadata is an
+    /// error, so a caller can tell whether it was already frozen.
+    pub fn freeze_metadata<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        token_id: TokenID,
+    ) -> Result<()> {
+        self.check_metadata_editor(bs, caller)?;
+        self.modify_metadata(bs, token_id, |token| token.metadata_frozen = true)
+    }
+
+    fn check_metadata_editor<BS: Blockstore>(&self, bs: &BS, caller: ActorID) -> Result<()> {
+        match &self.access_control {
+            Some(access_control) => {
+                Ok(access_control.require_role(bs, caller, METADATA_EDITOR_ROLE)?)
+            }
+            None => Err(AccessControlError::MissingRole {
+                account: caller,
+                role: METADATA_EDITOR_ROLE.into(),
+            }
+            .into()),
+        }
+    }
+
+    /// Applies a change to the data of a token whose metadata isn't frozen.
+    fn modify_metadata<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        token_id: TokenID,
+        f: impl FnOnce(&mut TokenData),
+    ) -> Result<()> {
+        let mut token_array = self.get_token_data_amt(bs)?;
+        let mut token =
+            token_array.get(token_id)?.ok_or(StateError::TokenNotFound(token_id))?.clone();
+        if token.metadata_frozen {
+            return Err(StateError::MetadataFrozen(token_id));
+        }
+        f(&mut token);
+        token_array.set(token_id, token)?;
+        self.token_data = token_array.flush()?;
+        Ok(())
+    }
+
     /// Get the owner of a token.
     pub fn get_owner<BS: Blockstore>(&self, bs: &BS, token_id: TokenID) -> Result<ActorID> {
         let token_data_array = self.get_token_data_amt(bs)?;
@@ -620,9 +1096,9 @@ impl NFTState {
         Ok((token_ids, next_cursor))
     }
 
-    /// List the tokens owned by an actor. In the reference implementation this is may be a
-    /// prohibitievly expensive operation as it involves iterating over the entire token set.
-    /// Returns a bitfield of the tokens owned by the actor and a cursor to the next page of data.
+    /// List the tokens owned by an actor, reading only the owner's token list.
+    /// Returns a bitfield of at most `limit` tokens owned by the actor and a cursor to the next page
+    /// of data. Pages are taken in list order, so a page may hold any of the owner's tokens.
     pub fn list_owned_tokens<BS: Blockstore>(
         &self,
         bs: &BS,
@@ -630,22 +1106,45 @@ impl NFTState {
         cursor: Option<Cursor>,
         limit: u64,
     ) -> Result<(TokenSet, Option<Cursor>)> {
-        let token_data_array = self.get_token_amt_for_cursor(bs, &cursor)?;
+        let list = self.get_owner_token_list(bs, owner)?;
+        // cursors page through the owner's list, which changes whenever the owner's tokens do
+        if let Some(cursor) = &cursor {
+            if Some(cursor.root) != list.as_ref().map(|(root, _)| *root) {
+                return Err(StateError::InvalidCursor);
+            }
+        }
+        let Some((root, list)) = list else {
+            return Ok((TokenSet::new(), None));
+        };
 
-        // Build the TokenSet
         let mut token_ids = TokenSet::new();
         let (_, next_key) =
-            token_data_array.for_each_ranged(cursor.map(|r| r.index), Some(limit), |i, data| {
-                if data.owner == owner {
-                    token_ids.set(i);
-                }
+            list.for_each_ranged(cursor.map(|r| r.index), Some(limit), |_, &token_id| {
+                token_ids.set(token_id);
                 Ok(())
             })?;
 
-        let next_cursor = next_key.map(|key| Cursor::new(self.token_data, key));
+        let next_cursor = next_key.map(|key| Cursor::new(root, key));
         Ok((token_ids, next_cursor))
     }
 
+    /// Returns the token at a position in the list of tokens owned by an actor.
+    ///
+    /// Positions run from zero up to the owner's balance. Tokens are listed in the order they were
+    /// received, except that removing a token moves the owner's last token into its position.
+    pub fn token_of_owner_by_index<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        owner: ActorID,
+        index: u64,
+    ) -> Result<TokenID> {
+        let token_id = match self.get_owner_token_list(bs, owner)? {
+            Some((_, list)) => list.get(index)?.copied(),
+            None => None,
+        };
+        token_id.ok_or(StateError::OwnerIndexOutOfBounds { owner, index })
+    }
+
     /// List all the token operators for a given `token_id`.
     pub fn list_token_operators<BS: Blockstore>(
         &self,
@@ -674,7 +1173,8 @@ impl NFTState {
         Ok((actor_set, next_cursor))
     }
 
-    /// Enumerates tokens for which an account is an operator.
+    /// Enumerates tokens for which an account is a token-level operator, reading only the
+    /// operator's entry in the reverse index.
     pub fn list_operator_tokens<BS: Blockstore>(
         &self,
         bs: &BS,
@@ -682,20 +1182,36 @@ impl NFTState {
         cursor: Option<Cursor>,
         limit: u64,
     ) -> Result<(TokenSet, Option<Cursor>)> {
-        let token_data_array = self.get_token_amt_for_cursor(bs, &cursor)?;
-
-        // Build the TokenSet
-        let mut operatable_tokens = TokenSet::new();
-        let (_, next_key) =
-            token_data_array.for_each_ranged(cursor.map(|r| r.index), Some(limit), |i, data| {
-                if data.operators.get(operator) {
-                    operatable_tokens.set(i);
-                }
-                Ok(())
-            })?;
+        if let Some(cursor) = &cursor {
+            if cursor.root != self.operator_tokens {
+                return Err(StateError::InvalidCursor);
+            }
+        }
+        let operator_tokens = self.get_operator_tokens_hamt(bs)?;
+        let Some(tokens) = operator_tokens.get(&actor_id_key(operator))? else {
+            return Ok((TokenSet::new(), None));
+        };
+        Ok(paginate_set(tokens, self.operator_tokens, cursor, limit))
+    }
 
-        let next_cursor = next_key.map(|key| Cursor::new(self.token_data, key));
-        Ok((operatable_tokens, next_cursor))
+    /// Enumerates the owners that approved an account as an account-level operator.
+    pub fn list_operator_owners<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        operator: ActorID,
+        cursor: Option<Cursor>,
+        limit: u64,
+    ) -> Result<(ActorIDSet, Option<Cursor>)> {
+        if let Some(cursor) = &cursor {
+            if cursor.root != self.operator_owners {
+                return Err(StateError::InvalidCursor);
+            }
+        }
+        let operator_owners = self.get_operator_owners_hamt(bs)?;
+        let Some(owners) = operator_owners.get(&actor_id_key(operator))? else {
+            return Ok((ActorIDSet::new(), None));
+        };
+        Ok(paginate_set(owners, self.operator_owners, cursor, limit))
     }
 
     /// List all the token operators for a given account.
@@ -755,15 +1271,26 @@ pub enum StateInvariantError {
     State(#[from] StateError),
     #[error("entry for {0:?} in owner map had no tokens and no operators")]
     ExplicitEmptyOwner(u64),
+    #[error(
+        "the token index of {owner:?} lists {indexed:?} but the token array records {owned:?}"
+    )]
+    TokenIndexMismatch { owner: ActorID, indexed: TokenSet, owned: TokenSet },
+    #[error("token {token_id:?} is listed at position {listed:?} of its owner's tokens but recorded at {recorded:?}")]
+    TokenPositionMismatch { token_id: TokenID, listed: u64, recorded: Option<u64> },
+    #[error("the operator token index of {operator:?} lists {indexed:?} but the token array records approvals for {approved:?}")]
+    OperatorTokensMismatch { operator: ActorID, indexed: TokenSet, approved: TokenSet },
+    #[error("the operator owner index of {operator:?} lists {indexed:?} but the owner map records approvals from {approved:?}")]
+    OperatorOwnersMismatch { operator: ActorID, indexed: ActorIDSet, approved: ActorIDSet },
 }
 
 impl NFTState {
     /**
      * Checks that the state is internally consistent and obeys the specified invariants
      *
-     * Checks that balances in the TokenArray and OwnerMap are consistent. Checks that the total supply
-     * is consistent with the number of tokens in the TokenArray. Checks that the OwnerHamt is clear of
-     * semantically empty entries. Checks that all bytes keys are valid actor ids.
+     * Checks that balances in the TokenArray and OwnerMap, and the owner token lists, are consistent.
+     * Checks that the total supply is consistent with the number of tokens in the TokenArray. Checks
+     * that the OwnerHamt is clear of semantically empty entries. Checks that all bytes keys are valid
+     * actor ids.
      *
      * Returns a state summary that can be used to check application specific invariants and a list
      * of errors that were found.
@@ -819,6 +1346,9 @@ impl NFTState {
 
         // tally the ownership of each token to check for consistency against owner_data
         let mut counted_balances = HashMap::<ActorID, u64>::new();
+        let mut owned_tokens = HashMap::<ActorID, TokenSet>::new();
+        let mut approved_tokens = HashMap::<ActorID, TokenSet>::new();
+        let mut approved_owners = HashMap::<ActorID, ActorIDSet>::new();
 
         let mut token_map = HashMap::<TokenID, TokenData>::new();
         token_data
@@ -827,6 +1357,10 @@ impl NFTState {
                 let owner = data.owner;
                 let count = counted_balances.entry(owner).or_insert(0);
                 *count += 1;
+                owned_tokens.entry(owner).or_default().set(id);
+                for operator in data.operators.iter() {
+                    approved_tokens.entry(operator).or_default().set(id);
+                }
 
                 token_map.insert(id, data.clone());
                 Ok(())
@@ -847,6 +1381,10 @@ impl NFTState {
                         });
                     }
 
+                    for operator in data.operators.iter() {
+                        approved_owners.entry(operator).or_default().set(actor_id);
+                    }
+
                     // if balance is zero and there are no operators, there should be no entry in the owner map
                     if data.balance == 0 && data.operators.is_empty() {
                         errors.push(StateInvariantError::ExplicitEmptyOwner(actor_id));
@@ -861,6 +1399,49 @@ impl NFTState {
             })
             .unwrap();
 
+        // state that hasn't been upgraded yet has no indexes to check
+        if self.version < STATE_VERSION {
+            return (
+                StateSummary {
+                    owner_data: Some(owner_map),
+                    token_data: Some(token_map),
+                    total_supply: self.total_supply,
+                },
+                errors,
+            );
+        }
+
+        // check the owner token lists hold exactly the tokens owned in the token array
+        self.check_owner_token_index(bs, owned_tokens, &mut errors);
+
+        // check the reverse operator indexes are consistent with the approvals they index
+        match self.get_operator_tokens_hamt(bs) {
+            Ok(operator_tokens) => Self::check_operator_index(
+                &operator_tokens,
+                approved_tokens,
+                &mut errors,
+                |operator, indexed, approved| StateInvariantError::OperatorTokensMismatch {
+                    operator,
+                    indexed,
+                    approved,
+                },
+            ),
+            Err(e) => errors.push(e.into()),
+        }
+        match self.get_operator_owners_hamt(bs) {
+            Ok(operator_owners) => Self::check_operator_index(
+                &operator_owners,
+                approved_owners,
+                &mut errors,
+                |operator, indexed, approved| StateInvariantError::OperatorOwnersMismatch {
+                    operator,
+                    indexed,
+                    approved,
+                },
+            ),
+            Err(e) => errors.push(e.into()),
+        }
+
         (
             StateSummary {
                 owner_data: Some(owner_map),
@@ -871,6 +1452,96 @@ impl NFTState {
         )
     }
 
+    /// Checks that each owner's token list holds exactly the tokens it owns, and that each listed
+    /// token's recorded position matches its place in the list.
+    fn check_owner_token_index<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        mut owned: HashMap<ActorID, TokenSet>,
+        errors: &mut Vec<StateInvariantError>,
+    ) {
+        let (owner_tokens, positions) =
+            match (self.get_owner_tokens_hamt(bs), self.get_token_positions_amt(bs)) {
+                (Ok(owner_tokens), Ok(positions)) => (owner_tokens, positions),
+                (Err(e), _) | (_, Err(e)) => {
+                    errors.push(e.into());
+                    return;
+                }
+            };
+
+        owner_tokens
+            .for_each(|owner_key, root| {
+                if let Some(owner) = Self::decode_key_addr(owner_key, errors) {
+                    let mut indexed = TokenSet::new();
+                    match Amt::<TokenID, &BS>::load(root, bs) {
+                        Ok(list) => list.for_each(|position, &token_id| {
+                            indexed.set(token_id);
+                            let recorded = positions.get(token_id)?.copied();
+                            if recorded != Some(position) {
+                                errors.push(StateInvariantError::TokenPositionMismatch {
+                                    token_id,
+                                    listed: position,
+                                    recorded,
+                                });
+                            }
+                            Ok(())
+                        })?,
+                        Err(e) => errors.push(StateError::from(e).into()),
+                    }
+
+                    let expected = owned.remove(&owner).unwrap_or_default();
+                    if indexed != expected {
+                        errors.push(StateInvariantError::TokenIndexMismatch {
+                            owner,
+                            indexed,
+                            owned: expected,
+                        });
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        // owners missing from the index
+        let mut missing: Vec<_> = owned.into_iter().collect();
+        missing.sort_by_key(|(owner, _)| *owner);
+        for (owner, owned) in missing {
+            errors.push(StateInvariantError::TokenIndexMismatch {
+                owner,
+                indexed: TokenSet::new(),
+                owned,
+            });
+        }
+    }
+
+    /// Checks that each operator's entry in a reverse operator index matches the approvals recorded
+    /// for it, and that there are no empty entries.
+    fn check_operator_index<BS: Blockstore>(
+        index: &OperatorIndex<'_, BS>,
+        mut approved: HashMap<ActorID, BitField>,
+        errors: &mut Vec<StateInvariantError>,
+        mismatch: impl Fn(ActorID, BitField, BitField) -> StateInvariantError,
+    ) {
+        index
+            .for_each(|operator_key, indexed| {
+                if let Some(operator) = Self::decode_key_addr(operator_key, errors) {
+                    let expected = approved.remove(&operator).unwrap_or_default();
+                    if indexed.is_empty() || *indexed != expected {
+                        errors.push(mismatch(operator, indexed.clone(), expected));
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        // approvals missing from the index
+        let mut missing: Vec<_> = approved.into_iter().collect();
+        missing.sort_by_key(|(operator, _)| *operator);
+        for (operator, expected) in missing {
+            errors.push(mismatch(operator, BitField::new(), expected));
+        }
+    }
+
     /// Helper to decode keys from bytes, recording errors if they fail.
     fn decode_key_addr(key: &BytesKey, errors: &mut Vec<StateInvariantError>) -> Option<ActorID> {
         match decode_actor_id(key) {
@@ -883,6 +1554,23 @@ impl NFTState {
     }
 }
 
+/// Returns a page of at most `limit` entries of a set, starting from the cursor's index, and a
+/// cursor to the next entry if there is one.
+fn paginate_set(
+    set: &BitField,
+    root: Cid,
+    cursor: Option<Cursor>,
+    limit: u64,
+) -> (BitField, Option<Cursor>) {
+    let range_start = cursor.map(|c| c.index).unwrap_or(0);
+    let mut entries = set.iter().skip_while(|&entry| entry < range_start);
+    let mut page = BitField::new();
+    entries.by_ref().take(limit as usize).for_each(|entry| page.set(entry));
+
+    let next_cursor = entries.next().map(|entry| Cursor::new(root, entry));
+    (page, next_cursor)
+}
+
 pub fn actor_id_key(a: ActorID) -> BytesKey {
     a.encode_var_vec().into()
 }
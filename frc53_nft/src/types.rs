@@ -4,8 +4,13 @@ use fvm_actor_utils::receiver::RecipientData;
 use fvm_ipld_bitfield::BitField;
 use fvm_ipld_encoding::tuple::*;
 use fvm_ipld_encoding::RawBytes;
+use fvm_ipld_encoding::DAG_CBOR;
 use fvm_shared::address::Address;
 use fvm_shared::ActorID;
+use ipld_core::ipld::Ipld;
+use multihash_codetable::{Code, MultihashDigest};
+use serde::de::{self, IgnoredAny};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[cfg(doc)]
 use super::state::Cursor;
@@ -20,6 +25,76 @@ pub type TokenSet = BitField;
 /// corresponds to a ActorID.
 pub type ActorIDSet = BitField;
 
+/// The metadata of a token.
+///
+/// Each form is encoded as its bare value: a string as a CBOR text string, a link as a CID and a
+/// record as a CBOR byte string. Token metadata stored as a plain string before the other forms
+/// existed therefore still decodes.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum TokenMetadata {
+    /// Free-form metadata, usually a URI.
+    String(String),
+    /// A link to metadata held outside the actor, such as on IPFS.
+    Link(Cid),
+    /// A structured record, encoded as DAG-CBOR and stored inline with the token.
+    Record(RawBytes),
+}
+
+impl Serialize for TokenMetadata {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            TokenMetadata::String(uri) => serializer.serialize_str(uri),
+            TokenMetadata::Link(cid) => cid.serialize(serializer),
+            TokenMetadata::Record(record) => record.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenMetadata {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Ipld::deserialize(deserializer)? {
+            Ipld::String(uri) => Ok(TokenMetadata::String(uri)),
+            Ipld::Link(cid) => Ok(TokenMetadata::Link(cid)),
+            Ipld::Bytes(record) => Ok(TokenMetadata::Record(RawBytes::new(record))),
+            _ => Err(de::Error::custom("token metadata must be a string, a link or bytes")),
+        }
+    }
+}
+
+impl TokenMetadata {
+    /// Returns a URI for the metadata: the string itself, or an `ipfs://` URI for a link. Records
+    /// have no URI.
+    pub fn uri(&self) -> Option<String> {
+        match self {
+            TokenMetadata::String(uri) => Some(uri.clone()),
+            TokenMetadata::Link(cid) => Some(format!("ipfs://{cid}")),
+            TokenMetadata::Record(_) => None,
+        }
+    }
+
+    /// Returns the CID of the metadata: the linked CID, or the CID of a record as a DAG-CBOR
+    /// block. Strings have no CID.
+    pub fn cid(&self) -> Option<Cid> {
+        match self {
+            TokenMetadata::String(_) => None,
+            TokenMetadata::Link(cid) => Some(*cid),
+            TokenMetadata::Record(record) => {
+                Some(Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(record.bytes())))
+            }
+        }
+    }
+
+    /// Returns whether the metadata is well formed, that is records are a single DAG-CBOR item.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            TokenMetadata::Record(record) => {
+                fvm_ipld_encoding::from_slice::<IgnoredAny>(record.bytes()).is_ok()
+            }
+            _ => true,
+        }
+    }
+}
+
 /// A trait to be implemented by FRC-0053 compliant actors.
 pub trait FRC53NFT {
     /// A descriptive name for the collection of NFTs in this actor.
@@ -28,8 +103,8 @@ pub trait FRC53NFT {
     /// An abbreviated name for NFTs in this contract.
     fn symbol(&self) -> String;
 
-    /// Gets a link to associated metadata for a given NFT.
-    fn metadata(&self, params: TokenID) -> Cid;
+    /// Gets the metadata for a given NFT, as a string, a link or a structured record.
+    fn metadata(&self, params: TokenID) -> TokenMetadata;
 
     /// Gets the total number of NFTs in this actor.
     fn total_supply(&self) -> u64;
@@ -236,6 +311,21 @@ pub struct ListOperatorTokensReturn {
     pub next_cursor: Option<RawBytes>,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListOperatorOwnersParams {
+    pub operator: Address,
+    /// Opaque serialisation of [`Cursor`], with empty cursor meaning start of list.
+    pub cursor: RawBytes,
+    pub limit: u64,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct ListOperatorOwnersReturn {
+    pub owners: ActorIDSet,
+    /// Opaque serialisation of [`Cursor`], with empty cursor meaning start of list.
+    pub next_cursor: Option<RawBytes>,
+}
+
 #[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
 pub struct ListAccountOperatorsParams {
     pub owner: Address,
@@ -250,3 +340,9 @@ pub struct ListAccountOperatorsReturn {
     /// Opaque serialisation of [`Cursor`], with empty cursor meaning start of list.
     pub next_cursor: Option<RawBytes>,
 }
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct UpdateMetadataParams {
+    pub token_id: TokenID,
+    pub metadata: TokenMetadata,
+}
@@ -0,0 +1,357 @@
+//! Role-based access control for actors.
+//!
+//! Roles are named sets of actors, stored in a HAMT keyed by role name. Each role has an admin
+//! role whose members may grant and revoke it, which defaults to [`ADMIN_ROLE`]. The admin role is
+//! held by a single actor and can only change hands through a two-step transfer, so it can't be
+//! handed to an address that is unable to use it.
+
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_hamt::{BytesKey, Error as HamtError, Hamt};
+use fvm_shared::error::ExitCode;
+use fvm_shared::ActorID;
+use thiserror::Error;
+
+/// The role held by the actor's admin. It is the admin role of every other role by default.
+pub const ADMIN_ROLE: &str = "admin";
+/// Conventional role for actors allowed to mint.
+pub const MINTER_ROLE: &str = "minter";
+/// Conventional role for actors allowed to burn.
+pub const BURNER_ROLE: &str = "burner";
+/// Conventional role for actors allowed to edit metadata.
+pub const METADATA_EDITOR_ROLE: &str = "metadata-editor";
+
+const HAMT_BIT_WIDTH: u32 = 3;
+
+#[derive(Error, Debug)]
+pub enum AccessControlError {
+    #[error("ipld hamt error: {0}")]
+    IpldHamt(#[from] HamtError),
+    #[error("actor {account:?} is missing role {role:?}")]
+    MissingRole { account: ActorID, role: String },
+    #[error("actor {0:?} is not the pending admin")]
+    NotPendingAdmin(ActorID),
+    #[error("the admin role can only change hands by transfer")]
+    AdminRole,
+}
+
+impl From<&AccessControlError> for ExitCode {
+    fn from(error: &AccessControlError) -> Self {
+        match error {
+            AccessControlError::IpldHamt(_) => ExitCode::USR_SERIALIZATION,
+            AccessControlError::MissingRole { account: _, role: _ }
+            | AccessControlError::NotPendingAdmin(_) => ExitCode::USR_FORBIDDEN,
+            AccessControlError::AdminRole => ExitCode::USR_ILLEGAL_ARGUMENT,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, AccessControlError>;
+
+type RoleMap<'bs, BS> = Hamt<&'bs BS, RoleData, BytesKey>;
+
+/// The members of a role and the role that administers it.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct RoleData {
+    pub admin_role: String,
+    /// Members in ascending order.
+    pub members: Vec<ActorID>,
+}
+
+impl Default for RoleData {
+    fn default() -> Self {
+        Self { admin_role: ADMIN_ROLE.into(), members: Vec::new() }
+    }
+}
+
+/// Access control state IPLD structure, for embedding in actor state.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct AccessControlState {
+    /// The actor holding [`ADMIN_ROLE`]. None once the admin has renounced it.
+    pub admin: Option<ActorID>,
+    /// The actor nominated to become admin, until it accepts.
+    pub pending_admin: Option<ActorID>,
+    /// `Map<Role, RoleData>` as a [`Hamt`]. Roles with no members and the default admin role are
+    /// not stored.
+    pub roles: Cid,
+}
+
+impl AccessControlState {
+    /// Creates a new access control state with the given admin and no other role members.
+    pub fn new<BS: Blockstore>(bs: &BS, admin: ActorID) -> Result<Self> {
+        let roles = RoleMap::new_with_bit_width(bs, HAMT_BIT_WIDTH).flush()?;
+        Ok(Self { admin: Some(admin), pending_admin: None, roles })
+    }
+
+    /// Returns whether an actor holds a role.
+    pub fn has_role<BS: Blockstore>(&self, bs: &BS, account: ActorID, role: &str) -> Result<bool> {
+        if role == ADMIN_ROLE {
+            return Ok(self.admin == Some(account));
+        }
+        Ok(self.get_role(bs, role)?.members.binary_search(&account).is_ok())
+    }
+
+    /// Returns [`AccessControlError::MissingRole`] unless the caller holds the role.
+    pub fn require_role<BS: Blockstore>(&self, bs: &BS, caller: ActorID, role: &str) -> Result<()> {
+        if !self.has_role(bs, caller, role)? {
+            return Err(AccessControlError::MissingRole { account: caller, role: role.into() });
+        }
+        Ok(())
+    }
+
+    /// Returns the members of a role in ascending order.
+    pub fn members<BS: Blockstore>(&self, bs: &BS, role: &str) -> Result<Vec<ActorID>> {
+        if role == ADMIN_ROLE {
+            return Ok(self.admin.into_iter().collect());
+        }
+        Ok(self.get_role(bs, role)?.members)
+    }
+
+    /// Returns the role whose members may grant and revoke a role.
+    pub fn role_admin<BS: Blockstore>(&self, bs: &BS, role: &str) -> Result<String> {
+        if role == ADMIN_ROLE {
+            return Ok(ADMIN_ROLE.into());
+        }
+        Ok(self.get_role(bs, role)?.admin_role)
+    }
+
+    /// Grants a role to an account, returning whether it was newly granted.
+    ///
+    /// The caller must hold the role's admin role.
+    pub fn grant_role<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        role: &str,
+        account: ActorID,
+    ) -> Result<bool> {
+        let mut data = self.get_role_for_admin(bs, caller, role)?;
+        let granted = match data.members.binary_search(&account) {
+            Ok(_) => false,
+            Err(index) => {
+                data.members.insert(index, account);
+                true
+            }
+        };
+        self.set_role(bs, role, data)?;
+        Ok(granted)
+    }
+
+    /// Revokes a role from an account, returning whether it held the role.
+    ///
+    /// The caller must hold the role's admin role.
+    pub fn revoke_role<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        role: &str,
+        account: ActorID,
+    ) -> Result<bool> {
+        let data = self.get_role_for_admin(bs, caller, role)?;
+        self.remove_member(bs, role, data, account)
+    }
+
+    /// Gives up a role held by the caller, returning whether it held the role.
+    ///
+    /// Renouncing [`ADMIN_ROLE`] leaves the actor without an admin, permanently freezing the
+    /// membership of any role it administers.
+    pub fn renounce_role<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        role: &str,
+    ) -> Result<bool> {
+        if role == ADMIN_ROLE {
+            if self.admin != Some(caller) {
+                return Ok(false);
+            }
+            self.admin = None;
+            self.pending_admin = None;
+            return Ok(true);
+        }
+        let data = self.get_role(bs, role)?;
+        self.remove_member(bs, role, data, caller)
+    }
+
+    /// Changes the role whose members may grant and revoke a role.
+    ///
+    /// The caller must hold the role's current admin role.
+    pub fn set_role_admin<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        caller: ActorID,
+        role: &str,
+        admin_role: &str,
+    ) -> Result<()> {
+        let mut data = self.get_role_for_admin(bs, caller, role)?;
+        data.admin_role = admin_role.into();
+        self.set_role(bs, role, data)
+    }
+
+    /// Nominates a new admin, who must call [`AccessControlState::accept_admin`] to take over.
+    ///
+    /// The caller must be the admin. A later nomination replaces an earlier one.
+    pub fn transfer_admin(&mut self, caller: ActorID, new_admin: ActorID) -> Result<()> {
+        if self.admin != Some(caller) {
+            return Err(AccessControlError::MissingRole {
+                account: caller,
+                role: ADMIN_ROLE.into(),
+            });
+        }
+        self.pending_admin = Some(new_admin);
+        Ok(())
+    }
+
+    /// Completes an admin transfer. The caller must be the nominated admin.
+    pub fn accept_admin(&mut self, caller: ActorID) -> Result<()> {
+        if self.pending_admin != Some(caller) {
+            return Err(AccessControlError::NotPendingAdmin(caller));
+        }
+        self.admin = Some(caller);
+        self.pending_admin = None;
+        Ok(())
+    }
+
+    /// Get the role map as a HAMT.
+    pub fn get_role_map<'bs, BS: Blockstore>(&self, bs: &'bs BS) -> Result<RoleMap<'bs, BS>> {
+        Ok(RoleMap::load_with_bit_width(&self.roles, bs, HAMT_BIT_WIDTH)?)
+    }
+
+    fn get_role<BS: Blockstore>(&self, bs: &BS, role: &str) -> Result<RoleData> {
+        let role_map = self.get_role_map(bs)?;
+        Ok(role_map.get(&role_key(role))?.cloned().unwrap_or_default())
+    }
+
+    /// Gets a role's data after checking that the caller administers it.
+    fn get_role_for_admin<BS: Blockstore>(
+        &self,
+        bs: &BS,
+        caller: ActorID,
+        role: &str,
+    ) -> Result<RoleData> {
+        if role == ADMIN_ROLE {
+            return Err(AccessControlError::AdminRole);
+        }
+        let data = self.get_role(bs, role)?;
+        self.require_role(bs, caller, &data.admin_role)?;
+        Ok(data)
+    }
+
+    fn remove_member<BS: Blockstore>(
+        &mut self,
+        bs: &BS,
+        role: &str,
+        mut data: RoleData,
+        account: ActorID,
+    ) -> Result<bool> {
+        match data.members.binary_search(&account) {
+            Ok(index) => {
+                data.members.remove(index);
+                self.set_role(bs, role, data)?;
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    fn set_role<BS: Blockstore>(&mut self, bs: &BS, role: &str, data: RoleData) -> Result<()> {
+        let mut role_map = self.get_role_map(bs)?;
+        if data == RoleData::default() {
+            role_map.delete(&role_key(role))?;
+        } else {
+            role_map.set(role_key(role), data)?;
+        }
+        self.roles = role_map.flush()?;
+        Ok(())
+    }
+}
+
+fn role_key(role: &str) -> BytesKey {
+    role.as_bytes().into()
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::ActorID;
+
+    use super::{AccessControlError, AccessControlState, ADMIN_ROLE, BURNER_ROLE, MINTER_ROLE};
+
+    const ADMIN: ActorID = 1;
+    const ALICE: ActorID = 2;
+    const BOB: ActorID = 3;
+
+    #[test]
+    fn it_grants_and_revokes_roles() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = AccessControlState::new(bs, ADMIN).unwrap();
+        let empty_roles = state.roles;
+        assert!(state.has_role(bs, ADMIN, ADMIN_ROLE).unwrap());
+        assert!(!state.has_role(bs, ALICE, MINTER_ROLE).unwrap());
+
+        assert!(state.grant_role(bs, ADMIN, MINTER_ROLE, BOB).unwrap());
+        assert!(state.grant_role(bs, ADMIN, MINTER_ROLE, ALICE).unwrap());
+        assert!(!state.grant_role(bs, ADMIN, MINTER_ROLE, ALICE).unwrap());
+        assert_eq!(state.members(bs, MINTER_ROLE).unwrap(), vec![ALICE, BOB]);
+        state.require_role(bs, ALICE, MINTER_ROLE).unwrap();
+        // roles are independent
+        assert!(!state.has_role(bs, ALICE, BURNER_ROLE).unwrap());
+
+        assert!(state.revoke_role(bs, ADMIN, MINTER_ROLE, ALICE).unwrap());
+        assert!(!state.revoke_role(bs, ADMIN, MINTER_ROLE, ALICE).unwrap());
+        assert!(state.renounce_role(bs, BOB, MINTER_ROLE).unwrap());
+        let err = state.require_role(bs, ALICE, MINTER_ROLE).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        assert_eq!(err.to_string(), "actor 2 is missing role \"minter\"");
+
+        // empty roles are removed from the map
+        assert_eq!(state.roles, empty_roles);
+    }
+
+    #[test]
+    fn only_role_admins_can_grant_and_revoke() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = AccessControlState::new(bs, ADMIN).unwrap();
+        let err = state.grant_role(bs, ALICE, MINTER_ROLE, ALICE).unwrap_err();
+        assert!(matches!(err, AccessControlError::MissingRole { account: ALICE, .. }));
+
+        // delegate administration of minters to burners
+        state.set_role_admin(bs, ADMIN, MINTER_ROLE, BURNER_ROLE).unwrap();
+        assert_eq!(state.role_admin(bs, MINTER_ROLE).unwrap(), BURNER_ROLE);
+        state.grant_role(bs, ADMIN, BURNER_ROLE, ALICE).unwrap();
+        state.grant_role(bs, ALICE, MINTER_ROLE, BOB).unwrap();
+        let err = state.revoke_role(bs, ADMIN, MINTER_ROLE, BOB).unwrap_err();
+        assert!(matches!(err, AccessControlError::MissingRole { account: ADMIN, .. }));
+
+        // the admin role can't be granted directly
+        let err = state.grant_role(bs, ADMIN, ADMIN_ROLE, ALICE).unwrap_err();
+        assert!(matches!(err, AccessControlError::AdminRole));
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_ILLEGAL_ARGUMENT);
+    }
+
+    #[test]
+    fn it_transfers_admin_in_two_steps() {
+        let bs = &MemoryBlockstore::new();
+        let mut state = AccessControlState::new(bs, ADMIN).unwrap();
+
+        let err = state.transfer_admin(ALICE, ALICE).unwrap_err();
+        assert!(matches!(err, AccessControlError::MissingRole { account: ALICE, .. }));
+        state.transfer_admin(ADMIN, ALICE).unwrap();
+        // the nomination doesn't take effect until accepted
+        assert!(state.has_role(bs, ADMIN, ADMIN_ROLE).unwrap());
+        let err = state.accept_admin(BOB).unwrap_err();
+        assert!(matches!(err, AccessControlError::NotPendingAdmin(BOB)));
+
+        state.accept_admin(ALICE).unwrap();
+        assert_eq!(state.members(bs, ADMIN_ROLE).unwrap(), vec![ALICE]);
+        assert_eq!(state.pending_admin, None);
+        state.grant_role(bs, ALICE, MINTER_ROLE, BOB).unwrap();
+
+        // renouncing the admin role leaves no admin
+        assert!(state.renounce_role(bs, ALICE, ADMIN_ROLE).unwrap());
+        assert!(state.members(bs, ADMIN_ROLE).unwrap().is_empty());
+        state.grant_role(bs, ALICE, MINTER_ROLE, ALICE).unwrap_err();
+    }
+}
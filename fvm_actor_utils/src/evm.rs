@@ -0,0 +1,470 @@
+//! Utilities for serving calls from Ethereum accounts and contracts on the FEVM.
+//!
+//! Ethereum transactions and EVM contracts reach native actors via the `InvokeEVM` method, passing
+//! Solidity ABI encoded calldata wrapped in a CBOR byte string. This module provides a minimal ABI
+//! codec for the static types, byte strings and arrays used by the token standards, along with
+//! conversions between Ethereum and Filecoin addresses.
+use frc42_dispatch::method_hash;
+use frc42_dispatch::selector::Selector;
+use fvm_ipld_encoding::ipld_block::IpldBlock;
+use fvm_ipld_encoding::{BytesDe, BytesSer};
+use fvm_shared::address::{Address, Payload};
+use fvm_shared::bigint::{BigInt, Sign};
+use fvm_shared::error::ExitCode;
+use fvm_shared::ActorID;
+use thiserror::Error;
+
+use crate::messaging::Messaging;
+
+/// Method number used by the FEVM to invoke contracts, as used by Ethereum transactions sent to
+/// native actors.
+pub const INVOKE_EVM_METHOD_NUM: u64 = method_hash!("InvokeEVM");
+
+/// Actor ID of the Ethereum Address Manager, whose namespace holds f410 addresses.
+pub const EAM_ACTOR_ID: ActorID = 10;
+
+/// Builtin actor type of EVM contracts, as returned by `get_builtin_actor_type`.
+pub const EVM_ACTOR_TYPE: i32 = 14;
+
+/// A 20-byte Ethereum address.
+pub type EthAddress = [u8; 20];
+
+/// A single 32-byte ABI word.
+pub type Word = [u8; 32];
+
+const WORD_SIZE: usize = 32;
+
+/// Prefix of an Ethereum address that masks a Filecoin actor ID, as understood by the FEVM.
+const ID_ADDRESS_PREFIX: [u8; 12] = [0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum AbiError {
+    #[error("calldata does not contain a function selector")]
+    MissingSelector,
+    #[error("unknown function selector {0:02x?}")]
+    UnknownSelector(Selector),
+    #[error("calldata too short to read argument {0}")]
+    Truncated(usize),
+    #[error("argument {index} is not a valid {kind}")]
+    InvalidArgument { index: usize, kind: &'static str },
+    #[error("value does not fit in a uint256")]
+    Overflow,
+    #[error("invalid InvokeEVM params: {0}")]
+    InvalidParams(String),
+}
+
+impl From<&AbiError> for ExitCode {
+    fn from(error: &AbiError) -> Self {
+        match error {
+            AbiError::MissingSelector | AbiError::UnknownSelector(_) => {
+                ExitCode::USR_UNHANDLED_MESSAGE
+            }
+            AbiError::Truncated(_) | AbiError::InvalidArgument { .. } => {
+                ExitCode::USR_ILLEGAL_ARGUMENT
+            }
+            AbiError::Overflow => ExitCode::USR_ILLEGAL_STATE,
+            AbiError::InvalidParams(_) => ExitCode::USR_SERIALIZATION,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, AbiError>;
+
+/// Converts an Ethereum address to its Filecoin equivalent.
+///
+/// Masked ID addresses (`0xff0000000000000000000000` followed by a big-endian actor ID) become ID
+/// addresses, all others become f410 addresses in the EAM namespace.
+pub fn eth_to_address(eth: &EthAddress) -> Address {
+    if eth[..12] == ID_ADDRESS_PREFIX {
+        let id = u64::from_be_bytes(eth[12..].try_into().expect("slice is 8 bytes"));
+        Address::new_id(id)
+    } else {
+        Address::new_delegated(EAM_ACTOR_ID, eth).expect("20 byte subaddress is always valid")
+    }
+}
+
+/// Converts a Filecoin address to the Ethereum address an EVM contract would use for it.
+///
+/// Returns `None` for addresses that have no Ethereum form (i.e. key and actor addresses). Callers
+/// should resolve these to an ID first.
+pub fn address_to_eth(address: &Address) -> Option<EthAddress> {
+    match address.payload() {
+        Payload::ID(id) => Some(id_to_eth(*id)),
+        Payload::Delegated(delegated) if delegated.namespace() == EAM_ACTOR_ID => {
+            delegated.subaddress().try_into().ok()
+        }
+        _ => None,
+    }
+}
+
+/// Returns the masked Ethereum address of an actor ID.
+pub fn id_to_eth(id: ActorID) -> EthAddress {
+    let mut eth = [0u8; 20];
+    eth[..12].copy_from_slice(&ID_ADDRESS_PREFIX);
+    eth[12..].copy_from_slice(&id.to_be_bytes());
+    eth
+}
+
+/// Returns the Ethereum address of an actor, for reporting to EVM callers.
+///
+/// This is the actor's f410 address if it has one, falling back to its masked ID address.
+pub fn actor_eth_address(msg: &dyn Messaging, id: ActorID) -> EthAddress {
+    msg.lookup_delegated_address(id)
+        .and_then(|address| address_to_eth(&address))
+        .unwrap_or_else(|| id_to_eth(id))
+}
+
+/// Extracts the ABI calldata from the params of an `InvokeEVM` message.
+///
+/// Calls without params (e.g. plain value transfers) have empty calldata.
+pub fn calldata_from_params(params: Option<IpldBlock>) -> Result<Vec<u8>> {
+    match params {
+        Some(block) => block
+            .deserialize::<BytesDe>()
+            .map(|bytes| bytes.0)
+            .map_err(|e| AbiError::InvalidParams(e.to_string())),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Wraps ABI encoded data in a CBOR byte string, as expected in the params and return of an
+/// `InvokeEVM` message.
+pub fn return_data_block(data: &[u8]) -> Result<IpldBlock> {
+    IpldBlock::serialize_cbor(&BytesSer(data))
+        .map_err(|e| AbiError::InvalidParams(e.to_string()))
+        .map(|block| block.expect("byte strings are never empty blocks"))
+}
+
+/// Reads arguments from Solidity ABI encoded data.
+///
+/// Arguments are addressed by their index in the head of the encoding. Dynamic types are followed
+/// through their offsets.
+#[derive(Clone, Copy, Debug)]
+pub struct AbiDecoder<'a> {
+    args: &'a [u8],
+}
+
+impl<'a> AbiDecoder<'a> {
+    /// Splits calldata into its function selector and a decoder for its arguments.
+    pub fn from_calldata(calldata: &'a [u8]) -> Result<(Selector, Self)> {
+        if calldata.len() < 4 {
+            return Err(AbiError::MissingSelector);
+        }
+        let (selector, args) = calldata.split_at(4);
+        Ok((selector.try_into().expect("slice is 4 bytes"), Self { args }))
+    }
+
+    /// Creates a decoder for ABI encoded arguments without a selector, such as return data.
+    pub fn new(args: &'a [u8]) -> Self {
+        Self { args }
+    }
+
+    fn word_at(&self, offset: usize, index: usize) -> Result<&'a Word> {
+        let end = offset.checked_add(WORD_SIZE).ok_or(AbiError::Truncated(index))?;
+        self.args
+            .get(offset..end)
+            .map(|word| word.try_into().expect("slice is a word"))
+            .ok_or(AbiError::Truncated(index))
+    }
+
+    /// Reads the word at the given argument index.
+    pub fn word(&self, index: usize) -> Result<&'a Word> {
+        self.word_at(index * WORD_SIZE, index)
+    }
+
+    /// Reads a `uint256` argument.
+    pub fn uint256(&self, index: usize) -> Result<BigInt> {
+        Ok(BigInt::from_bytes_be(Sign::Plus, self.word(index)?))
+    }
+
+    /// Reads a `uint256` argument that must fit in a [`u64`] (e.g. a token ID).
+    pub fn uint64(&self, index: usize) -> Result<u64> {
+        word_to_u64(self.word(index)?).ok_or(AbiError::InvalidArgument { index, kind: "uint64" })
+    }
+
+    /// Reads a `bool` argument.
+    pub fn bool(&self, index: usize) -> Result<bool> {
+        match word_to_u64(self.word(index)?) {
+            Some(0) => Ok(false),
+            Some(1) => Ok(true),
+            _ => Err(AbiError::InvalidArgument { index, kind: "bool" }),
+        }
+    }
+
+    /// Reads an `address` argument, converting it with [`eth_to_address`].
+    pub fn address(&self, index: usize) -> Result<Address> {
+        let word = self.word(index)?;
+        if word[..12].iter().any(|b| *b != 0) {
+            return Err(AbiError::InvalidArgument { index, kind: "address" });
+        }
+        Ok(eth_to_address(word[12..].try_into().expect("slice is 20 bytes")))
+    }
+
+    /// Reads a `bytes4` argument.
+    pub fn bytes4(&self, index: usize) -> Result<Selector> {
+        let word = self.word(index)?;
+        if word[4..].iter().any(|b| *b != 0) {
+            return Err(AbiError::InvalidArgument { index, kind: "bytes4" });
+        }
+        Ok(word[..4].try_into().expect("slice is 4 bytes"))
+    }
+
+    /// Reads a dynamic `bytes` argument.
+    pub fn bytes(&self, index: usize) -> Result<&'a [u8]> {
+        let invalid = AbiError::InvalidArgument { index, kind: "bytes" };
+        let offset = self.uint64(index).map_err(|_| invalid.clone())? as usize;
+        let len = word_to_u64(self.word_at(offset, index)?).ok_or(invalid.clone())? as usize;
+        let start = offset + WORD_SIZE;
+        let end = start.checked_add(len).ok_or(invalid)?;
+        self.args.get(start..end).ok_or(AbiError::Truncated(index))
+    }
+}
+
+/// Reads a word as a [`u64`] if it fits.
+fn word_to_u64(word: &Word) -> Option<u64> {
+    if word[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(word[24..].try_into().expect("slice is 8 bytes")))
+}
+
+#[derive(Clone, Debug)]
+enum AbiValue {
+    Static(Word),
+    /// Encoded contents of a dynamic value, referenced by an offset in the head.
+    Dynamic(Vec<u8>),
+}
+
+/// Builds Solidity ABI encoded calldata or return data.
+#[derive(Clone, Debug, Default)]
+pub struct AbiEncoder {
+    selector: Option<Selector>,
+    values: Vec<AbiValue>,
+}
+
+impl AbiEncoder {
+    /// Creates an encoder for return data.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an encoder for calldata of the function with the given selector.
+    pub fn with_selector(selector: Selector) -> Self {
+        Self { selector: Some(selector), values: Vec::new() }
+    }
+
+    /// Appends a raw word.
+    pub fn word(mut self, word: Word) -> Self {
+        self.values.push(AbiValue::Static(word));
+        self
+    }
+
+    /// Appends a `uint256`, failing if the value is negative or too large.
+    pub fn uint256(self, value: &BigInt) -> Result<Self> {
+        Ok(self.word(uint256_word(value)?))
+    }
+
+    /// Appends a `uint256` holding a [`u64`].
+    pub fn uint64(self, value: u64) -> Self {
+        self.word(u64_word(value))
+    }
+
+    /// Appends a `bool`.
+    pub fn bool(self, value: bool) -> Self {
+        self.uint64(value as u64)
+    }
+
+    /// Appends an `address`.
+    pub fn address(self, address: &EthAddress) -> Self {
+        let mut word = [0u8; WORD_SIZE];
+        word[12..].copy_from_slice(address);
+        self.word(word)
+    }
+
+    /// Appends a `bytes4`.
+    pub fn bytes4(self, value: Selector) -> Self {
+        let mut word = [0u8; WORD_SIZE];
+        word[..4].copy_from_slice(&value);
+        self.word(word)
+    }
+
+    /// Appends a dynamic `bytes`.
+    pub fn bytes(mut self, value: &[u8]) -> Self {
+        let mut encoded = u64_word(value.len() as u64).to_vec();
+        encoded.extend_from_slice(value);
+        encoded.resize(WORD_SIZE + value.len().div_ceil(WORD_SIZE) * WORD_SIZE, 0);
+        self.values.push(AbiValue::Dynamic(encoded));
+        self
+    }
+
+    /// Appends a dynamic `string`.
+    pub fn string(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    /// Appends a dynamic `uint256[]` holding [`u64`] values.
+    pub fn uint64_array(mut self, values: &[u64]) -> Self {
+        let mut encoded = u64_word(values.len() as u64).to_vec();
+        values.iter().for_each(|v| encoded.extend_from_slice(&u64_word(*v)));
+        self.values.push(AbiValue::Dynamic(encoded));
+        self
+    }
+
+    /// Returns the encoded data, prefixed by the selector if there is one.
+    pub fn finish(self) -> Vec<u8> {
+        let mut head = self.selector.map(|s| s.to_vec()).unwrap_or_default();
+        let mut tail = Vec::new();
+        let head_len = self.values.len() * WORD_SIZE;
+        for value in self.values {
+            match value {
+                AbiValue::Static(word) => head.extend_from_slice(&word),
+                AbiValue::Dynamic(encoded) => {
+                    head.extend_from_slice(&u64_word((head_len + tail.len()) as u64));
+                    tail.extend(encoded);
+                }
+            }
+        }
+        head.extend(tail);
+        head
+    }
+}
+
+fn u64_word(value: u64) -> Word {
+    let mut word = [0u8; WORD_SIZE];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn uint256_word(value: &BigInt) -> Result<Word> {
+    let (sign, bytes) = value.to_bytes_be();
+    if sign == Sign::Minus || bytes.len() > WORD_SIZE {
+        return Err(AbiError::Overflow);
+    }
+    let mut word = [0u8; WORD_SIZE];
+    word[WORD_SIZE - bytes.len()..].copy_from_slice(&bytes);
+    Ok(word)
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+    use fvm_ipld_encoding::ipld_block::IpldBlock;
+    use fvm_ipld_encoding::BytesSer;
+    use fvm_shared::address::Address;
+    use fvm_shared::bigint::BigInt;
+    use fvm_shared::error::ExitCode;
+
+    use super::*;
+    use crate::syscalls::fake_syscalls::FakeSyscalls;
+    use crate::util::ActorRuntime;
+
+    const ETH: EthAddress = [0x11; 20];
+
+    #[test]
+    fn it_converts_addresses() {
+        let delegated = eth_to_address(&ETH);
+        assert_eq!(delegated, Address::new_delegated(EAM_ACTOR_ID, &ETH).unwrap());
+        assert_eq!(address_to_eth(&delegated), Some(ETH));
+
+        let masked = id_to_eth(1234);
+        assert_eq!(eth_to_address(&masked), Address::new_id(1234));
+        assert_eq!(address_to_eth(&Address::new_id(1234)), Some(masked));
+
+        assert_eq!(address_to_eth(&Address::new_secp256k1(&[1; 65]).unwrap()), None);
+        assert_eq!(address_to_eth(&Address::new_delegated(32, &ETH).unwrap()), None);
+    }
+
+    #[test]
+    fn it_reports_actor_eth_addresses() {
+        let runtime = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let id = runtime.initialize_account(&eth_to_address(&ETH)).unwrap();
+        assert_eq!(actor_eth_address(&runtime, id), ETH);
+
+        // actors without a delegated address are reported by their masked ID
+        let id = runtime.initialize_account(&Address::new_secp256k1(&[1; 65]).unwrap()).unwrap();
+        assert_eq!(actor_eth_address(&runtime, id), id_to_eth(id));
+    }
+
+    #[test]
+    fn it_decodes_calldata() {
+        // transfer(0x1111..11, 1000)
+        let calldata =
+            AbiEncoder::with_selector([0xa9, 0x05, 0x9c, 0xbb]).address(&ETH).uint64(1000).finish();
+        assert_eq!(calldata.len(), 4 + 2 * 32);
+
+        let (selector, args) = AbiDecoder::from_calldata(&calldata).unwrap();
+        assert_eq!(selector, [0xa9, 0x05, 0x9c, 0xbb]);
+        assert_eq!(args.address(0).unwrap(), eth_to_address(&ETH));
+        assert_eq!(args.uint256(1).unwrap(), BigInt::from(1000));
+        assert_eq!(args.uint64(1).unwrap(), 1000);
+        assert_eq!(args.word(2).unwrap_err(), AbiError::Truncated(2));
+        assert_eq!(args.bool(1).unwrap_err(), AbiError::InvalidArgument { index: 1, kind: "bool" });
+
+        assert_eq!(AbiDecoder::from_calldata(&[1, 2]).unwrap_err(), AbiError::MissingSelector);
+    }
+
+    #[test]
+    fn it_rejects_dirty_addresses() {
+        let mut data = AbiEncoder::new().address(&ETH).finish();
+        data[0] = 1;
+        assert_eq!(
+            AbiDecoder::new(&data).address(0).unwrap_err(),
+            AbiError::InvalidArgument { index: 0, kind: "address" }
+        );
+    }
+
+    #[test]
+    fn it_encodes_dynamic_values() {
+        let data = AbiEncoder::new().uint64(7).string("abc").uint64_array(&[1, 2]).finish();
+
+        let mut expected = Vec::new();
+        expected.extend(u64_word(7));
+        expected.extend(u64_word(3 * 32)); // offset of "abc"
+        expected.extend(u64_word(5 * 32)); // offset of the array
+        expected.extend(u64_word(3));
+        let mut abc = [0u8; 32];
+        abc[..3].copy_from_slice(b"abc");
+        expected.extend(abc);
+        expected.extend(u64_word(2));
+        expected.extend(u64_word(1));
+        expected.extend(u64_word(2));
+        assert_eq!(data, expected);
+
+        let decoder = AbiDecoder::new(&data);
+        assert_eq!(decoder.uint64(0).unwrap(), 7);
+        assert_eq!(decoder.bytes(1).unwrap(), b"abc");
+        assert_eq!(AbiDecoder::new(&data[..4 * 32]).bytes(1).unwrap_err(), AbiError::Truncated(1));
+    }
+
+    #[test]
+    fn it_encodes_uint256() {
+        let max = BigInt::from_bytes_be(Sign::Plus, &[0xff; 32]);
+        let data =
+            AbiEncoder::new().uint256(&max).unwrap().bool(true).bytes4([1, 2, 3, 4]).finish();
+        let decoder = AbiDecoder::new(&data);
+        assert_eq!(decoder.uint256(0).unwrap(), max);
+        assert_eq!(
+            decoder.uint64(0).unwrap_err(),
+            AbiError::InvalidArgument { index: 0, kind: "uint64" }
+        );
+        assert!(decoder.bool(1).unwrap());
+        assert_eq!(decoder.bytes4(2).unwrap(), [1, 2, 3, 4]);
+
+        assert_eq!(AbiEncoder::new().uint256(&(max + 1)).unwrap_err(), AbiError::Overflow);
+        assert_eq!(AbiEncoder::new().uint256(&BigInt::from(-1)).unwrap_err(), AbiError::Overflow);
+    }
+
+    #[test]
+    fn it_wraps_invoke_evm_params() {
+        let calldata = vec![1, 2, 3, 4, 5];
+        let params = IpldBlock::serialize_cbor(&BytesSer(&calldata)).unwrap();
+        assert_eq!(calldata_from_params(params).unwrap(), calldata);
+        assert_eq!(calldata_from_params(None).unwrap(), Vec::<u8>::new());
+
+        let block = return_data_block(&calldata).unwrap();
+        assert_eq!(calldata_from_params(Some(block)).unwrap(), calldata);
+
+        let err = calldata_from_params(IpldBlock::serialize_cbor(&42u64).unwrap()).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_SERIALIZATION);
+    }
+}
@@ -1,6 +1,9 @@
+pub mod access_control;
 pub mod actor;
 pub mod blockstore;
+pub mod evm;
 pub mod messaging;
+pub mod pause;
 pub mod receiver;
 
 pub mod shared_blockstore;
@@ -1,13 +1,15 @@
 use frc42_dispatch::method_hash;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_ipld_encoding::Error as IpldError;
-use fvm_sdk::{send, sys::ErrorNumber};
+use fvm_sdk::{actor, send, sys::ErrorNumber};
 use fvm_shared::error::ExitCode;
 use fvm_shared::sys::SendFlags;
-use fvm_shared::{address::Address, econ::TokenAmount};
+use fvm_shared::{address::Address, econ::TokenAmount, ActorID};
 use fvm_shared::{MethodNum, Response};
 use thiserror::Error;
 
+use crate::evm::EVM_ACTOR_TYPE;
+
 pub type Result<T> = std::result::Result<T, MessagingError>;
 
 #[derive(Error, Debug)]
@@ -56,6 +58,19 @@ pub trait Messaging {
         params: Option<IpldBlock>,
         value: TokenAmount,
     ) -> Result<Response>;
+
+    /// Looks up the delegated (f4) address of an actor, if it has one.
+    fn lookup_delegated_address(&self, _actor: ActorID) -> Option<Address> {
+        None
+    }
+
+    /// Returns true if the actor at an address is an EVM contract.
+    ///
+    /// Receiver hooks are delivered to EVM contracts as Solidity callbacks rather than as native
+    /// `Receive` messages.
+    fn is_evm_actor(&self, _address: &Address) -> bool {
+        false
+    }
 }
 
 /// This method number comes from taking the name as "Receive" and applying
@@ -75,4 +90,14 @@ impl Messaging for FvmMessenger {
     ) -> Result<Response> {
         Ok(send::send(to, method, params, value, None, SendFlags::empty())?)
     }
+
+    fn lookup_delegated_address(&self, actor: ActorID) -> Option<Address> {
+        actor::lookup_delegated_address(actor)
+    }
+
+    fn is_evm_actor(&self, address: &Address) -> bool {
+        actor::get_actor_code_cid(address)
+            .and_then(|code| actor::get_builtin_actor_type(&code))
+            .is_some_and(|actor_type| actor_type == EVM_ACTOR_TYPE)
+    }
 }
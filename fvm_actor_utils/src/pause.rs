@@ -0,0 +1,129 @@
+use fvm_ipld_encoding::tuple::*;
+use fvm_shared::error::ExitCode;
+use fvm_shared::ActorID;
+use thiserror::Error;
+
+/// Exit code for operations rejected while paused.
+///
+/// This is the first exit code available for actor-specific errors, so callers can distinguish a
+/// paused actor from one that failed for another reason.
+pub const ERR_PAUSED: ExitCode = ExitCode::new(32);
+
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum PauseError {
+    #[error("operation rejected while paused")]
+    Paused,
+    #[error("{caller:?} is not the authorised pauser {pauser:?}")]
+    NotPauser { caller: ActorID, pauser: ActorID },
+    #[error("pausing is not enabled")]
+    NotPausable,
+}
+
+impl From<&PauseError> for ExitCode {
+    fn from(error: &PauseError) -> Self {
+        match error {
+            PauseError::Paused => ERR_PAUSED,
+            PauseError::NotPauser { caller: _, pauser: _ } => ExitCode::USR_FORBIDDEN,
+            PauseError::NotPausable => ExitCode::USR_ILLEGAL_STATE,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, PauseError>;
+
+/// A paused flag and the actor authorised to toggle it.
+///
+/// Actors embed this in their state to halt operations during an incident. Operations that should
+/// be halted call [`PauseState::check_not_paused`] before making any changes.
+///
+/// The pauser is recorded here rather than as a role in
+/// [`AccessControlState`](crate::access_control::AccessControlState), so it is the only authority
+/// over pausing.
+#[derive(Serialize_tuple, Deserialize_tuple, PartialEq, Eq, Clone, Debug)]
+pub struct PauseState {
+    /// The actor authorised to pause and unpause.
+    pub pauser: ActorID,
+    pub paused: bool,
+}
+
+impl PauseState {
+    /// Creates an unpaused state with the given pauser.
+    pub fn new(pauser: ActorID) -> Self {
+        Self { pauser, paused: false }
+    }
+
+    /// Returns [`PauseError::Paused`] if paused.
+    pub fn check_not_paused(&self) -> Result<()> {
+        if self.paused {
+            return Err(PauseError::Paused);
+        }
+        Ok(())
+    }
+
+    /// Pauses, if the caller is the pauser. Pausing while already paused is a no-op.
+    pub fn pause(&mut self, caller: ActorID) -> Result<()> {
+        self.check_pauser(caller)?;
+        self.paused = true;
+        Ok(())
+    }
+
+    /// Unpauses, if the caller is the pauser. Unpausing while not paused is a no-op.
+    pub fn unpause(&mut self, caller: ActorID) -> Result<()> {
+        self.check_pauser(caller)?;
+        self.paused = false;
+        Ok(())
+    }
+
+    /// Hands the pauser role to another actor, if the caller is the pauser.
+    pub fn set_pauser(&mut self, caller: ActorID, pauser: ActorID) -> Result<()> {
+        self.check_pauser(caller)?;
+        self.pauser = pauser;
+        Ok(())
+    }
+
+    fn check_pauser(&self, caller: ActorID) -> Result<()> {
+        if caller != self.pauser {
+            return Err(PauseError::NotPauser { caller, pauser: self.pauser });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_shared::error::ExitCode;
+
+    use super::{PauseError, PauseState, ERR_PAUSED};
+
+    const PAUSER: u64 = 1;
+    const OTHER: u64 = 2;
+
+    #[test]
+    fn it_pauses_and_unpauses() {
+        let mut state = PauseState::new(PAUSER);
+        state.check_not_paused().unwrap();
+
+        state.pause(PAUSER).unwrap();
+        let err = state.check_not_paused().unwrap_err();
+        assert_eq!(err, PauseError::Paused);
+        assert_eq!(ExitCode::from(&err), ERR_PAUSED);
+
+        state.unpause(PAUSER).unwrap();
+        state.check_not_paused().unwrap();
+    }
+
+    #[test]
+    fn only_the_pauser_can_toggle() {
+        let mut state = PauseState::new(PAUSER);
+        let err = state.pause(OTHER).unwrap_err();
+        assert_eq!(err, PauseError::NotPauser { caller: OTHER, pauser: PAUSER });
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        assert!(!state.paused);
+
+        // the role can be handed over
+        state.set_pauser(PAUSER, OTHER).unwrap();
+        state.pause(OTHER).unwrap();
+        state.unpause(PAUSER).unwrap_err();
+        assert!(state.paused);
+    }
+}
@@ -0,0 +1,49 @@
+use frc42_dispatch::selector::Selector;
+use fvm_shared::bigint::BigInt;
+use fvm_shared::ActorID;
+
+use crate::evm::{actor_eth_address, AbiEncoder, AbiError};
+use crate::messaging::Messaging;
+
+/// An argument to a Solidity receiver callback.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EvmArg {
+    /// An actor, passed as an `address`.
+    Actor(ActorID),
+    /// A `uint256`.
+    Uint256(BigInt),
+    /// A `uint256[]`.
+    Uint64Array(Vec<u64>),
+    /// A dynamic `bytes`.
+    Bytes(Vec<u8>),
+}
+
+/// A receiver hook expressed as a call to a Solidity callback, for delivery to EVM contracts.
+///
+/// The callback must return its own selector as a `bytes4` to accept the assets, as with the
+/// `onERC721Received` convention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvmCallback {
+    /// Selector of the callback function, which is also the expected return value.
+    pub selector: Selector,
+    pub args: Vec<EvmArg>,
+}
+
+impl EvmCallback {
+    /// ABI encodes the callback as calldata.
+    ///
+    /// Actors are passed by their f410 address where they have one, otherwise by their masked ID
+    /// address.
+    pub fn calldata(&self, msg: &dyn Messaging) -> Result<Vec<u8>, AbiError> {
+        let mut encoder = AbiEncoder::with_selector(self.selector);
+        for arg in &self.args {
+            encoder = match arg {
+                EvmArg::Actor(id) => encoder.address(&actor_eth_address(msg, *id)),
+                EvmArg::Uint256(value) => encoder.uint256(value)?,
+                EvmArg::Uint64Array(values) => encoder.uint64_array(values),
+                EvmArg::Bytes(bytes) => encoder.bytes(bytes),
+            };
+        }
+        Ok(encoder.finish())
+    }
+}
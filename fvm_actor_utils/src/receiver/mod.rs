@@ -7,8 +7,15 @@ use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode};
 use num_traits::Zero;
 use thiserror::Error;
 
+use crate::evm::{
+    calldata_from_params, return_data_block, AbiDecoder, AbiError, INVOKE_EVM_METHOD_NUM,
+};
 use crate::messaging::{Messaging, MessagingError, RECEIVER_HOOK_METHOD_NUM};
 
+pub mod evm;
+
+use self::evm::EvmCallback;
+
 /// Parameters for universal receiver.
 ///
 /// Actual payload varies with asset type.
@@ -48,6 +55,10 @@ pub enum ReceiverHookError {
     Messaging(#[from] MessagingError),
     #[error("receiver hook error from {address:?}: exit_code={exit_code:?}, return_data={return_data:?}")]
     Receiver { address: Address, exit_code: ExitCode, return_data: RawBytes },
+    #[error("error encoding evm callback: {0}")]
+    Abi(#[from] AbiError),
+    #[error("evm receiver {address:?} did not accept the assets")]
+    EvmRejected { address: Address },
 }
 
 impl ReceiverHookError {
@@ -74,6 +85,8 @@ impl From<&ReceiverHookError> for ExitCode {
             ReceiverHookError::IpldEncoding(_) => ExitCode::USR_SERIALIZATION,
             ReceiverHookError::Receiver { address: _, return_data: _, exit_code } => *exit_code,
             ReceiverHookError::Messaging(e) => e.into(),
+            ReceiverHookError::Abi(e) => e.into(),
+            ReceiverHookError::EvmRejected { address: _ } => ExitCode::USR_FORBIDDEN,
         }
     }
 }
@@ -94,6 +107,7 @@ pub struct ReceiverHook<T: RecipientData> {
     address: Address,
     token_type: ReceiverType,
     token_params: RawBytes,
+    evm_callback: Option<EvmCallback>,
     called: bool,
     result_data: Option<T>,
 }
@@ -110,11 +124,20 @@ impl<T: RecipientData> ReceiverHook<T> {
             address,
             token_params,
             token_type,
+            evm_callback: None,
             called: false,
             result_data: Some(result_data),
         }
     }
 
+    /// Sets the Solidity callback used to deliver the hook if the receiver is an EVM contract.
+    ///
+    /// Without a callback, EVM receivers are sent the same `Receive` message as native actors.
+    pub fn with_evm_callback(mut self, callback: EvmCallback) -> Self {
+        self.evm_callback = Some(callback);
+        self
+    }
+
     /// Call the receiver hook and return the result.
     ///
     /// Requires the same [`Messaging`] trait as the `Token`. E.g., `hook.call(token.msg())?;`.
@@ -131,6 +154,12 @@ impl<T: RecipientData> ReceiverHook<T> {
 
         self.called = true;
 
+        if let Some(callback) = self.evm_callback.take() {
+            if msg.is_evm_actor(&self.address) {
+                return self.call_evm(msg, callback);
+            }
+        }
+
         let params = UniversalReceiverParams {
             type_: self.token_type,
             payload: mem::take(&mut self.token_params), // once encoded and sent, we don't need this anymore
@@ -162,6 +191,98 @@ impl<T: RecipientData> ReceiverHook<T> {
             )),
         }
     }
+
+    /// Delivers the hook to an EVM contract by invoking its Solidity callback.
+    ///
+    /// The contract accepts by returning the callback's selector. Any other return, or a revert,
+    /// rejects the assets.
+    fn call_evm(
+        &mut self,
+        msg: &dyn Messaging,
+        callback: EvmCallback,
+    ) -> std::result::Result<T, ReceiverHookError> {
+        let calldata = callback.calldata(msg)?;
+        let ret = msg.send(
+            &self.address,
+            INVOKE_EVM_METHOD_NUM,
+            Some(return_data_block(&calldata)?),
+            TokenAmount::zero(),
+        )?;
+
+        if !ret.exit_code.is_success() {
+            return Err(ReceiverHookError::new_receiver_error(
+                self.address,
+                ret.exit_code,
+                ret.return_data,
+            ));
+        }
+
+        let return_data = calldata_from_params(ret.return_data)?;
+        match AbiDecoder::new(&return_data).bytes4(0) {
+            Ok(magic) if magic == callback.selector => {
+                self.result_data.as_mut().unwrap().set_recipient_data(RawBytes::default());
+                Ok(self.result_data.take().unwrap())
+            }
+            _ => Err(ReceiverHookError::EvmRejected { address: self.address }),
+        }
+    }
+}
+
+/// Implements guarded calls to the receiver hooks of a batch operation.
+///
+/// Batch mints and transfers update state for all recipients at once and return this so that state
+/// can be saved before calling the hooks. The hooks are called in order and the batch fails at the
+/// first hook that fails. Since earlier hooks may already have been called, the actor must then
+/// abort to discard the whole batch.
+#[derive(Debug)]
+pub struct BatchReceiverHook<T: RecipientData> {
+    hooks: Vec<ReceiverHook<T>>,
+    called: bool,
+}
+
+impl<T: RecipientData> BatchReceiverHook<T> {
+    /// Construct a new BatchReceiverHook from the hooks of each recipient, in order.
+    pub fn new(hooks: Vec<ReceiverHook<T>>) -> Self {
+        BatchReceiverHook { hooks, called: false }
+    }
+
+    /// Returns the number of hooks in the batch.
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Returns true if the batch has no hooks.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Call each receiver hook in order and return their results.
+    ///
+    /// Returns:
+    ///
+    /// - An error if already called.
+    /// - The error of the first hook call to fail. The remaining hooks are not called.
+    /// - The return data of every hook upon success, in order.
+    pub fn call(&mut self, msg: &dyn Messaging) -> std::result::Result<Vec<T>, ReceiverHookError> {
+        if self.called {
+            return Err(ReceiverHookError::AlreadyCalled);
+        }
+
+        self.called = true;
+
+        let mut results = Vec::with_capacity(self.hooks.len());
+        for i in 0..self.hooks.len() {
+            match self.hooks[i].call(msg) {
+                Ok(res) => results.push(res),
+                Err(e) => {
+                    // the batch is aborted, so the remaining hooks must not panic when dropped
+                    self.hooks[i + 1..].iter_mut().for_each(|hook| hook.called = true);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(results)
+    }
 }
 
 /// Drop implements the panic if not called behaviour.
@@ -183,11 +304,22 @@ mod test {
     use fvm_ipld_encoding::RawBytes;
     use fvm_shared::address::Address;
 
-    use super::{ReceiverHook, RecipientData};
+    use fvm_shared::bigint::BigInt;
+    use fvm_shared::error::ExitCode;
+
+    use super::evm::{EvmArg, EvmCallback};
+    use super::{BatchReceiverHook, ReceiverHook, ReceiverHookError, RecipientData};
+    use crate::evm::{
+        calldata_from_params, id_to_eth, return_data_block, AbiDecoder, AbiEncoder, EVM_ACTOR_TYPE,
+        INVOKE_EVM_METHOD_NUM,
+    };
+    use crate::messaging::RECEIVER_HOOK_METHOD_NUM;
     use crate::{syscalls::fake_syscalls::FakeSyscalls, util::ActorRuntime};
 
     const ALICE: Address = Address::new_id(2);
+    const CALLBACK: [u8; 4] = [0xca, 0x11, 0xba, 0xc4];
 
+    #[derive(Debug)]
     struct TestReturn;
 
     impl RecipientData for TestReturn {
@@ -212,6 +344,76 @@ mod test {
         assert!(util.syscalls.last_message.borrow().is_some());
     }
 
+    fn generate_evm_hook() -> ReceiverHook<TestReturn> {
+        generate_hook().with_evm_callback(EvmCallback {
+            selector: CALLBACK,
+            args: vec![EvmArg::Actor(2), EvmArg::Uint256(BigInt::from(100))],
+        })
+    }
+
+    #[test]
+    fn calls_evm_callback() {
+        let util = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        util.syscalls.set_actor_type(2, EVM_ACTOR_TYPE);
+        let accept = AbiEncoder::new().bytes4(CALLBACK).finish();
+        util.syscalls.next_return_data.replace(Some(return_data_block(&accept).unwrap()));
+
+        generate_evm_hook().call(&util).unwrap();
+
+        let message = util.syscalls.last_message.borrow().clone().unwrap();
+        assert_eq!(message.method, INVOKE_EVM_METHOD_NUM);
+        let calldata = calldata_from_params(message.params).unwrap();
+        let (selector, args) = AbiDecoder::from_calldata(&calldata).unwrap();
+        assert_eq!(selector, CALLBACK);
+        assert_eq!(args.word(0).unwrap()[12..], id_to_eth(2));
+        assert_eq!(args.uint64(1).unwrap(), 100);
+    }
+
+    #[test]
+    fn evm_receivers_must_return_selector() {
+        let util = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        util.syscalls.set_actor_type(2, EVM_ACTOR_TYPE);
+        // the fake runtime echoes the calldata, which isn't an ABI encoded selector
+        let err = generate_evm_hook().call(&util).unwrap_err();
+        assert!(matches!(err, ReceiverHookError::EvmRejected { address: ALICE }));
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+    }
+
+    #[test]
+    fn calls_native_receivers_with_evm_callback() {
+        let util = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        generate_evm_hook().call(&util).unwrap();
+        let message = util.syscalls.last_message.borrow().clone().unwrap();
+        assert_eq!(message.method, RECEIVER_HOOK_METHOD_NUM);
+    }
+
+    #[test]
+    fn calls_batch_hooks_in_order() {
+        let util = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        let mut batch = BatchReceiverHook::new(vec![generate_hook(), generate_evm_hook()]);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.call(&util).unwrap().len(), 2);
+        assert!(matches!(batch.call(&util).unwrap_err(), ReceiverHookError::AlreadyCalled));
+    }
+
+    #[test]
+    fn stops_batch_at_first_failure() {
+        let util = ActorRuntime::<FakeSyscalls, MemoryBlockstore>::new_test_runtime();
+        util.syscalls.set_actor_type(2, EVM_ACTOR_TYPE);
+        // the evm hook rejects, so the last hook is never called but doesn't panic when dropped
+        let mut batch = BatchReceiverHook::new(vec![
+            generate_hook().with_evm_callback(EvmCallback { selector: CALLBACK, args: vec![] }),
+            generate_evm_hook(),
+            generate_hook(),
+        ]);
+        let accept = AbiEncoder::new().bytes4(CALLBACK).finish();
+        util.syscalls.next_return_data.replace(Some(return_data_block(&accept).unwrap()));
+        let err = batch.call(&util).unwrap_err();
+        assert!(matches!(err, ReceiverHookError::EvmRejected { address: ALICE }));
+        let message = util.syscalls.last_message.borrow().clone().unwrap();
+        assert_eq!(message.method, INVOKE_EVM_METHOD_NUM);
+    }
+
     #[test]
     #[should_panic]
     fn panics_if_not_called() {
@@ -1,13 +1,20 @@
 use std::{cell::RefCell, collections::HashMap};
 
+use cid::multihash::Multihash;
 use cid::Cid;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
+use fvm_ipld_encoding::IPLD_RAW;
+use fvm_shared::address::Protocol;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::{Signature, SignatureType};
 use fvm_shared::{
     address::Address, econ::TokenAmount, error::ErrorNumber, error::ExitCode, ActorID, Response,
 };
 
 use super::Syscalls;
 
+const IDENTITY_HASH: u64 = 0x0;
+
 #[derive(Clone, Default, Debug)]
 pub struct TestMessage {
     pub method: u64,
@@ -34,6 +41,14 @@ pub struct FakeSyscalls {
     pub last_message: RefCell<Option<TestMessage>>,
     /// Flag to control message success.
     pub abort_next_send: RefCell<bool>,
+    /// Return data for the next message sent, instead of echoing its params.
+    pub next_return_data: RefCell<Option<IpldBlock>>,
+
+    /// Builtin actor types of actors in this runtime. Other actors have no code CID.
+    pub actor_types: RefCell<HashMap<ActorID, i32>>,
+
+    /// The current epoch.
+    pub epoch: RefCell<ChainEpoch>,
 }
 
 impl FakeSyscalls {
@@ -41,6 +56,30 @@ impl FakeSyscalls {
     pub fn set_caller_id(&self, new_id: ActorID) {
         self.caller_id.replace(new_id);
     }
+
+    /// Set the builtin actor type of an actor, giving it a code CID.
+    pub fn set_actor_type(&self, actor: ActorID, actor_type: i32) {
+        self.actor_types.borrow_mut().insert(actor, actor_type);
+    }
+
+    /// Set the current epoch.
+    pub fn set_epoch(&self, epoch: ChainEpoch) {
+        self.epoch.replace(epoch);
+    }
+
+    /// Produces a fake signature that this runtime accepts for the signer and plaintext.
+    ///
+    /// Fake signatures are the signer's address bytes followed by the plaintext, so any change to
+    /// either invalidates them.
+    pub fn sign(signer: &Address, plaintext: &[u8]) -> Signature {
+        let sig_type = match signer.protocol() {
+            Protocol::BLS => SignatureType::BLS,
+            _ => SignatureType::Secp256k1,
+        };
+        let mut bytes = signer.to_bytes();
+        bytes.extend_from_slice(plaintext);
+        Signature { sig_type, bytes }
+    }
 }
 
 impl Syscalls for FakeSyscalls {
@@ -98,7 +137,8 @@ impl Syscalls for FakeSyscalls {
             let message = TestMessage { method, params: params.clone(), value };
             self.last_message.replace(Some(message));
 
-            Ok(Response { exit_code: ExitCode::OK, return_data: params })
+            let return_data = self.next_return_data.take().or(params);
+            Ok(Response { exit_code: ExitCode::OK, return_data })
         }
     }
 
@@ -111,4 +151,43 @@ impl Syscalls for FakeSyscalls {
         let map = self.addresses.borrow();
         map.get(addr).copied()
     }
+
+    fn lookup_delegated_address(&self, actor: ActorID) -> Option<Address> {
+        let map = self.addresses.borrow();
+        map.iter()
+            .find(|(addr, id)| {
+                **id == actor && addr.protocol() == fvm_shared::address::Protocol::Delegated
+            })
+            .map(|(addr, _)| *addr)
+    }
+
+    fn get_actor_code_cid(&self, addr: &Address) -> Option<Cid> {
+        let actor = self.resolve_address(addr)?;
+        let actor_type = *self.actor_types.borrow().get(&actor)?;
+        // fake code CIDs are the identity hash of the actor type
+        let hash = Multihash::wrap(IDENTITY_HASH, &actor_type.to_be_bytes()).ok()?;
+        Some(Cid::new_v1(IPLD_RAW, hash))
+    }
+
+    fn get_builtin_actor_type(&self, code_cid: &Cid) -> Option<i32> {
+        let hash = code_cid.hash();
+        if code_cid.codec() != IPLD_RAW || hash.code() != IDENTITY_HASH {
+            return None;
+        }
+        Some(i32::from_be_bytes(hash.digest().try_into().ok()?))
+    }
+
+    fn curr_epoch(&self) -> ChainEpoch {
+        *self.epoch.borrow()
+    }
+
+    fn verify_signature(&self, signature: &Signature, signer: &Address, plaintext: &[u8]) -> bool {
+        match signer.protocol() {
+            Protocol::Secp256k1 | Protocol::BLS | Protocol::Delegated => {
+                *signature == Self::sign(signer, plaintext)
+            }
+            // only key and delegated addresses can sign
+            Protocol::ID | Protocol::Actor => false,
+        }
+    }
 }
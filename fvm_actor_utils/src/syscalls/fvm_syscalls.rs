@@ -2,10 +2,15 @@ use anyhow::Result;
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_sdk;
+use fvm_sdk::crypto::{hash_owned, recover_secp_public_key};
+use fvm_shared::address::Payload;
+use fvm_shared::crypto::hash::SupportedHashes;
+use fvm_shared::crypto::signature::{Signature, SignatureType, SECP_SIG_LEN};
 use fvm_shared::sys::SendFlags;
 use fvm_shared::{address::Address, MethodNum, Response};
 
 use super::Syscalls;
+use crate::evm::EAM_ACTOR_ID;
 use crate::util::ActorRuntime;
 
 /// Runtime that delegates to [`fvm_sdk`] allowing actors to be deployed on-chain.
@@ -45,6 +50,48 @@ impl Syscalls for FvmSyscalls {
     fn resolve_address(&self, addr: &Address) -> Option<fvm_shared::ActorID> {
         fvm_sdk::actor::resolve_address(addr)
     }
+
+    fn lookup_delegated_address(&self, actor: fvm_shared::ActorID) -> Option<Address> {
+        fvm_sdk::actor::lookup_delegated_address(actor)
+    }
+
+    fn get_actor_code_cid(&self, addr: &Address) -> Option<cid::Cid> {
+        fvm_sdk::actor::get_actor_code_cid(addr)
+    }
+
+    fn get_builtin_actor_type(&self, code_cid: &cid::Cid) -> Option<i32> {
+        fvm_sdk::actor::get_builtin_actor_type(code_cid)
+    }
+
+    fn curr_epoch(&self) -> fvm_shared::clock::ChainEpoch {
+        fvm_sdk::network::curr_epoch()
+    }
+
+    fn verify_signature(&self, signature: &Signature, signer: &Address, plaintext: &[u8]) -> bool {
+        match signer.payload() {
+            // the FVM only verifies signatures for f1 and f3 signers, so eth accounts are verified
+            // by recovering the public key and comparing its eth address to the signer
+            Payload::Delegated(delegated) if delegated.namespace() == EAM_ACTOR_ID => {
+                if signature.sig_type != SignatureType::Secp256k1 {
+                    return false;
+                }
+                let sig: &[u8; SECP_SIG_LEN] = match signature.bytes.as_slice().try_into() {
+                    Ok(sig) => sig,
+                    Err(_) => return false,
+                };
+                let hash = hash_owned(SupportedHashes::Keccak256, plaintext);
+                let hash = hash.as_slice().try_into().expect("keccak256 digest is 32 bytes");
+                let pubkey = match recover_secp_public_key(hash, sig) {
+                    Ok(pubkey) => pubkey,
+                    Err(_) => return false,
+                };
+                // the eth address is the last 20 bytes of the hash of the uncompressed key
+                let key_hash = hash_owned(SupportedHashes::Keccak256, &pubkey[1..]);
+                key_hash[12..] == *delegated.subaddress()
+            }
+            _ => fvm_sdk::crypto::verify_signature(signature, signer, plaintext).unwrap_or(false),
+        }
+    }
 }
 
 impl<S: Syscalls + Clone, BS: Blockstore + Clone> ActorRuntime<S, BS> {
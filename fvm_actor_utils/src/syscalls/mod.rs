@@ -1,7 +1,8 @@
 use cid::Cid;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
 use fvm_shared::{
-    address::Address, econ::TokenAmount, error::ErrorNumber, ActorID, MethodNum, Response,
+    address::Address, clock::ChainEpoch, crypto::signature::Signature, econ::TokenAmount,
+    error::ErrorNumber, ActorID, MethodNum, Response,
 };
 use thiserror::Error;
 
@@ -49,4 +50,107 @@ pub trait Syscalls {
     /// Returns None if the address cannot be resolved. Successfully resolving an address doesn't
     /// necessarily mean the actor exists (e.g., if the address was already an actor ID).
     fn resolve_address(&self, addr: &Address) -> Option<ActorID>;
+
+    /// Looks up the delegated (f4) address of an actor.
+    ///
+    /// Returns None if the actor doesn't exist or was not assigned a delegated address. The default
+    /// implementation always returns None.
+    fn lookup_delegated_address(&self, _actor: ActorID) -> Option<Address> {
+        None
+    }
+
+    /// Returns the code CID of an actor.
+    ///
+    /// Returns None if the actor cannot be found. The default implementation always returns None.
+    fn get_actor_code_cid(&self, _addr: &Address) -> Option<Cid> {
+        None
+    }
+
+    /// Determines whether a code CID belongs to a builtin actor, returning its type if so.
+    ///
+    /// The default implementation always returns None.
+    fn get_builtin_actor_type(&self, _code_cid: &Cid) -> Option<i32> {
+        None
+    }
+
+    /// Returns the current epoch.
+    ///
+    /// The default implementation always returns epoch zero. Implementations must override it to
+    /// use epoch-dependent features such as allowance expiries, vesting or vote checkpoints.
+    fn curr_epoch(&self) -> ChainEpoch {
+        0
+    }
+
+    /// Verifies that a signature is valid for a signer and plaintext.
+    ///
+    /// Supports f1 and f3 signers, as well as f410 signers whose secp256k1 signature is over the
+    /// keccak256 hash of the plaintext. Returns false for malformed signatures and unsupported
+    /// signers. The default implementation rejects every signature.
+    fn verify_signature(
+        &self,
+        _signature: &Signature,
+        _signer: &Address,
+        _plaintext: &[u8],
+    ) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::Cid;
+    use fvm_ipld_encoding::ipld_block::IpldBlock;
+    use fvm_shared::crypto::signature::Signature;
+    use fvm_shared::{
+        address::Address, econ::TokenAmount, error::ErrorNumber, ActorID, MethodNum, Response,
+    };
+
+    use super::{NoStateError, Syscalls};
+
+    /// Implements only the methods that have no default.
+    struct MinimalSyscalls;
+
+    impl Syscalls for MinimalSyscalls {
+        fn root(&self) -> Result<Cid, NoStateError> {
+            Err(NoStateError)
+        }
+
+        fn set_root(&self, _cid: &Cid) -> Result<(), NoStateError> {
+            Err(NoStateError)
+        }
+
+        fn receiver(&self) -> ActorID {
+            1
+        }
+
+        fn caller(&self) -> ActorID {
+            2
+        }
+
+        fn send(
+            &self,
+            _to: &Address,
+            _method: MethodNum,
+            _params: Option<IpldBlock>,
+            _value: TokenAmount,
+        ) -> Result<Response, ErrorNumber> {
+            Err(ErrorNumber::NotFound)
+        }
+
+        fn resolve_address(&self, addr: &Address) -> Option<ActorID> {
+            addr.id().ok()
+        }
+    }
+
+    #[test]
+    fn it_defaults_optional_syscalls() {
+        let syscalls = MinimalSyscalls;
+        let address = Address::new_id(1);
+        assert_eq!(syscalls.lookup_delegated_address(1), None);
+        assert_eq!(syscalls.get_actor_code_cid(&address), None);
+        assert_eq!(syscalls.get_builtin_actor_type(&Cid::default()), None);
+        assert_eq!(syscalls.curr_epoch(), 0);
+        let signature = Signature::new_secp256k1(vec![0; 65]);
+        assert!(!syscalls.verify_signature(&signature, &address, b"plaintext"));
+    }
 }
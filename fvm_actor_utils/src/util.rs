@@ -2,12 +2,15 @@ use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_blockstore::MemoryBlockstore;
 use fvm_ipld_encoding::ipld_block::IpldBlock;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::Signature;
 use fvm_shared::METHOD_SEND;
 use fvm_shared::{address::Address, econ::TokenAmount, error::ExitCode, ActorID};
 use fvm_shared::{MethodNum, Response};
 use num_traits::Zero;
 use thiserror::Error;
 
+use crate::evm::EVM_ACTOR_TYPE;
 use crate::messaging::{Messaging, MessagingError, Result as MessagingResult};
 use crate::shared_blockstore::SharedMemoryBlockstore;
 use crate::syscalls::fake_syscalls::FakeSyscalls;
@@ -89,6 +92,28 @@ impl<S: Syscalls, BS: Blockstore> ActorRuntime<S, BS> {
         self.syscalls.resolve_address(address).ok_or(MessagingError::AddressNotResolved(*address))
     }
 
+    /// Looks up the delegated (f4) address of an actor, if it has one.
+    pub fn lookup_delegated_address(&self, id: ActorID) -> Option<Address> {
+        self.syscalls.lookup_delegated_address(id)
+    }
+
+    /// Returns the current epoch.
+    pub fn curr_epoch(&self) -> ChainEpoch {
+        self.syscalls.curr_epoch()
+    }
+
+    /// Verifies that a signature is valid for a signer and plaintext.
+    ///
+    /// See [`Syscalls::verify_signature`] for the supported signers.
+    pub fn verify_signature(
+        &self,
+        signature: &Signature,
+        signer: &Address,
+        plaintext: &[u8],
+    ) -> bool {
+        self.syscalls.verify_signature(signature, signer, plaintext)
+    }
+
     /// Resolves an address to an ID address, sending a message to initialize an account there if
     /// it doesn't exist.
     ///
@@ -176,4 +201,15 @@ impl<S: Syscalls, BS: Blockstore> Messaging for ActorRuntime<S, BS> {
         let res = self.syscalls.send(to, method, params, value);
         Ok(res?)
     }
+
+    fn lookup_delegated_address(&self, actor: ActorID) -> Option<Address> {
+        self.syscalls.lookup_delegated_address(actor)
+    }
+
+    fn is_evm_actor(&self, address: &Address) -> bool {
+        self.syscalls
+            .get_actor_code_cid(address)
+            .and_then(|code| self.syscalls.get_builtin_actor_type(&code))
+            .is_some_and(|actor_type| actor_type == EVM_ACTOR_TYPE)
+    }
 }
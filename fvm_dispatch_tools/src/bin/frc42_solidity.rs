@@ -0,0 +1,103 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::exit;
+
+use clap::{Parser, Subcommand};
+use frc42_dispatch::hash::MethodResolver;
+use frc42_dispatch::selector::SelectorResolver;
+use fvm_dispatch_tools::blake2b::Blake2bHasher;
+use fvm_dispatch_tools::keccak::Keccak256Hasher;
+use fvm_dispatch_tools::solidity::{
+    find_collisions, generate_library, parse_manifest, ManifestEntry,
+};
+
+const LONG_ABOUT: &str =
+    "Maps between Solidity function selectors and FRC-0042 method numbers. A manifest lists one \
+FRC-0042 method name per line, optionally followed by the canonical Solidity signature of the \
+equivalent EVM function (e.g. `Transfer transfer(address,uint256)`). Lines starting with # are \
+ignored. Manifests are read from stdin if no path is given.";
+
+/// Solidity interop tooling for FRC-0042 method numbers.
+#[derive(Parser, Debug)]
+#[clap(
+    version,
+    about,
+    long_about = Some(LONG_ABOUT)
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Computes the 4-byte selector of a canonical Solidity function signature.
+    Selector { signature: String },
+    /// Generates a Solidity library of FRC-0042 method number constants from a manifest.
+    Library {
+        /// Name of the generated Solidity library.
+        #[arg(long)]
+        name: String,
+        manifest: Option<PathBuf>,
+    },
+    /// Checks a manifest for method number and selector collisions.
+    Check { manifest: Option<PathBuf> },
+}
+
+fn load_manifest(path: Option<PathBuf>) -> Vec<ManifestEntry> {
+    let manifest = match path {
+        Some(path) => std::fs::read_to_string(path),
+        None => {
+            let mut manifest = String::new();
+            io::stdin().read_to_string(&mut manifest).map(|_| manifest)
+        }
+    };
+    let manifest = manifest.unwrap_or_else(|e| fail(format!("Error reading manifest: {e}")));
+
+    parse_manifest(
+        &manifest,
+        &MethodResolver::new(Blake2bHasher {}),
+        &SelectorResolver::new(Keccak256Hasher {}),
+    )
+    .unwrap_or_else(|e| fail(format!("Error parsing manifest: {e}")))
+}
+
+/// Prints any collisions and exits with a failure code if there were some.
+fn check_collisions(entries: &[ManifestEntry]) {
+    let collisions = find_collisions(entries);
+    if !collisions.is_empty() {
+        for collision in collisions {
+            eprintln!("Collision: {collision}");
+        }
+        exit(1);
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{message}");
+    exit(1);
+}
+
+fn main() {
+    match Cli::parse().command {
+        Command::Selector { signature } => {
+            match SelectorResolver::new(Keccak256Hasher {}).selector(&signature) {
+                Ok(selector) => println!("0x{}", hex::encode(selector)),
+                Err(e) => fail(format!("Error computing selector: {e}")),
+            }
+        }
+        Command::Library { name, manifest } => {
+            let entries = load_manifest(manifest);
+            check_collisions(&entries);
+            match generate_library(&name, &entries) {
+                Ok(library) => print!("{library}"),
+                Err(e) => fail(format!("Error generating library: {e}")),
+            }
+        }
+        Command::Check { manifest } => {
+            let entries = load_manifest(manifest);
+            check_collisions(&entries);
+            println!("No collisions found in {} methods", entries.len());
+        }
+    }
+}
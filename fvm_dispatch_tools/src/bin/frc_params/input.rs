@@ -0,0 +1,188 @@
+use std::str::FromStr;
+
+use frc46_token::token::TOKEN_PRECISION;
+use frc53_nft::types::TokenID;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::{Address, Network};
+use fvm_shared::bigint::BigInt;
+use fvm_shared::econ::TokenAmount;
+use thiserror::Error;
+
+/// Number of decimal places represented by [`TOKEN_PRECISION`].
+const TOKEN_DECIMALS: usize = 18;
+
+/// Actor ID of the Ethereum Address Manager, the namespace for f410 addresses.
+const EAM_ACTOR_ID: u64 = 10;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum InputError {
+    #[error("invalid address {0:?}")]
+    InvalidAddress(String),
+    #[error("invalid token amount {0:?}")]
+    InvalidAmount(String),
+    #[error("token amount {input:?} has more than {TOKEN_DECIMALS} decimal places")]
+    TooManyDecimals { input: String },
+    #[error("token amount {input:?} is not a multiple of the granularity ({granularity} atto)")]
+    InvalidGranularity { input: String, granularity: u64 },
+    #[error("invalid token id list {0:?}")]
+    InvalidTokenIds(String),
+    #[error("invalid hex data {0:?}")]
+    InvalidHex(String),
+}
+
+/// Parses an address in any protocol.
+///
+/// Both mainnet (`f`) and testnet (`t`) prefixes are accepted, as the network is not part of the
+/// CBOR encoding. A `0x`-prefixed Ethereum address is converted to its f410 equivalent.
+pub fn parse_address(input: &str) -> Result<Address, InputError> {
+    let invalid = || InputError::InvalidAddress(input.to_string());
+
+    if let Some(eth) = input.strip_prefix("0x") {
+        let bytes = hex::decode(eth).map_err(|_| invalid())?;
+        if bytes.len() != 20 {
+            return Err(invalid());
+        }
+        return Address::new_delegated(EAM_ACTOR_ID, &bytes).map_err(|_| invalid());
+    }
+
+    let network = if input.starts_with('t') { Network::Testnet } else { Network::Mainnet };
+    network.parse_address(input).map_err(|_| invalid())
+}
+
+/// Parses a decimal token amount (e.g. `"1.5"`) into atto units.
+///
+/// The amount is scaled by [`TOKEN_PRECISION`] and must be a whole multiple of `granularity`.
+pub fn parse_token_amount(input: &str, granularity: u64) -> Result<TokenAmount, InputError> {
+    let invalid = || InputError::InvalidAmount(input.to_string());
+
+    let (whole, fraction) = input.split_once('.').unwrap_or((input, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if fraction.len() > TOKEN_DECIMALS {
+        return Err(InputError::TooManyDecimals { input: input.to_string() });
+    }
+
+    let whole =
+        if whole.is_empty() { BigInt::default() } else { whole.parse().map_err(|_| invalid())? };
+    let fraction: BigInt = if fraction.is_empty() {
+        BigInt::default()
+    } else {
+        format!("{fraction:0<TOKEN_DECIMALS$}").parse().map_err(|_| invalid())?
+    };
+
+    let atto = whole * TOKEN_PRECISION + fraction;
+    if granularity == 0 || atto.clone() % granularity != BigInt::default() {
+        return Err(InputError::InvalidGranularity { input: input.to_string(), granularity });
+    }
+    Ok(TokenAmount::from_atto(atto))
+}
+
+/// Parses a comma-separated list of token ids, where each element may be an inclusive range
+/// (e.g. `"1,4-6"` gives `[1, 4, 5, 6]`).
+pub fn parse_token_ids(input: &str) -> Result<Vec<TokenID>, InputError> {
+    let invalid = || InputError::InvalidTokenIds(input.to_string());
+
+    let mut token_ids = Vec::new();
+    for part in input.split(',').map(str::trim) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start = TokenID::from_str(start.trim()).map_err(|_| invalid())?;
+                let end = TokenID::from_str(end.trim()).map_err(|_| invalid())?;
+                if start > end {
+                    return Err(invalid());
+                }
+                token_ids.extend(start..=end);
+            }
+            None => token_ids.push(TokenID::from_str(part).map_err(|_| invalid())?),
+        }
+    }
+    Ok(token_ids)
+}
+
+/// Parses optional hex encoded operator data, with or without a `0x` prefix.
+pub fn parse_data(input: &str) -> Result<RawBytes, InputError> {
+    let data = input.strip_prefix("0x").unwrap_or(input);
+    hex::decode(data).map(RawBytes::new).map_err(|_| InputError::InvalidHex(input.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+
+    use super::*;
+
+    #[test]
+    fn it_parses_decimal_amounts() {
+        assert_eq!(parse_token_amount("1", 1).unwrap(), TokenAmount::from_whole(1));
+        assert_eq!(
+            parse_token_amount("1.5", 1).unwrap(),
+            TokenAmount::from_atto(1_500_000_000_000_000_000u64)
+        );
+        assert_eq!(
+            parse_token_amount(".000000000000000001", 1).unwrap(),
+            TokenAmount::from_atto(1)
+        );
+        assert_eq!(parse_token_amount("0", 1).unwrap(), TokenAmount::from_atto(0));
+
+        assert_eq!(
+            parse_token_amount("0.0000000000000000001", 1).unwrap_err(),
+            InputError::TooManyDecimals { input: "0.0000000000000000001".into() }
+        );
+        assert_eq!(
+            parse_token_amount("-1", 1).unwrap_err(),
+            InputError::InvalidAmount("-1".into())
+        );
+        assert_eq!(parse_token_amount(".", 1).unwrap_err(), InputError::InvalidAmount(".".into()));
+        assert_eq!(
+            parse_token_amount("1e5", 1).unwrap_err(),
+            InputError::InvalidAmount("1e5".into())
+        );
+    }
+
+    #[test]
+    fn it_enforces_granularity() {
+        assert_eq!(parse_token_amount("3", TOKEN_PRECISION).unwrap(), TokenAmount::from_whole(3));
+        assert_eq!(
+            parse_token_amount("3.1", TOKEN_PRECISION).unwrap_err(),
+            InputError::InvalidGranularity { input: "3.1".into(), granularity: TOKEN_PRECISION }
+        );
+        assert_eq!(
+            parse_token_amount("1", 0).unwrap_err(),
+            InputError::InvalidGranularity { input: "1".into(), granularity: 0 }
+        );
+    }
+
+    #[test]
+    fn it_parses_addresses() {
+        assert_eq!(parse_address("f01234").unwrap(), Address::new_id(1234));
+        assert_eq!(parse_address("t01234").unwrap(), Address::new_id(1234));
+
+        let eth = parse_address("0x00000000000000000000000000000000000000ff").unwrap();
+        let mut bytes = [0u8; 20];
+        bytes[19] = 0xff;
+        assert_eq!(eth, Address::new_delegated(EAM_ACTOR_ID, &bytes).unwrap());
+
+        parse_address("x01234").unwrap_err();
+        parse_address("0x1234").unwrap_err();
+    }
+
+    #[test]
+    fn it_parses_token_ids() {
+        assert_eq!(parse_token_ids("7").unwrap(), vec![7]);
+        assert_eq!(parse_token_ids("1, 4-6").unwrap(), vec![1, 4, 5, 6]);
+        parse_token_ids("6-4").unwrap_err();
+        parse_token_ids("1,,2").unwrap_err();
+    }
+
+    #[test]
+    fn it_parses_data() {
+        assert_eq!(parse_data("").unwrap(), RawBytes::default());
+        assert_eq!(parse_data("0xcafe").unwrap(), RawBytes::new(vec![0xca, 0xfe]));
+        parse_data("xyz").unwrap_err();
+    }
+}
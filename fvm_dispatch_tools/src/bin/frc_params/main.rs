@@ -0,0 +1,301 @@
+mod input;
+mod output;
+
+use std::process::exit;
+
+use clap::{Args, Parser, Subcommand};
+use frc42_dispatch::method_hash;
+use frc46_token::token::types as frc46;
+use frc53_nft::types as frc53;
+use frc53_nft::types::TokenID;
+use fvm_ipld_encoding::ser::Serialize;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+
+use input::{parse_address, parse_data, parse_token_amount, parse_token_ids, InputError};
+use output::EncodedMessage;
+
+const LONG_ABOUT: &str =
+    "Builds the CBOR params for an FRC-0046 or FRC-0053 method call, printing the FRC-0042 \
+method number together with hex and base64 encodings of the params. The output can be used for \
+multisig proposals or with `lotus send --method <number> --params-hex <hex>`.";
+
+/// Encodes params for FRC-0046 and FRC-0053 token methods.
+#[derive(Parser, Debug)]
+#[clap(
+    version,
+    about,
+    long_about = Some(LONG_ABOUT)
+)]
+struct Cli {
+    #[command(subcommand)]
+    standard: Standard,
+}
+
+#[derive(Subcommand, Debug)]
+enum Standard {
+    /// FRC-0046 fungible token methods.
+    Frc46(Frc46Args),
+    /// FRC-0053 non-fungible token methods.
+    Frc53 {
+        #[command(subcommand)]
+        method: Frc53Method,
+    },
+}
+
+#[derive(Args, Debug)]
+struct Frc46Args {
+    /// Granularity of the token in atto units. Amounts must be a whole multiple of it.
+    #[arg(long, default_value_t = 1)]
+    granularity: u64,
+
+    #[command(subcommand)]
+    method: Frc46Method,
+}
+
+/// Amounts are decimal strings in whole tokens (e.g. `1.5`), with up to 18 decimal places.
+#[derive(Subcommand, Debug)]
+enum Frc46Method {
+    Transfer {
+        #[arg(value_parser = parse_address)]
+        to: Address,
+        amount: String,
+        /// Hex encoded operator data.
+        #[arg(long, default_value = "", value_parser = parse_data)]
+        operator_data: RawBytes,
+    },
+    TransferFrom {
+        #[arg(value_parser = parse_address)]
+        from: Address,
+        #[arg(value_parser = parse_address)]
+        to: Address,
+        amount: String,
+        /// Hex encoded operator data.
+        #[arg(long, default_value = "", value_parser = parse_data)]
+        operator_data: RawBytes,
+    },
+    IncreaseAllowance {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+        increase: String,
+    },
+    DecreaseAllowance {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+        decrease: String,
+    },
+    RevokeAllowance {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+    },
+    Allowance {
+        #[arg(value_parser = parse_address)]
+        owner: Address,
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+    },
+    BalanceOf {
+        #[arg(value_parser = parse_address)]
+        owner: Address,
+    },
+    Burn {
+        amount: String,
+    },
+    BurnFrom {
+        #[arg(value_parser = parse_address)]
+        owner: Address,
+        amount: String,
+    },
+}
+
+/// Token ids are comma-separated and may include inclusive ranges (e.g. `1,4-6`).
+#[derive(Subcommand, Debug)]
+enum Frc53Method {
+    Transfer {
+        #[arg(value_parser = parse_address)]
+        to: Address,
+        token_ids: String,
+        /// Hex encoded operator data.
+        #[arg(long, default_value = "", value_parser = parse_data)]
+        operator_data: RawBytes,
+    },
+    TransferFrom {
+        #[arg(value_parser = parse_address)]
+        from: Address,
+        #[arg(value_parser = parse_address)]
+        to: Address,
+        token_ids: String,
+        /// Hex encoded operator data.
+        #[arg(long, default_value = "", value_parser = parse_data)]
+        operator_data: RawBytes,
+    },
+    Burn {
+        token_ids: String,
+    },
+    BurnFrom {
+        #[arg(value_parser = parse_address)]
+        from: Address,
+        token_ids: String,
+    },
+    Approve {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+        token_ids: String,
+    },
+    Revoke {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+        token_ids: String,
+    },
+    ApproveForAll {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+    },
+    RevokeForAll {
+        #[arg(value_parser = parse_address)]
+        operator: Address,
+    },
+    BalanceOf {
+        #[arg(value_parser = parse_address)]
+        owner: Address,
+    },
+    OwnerOf {
+        token_id: TokenID,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum EncodeError {
+    #[error(transparent)]
+    Input(#[from] InputError),
+    #[error("failed to encode params: {0}")]
+    Encoding(#[from] fvm_ipld_encoding::Error),
+}
+
+/// Serializes the params as CBOR.
+fn encode<T: Serialize>(
+    method_name: &'static str,
+    method_number: u64,
+    params: &T,
+) -> Result<EncodedMessage, EncodeError> {
+    Ok(EncodedMessage { method_name, method_number, params: RawBytes::serialize(params)? })
+}
+
+fn encode_frc46(args: Frc46Args) -> Result<EncodedMessage, EncodeError> {
+    let amount = |input: &str| parse_token_amount(input, args.granularity);
+
+    match args.method {
+        Frc46Method::Transfer { to, amount: value, operator_data } => encode(
+            "Transfer",
+            method_hash!("Transfer"),
+            &frc46::TransferParams { to, amount: amount(&value)?, operator_data },
+        ),
+        Frc46Method::TransferFrom { from, to, amount: value, operator_data } => encode(
+            "TransferFrom",
+            method_hash!("TransferFrom"),
+            &frc46::TransferFromParams { from, to, amount: amount(&value)?, operator_data },
+        ),
+        Frc46Method::IncreaseAllowance { operator, increase } => encode(
+            "IncreaseAllowance",
+            method_hash!("IncreaseAllowance"),
+            // allowances need not be a multiple of the granularity
+            &frc46::IncreaseAllowanceParams {
+                operator,
+                increase: parse_token_amount(&increase, 1)?,
+            },
+        ),
+        Frc46Method::DecreaseAllowance { operator, decrease } => encode(
+            "DecreaseAllowance",
+            method_hash!("DecreaseAllowance"),
+            &frc46::DecreaseAllowanceParams {
+                operator,
+                decrease: parse_token_amount(&decrease, 1)?,
+            },
+        ),
+        Frc46Method::RevokeAllowance { operator } => encode(
+            "RevokeAllowance",
+            method_hash!("RevokeAllowance"),
+            &frc46::RevokeAllowanceParams { operator },
+        ),
+        Frc46Method::Allowance { owner, operator } => encode(
+            "Allowance",
+            method_hash!("Allowance"),
+            &frc46::GetAllowanceParams { owner, operator },
+        ),
+        Frc46Method::BalanceOf { owner } => encode("BalanceOf", method_hash!("BalanceOf"), &owner),
+        Frc46Method::Burn { amount: value } => {
+            encode("Burn", method_hash!("Burn"), &frc46::BurnParams { amount: amount(&value)? })
+        }
+        Frc46Method::BurnFrom { owner, amount: value } => encode(
+            "BurnFrom",
+            method_hash!("BurnFrom"),
+            &frc46::BurnFromParams { owner, amount: amount(&value)? },
+        ),
+    }
+}
+
+fn encode_frc53(method: Frc53Method) -> Result<EncodedMessage, EncodeError> {
+    match method {
+        Frc53Method::Transfer { to, token_ids, operator_data } => encode(
+            "Transfer",
+            method_hash!("Transfer"),
+            &frc53::TransferParams { to, token_ids: parse_token_ids(&token_ids)?, operator_data },
+        ),
+        Frc53Method::TransferFrom { from, to, token_ids, operator_data } => encode(
+            "TransferFrom",
+            method_hash!("TransferFrom"),
+            &frc53::TransferFromParams {
+                from,
+                to,
+                token_ids: parse_token_ids(&token_ids)?,
+                operator_data,
+            },
+        ),
+        Frc53Method::Burn { token_ids } => {
+            encode("Burn", method_hash!("Burn"), &parse_token_ids(&token_ids)?)
+        }
+        Frc53Method::BurnFrom { from, token_ids } => encode(
+            "BurnFrom",
+            method_hash!("BurnFrom"),
+            &frc53::BurnFromParams { from, token_ids: parse_token_ids(&token_ids)? },
+        ),
+        Frc53Method::Approve { operator, token_ids } => encode(
+            "Approve",
+            method_hash!("Approve"),
+            &frc53::ApproveParams { operator, token_ids: parse_token_ids(&token_ids)? },
+        ),
+        Frc53Method::Revoke { operator, token_ids } => encode(
+            "Revoke",
+            method_hash!("Revoke"),
+            &frc53::RevokeParams { operator, token_ids: parse_token_ids(&token_ids)? },
+        ),
+        Frc53Method::ApproveForAll { operator } => encode(
+            "ApproveForAll",
+            method_hash!("ApproveForAll"),
+            &frc53::ApproveForAllParams { operator },
+        ),
+        Frc53Method::RevokeForAll { operator } => encode(
+            "RevokeForAll",
+            method_hash!("RevokeForAll"),
+            &frc53::RevokeForAllParams { operator },
+        ),
+        Frc53Method::BalanceOf { owner } => encode("BalanceOf", method_hash!("BalanceOf"), &owner),
+        Frc53Method::OwnerOf { token_id } => encode("OwnerOf", method_hash!("OwnerOf"), &token_id),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let res = match cli.standard {
+        Standard::Frc46(args) => encode_frc46(args),
+        Standard::Frc53 { method } => encode_frc53(method),
+    };
+
+    match res {
+        Ok(message) => println!("{message}"),
+        Err(e) => {
+            eprintln!("Error encoding params: {e}");
+            exit(1);
+        }
+    }
+}
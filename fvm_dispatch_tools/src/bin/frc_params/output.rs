@@ -0,0 +1,39 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use fvm_ipld_encoding::RawBytes;
+
+/// An encoded message, ready to be pasted into a multisig proposal or `lotus send`.
+pub struct EncodedMessage {
+    pub method_name: &'static str,
+    pub method_number: u64,
+    pub params: RawBytes,
+}
+
+impl std::fmt::Display for EncodedMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "method: {}", self.method_name)?;
+        writeln!(f, "method_number: {}", self.method_number)?;
+        writeln!(f, "params_hex: {}", hex::encode(self.params.bytes()))?;
+        write!(f, "params_base64: {}", STANDARD.encode(self.params.bytes()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_encoding::RawBytes;
+
+    use super::EncodedMessage;
+
+    #[test]
+    fn it_displays_hex_and_base64_params() {
+        let message = EncodedMessage {
+            method_name: "Transfer",
+            method_number: 1,
+            params: RawBytes::new(b"foobar".to_vec()),
+        };
+        assert_eq!(
+            message.to_string(),
+            "method: Transfer\nmethod_number: 1\nparams_hex: 666f6f626172\nparams_base64: Zm9vYmFy"
+        );
+    }
+}
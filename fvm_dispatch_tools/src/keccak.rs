@@ -0,0 +1,9 @@
+use frc42_dispatch::hash::Hasher;
+use sha3::{Digest, Keccak256};
+
+pub struct Keccak256Hasher {}
+impl Hasher for Keccak256Hasher {
+    fn hash(&self, bytes: &[u8]) -> Vec<u8> {
+        Keccak256::digest(bytes).to_vec()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod blake2b;
+pub mod keccak;
+pub mod solidity;
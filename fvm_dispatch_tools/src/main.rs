@@ -1,11 +1,9 @@
-mod blake2b;
-
 use std::io::{self, BufRead};
 use std::process::exit;
 
-use blake2b::Blake2bHasher;
 use clap::Parser;
 use frc42_dispatch::hash::MethodResolver;
+use fvm_dispatch_tools::blake2b::Blake2bHasher;
 
 const LONG_ABOUT: &str =
     "Pass a single method name as a command line argument or a list of method names, separated by \
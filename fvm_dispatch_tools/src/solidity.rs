@@ -0,0 +1,387 @@
+//! Helpers for calling FVM actors from EVM contracts.
+//!
+//! An interface manifest lists the FRC-0042 method names exported by an actor, one per line,
+//! optionally followed by the canonical Solidity signature of an equivalent EVM function:
+//!
+//! ```text
+//! # FRC-0046 token
+//! Transfer transfer(address,uint256)
+//! TotalSupply totalSupply()
+//! Granularity
+//! ```
+//!
+//! From a manifest we can generate a Solidity library of `uint64` method number constants (for use
+//! with the `call_actor` precompile) and check for collisions within and between the two schemes.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use frc42_dispatch::hash::{Hasher, MethodNameErr, MethodResolver};
+use frc42_dispatch::selector::{Selector, SelectorResolver, SignatureErr};
+use thiserror::Error;
+
+/// A single method of an interface manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub method_name: String,
+    pub method_number: u64,
+    /// The Solidity signature and selector of the equivalent EVM function, if any.
+    pub solidity: Option<(String, Selector)>,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ManifestError {
+    #[error("line {line}: invalid method name {name:?}: {source}")]
+    MethodName { line: usize, name: String, source: MethodNameErr },
+    #[error("line {line}: invalid Solidity signature {signature:?}: {source}")]
+    Signature { line: usize, signature: String, source: SignatureErr },
+    #[error("line {line}: expected `MethodName [solidity_signature]`")]
+    Malformed { line: usize },
+    #[error("{0:?} is not a valid Solidity library name")]
+    LibraryName(String),
+    #[error("different entries would both declare the Solidity constant {0}")]
+    DuplicateConstant(String),
+}
+
+/// A clash between two method identifiers that would make dispatch ambiguous.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Collision {
+    /// Two FRC-0042 method names hash to the same method number.
+    MethodNumber { number: u64, names: (String, String) },
+    /// Two Solidity signatures hash to the same selector.
+    Selector { selector: Selector, signatures: (String, String) },
+    /// A Solidity selector, read as a big-endian integer, equals an FRC-0042 method number.
+    CrossScheme { number: u64, method_name: String, signature: String },
+}
+
+impl std::fmt::Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collision::MethodNumber { number, names: (a, b) } => {
+                write!(f, "method names {a:?} and {b:?} both hash to method number {number}")
+            }
+            Collision::Selector { selector, signatures: (a, b) } => write!(
+                f,
+                "signatures {a:?} and {b:?} both hash to selector 0x{}",
+                hex::encode(selector)
+            ),
+            Collision::CrossScheme { number, method_name, signature } => write!(
+                f,
+                "selector of {signature:?} equals the method number of {method_name:?} ({number})"
+            ),
+        }
+    }
+}
+
+/// Parses an interface manifest, computing method numbers and selectors for each entry.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn parse_manifest<M: Hasher, S: Hasher>(
+    manifest: &str,
+    methods: &MethodResolver<M>,
+    selectors: &SelectorResolver<S>,
+) -> Result<Vec<ManifestEntry>, ManifestError> {
+    let mut entries = Vec::new();
+    for (i, line) in manifest.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let name = parts.next().expect("line is not empty");
+        let signature = parts.next();
+        if parts.next().is_some() {
+            return Err(ManifestError::Malformed { line: line_number });
+        }
+
+        let method_number = methods.method_number(name).map_err(|source| {
+            ManifestError::MethodName { line: line_number, name: name.to_string(), source }
+        })?;
+        let solidity = signature
+            .map(|signature| {
+                selectors
+                    .selector(signature)
+                    .map(|selector| (signature.to_string(), selector))
+                    .map_err(|source| ManifestError::Signature {
+                        line: line_number,
+                        signature: signature.to_string(),
+                        source,
+                    })
+            })
+            .transpose()?;
+
+        entries.push(ManifestEntry { method_name: name.to_string(), method_number, solidity });
+    }
+    Ok(entries)
+}
+
+/// Finds all collisions between the method numbers and selectors in a manifest.
+///
+/// Repeated entries with an identical name or signature are not considered collisions.
+pub fn find_collisions(entries: &[ManifestEntry]) -> Vec<Collision> {
+    let mut collisions = Vec::new();
+    let mut numbers: HashMap<u64, &str> = HashMap::new();
+    let mut selectors: HashMap<Selector, &str> = HashMap::new();
+
+    for entry in entries {
+        match numbers.get(&entry.method_number) {
+            Some(&other) if other != entry.method_name => {
+                collisions.push(Collision::MethodNumber {
+                    number: entry.method_number,
+                    names: (other.to_string(), entry.method_name.clone()),
+                })
+            }
+            _ => {
+                numbers.insert(entry.method_number, &entry.method_name);
+            }
+        }
+
+        if let Some((signature, selector)) = &entry.solidity {
+            match selectors.get(selector) {
+                Some(&other) if other != signature => collisions.push(Collision::Selector {
+                    selector: *selector,
+                    signatures: (other.to_string(), signature.clone()),
+                }),
+                _ => {
+                    selectors.insert(*selector, signature);
+                }
+            }
+        }
+    }
+
+    for entry in entries {
+        if let Some((signature, selector)) = &entry.solidity {
+            let number = u32::from_be_bytes(*selector) as u64;
+            if let Some(&method_name) = numbers.get(&number) {
+                collisions.push(Collision::CrossScheme {
+                    number,
+                    method_name: method_name.to_string(),
+                    signature: signature.clone(),
+                });
+            }
+        }
+    }
+
+    collisions
+}
+
+/// Generates a Solidity library declaring a `uint64` constant for the FRC-0042 method number of
+/// each manifest entry, and a `bytes4` constant for each Solidity selector.
+///
+/// Constants are named after the method in `UPPER_SNAKE_CASE`, e.g. `TransferFrom` becomes
+/// `TRANSFER_FROM` and its selector `TRANSFER_FROM_SELECTOR`. Repeated identical entries are only
+/// declared once, while different entries that would declare the same constant are an error.
+pub fn generate_library(name: &str, entries: &[ManifestEntry]) -> Result<String, ManifestError> {
+    let mut chars = name.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(ManifestError::LibraryName(name.to_string()));
+    }
+
+    let mut library = String::new();
+    // writing to a String cannot fail
+    writeln!(library, "// SPDX-License-Identifier: MIT OR Apache-2.0").unwrap();
+    writeln!(library, "// Generated by frc42_solidity. Do not edit.").unwrap();
+    writeln!(library, "pragma solidity >=0.8.0;").unwrap();
+    writeln!(library).unwrap();
+    writeln!(
+        library,
+        "/// @notice FRC-0042 method numbers for calling FVM actors from EVM contracts"
+    )
+    .unwrap();
+    writeln!(library, "library {name} {{").unwrap();
+    let mut declared: HashMap<String, &ManifestEntry> = HashMap::new();
+    for entry in entries {
+        let constant = constant_name(&entry.method_name);
+        match declared.get(&constant) {
+            Some(&other) if other == entry => continue,
+            Some(_) => return Err(ManifestError::DuplicateConstant(constant)),
+            None => {}
+        }
+        if !declared.is_empty() {
+            writeln!(library).unwrap();
+        }
+        declared.insert(constant.clone(), entry);
+        writeln!(library, "    /// @dev FRC-0042 method number of `{}`", entry.method_name)
+            .unwrap();
+        writeln!(library, "    uint64 internal constant {constant} = {};", entry.method_number)
+            .unwrap();
+        if let Some((signature, selector)) = &entry.solidity {
+            writeln!(library, "    /// @dev Solidity selector of `{signature}`").unwrap();
+            writeln!(
+                library,
+                "    bytes4 internal constant {constant}_SELECTOR = 0x{};",
+                hex::encode(selector)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(library, "}}").unwrap();
+
+    Ok(library)
+}
+
+/// Converts a `CamelCase` FRC-0042 method name to `UPPER_SNAKE_CASE`.
+fn constant_name(method_name: &str) -> String {
+    let mut constant = String::with_capacity(method_name.len() + 4);
+    let mut prev: Option<char> = None;
+    for c in method_name.chars() {
+        if c.is_ascii_uppercase()
+            && prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            constant.push('_');
+        }
+        constant.push(c.to_ascii_uppercase());
+        prev = Some(c);
+    }
+    constant
+}
+
+#[cfg(test)]
+mod test {
+    use frc42_dispatch::hash::{MethodNameErr, MethodResolver};
+    use frc42_dispatch::method_hash;
+    use frc42_dispatch::selector::{SelectorResolver, SignatureErr};
+
+    use super::*;
+    use crate::blake2b::Blake2bHasher;
+    use crate::keccak::Keccak256Hasher;
+
+    fn parse(manifest: &str) -> Result<Vec<ManifestEntry>, ManifestError> {
+        parse_manifest(
+            manifest,
+            &MethodResolver::new(Blake2bHasher {}),
+            &SelectorResolver::new(Keccak256Hasher {}),
+        )
+    }
+
+    #[test]
+    fn it_computes_known_selectors() {
+        let resolver = SelectorResolver::new(Keccak256Hasher {});
+        assert_eq!(
+            resolver.selector("transfer(address,uint256)").unwrap(),
+            [0xa9, 0x05, 0x9c, 0xbb]
+        );
+        assert_eq!(resolver.selector("balanceOf(address)").unwrap(), [0x70, 0xa0, 0x82, 0x31]);
+    }
+
+    #[test]
+    fn it_parses_manifests() {
+        let entries =
+            parse("# comment\n\nTransfer transfer(address,uint256)\n  Granularity  \n").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                ManifestEntry {
+                    method_name: "Transfer".into(),
+                    method_number: method_hash!("Transfer"),
+                    solidity: Some(("transfer(address,uint256)".into(), [0xa9, 0x05, 0x9c, 0xbb])),
+                },
+                ManifestEntry {
+                    method_name: "Granularity".into(),
+                    method_number: method_hash!("Granularity"),
+                    solidity: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse("Transfer\ntransfer").unwrap_err(),
+            ManifestError::MethodName {
+                line: 2,
+                name: "transfer".into(),
+                source: MethodNameErr::IllegalName(
+                    frc42_dispatch::hash::IllegalNameErr::NotValidStart
+                ),
+            }
+        );
+        assert_eq!(
+            parse("Transfer transfer(address,uint)").unwrap_err(),
+            ManifestError::Signature {
+                line: 1,
+                signature: "transfer(address,uint)".into(),
+                source: SignatureErr::IllegalType("uint".into()),
+            }
+        );
+        assert_eq!(parse("Transfer a() b()").unwrap_err(), ManifestError::Malformed { line: 1 });
+    }
+
+    #[test]
+    fn it_finds_collisions() {
+        let entry = |name: &str, number, solidity: Option<(&str, Selector)>| ManifestEntry {
+            method_name: name.into(),
+            method_number: number,
+            solidity: solidity.map(|(s, sel)| (s.into(), sel)),
+        };
+
+        // no collisions in a real manifest, even with duplicated entries
+        let entries = parse("Transfer transfer(address,uint256)\nBurn\nBurn").unwrap();
+        assert!(find_collisions(&entries).is_empty());
+
+        let entries = vec![
+            entry("A", 0x01020304, Some(("a()", [0, 0, 0, 1]))),
+            entry("B", 0x01020304, Some(("b()", [0, 0, 0, 1]))),
+            entry("C", 0x05060708, Some(("c()", [1, 2, 3, 4]))),
+        ];
+        assert_eq!(
+            find_collisions(&entries),
+            vec![
+                Collision::MethodNumber { number: 0x01020304, names: ("A".into(), "B".into()) },
+                Collision::Selector {
+                    selector: [0, 0, 0, 1],
+                    signatures: ("a()".into(), "b()".into())
+                },
+                Collision::CrossScheme {
+                    number: 0x01020304,
+                    method_name: "A".into(),
+                    signature: "c()".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn it_generates_libraries() {
+        let entries =
+            parse("TransferFrom transferFrom(address,address,uint256)\nBalanceOf").unwrap();
+        let library = generate_library("FRC46Methods", &entries).unwrap();
+        assert!(library.contains("library FRC46Methods {"));
+        assert!(library.contains(&format!(
+            "uint64 internal constant TRANSFER_FROM = {};",
+            method_hash!("TransferFrom")
+        )));
+        assert!(library.contains("bytes4 internal constant TRANSFER_FROM_SELECTOR = 0x23b872dd;"));
+        assert!(library.contains(&format!(
+            "uint64 internal constant BALANCE_OF = {};",
+            method_hash!("BalanceOf")
+        )));
+        assert!(!library.contains("BALANCE_OF_SELECTOR"));
+
+        assert_eq!(
+            generate_library("Not A Name", &entries).unwrap_err(),
+            ManifestError::LibraryName("Not A Name".into())
+        );
+
+        // repeated entries are declared once
+        let entries = parse("Burn burn(uint256)\nBalanceOf\nBurn burn(uint256)").unwrap();
+        let library = generate_library("FRC46Methods", &entries).unwrap();
+        assert_eq!(library.matches("uint64 internal constant BURN =").count(), 1);
+        assert_eq!(library.matches("bytes4 internal constant BURN_SELECTOR =").count(), 1);
+
+        // but conflicting entries for the same constant are rejected
+        let entries = parse("Burn burn(uint256)\nBurn burn(address,uint256)").unwrap();
+        assert_eq!(
+            generate_library("FRC46Methods", &entries).unwrap_err(),
+            ManifestError::DuplicateConstant("BURN".into())
+        );
+    }
+
+    #[test]
+    fn it_converts_constant_names() {
+        assert_eq!(constant_name("Transfer"), "TRANSFER");
+        assert_eq!(constant_name("TransferFrom"), "TRANSFER_FROM");
+        assert_eq!(constant_name("FRC46Transfer"), "FRC46_TRANSFER");
+        assert_eq!(constant_name("_Private"), "_PRIVATE");
+    }
+}
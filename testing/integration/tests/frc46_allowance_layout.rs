@@ -0,0 +1,75 @@
+use frc42_dispatch::method_hash;
+use frc46_token::token::state::{AllowanceLayout, TokenState, DEFAULT_HAMT_BIT_WIDTH};
+use frc46_token::token::types::TransferFromParams;
+use fvm_integration_tests::{dummy::DummyExterns, tester::Account};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::econ::TokenAmount;
+
+mod common;
+use common::frc46_token_helpers::TokenHelper;
+use common::{construct_tester, TestHelpers};
+use helix_test_actors::{BASIC_RECEIVING_ACTOR_BINARY, BASIC_TOKEN_ACTOR_BINARY};
+
+/// Compares the gas used by `TransferFrom` against tokens using the nested and flat allowance
+/// layouts, seeded with the same balances and allowances.
+#[test]
+fn flat_allowances_are_cheaper_to_use() {
+    let blockstore = MemoryBlockstore::default();
+    let mut tester = construct_tester(&blockstore);
+
+    let [alice, operator]: [Account; 2] = tester.create_accounts().unwrap();
+
+    let seed_state = |layout| {
+        let mut state =
+            TokenState::new_with_allowance_layout(&blockstore, DEFAULT_HAMT_BIT_WIDTH, layout)
+                .unwrap();
+        state.change_balance_by(&blockstore, alice.0, &TokenAmount::from_atto(1_000)).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(1_000)).unwrap();
+        state
+            .set_allowance(&blockstore, alice.0, operator.0, &TokenAmount::from_atto(500))
+            .unwrap();
+        // other owners' allowances give the nested layout a realistic spread of owners
+        for owner in 1_000..1_100 {
+            state
+                .set_allowance(&blockstore, owner, operator.0, &TokenAmount::from_atto(1))
+                .unwrap();
+        }
+        state
+    };
+
+    let nested_token = tester.install_actor_with_state(
+        BASIC_TOKEN_ACTOR_BINARY,
+        10000,
+        seed_state(AllowanceLayout::Nested),
+    );
+    let flat_token = tester.install_actor_with_state(
+        BASIC_TOKEN_ACTOR_BINARY,
+        10001,
+        seed_state(AllowanceLayout::Flat),
+    );
+    let receiver = tester.install_actor_stateless(BASIC_RECEIVING_ACTOR_BINARY, 10010);
+
+    // Instantiate machine
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    tester.call_method_ok(operator.1, receiver, method_hash!("Constructor"), None);
+
+    let mut transfer_from_gas = |token| {
+        let params = TransferFromParams {
+            from: alice.1,
+            to: receiver,
+            amount: TokenAmount::from_atto(100),
+            operator_data: RawBytes::default(),
+        };
+        let params = RawBytes::serialize(params).unwrap();
+        let ret_val =
+            tester.call_method_ok(operator.1, token, method_hash!("TransferFrom"), Some(params));
+        tester.assert_token_balance(operator.1, token, receiver, TokenAmount::from_atto(100));
+        ret_val.msg_receipt.gas_used
+    };
+
+    let nested_gas = transfer_from_gas(nested_token);
+    let flat_gas = transfer_from_gas(flat_token);
+    assert!(flat_gas < nested_gas);
+}
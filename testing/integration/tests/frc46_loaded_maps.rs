@@ -0,0 +1,67 @@
+use frc42_dispatch::method_hash;
+use frc46_token::token::state::TokenState;
+use fvm_integration_tests::{dummy::DummyExterns, tester::Account};
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_encoding::tuple::*;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+
+mod common;
+use common::frc46_token_helpers::TokenHelper;
+use common::{construct_tester, TestHelpers};
+use helix_test_actors::BASIC_TOKEN_ACTOR_BINARY;
+
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct RepeatBurnFromParams {
+    pub owner: Address,
+    pub amount: TokenAmount,
+    pub times: u64,
+}
+
+/// Compares the gas used by repeated burns through a `Token` with deferred flushing, which keeps its
+/// maps loaded until it is flushed, against the same burns through `TokenState`, which flushes them
+/// after every call.
+#[test]
+fn loaded_maps_are_cheaper_than_per_operation_flushes() {
+    let blockstore = MemoryBlockstore::default();
+    let mut tester = construct_tester(&blockstore);
+
+    let [alice, operator]: [Account; 2] = tester.create_accounts().unwrap();
+
+    let seed_state = || {
+        let mut state = TokenState::new(&blockstore).unwrap();
+        state.change_balance_by(&blockstore, alice.0, &TokenAmount::from_atto(1_000)).unwrap();
+        state.change_supply_by(&TokenAmount::from_atto(1_000)).unwrap();
+        state
+            .set_allowance(&blockstore, alice.0, operator.0, &TokenAmount::from_atto(1_000))
+            .unwrap();
+        // other holders give the balance map a realistic depth
+        for holder in 1_000..1_100 {
+            state.change_balance_by(&blockstore, holder, &TokenAmount::from_atto(1)).unwrap();
+            state.change_supply_by(&TokenAmount::from_atto(1)).unwrap();
+        }
+        state
+    };
+
+    let loaded_token =
+        tester.install_actor_with_state(BASIC_TOKEN_ACTOR_BINARY, 10000, seed_state());
+    let flushed_token =
+        tester.install_actor_with_state(BASIC_TOKEN_ACTOR_BINARY, 10001, seed_state());
+
+    // Instantiate machine
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    let mut repeat_burn_from_gas = |token, method| {
+        let params =
+            RepeatBurnFromParams { owner: alice.1, amount: TokenAmount::from_atto(10), times: 10 };
+        let params = RawBytes::serialize(params).unwrap();
+        let ret_val = tester.call_method_ok(operator.1, token, method, Some(params));
+        tester.assert_token_balance(operator.1, token, alice.1, TokenAmount::from_atto(900));
+        ret_val.msg_receipt.gas_used
+    };
+
+    let loaded_gas = repeat_burn_from_gas(loaded_token, method_hash!("RepeatBurnFrom"));
+    let flushed_gas = repeat_burn_from_gas(flushed_token, method_hash!("RepeatBurnFromState"));
+    assert!(loaded_gas < flushed_gas);
+}
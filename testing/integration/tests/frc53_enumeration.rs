@@ -1,9 +1,10 @@
 use frc42_dispatch::method_hash;
 use frc53_nft::state::NFTState;
 use frc53_nft::types::{
-    ListAccountOperatorsParams, ListAccountOperatorsReturn, ListOperatorTokensParams,
-    ListOperatorTokensReturn, ListOwnedTokensParams, ListOwnedTokensReturn,
-    ListTokenOperatorsParams, ListTokenOperatorsReturn, ListTokensParams, ListTokensReturn,
+    ListAccountOperatorsParams, ListAccountOperatorsReturn, ListOperatorOwnersParams,
+    ListOperatorOwnersReturn, ListOperatorTokensParams, ListOperatorTokensReturn,
+    ListOwnedTokensParams, ListOwnedTokensReturn, ListTokenOperatorsParams,
+    ListTokenOperatorsReturn, ListTokensParams, ListTokensReturn,
 };
 use fvm_actor_utils::shared_blockstore::SharedMemoryBlockstore;
 use fvm_integration_tests::{dummy::DummyExterns, tester::Account};
@@ -290,4 +291,27 @@ pub fn test_nft_enumerations() {
         assert_eq!(call_result.operators.len(), 1);
         assert!(call_result.next_cursor.is_none());
     }
+
+    // List OperatorOwners
+    {
+        // List all the owners that approved the operator at account level
+        let params = ListOperatorOwnersParams {
+            operator: operator.1,
+            cursor: RawBytes::default(),
+            limit: u64::MAX,
+        };
+        let params = RawBytes::serialize(params).unwrap();
+        let ret_val = tester.call_method_ok(
+            operator.1,
+            actor_address,
+            method_hash!("ListOperatorOwners"),
+            Some(params),
+        );
+        let call_result =
+            ret_val.msg_receipt.return_data.deserialize::<ListOperatorOwnersReturn>().unwrap();
+        // Only bob approved the operator
+        assert!(call_result.owners.get(bob.0));
+        assert_eq!(call_result.owners.len(), 1);
+        assert!(call_result.next_cursor.is_none());
+    }
 }
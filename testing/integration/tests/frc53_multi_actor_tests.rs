@@ -441,6 +441,67 @@ fn frc53_multi_actor_tests() {
     }
 }
 
+/// Compares the gas used to transfer a batch of tokens with transferring a single token.
+///
+/// The token array and owner map are flushed once per batch, so each extra token in a batch should
+/// cost far less than a transfer of its own.
+#[test]
+fn frc53_batch_transfer_gas() {
+    let blockstore = MemoryBlockstore::default();
+    let mut tester = construct_tester(&blockstore);
+
+    let operator: [Account; 1] = tester.create_accounts().unwrap();
+    let op_addr = operator[0].1;
+
+    let initial_nft_state = NFTState::new(&blockstore).unwrap();
+
+    let token_actor =
+        tester.install_actor_with_state(BASIC_NFT_ACTOR_BINARY, 10000, initial_nft_state);
+    let alice = tester.install_actor_stateless(FRC53_TEST_ACTOR_BINARY, 10010);
+    let bob = tester.install_actor_stateless(FRC53_TEST_ACTOR_BINARY, 10011);
+
+    // instantiate machine
+    tester.instantiate_machine(DummyExterns).unwrap();
+
+    // construct actors
+    for actor in [token_actor, alice, bob] {
+        let ret_val = tester.call_method(op_addr, actor, method_hash!("Constructor"), None);
+        assert!(ret_val.msg_receipt.exit_code.is_success());
+    }
+
+    // mint tokens to alice
+    {
+        let mint_params = MintParams {
+            initial_owner: alice,
+            metadata: vec![String::default(); 11],
+            operator_data: action(TestAction::Accept),
+        };
+        let params = RawBytes::serialize(mint_params).unwrap();
+        tester.call_method_ok(op_addr, token_actor, method_hash!("Mint"), Some(params));
+        tester.assert_nft_balance(op_addr, token_actor, alice, 11);
+    }
+
+    let mut transfer_gas = |token_ids: Vec<TokenID>| {
+        let params = action_params(
+            token_actor,
+            TestAction::Transfer(bob, token_ids, action(TestAction::Accept)),
+        );
+        let ret_val = tester.call_method_ok(op_addr, alice, method_hash!("Action"), Some(params));
+        let action_receipt = ret_val.msg_receipt.return_data.deserialize::<Receipt>().unwrap();
+        assert!(action_receipt.exit_code.is_success());
+        ret_val.msg_receipt.gas_used
+    };
+
+    let single_gas = transfer_gas(vec![0]);
+    let batch_gas = transfer_gas((1..=10).collect());
+    // each extra token in the batch costs less than a transfer of its own
+    let extra_token_gas = (batch_gas - single_gas) / 9;
+    assert!(extra_token_gas < single_gas);
+
+    tester.assert_nft_balance(op_addr, token_actor, alice, 0);
+    tester.assert_nft_balance(op_addr, token_actor, bob, 11);
+}
+
 /// These types have been duplicated from `frc53_test_actor` as we can't import into rust from a
 /// cdylib.
 #[derive(Serialize, Deserialize, Debug)]
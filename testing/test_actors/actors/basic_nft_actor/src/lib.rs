@@ -1,16 +1,22 @@
 use frc42_dispatch::match_method;
 use frc53_nft::{
+    erc721::Erc721Call,
     state::NFTState,
     types::{
         ApproveForAllParams, ApproveParams, BurnFromParams, ListAccountOperatorsParams,
-        ListOperatorTokensParams, ListOwnedTokensParams, ListTokenOperatorsParams,
-        ListTokensParams, RevokeForAllParams, RevokeParams, TokenID, TransferFromParams,
-        TransferParams,
+        ListOperatorOwnersParams, ListOperatorTokensParams, ListOwnedTokensParams,
+        ListTokenOperatorsParams, ListTokensParams, RevokeForAllParams, RevokeParams, TokenID,
+        TransferFromParams, TransferParams, UpdateMetadataParams,
     },
     NFT,
 };
 use fvm_actor_utils::{
-    blockstore::Blockstore, messaging::FvmMessenger, syscalls::fvm_syscalls::FvmSyscalls,
+    access_control::{AccessControlState, METADATA_EDITOR_ROLE},
+    blockstore::Blockstore,
+    evm::{calldata_from_params, return_data_block},
+    messaging::FvmMessenger,
+    pause::PauseState,
+    syscalls::fvm_syscalls::FvmSyscalls,
     util::ActorRuntime,
 };
 use fvm_ipld_encoding::{de::DeserializeOwned, ser, tuple::*, RawBytes, DAG_CBOR};
@@ -56,6 +62,25 @@ fn invoke(params: u32) -> u32 {
             let res = handle.metadata(params).unwrap();
             return_ipld(&res).unwrap()
         }
+        "TokenMetadata" => {
+            let params = deserialize_params::<TokenID>(params);
+            let res = handle.token_metadata(params).unwrap();
+            return_ipld(&res).unwrap()
+        }
+        "UpdateMetadata" => {
+            let params = deserialize_params::<UpdateMetadataParams>(params);
+            handle.update_metadata(&caller_address(), params.token_id, params.metadata).unwrap();
+            let cid = handle.flush().unwrap();
+            sdk::sself::set_root(&cid).unwrap();
+            NO_DATA_BLOCK_ID
+        }
+        "FreezeMetadata" => {
+            let params = deserialize_params::<TokenID>(params);
+            handle.freeze_metadata(&caller_address(), params).unwrap();
+            let cid = handle.flush().unwrap();
+            sdk::sself::set_root(&cid).unwrap();
+            NO_DATA_BLOCK_ID
+        }
         "Mint" => {
             let params = deserialize_params::<MintParams>(params);
             let caller = Address::new_id(sdk::message::caller());
@@ -172,11 +197,44 @@ fn invoke(params: u32) -> u32 {
             let res = handle.list_operator_tokens(&params.operator, params.cursor, params.limit).unwrap();
             return_ipld(&res).unwrap()
         }
+        "ListOperatorOwners" => {
+            let params = deserialize_params::<ListOperatorOwnersParams>(params);
+            let res = handle.list_operator_owners(&params.operator, params.cursor, params.limit).unwrap();
+            return_ipld(&res).unwrap()
+        }
         "ListAccountOperators" => {
             let params = deserialize_params::<ListAccountOperatorsParams>(params);
             let res = handle.list_account_operators(&params.owner, params.cursor, params.limit).unwrap();
             return_ipld(&res).unwrap()
         }
+        "Pause" => {
+            handle.pause(&caller_address()).unwrap();
+            let cid = handle.flush().unwrap();
+            sdk::sself::set_root(&cid).unwrap();
+            NO_DATA_BLOCK_ID
+        }
+        "Unpause" => {
+            handle.unpause(&caller_address()).unwrap();
+            let cid = handle.flush().unwrap();
+            sdk::sself::set_root(&cid).unwrap();
+            NO_DATA_BLOCK_ID
+        }
+        "InvokeEVM" => {
+            // serve ERC-721 callers from the FEVM
+            let calldata = calldata_from_params(sdk::message::params_raw(params).unwrap()).unwrap();
+            let call = Erc721Call::decode(&calldata).unwrap();
+            let mutates_state = call.mutates_state();
+            let res = call.invoke(&mut handle, &caller_address()).unwrap();
+
+            if mutates_state {
+                let cid = handle.flush().unwrap();
+                sdk::sself::set_root(&cid).unwrap();
+            }
+
+            let ret_val = res.complete(&messenger).unwrap();
+            let block = return_data_block(&ret_val).unwrap();
+            sdk::ipld::put_block(block.codec, block.data.as_slice()).unwrap()
+        }
         _ => {
             sdk::vm::abort(ExitCode::USR_ILLEGAL_ARGUMENT.value(), Some(&format!("Unknown method number {method_num:?} was invoked")));
         }
@@ -185,7 +243,14 @@ fn invoke(params: u32) -> u32 {
 
 pub fn constructor() {
     let bs = Blockstore {};
-    let nft_state = NFTState::new(&bs).unwrap();
+    let mut nft_state = NFTState::new(&bs).unwrap();
+    // the deployer can pause the collection
+    nft_state.pause = Some(PauseState::new(sdk::message::caller()));
+    // and administers the roles of the collection, starting out as its metadata editor
+    let deployer = sdk::message::caller();
+    let mut access_control = AccessControlState::new(&bs, deployer).unwrap();
+    access_control.grant_role(&bs, deployer, METADATA_EDITOR_ROLE, deployer).unwrap();
+    nft_state.access_control = Some(access_control);
     let state_cid = nft_state.save(&bs).unwrap();
     sdk::sself::set_root(&state_cid).unwrap();
 }
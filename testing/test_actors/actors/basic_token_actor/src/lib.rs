@@ -11,6 +11,7 @@ use frc46_token::token::types::{
 };
 use frc46_token::token::Token;
 use fvm_actor_utils::blockstore::Blockstore;
+use fvm_actor_utils::pause::PauseState;
 use fvm_actor_utils::syscalls::fvm_syscalls::FvmSyscalls;
 use fvm_actor_utils::util::ActorRuntime;
 use fvm_ipld_encoding::tuple::*;
@@ -155,6 +156,13 @@ pub struct MintParams {
     pub operator_data: RawBytes,
 }
 
+#[derive(Serialize_tuple, Deserialize_tuple, Clone, Debug)]
+pub struct RepeatBurnFromParams {
+    pub owner: Address,
+    pub amount: TokenAmount,
+    pub times: u64,
+}
+
 impl BasicToken<'_> {
     fn reload(&mut self, initial_cid: &Cid) -> Result<(), RuntimeError> {
         // todo: revise error type here so it plays nice with the result and doesn't need unwrap
@@ -184,6 +192,42 @@ impl BasicToken<'_> {
 
         Ok(ret)
     }
+
+    /// Burns from an owner several times through the token handle, which keeps its maps loaded
+    /// until the state is flushed.
+    fn repeat_burn_from(&mut self, params: RepeatBurnFromParams) -> Result<(), RuntimeError> {
+        let operator = caller_address();
+        for _ in 0..params.times {
+            self.util.burn_from(&operator, &params.owner, &params.amount)?;
+        }
+        Ok(())
+    }
+}
+
+/// Burns from an owner several times through the [`frc46_token::token::state::TokenState`] methods, which load and flush
+/// the maps they touch on every call.
+fn repeat_burn_from_state(params: u32) -> u32 {
+    let params: RepeatBurnFromParams = deserialize_params(params);
+    let root_cid = sdk::sself::root().unwrap();
+    let helper = ActorRuntime::<FvmSyscalls, Blockstore>::new_fvm_runtime();
+    let mut token_state =
+        Token::<FvmSyscalls, Blockstore>::load_state(helper.bs(), &root_cid).unwrap();
+
+    let operator = sdk::message::caller();
+    let owner = helper.resolve_id(&params.owner).unwrap();
+    let delta = -params.amount.clone();
+    let epoch = helper.curr_epoch();
+    for _ in 0..params.times {
+        token_state
+            .attempt_use_allowance_at(&helper, operator, owner, &params.amount, epoch)
+            .unwrap();
+        token_state.change_balance_by(&helper, owner, &delta).unwrap();
+        token_state.change_supply_by(&delta).unwrap();
+    }
+
+    let cid = token_state.save(&helper).unwrap();
+    sdk::sself::set_root(&cid).unwrap();
+    NO_DATA_BLOCK_ID
 }
 
 #[derive(Error, Debug)]
@@ -214,6 +258,10 @@ pub fn invoke(params: u32) -> u32 {
     match method_num {
         // Actor constructor
         1 => constructor(),
+        0x88af3f9b => {
+            // RepeatBurnFromState
+            repeat_burn_from_state(params)
+        }
 
         // Standard token interface
         rest => {
@@ -223,7 +271,9 @@ pub fn invoke(params: u32) -> u32 {
             let mut token_state =
                 Token::<FvmSyscalls, Blockstore>::load_state(helper.bs(), &root_cid).unwrap();
 
-            let mut token_actor = BasicToken { util: Token::wrap(&helper, 1, &mut token_state) };
+            // every method flushes the token before setting the root or calling a receiver hook
+            let util = Token::wrap(&helper, 1, &mut token_state).with_deferred_flush();
+            let mut token_actor = BasicToken { util };
 
             // Method numbers calculated via fvm_dispatch_tools using CamelCase names derived from
             // the corresponding FRC46Token trait methods.
@@ -287,6 +337,14 @@ pub fn invoke(params: u32) -> u32 {
                     sdk::sself::set_root(&cid).unwrap();
                     return_ipld(&res).unwrap()
                 }
+                0xb19a37a2 => {
+                    // BurnFrom
+                    let params = deserialize_params(params);
+                    let res = token_actor.burn_from(params).unwrap();
+                    let cid = token_actor.util.flush().unwrap();
+                    sdk::sself::set_root(&cid).unwrap();
+                    return_ipld(&res).unwrap()
+                }
                 0xd7d4deed => {
                     // TransferFrom
                     let params = deserialize_params(params);
@@ -308,6 +366,28 @@ pub fn invoke(params: u32) -> u32 {
                     let res = token_actor.mint(params).unwrap();
                     return_ipld(&res).unwrap()
                 }
+                0xc2e00620 => {
+                    // RepeatBurnFrom
+                    let params = deserialize_params(params);
+                    token_actor.repeat_burn_from(params).unwrap();
+                    let cid = token_actor.util.flush().unwrap();
+                    sdk::sself::set_root(&cid).unwrap();
+                    NO_DATA_BLOCK_ID
+                }
+                0x0ab827d3 => {
+                    // Pause
+                    token_actor.util.pause(&caller_address()).unwrap();
+                    let cid = token_actor.util.flush().unwrap();
+                    sdk::sself::set_root(&cid).unwrap();
+                    NO_DATA_BLOCK_ID
+                }
+                0xff55bf1f => {
+                    // Unpause
+                    token_actor.util.unpause(&caller_address()).unwrap();
+                    let cid = token_actor.util.flush().unwrap();
+                    sdk::sself::set_root(&cid).unwrap();
+                    NO_DATA_BLOCK_ID
+                }
                 _ => {
                     sdk::vm::abort(
                         ExitCode::USR_UNHANDLED_MESSAGE.value(),
@@ -322,6 +402,8 @@ pub fn invoke(params: u32) -> u32 {
 fn constructor() -> u32 {
     let helper = ActorRuntime::<FvmSyscalls, Blockstore>::new_fvm_runtime();
     let mut token_state = Token::<FvmSyscalls, Blockstore>::create_state(helper.bs()).unwrap();
+    // the deployer can pause the token
+    token_state.pause = Some(PauseState::new(sdk::message::caller()));
     let mut token = Token::wrap(&helper, 1, &mut token_state);
     let cid = token.flush().unwrap();
     sdk::sself::set_root(&cid).unwrap();
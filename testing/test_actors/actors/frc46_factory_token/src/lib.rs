@@ -7,8 +7,8 @@ use fvm_actor_utils::{
 use fvm_sdk::NO_DATA_BLOCK_ID;
 use fvm_shared::error::ExitCode;
 use token_impl::{
-    construct_token, deserialize_params, frc46_invoke, return_ipld, FactoryToken, MintParams,
-    RuntimeError,
+    construct_token, deserialize_params, evm_calldata, frc46_invoke, return_evm, return_ipld,
+    FactoryToken, MintParams, RoleParams, RuntimeError,
 };
 
 fn token_invoke(method_num: u64, params: u32) -> Result<u32, RuntimeError> {
@@ -36,6 +36,48 @@ fn token_invoke(method_num: u64, params: u32) -> Result<u32, RuntimeError> {
             // no return
             Ok(NO_DATA_BLOCK_ID)
         }
+        "GrantRole" => {
+            let root_cid = runtime.root_cid()?;
+            let params: RoleParams = deserialize_params(params);
+            let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
+            token_actor.grant_role(params)?;
+            let cid = token_actor.save()?;
+            token_actor.runtime().set_root(&cid)?;
+            Ok(NO_DATA_BLOCK_ID)
+        }
+        "RevokeRole" => {
+            let root_cid = runtime.root_cid()?;
+            let params: RoleParams = deserialize_params(params);
+            let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
+            token_actor.revoke_role(params)?;
+            let cid = token_actor.save()?;
+            token_actor.runtime().set_root(&cid)?;
+            Ok(NO_DATA_BLOCK_ID)
+        }
+        "Pause" => {
+            let root_cid = runtime.root_cid()?;
+            let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
+            token_actor.pause()?;
+            let cid = token_actor.save()?;
+            token_actor.runtime().set_root(&cid)?;
+            Ok(NO_DATA_BLOCK_ID)
+        }
+        "Unpause" => {
+            let root_cid = runtime.root_cid()?;
+            let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
+            token_actor.unpause()?;
+            let cid = token_actor.save()?;
+            token_actor.runtime().set_root(&cid)?;
+            Ok(NO_DATA_BLOCK_ID)
+        }
+        "InvokeEVM" => {
+            // serve ERC-20 callers from the FEVM
+            let root_cid = runtime.root_cid()?;
+            let calldata = evm_calldata(params)?;
+            let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
+            let res = token_actor.invoke_evm(&calldata)?;
+            return_evm(&res)
+        }
         _ => {
             let root_cid = runtime.root_cid()?;
             let mut token_actor = FactoryToken::load(runtime, &root_cid)?;
@@ -2,6 +2,7 @@
 
 use cid::Cid;
 use frc42_dispatch::match_method;
+use frc46_token::erc20::{Erc20Call, Erc20Error};
 use frc46_token::token::{
     state::{StateError, TokenState},
     types::{
@@ -13,7 +14,10 @@ use frc46_token::token::{
     Token, TokenError,
 };
 use fvm_actor_utils::{
+    access_control::{AccessControlError, AccessControlState, ADMIN_ROLE, MINTER_ROLE},
+    evm::{calldata_from_params, return_data_block},
     messaging::MessagingError,
+    pause::PauseState,
     receiver::ReceiverHookError,
     syscalls::Syscalls,
     util::{ActorError, ActorRuntime},
@@ -57,8 +61,10 @@ pub enum RuntimeError {
     State(#[from] StateError),
     #[error("actor messaging error {0}")]
     Messaging(#[from] MessagingError),
-    #[error("address not authorized")]
-    AddressNotAuthorized,
+    #[error("erc20 error {0}")]
+    Erc20(#[from] Erc20Error),
+    #[error("access control error {0}")]
+    AccessControl(#[from] AccessControlError),
     #[error("minting has been permanently disabled")]
     MintingDisabled,
 }
@@ -94,9 +100,9 @@ impl From<&RuntimeError> for ExitCode {
             }
             RuntimeError::State(e) => e.into(),
             RuntimeError::Messaging(e) => e.into(),
-            RuntimeError::AddressNotAuthorized | RuntimeError::MintingDisabled => {
-                ExitCode::USR_FORBIDDEN
-            }
+            RuntimeError::Erc20(e) => e.into(),
+            RuntimeError::AccessControl(e) => e.into(),
+            RuntimeError::MintingDisabled => ExitCode::USR_FORBIDDEN,
         }
     }
 }
@@ -108,7 +114,8 @@ pub struct ConstructorParams {
     pub granularity: u64,
     /// Authorised mint operator.
     ///
-    /// Only this address can mint tokens or remove themselves to permanently disable minting.
+    /// This address is the token's admin and initially its only minter. It can grant the minter
+    /// role to others or permanently disable minting.
     pub minter: Address,
 }
 
@@ -118,7 +125,7 @@ pub fn construct_token<S: Syscalls, BS: Blockstore>(
 ) -> Result<u32, RuntimeError> {
     let minter = runtime.resolve_id(&params.minter)?;
     let token =
-        FactoryToken::new(runtime, params.name, params.symbol, params.granularity, Some(minter));
+        FactoryToken::new(runtime, params.name, params.symbol, params.granularity, Some(minter))?;
 
     let cid = token.save()?;
     token.runtime.set_root(&cid)?;
@@ -134,8 +141,21 @@ pub struct FactoryTokenState {
     pub name: String,
     pub symbol: String,
     pub granularity: u64,
-    /// Address of authorised minting operator.
+    /// The minting operator the token was constructed with, and the admin of its roles.
+    ///
+    /// Cleared when minting is permanently disabled.
     pub minter: Option<ActorID>,
+    /// Holders of [`MINTER_ROLE`], administered by the minter.
+    ///
+    /// Absent in state written before roles were introduced, and created on load from `minter`.
+    #[serde(default)]
+    pub access_control: Option<AccessControlState>,
+}
+
+#[derive(Serialize_tuple, Deserialize_tuple, Debug)]
+pub struct RoleParams {
+    pub role: String,
+    pub account: Address,
 }
 
 pub struct FactoryToken<S: Syscalls, BS: Blockstore> {
@@ -149,12 +169,29 @@ impl FactoryTokenState {
     /// This is for internal use only as part of [`FactoryToken::load`].
     fn load<BS: Blockstore>(runtime: &BS, cid: &Cid) -> Result<Self, RuntimeError> {
         match runtime.get_cbor::<Self>(cid) {
-            Ok(Some(s)) => Ok(s),
+            Ok(Some(mut s)) => {
+                // the embedded token state may have been written by an earlier library version
+                s.token.upgrade(runtime)?;
+                if let (None, Some(minter)) = (&s.access_control, s.minter) {
+                    s.access_control = Some(Self::minter_roles(runtime, minter)?);
+                }
+                Ok(s)
+            }
             // TODO: improve on these errors?
             Ok(None) => Err(RuntimeError::Deserialization("no data found".into())),
             Err(e) => Err(RuntimeError::Deserialization(e.to_string())),
         }
     }
+
+    /// Creates access control state with the minter as admin and sole holder of [`MINTER_ROLE`].
+    fn minter_roles<BS: Blockstore>(
+        bs: &BS,
+        minter: ActorID,
+    ) -> Result<AccessControlState, RuntimeError> {
+        let mut access_control = AccessControlState::new(bs, minter)?;
+        access_control.grant_role(bs, minter, MINTER_ROLE, minter)?;
+        Ok(access_control)
+    }
 }
 
 /// Implementation of the token API in a FVM actor.
@@ -289,17 +326,16 @@ impl<S: Syscalls, BS: Blockstore> FactoryToken<S, BS> {
         symbol: String,
         granularity: u64,
         minter: Option<ActorID>,
-    ) -> Self {
-        FactoryToken {
-            state: FactoryTokenState {
-                token: TokenState::new(&runtime).unwrap(),
-                name,
-                symbol,
-                granularity,
-                minter,
-            },
+    ) -> Result<Self, RuntimeError> {
+        let mut token = TokenState::new(&runtime)?;
+        // the minter can also pause the token
+        token.pause = minter.map(PauseState::new);
+        let access_control =
+            minter.map(|minter| FactoryTokenState::minter_roles(&runtime, minter)).transpose()?;
+        Ok(FactoryToken {
+            state: FactoryTokenState { token, name, symbol, granularity, minter, access_control },
             runtime,
-        }
+        })
     }
 
     pub fn caller_address(&self) -> Address {
@@ -338,13 +374,10 @@ impl<S: Syscalls, BS: Blockstore> FactoryToken<S, BS> {
     }
 
     pub fn mint(&mut self, params: MintParams) -> Result<MintReturn, RuntimeError> {
-        // check if the caller matches our authorise mint operator
         // no minter address means minting has been permanently disabled
-        let minter = self.state.minter.ok_or(RuntimeError::MintingDisabled)?;
+        let access_control = self.access_control()?;
         let caller_id = self.runtime.caller();
-        if caller_id != minter {
-            return Err(RuntimeError::AddressNotAuthorized);
-        }
+        access_control.require_role(&self.runtime, caller_id, MINTER_ROLE)?;
 
         let mut hook = self.token().mint(
             &Address::new_id(caller_id),
@@ -367,19 +400,78 @@ impl<S: Syscalls, BS: Blockstore> FactoryToken<S, BS> {
 
     /// Permanently disable minting.
     ///
-    /// Only the authorised mint operator can call this.
+    /// Only the admin, initially the authorised mint operator, can call this.
     pub fn disable_mint(&mut self) -> Result<(), RuntimeError> {
-        // no minter means minting has already been permanently disabled
-        // we return this if already disabled because it will make more sense than failing the address check below
-        let minter = self.state.minter.ok_or(RuntimeError::MintingDisabled)?;
-        let caller_id = self.runtime.caller();
-        if caller_id != minter {
-            return Err(RuntimeError::AddressNotAuthorized);
-        }
+        // we return this if already disabled because it will make more sense than failing the role check below
+        let access_control = self.access_control()?;
+        access_control.require_role(&self.runtime, self.runtime.caller(), ADMIN_ROLE)?;
 
         self.state.minter = None;
+        self.state.access_control = None;
+        Ok(())
+    }
+
+    /// Grants a role to an account.
+    ///
+    /// Only members of the role's admin role, initially the minter, can call this.
+    pub fn grant_role(&mut self, params: RoleParams) -> Result<(), RuntimeError> {
+        let account = self.runtime.resolve_id(&params.account)?;
+        let caller_id = self.runtime.caller();
+        let access_control =
+            self.state.access_control.as_mut().ok_or(RuntimeError::MintingDisabled)?;
+        access_control.grant_role(&self.runtime, caller_id, &params.role, account)?;
+        Ok(())
+    }
+
+    /// Revokes a role from an account.
+    ///
+    /// Only members of the role's admin role, initially the minter, can call this.
+    pub fn revoke_role(&mut self, params: RoleParams) -> Result<(), RuntimeError> {
+        let account = self.runtime.resolve_id(&params.account)?;
+        let caller_id = self.runtime.caller();
+        let access_control =
+            self.state.access_control.as_mut().ok_or(RuntimeError::MintingDisabled)?;
+        access_control.revoke_role(&self.runtime, caller_id, &params.role, account)?;
         Ok(())
     }
+
+    /// The token's roles, which are dropped once minting has been permanently disabled.
+    fn access_control(&self) -> Result<&AccessControlState, RuntimeError> {
+        self.state.access_control.as_ref().ok_or(RuntimeError::MintingDisabled)
+    }
+
+    /// Pauses transfers, mints and burns.
+    ///
+    /// Only the pauser, initially the minter, can call this.
+    pub fn pause(&mut self) -> Result<(), RuntimeError> {
+        let caller = self.caller_address();
+        Ok(self.token().pause(&caller)?)
+    }
+
+    /// Resumes transfers, mints and burns.
+    ///
+    /// Only the pauser can call this.
+    pub fn unpause(&mut self) -> Result<(), RuntimeError> {
+        let caller = self.caller_address();
+        Ok(self.token().unpause(&caller)?)
+    }
+
+    /// Serves an ERC-20 call made through the FEVM `InvokeEVM` method, returning the ABI encoded
+    /// return data.
+    pub fn invoke_evm(&mut self, calldata: &[u8]) -> Result<Vec<u8>, RuntimeError> {
+        let call = Erc20Call::decode(calldata).map_err(Erc20Error::from)?;
+        let mutates_state = call.mutates_state();
+        let caller = self.caller_address();
+        let (name, symbol) = (self.state.name.clone(), self.state.symbol.clone());
+        let res = call.invoke(&mut self.token(), &caller, &name, &symbol)?;
+
+        if mutates_state {
+            let cid = self.save()?;
+            self.runtime.set_root(&cid)?;
+        }
+
+        Ok(res.complete(self.token().runtime())?)
+    }
 }
 
 pub fn deserialize_params<O: DeserializeOwned>(params: u32) -> O {
@@ -396,6 +488,18 @@ where
     Ok(sdk::ipld::put_block(DAG_CBOR, bytes.as_slice())?)
 }
 
+/// Extracts the ABI calldata from the params of an `InvokeEVM` message.
+pub fn evm_calldata(params: u32) -> Result<Vec<u8>, RuntimeError> {
+    let params = sdk::message::params_raw(params)?;
+    Ok(calldata_from_params(params).map_err(Erc20Error::from)?)
+}
+
+/// Saves ABI encoded return data for an `InvokeEVM` message to the blockstore.
+pub fn return_evm(data: &[u8]) -> Result<u32, RuntimeError> {
+    let block = return_data_block(data).map_err(Erc20Error::from)?;
+    Ok(sdk::ipld::put_block(block.codec, block.data.as_slice())?)
+}
+
 /// Generic invoke for FRC46 Token methods.
 ///
 /// Given a method number and parameter block id, invokes the appropriate method on the FRC46Token
@@ -542,6 +646,7 @@ where
 
 #[cfg(test)]
 mod test {
+    use frc46_token::erc20::selectors;
     use frc46_token::token::{
         types::{
             BurnFromParams, BurnParams, DecreaseAllowanceParams, FRC46Token, GetAllowanceParams,
@@ -550,13 +655,18 @@ mod test {
         TokenError,
     };
     use fvm_actor_utils::{
-        shared_blockstore::SharedMemoryBlockstore, syscalls::fake_syscalls::FakeSyscalls,
+        access_control::{AccessControlError, MINTER_ROLE},
+        evm::{id_to_eth, AbiDecoder, AbiEncoder},
+        pause::ERR_PAUSED,
+        shared_blockstore::SharedMemoryBlockstore,
+        syscalls::fake_syscalls::FakeSyscalls,
         util::ActorRuntime,
     };
-    use fvm_ipld_encoding::RawBytes;
-    use fvm_shared::{address::Address, bigint::Zero, econ::TokenAmount};
+    use fvm_ipld_encoding::{CborStore, RawBytes};
+    use fvm_shared::{address::Address, bigint::Zero, econ::TokenAmount, error::ExitCode};
+    use multihash_codetable::Code;
 
-    use crate::{FactoryToken, MintParams, RuntimeError};
+    use crate::{FactoryToken, MintParams, RoleParams, RuntimeError};
 
     const ALICE: Address = Address::new_id(1);
     const BOB: Address = Address::new_id(2);
@@ -578,6 +688,7 @@ mod test {
             1,
             Some(actor_id),
         )
+        .unwrap()
     }
 
     #[test]
@@ -613,7 +724,7 @@ mod test {
 
         // check error
         match err {
-            RuntimeError::AddressNotAuthorized => {}
+            RuntimeError::AccessControl(AccessControlError::MissingRole { .. }) => {}
             _ => panic!("unexpected error"),
         }
     }
@@ -670,11 +781,86 @@ mod test {
 
         // check error
         match err {
-            RuntimeError::AddressNotAuthorized => {}
+            RuntimeError::AccessControl(AccessControlError::MissingRole { .. }) => {}
             _ => panic!("unexpected error"),
         }
     }
 
+    #[test]
+    fn it_grants_and_revokes_the_minter_role() {
+        let mut token = setup_token(&ALICE);
+        let mint_params = MintParams {
+            initial_owner: BOB,
+            amount: TokenAmount::from_whole(10),
+            operator_data: RawBytes::default(),
+        };
+
+        token.grant_role(RoleParams { role: MINTER_ROLE.into(), account: BOB }).unwrap();
+        token.runtime.syscalls.set_caller_id(token.runtime.resolve_id(&BOB).unwrap());
+        token.mint(mint_params.clone()).unwrap();
+        assert_eq!(token.total_supply(), TokenAmount::from_whole(10));
+
+        // only the admin can revoke the role
+        let err =
+            token.revoke_role(RoleParams { role: MINTER_ROLE.into(), account: BOB }).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        token.runtime.syscalls.set_caller_id(token.runtime.resolve_id(&ALICE).unwrap());
+        token.revoke_role(RoleParams { role: MINTER_ROLE.into(), account: BOB }).unwrap();
+
+        token.runtime.syscalls.set_caller_id(token.runtime.resolve_id(&BOB).unwrap());
+        let err = token.mint(mint_params).unwrap_err();
+        assert!(matches!(err, RuntimeError::AccessControl(AccessControlError::MissingRole { .. })));
+    }
+
+    #[test]
+    fn it_loads_state_written_before_roles() {
+        let mut token = setup_token(&ALICE);
+        let state = &token.state;
+        let old_state = (&state.token, &state.name, &state.symbol, state.granularity, state.minter);
+        let cid = token.runtime.put_cbor(&old_state, Code::Blake2b256).unwrap();
+
+        let runtime = token.runtime.clone();
+        token = FactoryToken::load(runtime, &cid).unwrap();
+        // the minter is given the minter role
+        token
+            .mint(MintParams {
+                initial_owner: BOB,
+                amount: TokenAmount::from_whole(10),
+                operator_data: RawBytes::default(),
+            })
+            .unwrap();
+        assert_eq!(token.total_supply(), TokenAmount::from_whole(10));
+    }
+
+    #[test]
+    fn it_serves_erc20_callers() {
+        let mut token = setup_token(&ALICE);
+        token
+            .mint(MintParams {
+                initial_owner: ALICE,
+                amount: TokenAmount::from_whole(10),
+                operator_data: RawBytes::default(),
+            })
+            .unwrap();
+
+        // alice transfers to bob's masked ID address
+        let calldata = AbiEncoder::with_selector(selectors::TRANSFER)
+            .address(&id_to_eth(2))
+            .uint256(TokenAmount::from_whole(4).atto())
+            .unwrap()
+            .finish();
+        let ret = token.invoke_evm(&calldata).unwrap();
+        assert!(AbiDecoder::new(&ret).bool(0).unwrap());
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::from_whole(4));
+
+        let calldata = AbiEncoder::with_selector(selectors::SYMBOL).finish();
+        let ret = token.invoke_evm(&calldata).unwrap();
+        assert_eq!(AbiDecoder::new(&ret).bytes(0).unwrap(), b"TEST");
+
+        let err = token.invoke_evm(&[0xde, 0xad, 0xbe, 0xef]).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_UNHANDLED_MESSAGE);
+    }
+
     #[test]
     fn it_has_name_and_symbol() {
         let token = setup_token(&ALICE);
@@ -695,7 +881,8 @@ mod test {
             String::from("TEST"),
             10,
             Some(actor_id),
-        );
+        )
+        .unwrap();
 
         {
             let ret = token
@@ -770,6 +957,38 @@ mod test {
         assert_eq!(token.total_supply(), TokenAmount::from_whole(10));
     }
 
+    #[test]
+    fn it_pauses_transfers() {
+        let mut token = setup_token(&ALICE);
+        token
+            .mint(MintParams {
+                initial_owner: ALICE,
+                amount: TokenAmount::from_whole(10),
+                operator_data: RawBytes::default(),
+            })
+            .unwrap();
+
+        // the minter is the pauser
+        token.runtime.syscalls.set_caller_id(token.runtime.resolve_id(&BOB).unwrap());
+        let err = token.pause().unwrap_err();
+        assert_eq!(ExitCode::from(&err), ExitCode::USR_FORBIDDEN);
+        token.runtime.syscalls.set_caller_id(token.runtime.resolve_id(&ALICE).unwrap());
+        token.pause().unwrap();
+
+        let params = TransferParams {
+            to: BOB,
+            amount: TokenAmount::from_whole(5),
+            operator_data: RawBytes::default(),
+        };
+        let err = token.transfer(params.clone()).unwrap_err();
+        assert_eq!(ExitCode::from(&err), ERR_PAUSED);
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::zero());
+
+        token.unpause().unwrap();
+        token.transfer(params).unwrap();
+        assert_eq!(token.balance_of(BOB).unwrap(), TokenAmount::from_whole(5));
+    }
+
     #[test]
     fn it_transfers_from_allowance() {
         let mut token = setup_token(&ALICE);